@@ -1,5 +1,5 @@
 use webgpu_game_engine::{
-    engine::{service::EnabledServices, winit::{run_winit, WinitSettings}}, importer::obj::load_obj, renderer::light::Light, scene::{camera::Camera, object3d::Object3D, scene::Scene}, util::{frame_delta::get_frame_delta, orbit_controls::{init_orbit_controls, orbit_controls}, simple_axes::simple_axes}
+    engine::{service::EnabledServices, winit::{run_winit, WinitSettings}}, importer::obj::load_obj, renderer::light::Light, scene::{camera::Camera, grid::Grid, object3d::{Object3D, ALL_LAYERS}, scene::Scene}, util::{frame_delta::get_frame_delta, orbit_controls::{init_orbit_controls, orbit_controls}}
 };
 
 // we will write it down later
@@ -8,19 +8,21 @@ static mut SCENE: Option<Scene> = None;
 pub fn main() {
     let light = Light::point_light(glm::vec4(0.0, 0.0,2.0, 1.0), glm::vec3(1.0, 1.0, 1.0));
     let object3d = load_obj("examples/dice/dice.obj").unwrap();
-    let axes3d = simple_axes();
+    let grid = Grid::new_object_3d(None, Grid::default());
     let mut scene_root = Object3D::create_empty();
     scene_root.children.push(object3d);
-    scene_root.children.push(axes3d);
+    scene_root.children.push(grid);
     let camera = Camera::PerspectiveCamera {
         world_to_local: glm::ext::look_at(
             glm::vec3(4.0, 0.5, 0.5),
             glm::vec3(0.0, 0.0, 0.0),
             glm::vec3(0.0, 1.0, 0.0),
         ),
+        fov: 90.0,
         near: 0.5,
         far: 10.0,
-        aspect: 1.5,
+        aspect: 1.0, // follows the window size
+        layers: ALL_LAYERS,
     };
     unsafe {
         let _ = SCENE.insert({
@@ -40,6 +42,8 @@ pub fn main() {
         &WinitSettings {
             window_width: 800,
             window_height: 600,
+            title: "Dice".to_string(),
+            ..Default::default()
         },
         enabled_services,
         render_loop,
@@ -4,7 +4,7 @@ use webgpu_game_engine::{
         light::Light,
         material::{Material, PhongMaterial},
     },
-    scene::{camera::Camera, mesh::Mesh, object3d::Object3D, scene::Scene}, util::{frame_delta::get_frame_delta, orbit_controls::{init_orbit_controls, orbit_controls}, simple_axes::simple_axes},
+    scene::{camera::Camera, grid::Grid, mesh::Mesh, object3d::{Object3D, ALL_LAYERS}, scene::Scene}, util::{frame_delta::get_frame_delta, orbit_controls::{init_orbit_controls, orbit_controls}},
 };
 
 const TRIANGLE_VERTICES: [f32; 36] = [
@@ -29,19 +29,21 @@ pub fn main() {
         5.0,
     ));
     let object3d = Mesh::new_object_3d(None, vertex_array, index_array, material);
-    let axes3d = simple_axes();
+    let grid = Grid::new_object_3d(None, Grid::default());
     let mut scene_root = Object3D::create_empty();
     scene_root.children.push(object3d);
-    scene_root.children.push(axes3d);
+    scene_root.children.push(grid);
     let camera = Camera::PerspectiveCamera {
         world_to_local: glm::ext::look_at(
             glm::vec3(4.0, 0.5, 0.5),
             glm::vec3(0.0, 0.0, 0.0),
             glm::vec3(0.0, 1.0, 0.0),
         ),
+        fov: 90.0,
         near: 0.5,
         far: 10.0,
-        aspect: 1.5,
+        aspect: 1.0, // follows the window size
+        layers: ALL_LAYERS,
     };
     unsafe {
         let _ = SCENE.insert({
@@ -61,6 +63,8 @@ pub fn main() {
         &WinitSettings {
             window_width: 800,
             window_height: 600,
+            title: "Triangle".to_string(),
+            ..Default::default()
         },
         enabled_services,
         render_loop,
@@ -0,0 +1,105 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use lazy_static::lazy_static;
+use winit::keyboard::KeyCode;
+
+/// Writes every frame of the main window as `frame-000000.png`, `frame-000001.png`
+/// and so on, with the scene time advancing by exactly one frame each time.
+pub struct Recording {
+    pub directory: PathBuf,
+    pub fps: f64,
+    pub frame: u32,
+    /// scene time of the first frame
+    pub start_time: Option<f64>,
+}
+
+pub struct CaptureEvent {
+    /// where the PNG is being written
+    pub path: PathBuf,
+}
+
+/// Captures of the main window are taken after its frame is rendered. Files are written
+/// in the background, the handlers are called once the image is read back.
+pub struct CaptureService {
+    /// takes a screenshot into the working directory when the capture service is enabled
+    pub screenshot_key: Option<KeyCode>,
+    pub pending_screenshot: Option<PathBuf>,
+    pub recording: Option<Recording>,
+    pub event_handlers: Vec<fn(&CaptureEvent)>,
+}
+
+lazy_static! {
+    static ref SERVICE: Arc<RwLock<CaptureService>> = Arc::new(CaptureService { screenshot_key: Some(KeyCode::F12), pending_screenshot: None, recording: None, event_handlers: vec!() }.into());
+}
+
+impl CaptureService {
+    pub fn get() -> RwLockReadGuard<'static, CaptureService> {
+        SERVICE.read().unwrap()
+    }
+
+    pub fn get_mut() -> RwLockWriteGuard<'static, CaptureService> {
+        SERVICE.write().unwrap()
+    }
+
+    /// Save the next frame of the main window as a PNG file.
+    pub fn request_screenshot(&mut self, path: impl Into<PathBuf>) {
+        self.pending_screenshot = Some(path.into());
+    }
+
+    /// Save the next frame as `screenshot-<milliseconds since the epoch>.png` in the
+    /// working directory.
+    pub fn request_timestamped_screenshot(&mut self) {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        self.request_screenshot(format!("screenshot-{}.png", millis));
+    }
+
+    pub fn start_recording(&mut self, directory: impl Into<PathBuf>, fps: f64) -> std::io::Result<()> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        self.recording = Some(Recording { directory, fps, frame: 0, start_time: None });
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.recording = None;
+    }
+
+    /// The time to give to the scene for the next frame. While recording it follows the
+    /// frame count instead of the clock, so slow frames do not show up in the sequence.
+    pub fn frame_time(&mut self, elapsed: f64) -> f64 {
+        match &mut self.recording {
+            Some(recording) => {
+                let start_time = *recording.start_time.get_or_insert(elapsed);
+                start_time + recording.frame as f64 / recording.fps
+            }
+            None => elapsed,
+        }
+    }
+
+    /// Path to save the frame that was just rendered to, if it should be captured.
+    pub fn take_capture_path(&mut self) -> Option<PathBuf> {
+        if let Some(path) = self.pending_screenshot.take() {
+            return Some(path);
+        }
+        let recording = self.recording.as_mut()?;
+        let path = recording.directory.join(format!("frame-{:06}.png", recording.frame));
+        recording.frame += 1;
+        Some(path)
+    }
+
+    pub fn handle_capture(path: PathBuf) {
+        let event = CaptureEvent { path };
+        Self::get().dispatch(&event);
+    }
+
+    pub fn add_handler(&mut self, f: fn(&CaptureEvent)) {
+        self.event_handlers.push(f);
+    }
+
+    pub fn dispatch(&self, event: &CaptureEvent) {
+        self.event_handlers.iter().for_each(|f| f(event));
+    }
+}
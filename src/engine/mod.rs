@@ -1,3 +1,7 @@
 pub mod winit;
 pub mod service;
 pub mod mouse_service;
+pub mod picking_service;
+pub mod capture_service;
+pub mod window_service;
+pub mod ui_service;
@@ -0,0 +1,97 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use lazy_static::lazy_static;
+
+use super::mouse_service::MouseService;
+use crate::scene::object3d::ALL_LAYERS;
+
+#[derive(Clone)]
+pub struct PickHit {
+    /// `Object3D::id` of the object under the pixel
+    pub object_id: u32,
+    /// depth buffer value at the pixel, between 0 (near) and 1 (far)
+    pub depth: f32,
+}
+
+#[derive(Clone)]
+pub struct PickResult {
+    pub x: u32,
+    pub y: u32,
+    pub hit: Option<PickHit>,
+}
+
+/// Pick requests are served by the renderer, the result arrives in `last_result` and
+/// the handlers once the GPU is done with it, which can be a frame or more later.
+pub struct PickingService {
+    pub pending_request: Option<(u32, u32)>,
+    pub last_result: Option<PickResult>,
+    pub event_handlers: Vec<fn(&PickResult)>,
+    /// bitmask of the layers of the objects that can be picked, editor helpers can be
+    /// kept out of it on a layer of their own
+    pub layers: u32,
+}
+
+lazy_static! {
+    static ref SERVICE: Arc<RwLock<PickingService>> = Arc::new(PickingService { pending_request: None, last_result: None, event_handlers: vec!(), layers: ALL_LAYERS }.into());
+}
+
+impl PickingService {
+    pub fn get() -> RwLockReadGuard<'static, PickingService> {
+        SERVICE.read().unwrap()
+    }
+
+    pub fn get_mut() -> RwLockWriteGuard<'static, PickingService> {
+        SERVICE.write().unwrap()
+    }
+
+    /// Pick the object at the given pixel of the render target. A newer request replaces
+    /// one the renderer has not started on yet.
+    pub fn request_pick(&mut self, x: f64, y: f64) {
+        self.pending_request = Some((x.max(0.0) as u32, y.max(0.0) as u32));
+    }
+
+    pub fn request_pick_at_cursor(&mut self) {
+        let mouse_service = MouseService::get();
+        self.request_pick(mouse_service.last_x, mouse_service.last_y);
+    }
+
+    pub fn take_request(&mut self) -> Option<(u32, u32)> {
+        self.pending_request.take()
+    }
+
+    /// The handlers run without the service locked, so they can request the next pick.
+    pub fn handle_pick_result(result: PickResult) {
+        let event_handlers = {
+            let mut service = Self::get_mut();
+            service.last_result = Some(result.clone());
+            service.event_handlers.clone()
+        };
+
+        event_handlers.iter().for_each(|f| f(&result));
+    }
+
+    pub fn add_handler(&mut self, f: fn(&PickResult)) {
+        self.event_handlers.push(f);
+    }
+
+    pub fn dispatch(&self, result: &PickResult) {
+        self.event_handlers.iter().for_each(|f| f(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handlers_can_request_the_next_pick() {
+        PickingService::get_mut().add_handler(|result| {
+            if result.x == 1 {
+                PickingService::get_mut().request_pick(2.0, 3.0);
+            }
+        });
+        PickingService::handle_pick_result(PickResult { x: 1, y: 1, hit: None });
+
+        assert!(PickingService::get().last_result.as_ref().is_some_and(|result| result.x == 1));
+        assert_eq!(PickingService::get_mut().take_request(), Some((2, 3)));
+    }
+}
@@ -1,11 +1,17 @@
 pub enum ServiceType {
     MouseService,
     TweeningService,
+    CaptureService,
+    UiService,
 }
 
 pub struct EnabledServices {
     pub mouse_service: bool,
     pub tweening_service: bool,
+    /// screenshots by key, captures requested in code work without it
+    pub capture_service: bool,
+    /// egui over the main window, taking the input it uses from the other services
+    pub ui_service: bool,
 }
 
 impl Default for EnabledServices {
@@ -13,6 +19,8 @@ impl Default for EnabledServices {
         Self {
             mouse_service: false,
             tweening_service: false,
+            capture_service: false,
+            ui_service: false,
         }
     }
 }
@@ -22,6 +30,8 @@ impl EnabledServices {
         match service {
             ServiceType::MouseService => self.mouse_service = true,
             ServiceType::TweeningService => self.tweening_service = true,
+            ServiceType::CaptureService => self.capture_service = true,
+            ServiceType::UiService => self.ui_service = true,
         };
 
         self
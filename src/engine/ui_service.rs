@@ -0,0 +1,29 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use lazy_static::lazy_static;
+
+/// egui drawn over the main window. Build the UI with `context` from the render loop,
+/// anything shown there is drawn on top of that frame of the main window. Input the UI
+/// takes, see `egui::Context::wants_pointer_input` and `wants_keyboard_input`, is kept
+/// from the mouse and capture services.
+pub struct UiService {
+    pub context: egui::Context,
+}
+
+lazy_static! {
+    static ref SERVICE: Arc<RwLock<UiService>> = Arc::new(UiService { context: egui::Context::default() }.into());
+}
+
+impl UiService {
+    pub fn get() -> RwLockReadGuard<'static, UiService> {
+        SERVICE.read().unwrap()
+    }
+
+    pub fn get_mut() -> RwLockWriteGuard<'static, UiService> {
+        SERVICE.write().unwrap()
+    }
+
+    /// The egui context, it can be kept around as it is shared.
+    pub fn context() -> egui::Context {
+        Self::get().context.clone()
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use lazy_static::lazy_static;
+
+pub struct ResizeEvent {
+    /// index of the window, 0 is the main window and the rest are `WinitSettings::extra_windows`
+    pub window: usize,
+    /// in physical pixels
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f64,
+}
+
+/// Size of the main window. Resize events are dispatched for every window.
+pub struct WindowService {
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f64,
+    pub event_handlers: Vec<fn(&ResizeEvent)>,
+}
+
+lazy_static! {
+    static ref SERVICE: Arc<RwLock<WindowService>> = Arc::new(WindowService { width: 0, height: 0, scale_factor: 1.0, event_handlers: vec!() }.into());
+}
+
+impl WindowService {
+    pub fn get() -> RwLockReadGuard<'static, WindowService> {
+        SERVICE.read().unwrap()
+    }
+
+    pub fn get_mut() -> RwLockWriteGuard<'static, WindowService> {
+        SERVICE.write().unwrap()
+    }
+
+    pub fn handle_resize(window: usize, width: u32, height: u32, scale_factor: f64) {
+        if window == 0 {
+            let mut service = Self::get_mut();
+            service.width = width;
+            service.height = height;
+            service.scale_factor = scale_factor;
+        }
+        let event = ResizeEvent { window, width, height, scale_factor };
+
+        Self::get().dispatch(&event);
+    }
+
+    /// Window size in logical pixels.
+    pub fn logical_size(&self) -> (f64, f64) {
+        (self.width as f64 / self.scale_factor, self.height as f64 / self.scale_factor)
+    }
+
+    pub fn add_handler(&mut self, f: fn(&ResizeEvent)) {
+        self.event_handlers.push(f);
+    }
+
+    pub fn dispatch(&self, event: &ResizeEvent) {
+        self.event_handlers.iter().for_each(|f| f(event));
+    }
+}
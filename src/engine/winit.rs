@@ -1,22 +1,151 @@
 use crate::{
-    engine::mouse_service::MouseService,
+    engine::{
+        capture_service::CaptureService, mouse_service::MouseService, ui_service::UiService,
+        window_service::WindowService,
+    },
     renderer::{
-        material::MaterialManager, render::render_to_texture_view, wgpu_handles::WgpuHandles,
+        capture::{blit_capture_texture, create_capture_texture, read_texture_to_image, save_png},
+        material::MaterialManager,
+        particles::simulate_particles,
+        render::{
+            render_textures_to_offscreen_targets, render_window_to_texture_view, RenderTargetSize,
+        },
+        texture::texture_compression_features,
+        ui::{prepare_ui_frame, render_ui_to_texture_view, UiFrame},
+        wgpu_handles::WgpuHandles,
     },
     scene::scene::Scene,
 };
-use std::{collections::HashMap, sync::Arc, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use winit::{
-    event::{Event, MouseButton, WindowEvent},
-    event_loop::EventLoop,
-    window::{Window, WindowId},
+    event::{ElementState, Event, KeyEvent, MouseButton, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    keyboard::PhysicalKey,
+    window::{Fullscreen, Window, WindowId},
 };
 
 use super::service::EnabledServices;
 
+/// How long to wait before looking for an adapter again after the device was lost and
+/// none was found.
+const DEVICE_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
 pub struct WinitSettings {
     pub window_width: u32,
     pub window_height: u32,
+    /// extra windows have their index after it
+    pub title: String,
+    pub resizable: bool,
+    /// of the main window, extra windows are never fullscreen
+    pub fullscreen: Option<FullscreenMode>,
+    pub present_mode: PresentMode,
+    /// frames per second each window is drawn at most, without it they are drawn as fast
+    /// as the present mode allows
+    pub max_frame_rate: Option<f64>,
+    pub power_preference: wgpu::PowerPreference,
+    /// a software adapter, for machines without a GPU and for tests that should look the
+    /// same everywhere
+    pub force_fallback_adapter: bool,
+    /// the device is created with these or not at all, the optional features the renderer
+    /// makes use of are added when the adapter has them
+    pub required_features: wgpu::Features,
+    /// the texture size limits are raised to the ones of the adapter, so that windows as
+    /// large as the monitor can be drawn
+    pub required_limits: wgpu::Limits,
+    /// windows opened next to the main one, `SceneView::window` refers to them starting
+    /// from 1
+    pub extra_windows: Vec<ExtraWindowSettings>,
+}
+
+impl Default for WinitSettings {
+    fn default() -> Self {
+        Self {
+            window_width: 800,
+            window_height: 600,
+            title: "Scene".to_string(),
+            resizable: true,
+            fullscreen: None,
+            present_mode: PresentMode::Vsync,
+            max_frame_rate: None,
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_defaults(),
+            extra_windows: vec![],
+        }
+    }
+}
+
+pub struct ExtraWindowSettings {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullscreenMode {
+    /// a borderless window covering the monitor it is on
+    Borderless,
+    /// the primary monitor switches to its largest video mode with the highest refresh
+    /// rate, borderless if there is none
+    Exclusive,
+}
+
+impl FullscreenMode {
+    fn to_winit(self, event_loop: &EventLoop<()>) -> Fullscreen {
+        let video_mode = match self {
+            FullscreenMode::Borderless => None,
+            FullscreenMode::Exclusive => event_loop
+                .primary_monitor()
+                .or_else(|| event_loop.available_monitors().next())
+                .and_then(|monitor| {
+                    monitor.video_modes().max_by_key(|video_mode| {
+                        let size = video_mode.size();
+                        (
+                            size.width * size.height,
+                            video_mode.refresh_rate_millihertz(),
+                        )
+                    })
+                }),
+        };
+        match video_mode {
+            Some(video_mode) => Fullscreen::Exclusive(video_mode),
+            None => Fullscreen::Borderless(None),
+        }
+    }
+}
+
+/// Modes the surface cannot present with fall back to vsync, which every surface can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// frames wait for the vertical blank, no tearing
+    Vsync,
+    /// frames replace the one waiting for the vertical blank, no tearing and less latency
+    /// at the cost of drawing frames that are never shown
+    Mailbox,
+    /// frames are shown right away, they can tear
+    Immediate,
+}
+
+impl PresentMode {
+    fn to_wgpu(self, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let present_mode = match self {
+            PresentMode::Vsync => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        };
+        if supported.contains(&present_mode) {
+            present_mode
+        } else {
+            wgpu::PresentMode::Fifo
+        }
+    }
 }
 
 pub fn run_winit<'a, F>(settings: &WinitSettings, enabled_services: EnabledServices, render_loop: F)
@@ -34,82 +163,112 @@ pub async fn run_winit_async<'a, F>(
     F: Fn(f64) -> &'a mut Scene,
 {
     let event_loop = EventLoop::new().unwrap();
-    let mut viewports = Vec::with_capacity(1usize);
-
-    let window = winit::window::WindowBuilder::new()
-        .with_title("Scene".to_string())
-        .with_inner_size(winit::dpi::PhysicalSize::new(
-            settings.window_width,
-            settings.window_height,
-        ))
-        .build(&event_loop)
-        .unwrap();
-    let window = Arc::new(window);
-
-    viewports.push((
-        window,
-        wgpu::Color {
-            r: 1.0,
-            g: 0.6,
-            b: 0.8,
-            a: 1.0,
-        },
-    ));
+    let mut viewports = Vec::with_capacity(1 + settings.extra_windows.len());
+
+    let window_sizes = std::iter::once((settings.window_width, settings.window_height)).chain(
+        settings
+            .extra_windows
+            .iter()
+            .map(|extra_window| (extra_window.width, extra_window.height)),
+    );
+    for (index, (width, height)) in window_sizes.enumerate() {
+        let (title, fullscreen) = match index {
+            0 => (
+                settings.title.clone(),
+                settings.fullscreen.map(|mode| mode.to_winit(&event_loop)),
+            ),
+            _ => (format!("{} {}", settings.title, index), None),
+        };
+        let window = winit::window::WindowBuilder::new()
+            .with_title(title)
+            .with_inner_size(winit::dpi::PhysicalSize::new(width, height))
+            .with_resizable(settings.resizable)
+            .with_fullscreen(fullscreen)
+            .build(&event_loop)
+            .unwrap();
+        let window = Arc::new(window);
+
+        viewports.push((
+            window,
+            wgpu::Color {
+                r: 1.0,
+                g: 0.6,
+                b: 0.8,
+                a: 1.0,
+            },
+        ));
+    }
 
     let instance = wgpu::Instance::default();
     let viewports: Vec<_> = viewports
         .into_iter()
-        .map(|(window, color)| ViewportDesc::new(window, color, &instance))
-        .collect();
-    let adapter = instance
-        .request_adapter(&wgpu::RequestAdapterOptions {
-            // Request an adapter which can render to our surface
-            compatible_surface: viewports.first().map(|desc| &desc.surface),
-            ..Default::default()
+        .enumerate()
+        .map(|(index, (window, color))| {
+            ViewportDesc::new(index, window, color, settings.present_mode, &instance)
         })
-        .await
-        .expect("Failed to find an appropriate adapter");
-
-    // Create the logical device and command queue
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::downlevel_defaults(),
-            },
-            None,
-        )
-        .await
-        .expect("Failed to create device");
+        .collect();
+    let main_surface = viewports.first().map(|desc| &desc.surface);
+    let (adapter, device, queue) =
+        match request_adapter_and_device(&instance, main_surface, settings).await {
+            Ok(handles) => handles,
+            Err(error) => {
+                eprintln!("{}", error);
+                return;
+            }
+        };
 
     println!("Max Bind Groups: {}", device.limits().max_bind_groups);
-    let device = Arc::new(device);
+    let mut device = Arc::new(device);
+    let mut device_lost = watch_device_loss(&device);
 
     let mut viewports: HashMap<WindowId, Viewport> = viewports
         .into_iter()
         .map(|desc| (desc.window.id(), desc.build(&adapter, &device)))
         .collect();
 
-    let mut material_manager = MaterialManager::new();
-    let surface_capabilities = viewports
-        .iter_mut()
-        .next()
-        .unwrap()
-        .1
-        .get_surface_capabilities(&adapter);
-    material_manager.populate(&device, &surface_capabilities);
-    let mut material_manager = Arc::new(material_manager);
-
-    let mut wgpu_handles = WgpuHandles {
+    let mut material_manager = Arc::new(create_material_manager(&adapter, &device, &viewports));
+    let mut wgpu_handles = WgpuHandles::new(
         adapter,
         instance,
-        device: device.clone(),
+        device.clone(),
         queue,
-        material_manager: material_manager.clone(),
-    };
+        material_manager.clone(),
+    );
+
+    for viewport in viewports.values() {
+        let size = viewport.desc.window.inner_size();
+        WindowService::handle_resize(
+            viewport.desc.index,
+            size.width,
+            size.height,
+            viewport.desc.window.scale_factor(),
+        );
+    }
+
+    // the UI takes its input from the main window
+    let mut ui_state = viewports
+        .values()
+        .find(|viewport| viewport.desc.index == 0)
+        .filter(|_| enabled_services.ui_service)
+        .map(|viewport| {
+            egui_winit::State::new(
+                UiService::context(),
+                egui::ViewportId::ROOT,
+                &event_loop,
+                Some(viewport.desc.window.scale_factor() as f32),
+                Some(device.limits().max_texture_dimension_2d as usize),
+            )
+        });
 
     let start_instant = Instant::now();
+    let frame_interval = settings
+        .max_frame_rate
+        .filter(|max_frame_rate| *max_frame_rate > 0.0)
+        .map(|max_frame_rate| Duration::from_secs_f64(1.0 / max_frame_rate));
+    // windows held back by the frame rate cap or a lost device, with the time they are
+    // drawn again
+    let mut next_frames: HashMap<WindowId, Instant> = HashMap::new();
+    let mut current_frame: Option<Frame> = None;
 
     env_logger::init();
     event_loop
@@ -119,7 +278,36 @@ pub async fn run_winit_async<'a, F>(
             // the resources are properly cleaned up.
             let _ = (&wgpu_handles, &device, &material_manager);
 
+            if let Event::AboutToWait = event {
+                let now = Instant::now();
+                next_frames.retain(|window_id, next_frame| {
+                    if *next_frame > now {
+                        return true;
+                    }
+                    if let Some(viewport) = viewports.get(window_id) {
+                        viewport.desc.window.request_redraw();
+                    }
+                    false
+                });
+                target.set_control_flow(match next_frames.values().min() {
+                    Some(next_frame) => ControlFlow::WaitUntil(*next_frame),
+                    None => ControlFlow::Wait,
+                });
+                return;
+            }
+
             if let Event::WindowEvent { window_id, event } = event {
+                // input the UI uses is not passed on to the services, egui reports it
+                // from its wants_pointer_input and wants_keyboard_input as it arrives
+                let ui_consumed = match (&mut ui_state, viewports.get(&window_id)) {
+                    (Some(ui_state), Some(viewport)) if viewport.desc.index == 0 => {
+                        ui_state
+                            .on_window_event(&viewport.desc.window, &event)
+                            .consumed
+                    }
+                    _ => false,
+                };
+
                 match event {
                     WindowEvent::Resized(new_size) => {
                         // Recreate the swap chain with the new size
@@ -127,32 +315,221 @@ pub async fn run_winit_async<'a, F>(
                             viewport.resize(&device, new_size);
                             // On macos the window needs to be redrawn manually after resizing
                             viewport.desc.window.request_redraw();
+                            WindowService::handle_resize(
+                                viewport.desc.index,
+                                new_size.width,
+                                new_size.height,
+                                viewport.desc.window.scale_factor(),
+                            );
                         }
                     }
                     WindowEvent::RedrawRequested => {
+                        let frame_start = Instant::now();
+                        if device_lost.load(Ordering::Relaxed) {
+                            match pollster::block_on(recreate_device(
+                                &mut wgpu_handles,
+                                &mut viewports,
+                                settings,
+                            )) {
+                                Ok(()) => {
+                                    device = wgpu_handles.device.clone();
+                                    device_lost = watch_device_loss(&device);
+                                    material_manager = wgpu_handles.material_manager.clone();
+                                    // the UI textures of the frame went with the old device
+                                    current_frame = None;
+                                }
+                                // the adapter can take a while to come back after a
+                                // driver reset
+                                Err(error) => {
+                                    eprintln!("{}, trying again", error);
+                                    next_frames
+                                        .insert(window_id, frame_start + DEVICE_RETRY_INTERVAL);
+                                    return;
+                                }
+                            }
+                        }
+                        let main_window = viewports
+                            .values()
+                            .find(|viewport| viewport.desc.index == 0)
+                            .map(|viewport| viewport.desc.window.clone());
                         if let Some(viewport) = viewports.get_mut(&window_id) {
-                            let frame = viewport.get_current_texture();
+                            let Some(frame) = viewport.get_current_texture(&device) else {
+                                // minimized windows are drawn again once they are
+                                // restored and resized
+                                let size = viewport.desc.window.inner_size();
+                                if size.width > 0 && size.height > 0 {
+                                    viewport.desc.window.request_redraw();
+                                }
+                                return;
+                            };
                             let texture_view = frame
                                 .texture
                                 .create_view(&wgpu::TextureViewDescriptor::default());
-                            let scene = render_loop(start_instant.elapsed().as_secs_f64());
-                            render_to_texture_view(
+
+                            // every window draws the same frame, the next one starts when
+                            // a window that already drew it is redrawn
+                            let is_new_frame = current_frame
+                                .as_ref()
+                                .is_none_or(|frame| frame.drawn_windows.contains(&window_id));
+                            if is_new_frame {
+                                current_frame = None;
+                                let time = CaptureService::get_mut()
+                                    .frame_time(start_instant.elapsed().as_secs_f64());
+                                let mut ui_state = ui_state.as_mut().zip(main_window.as_ref());
+                                if let Some((ui_state, window)) = &mut ui_state {
+                                    let raw_input = ui_state.take_egui_input(window);
+                                    UiService::context().begin_frame(raw_input);
+                                }
+                                let scene = render_loop(time);
+                                scene.time = time;
+                                if enabled_services.mouse_service {
+                                    MouseService::get_mut().clear_deltas();
+                                }
+                                scene.drop_gpu_resources_of_lost_device(
+                                    wgpu_handles.device_generation,
+                                );
+                                scene.update_terrain_lods();
+                                // the figures of all windows are added up for the frame
+                                scene.render_stats.start_frame();
+                                let particle_stats = simulate_particles(
+                                    scene,
+                                    &mut wgpu_handles,
+                                    &material_manager,
+                                    time,
+                                );
+                                scene.render_stats.accumulate(&particle_stats);
+                                render_textures_to_offscreen_targets(
+                                    scene,
+                                    &mut wgpu_handles,
+                                    &mut material_manager,
+                                );
+                                let ui_frame = ui_state.map(|(ui_state, window)| {
+                                    let context = UiService::context();
+                                    let output = context.end_frame();
+                                    ui_state.handle_platform_output(window, output.platform_output);
+                                    UiFrame {
+                                        paint_jobs: context
+                                            .tessellate(output.shapes, output.pixels_per_point),
+                                        textures_delta: output.textures_delta,
+                                        pixels_per_point: output.pixels_per_point,
+                                    }
+                                });
+                                if let Some(ui_frame) = &ui_frame {
+                                    prepare_ui_frame(&mut wgpu_handles, ui_frame);
+                                }
+                                current_frame = Some(Frame {
+                                    scene,
+                                    ui_frame,
+                                    drawn_windows: HashSet::new(),
+                                });
+                            }
+                            let Some(Frame {
+                                scene,
+                                ui_frame,
+                                drawn_windows,
+                            }) = &mut current_frame
+                            else {
+                                return;
+                            };
+                            drawn_windows.insert(window_id);
+                            // the UI is drawn over the main window
+                            let ui_frame = ui_frame.as_ref().filter(|_| viewport.desc.index == 0);
+
+                            let render_target_size = RenderTargetSize {
+                                width: frame.texture.width(),
+                                height: frame.texture.height(),
+                                scale_factor: viewport.desc.window.scale_factor(),
+                            };
+
+                            let capture_path = match viewport.desc.index {
+                                0 => CaptureService::get_mut().take_capture_path(),
+                                _ => None,
+                            };
+                            // a surface that cannot be copied from gets the frame drawn
+                            // into a texture that can, which is then drawn onto it
+                            let capture_texture = capture_path
+                                .as_ref()
+                                .filter(|_| {
+                                    !viewport
+                                        .config
+                                        .usage
+                                        .contains(wgpu::TextureUsages::COPY_SRC)
+                                })
+                                .map(|_| {
+                                    create_capture_texture(
+                                        &device,
+                                        (render_target_size.width, render_target_size.height),
+                                        frame.texture.format(),
+                                    )
+                                });
+                            let capture_view = capture_texture
+                                .as_ref()
+                                .map(|texture| texture.create_view(&Default::default()));
+                            let target_view = capture_view.as_ref().unwrap_or(&texture_view);
+
+                            render_window_to_texture_view(
                                 scene,
                                 &mut wgpu_handles,
-                                &texture_view,
+                                target_view,
+                                render_target_size,
+                                viewport.desc.index,
                                 &mut material_manager,
                             );
-                            frame.present();
+                            if let Some(ui_frame) = ui_frame {
+                                render_ui_to_texture_view(
+                                    &mut wgpu_handles,
+                                    ui_frame,
+                                    target_view,
+                                    render_target_size,
+                                );
+                            }
 
-                            viewport.desc.window.request_redraw();
+                            if let Some(texture) = &capture_texture {
+                                blit_capture_texture(&wgpu_handles, texture, &texture_view);
+                            }
+                            if let Some(path) = capture_path {
+                                let texture = capture_texture.as_ref().unwrap_or(&frame.texture);
+                                match read_texture_to_image(&wgpu_handles, texture) {
+                                    Ok(image) => {
+                                        save_png(image, path.clone());
+                                        CaptureService::handle_capture(path);
+                                    }
+                                    Err(e) => eprintln!("{} ({})", e, path.display()),
+                                }
+                            }
+                            frame.present();
 
-                            if (enabled_services.mouse_service) {
-                                MouseService::get_mut().clear_deltas();
+                            match frame_interval {
+                                Some(frame_interval) => {
+                                    next_frames.insert(window_id, frame_start + frame_interval);
+                                }
+                                None => viewport.desc.window.request_redraw(),
                             }
                         }
                     }
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(key_code),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    } => {
+                        if !enabled_services.capture_service || ui_consumed {
+                            return;
+                        }
+                        let mut capture_service = CaptureService::get_mut();
+                        if capture_service.screenshot_key == Some(key_code) {
+                            capture_service.request_timestamped_screenshot();
+                        }
+                    }
                     WindowEvent::CloseRequested => {
-                        viewports.remove(&window_id);
+                        if let Some(viewport) = viewports.remove(&window_id) {
+                            wgpu_handles.window_targets.remove(&viewport.desc.index);
+                        }
+                        next_frames.remove(&window_id);
                         if viewports.is_empty() {
                             target.exit();
                         }
@@ -162,7 +539,11 @@ pub async fn run_winit_async<'a, F>(
                         state,
                         button,
                     } => {
-                        if !enabled_services.mouse_service {
+                        // releases always get through, so drags that started in the
+                        // scene end when the button is let go over the UI
+                        if !enabled_services.mouse_service
+                            || (ui_consumed && state == ElementState::Pressed)
+                        {
                             return;
                         }
                         match button {
@@ -181,7 +562,7 @@ pub async fn run_winit_async<'a, F>(
                         device_id,
                         position,
                     } => {
-                        if !enabled_services.mouse_service {
+                        if !enabled_services.mouse_service || ui_consumed {
                             return;
                         }
                         MouseService::handle_mouse_move(position.x, position.y);
@@ -193,9 +574,121 @@ pub async fn run_winit_async<'a, F>(
         .unwrap();
 }
 
+/// The scene `render_loop` returned for the frame that is being drawn, and the UI drawn
+/// over it. Windows redrawn before the next frame starts draw the same one.
+struct Frame<'a> {
+    scene: &'a mut Scene,
+    ui_frame: Option<UiFrame>,
+    /// windows that drew the frame already
+    drawn_windows: HashSet<WindowId>,
+}
+
+/// An adapter that can draw to `compatible_surface` and the device and queue created on
+/// it, as the settings ask for.
+async fn request_adapter_and_device(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface<'static>>,
+    settings: &WinitSettings,
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), String> {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: settings.power_preference,
+            force_fallback_adapter: settings.force_fallback_adapter,
+            compatible_surface,
+        })
+        .await
+        .ok_or("Failed to find an appropriate adapter")?;
+
+    // Create the logical device and command queue
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                // compressed textures the adapter cannot sample get decompressed on load,
+                // render stats go without GPU times if there are no timestamp queries
+                required_features: settings.required_features
+                    | (adapter.features()
+                        & (texture_compression_features() | wgpu::Features::TIMESTAMP_QUERY)),
+                required_limits: settings
+                    .required_limits
+                    .clone()
+                    .using_resolution(adapter.limits()),
+            },
+            None,
+        )
+        .await
+        .map_err(|error| format!("Failed to create device: {}", error))?;
+    Ok((adapter, device, queue))
+}
+
+/// The pipelines for the format of the main window.
+fn create_material_manager(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    viewports: &HashMap<WindowId, Viewport>,
+) -> MaterialManager {
+    let mut material_manager = MaterialManager::new();
+    let main_viewport = viewports
+        .values()
+        .min_by_key(|viewport| viewport.desc.index)
+        .unwrap();
+    material_manager.populate(device, &main_viewport.get_surface_capabilities(adapter));
+    material_manager
+}
+
+/// A flag that is set when the device is lost. Errors on a lost device are expected and
+/// only logged, before that they panic like they do without a handler.
+fn watch_device_loss(device: &wgpu::Device) -> Arc<AtomicBool> {
+    let device_lost = Arc::new(AtomicBool::new(false));
+
+    // this is also called when the device is dropped, each device has its own flag so
+    // that dropping one that was replaced does not count
+    let flag = device_lost.clone();
+    device.set_device_lost_callback(move |_, message| {
+        if !flag.swap(true, Ordering::Relaxed) {
+            eprintln!("Device lost: {}", message);
+        }
+    });
+    let flag = device_lost.clone();
+    device.on_uncaptured_error(Box::new(move |error| {
+        if flag.load(Ordering::Relaxed) {
+            eprintln!("wgpu error on the lost device: {}", error);
+        } else {
+            panic!("wgpu error: {}", error);
+        }
+    }));
+    device_lost
+}
+
+/// Create the device anew after it was lost, with the pipelines and surfaces on it. The
+/// adapter is requested again too, it can be gone as well after a driver reset. The
+/// scene has to be uploaded again afterwards.
+async fn recreate_device(
+    wgpu_handles: &mut WgpuHandles,
+    viewports: &mut HashMap<WindowId, Viewport>,
+    settings: &WinitSettings,
+) -> Result<(), String> {
+    let main_surface = viewports
+        .values()
+        .min_by_key(|viewport| viewport.desc.index)
+        .map(|viewport| &viewport.desc.surface);
+    let (adapter, device, queue) =
+        request_adapter_and_device(&wgpu_handles.instance, main_surface, settings).await?;
+
+    for viewport in viewports.values_mut() {
+        viewport.rebuild(&adapter, &device);
+    }
+    let material_manager = create_material_manager(&adapter, &device, viewports);
+    wgpu_handles.replace_device(adapter, Arc::new(device), queue, Arc::new(material_manager));
+    Ok(())
+}
+
 pub struct ViewportDesc {
+    /// 0 for the main window, extra windows follow in the order of the settings
+    pub index: usize,
     pub window: Arc<Window>,
     pub background: wgpu::Color,
+    pub present_mode: PresentMode,
     pub surface: wgpu::Surface<'static>,
 }
 
@@ -205,37 +698,86 @@ pub struct Viewport {
 }
 
 impl ViewportDesc {
-    pub fn new(window: Arc<Window>, background: wgpu::Color, instance: &wgpu::Instance) -> Self {
+    pub fn new(
+        index: usize,
+        window: Arc<Window>,
+        background: wgpu::Color,
+        present_mode: PresentMode,
+        instance: &wgpu::Instance,
+    ) -> Self {
         let surface = instance.create_surface(window.clone()).unwrap();
         Self {
+            index,
             window,
             background,
+            present_mode,
             surface,
         }
     }
 
     pub fn build(self, adapter: &wgpu::Adapter, device: &wgpu::Device) -> Viewport {
-        let size = self.window.inner_size();
-        let config = self
+        let config = self.surface_config(adapter, self.window.inner_size());
+        self.surface.configure(device, &config);
+        Viewport { desc: self, config }
+    }
+
+    fn surface_config(
+        &self,
+        adapter: &wgpu::Adapter,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> wgpu::SurfaceConfiguration {
+        let mut config = self
             .surface
             .get_default_config(adapter, size.width, size.height)
             .unwrap();
-        self.surface.configure(device, &config);
-        Viewport { desc: self, config }
+        let capabilities = self.surface.get_capabilities(adapter);
+        config.present_mode = self.present_mode.to_wgpu(&capabilities.present_modes);
+        // lets frame captures copy from the surface texture directly
+        if capabilities.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+            config.usage |= wgpu::TextureUsages::COPY_SRC;
+        }
+        config
     }
 }
 
 impl Viewport {
     pub fn resize(&mut self, device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) {
+        // minimized windows report a size of zero, which surfaces cannot have
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
         self.config.width = size.width;
         self.config.height = size.height;
         self.desc.surface.configure(device, &self.config);
     }
-    pub fn get_current_texture(&mut self) -> wgpu::SurfaceTexture {
-        self.desc
-            .surface
-            .get_current_texture()
-            .expect("Failed to acquire next swap chain texture")
+
+    /// Configure the surface for a device created in place of a lost one, which may be on
+    /// another adapter.
+    pub fn rebuild(&mut self, adapter: &wgpu::Adapter, device: &wgpu::Device) {
+        let size = winit::dpi::PhysicalSize::new(self.config.width, self.config.height);
+        self.config = self.desc.surface_config(adapter, size);
+        self.desc.surface.configure(device, &self.config);
+    }
+
+    /// The texture to draw the next frame into, or none if the frame is skipped. A lost
+    /// or outdated surface is configured again first, and there is nothing to draw into
+    /// while the window is minimized.
+    pub fn get_current_texture(&mut self, device: &wgpu::Device) -> Option<wgpu::SurfaceTexture> {
+        let size = self.desc.window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return None;
+        }
+        match self.desc.surface.get_current_texture() {
+            Ok(frame) => Some(frame),
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.resize(device, size);
+                self.desc.surface.get_current_texture().ok()
+            }
+            Err(wgpu::SurfaceError::Timeout) => None,
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                panic!("Out of memory acquiring the next frame")
+            }
+        }
     }
     pub fn get_surface_capabilities(&self, adapter: &wgpu::Adapter) -> wgpu::SurfaceCapabilities {
         self.desc.surface.get_capabilities(adapter)
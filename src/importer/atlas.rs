@@ -0,0 +1,297 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use wgpu::TextureFormat;
+
+use super::{
+    decompress::decompress,
+    texture::{load_texture, LoadTextureError, TextureData},
+};
+
+/// Transparent texels between the images, the outermost ones repeat the edge of the
+/// image so linear filtering does not pull in the neighbours.
+const ATLAS_PADDING: u32 = 2;
+
+/// Where an image is in its atlas, in texels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Loose images combined into one sRGB RGBA8 texture so they can be drawn together.
+#[derive(Debug)]
+pub struct TextureAtlas {
+    /// unique among all atlases, the renderer keeps the textures by it
+    pub id: u32,
+    pub texture: TextureData,
+    /// by file stem
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).copied()
+    }
+
+    /// Texture coordinates of a region as left, top, right and bottom.
+    pub fn uv_rect(&self, region: &AtlasRegion) -> glm::Vec4 {
+        let (width, height) = (self.texture.width as f32, self.texture.height as f32);
+        glm::vec4(
+            region.x as f32 / width,
+            region.y as f32 / height,
+            (region.x + region.width) as f32 / width,
+            (region.y + region.height) as f32 / height,
+        )
+    }
+}
+
+/// Place rectangles in rows from the tallest to the shortest, in an area `width` wide.
+/// Returns the top left corners in the order of `sizes` and the height of the area, a
+/// power of two.
+pub fn pack_shelves(sizes: &[(u32, u32)], width: u32) -> (Vec<(u32, u32)>, u32) {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|index| (std::cmp::Reverse(sizes[*index].1), *index));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for index in order {
+        let (rect_width, rect_height) = sizes[index];
+        if x + rect_width > width {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        positions[index] = (x, y);
+        x += rect_width;
+        shelf_height = shelf_height.max(rect_height);
+    }
+
+    (positions, (y + shelf_height).max(1).next_power_of_two())
+}
+
+/// Load the images with `load_texture` and pack them into an atlas. The regions are
+/// named after the file stems.
+pub fn load_texture_atlas<P>(
+    paths: impl IntoIterator<Item = P>,
+) -> Result<TextureAtlas, Box<dyn Error>>
+where
+    P: AsRef<Path>,
+{
+    let mut images = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .ok_or_else(|| LoadTextureError(format!("{} has no file name", path.display())))?;
+        images.push((name, load_texture(path)?));
+    }
+    pack_texture_atlas(images)
+}
+
+/// Pack decoded images into an atlas. Only the base level of each image is used, block
+/// compressed images are decompressed first. The names must be unique, the regions are
+/// looked up by them.
+pub fn pack_texture_atlas(
+    images: Vec<(String, TextureData)>,
+) -> Result<TextureAtlas, Box<dyn Error>> {
+    static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+    let mut names = HashSet::new();
+    for (name, _) in &images {
+        if !names.insert(name.as_str()) {
+            return Err(Box::new(LoadTextureError(format!(
+                "there is more than one image named {} in the atlas",
+                name
+            ))));
+        }
+    }
+
+    let mut decoded = Vec::with_capacity(images.len());
+    for (name, image) in images {
+        let image = match image.format {
+            TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba8Unorm => image,
+            format if format.is_compressed() => decompress(&image)?,
+            format => {
+                return Err(Box::new(LoadTextureError(format!(
+                    "{:?} images cannot be put in an atlas",
+                    format
+                ))))
+            }
+        };
+        decoded.push((name, image));
+    }
+
+    let sizes: Vec<(u32, u32)> = decoded
+        .iter()
+        .map(|(_, image)| {
+            (
+                image.width + 2 * ATLAS_PADDING,
+                image.height + 2 * ATLAS_PADDING,
+            )
+        })
+        .collect();
+    // roughly square, and wide enough for the widest image
+    let area: u32 = sizes.iter().map(|(width, height)| width * height).sum();
+    let widest = sizes.iter().map(|(width, _)| *width).max().unwrap_or(1);
+    let width = ((area as f32).sqrt().ceil() as u32)
+        .max(widest)
+        .next_power_of_two();
+    let (positions, height) = pack_shelves(&sizes, width);
+
+    let mut texels = vec![0u8; (width * height * 4) as usize];
+    let mut regions = HashMap::new();
+    for ((name, image), (x, y)) in decoded.into_iter().zip(positions) {
+        let region = AtlasRegion {
+            x: x + ATLAS_PADDING,
+            y: y + ATLAS_PADDING,
+            width: image.width,
+            height: image.height,
+        };
+        copy_with_extruded_edges(&image, &region, &mut texels, width);
+        regions.insert(name, region);
+    }
+
+    Ok(TextureAtlas {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        texture: TextureData {
+            width,
+            height,
+            format: TextureFormat::Rgba8UnormSrgb,
+            levels: vec![texels],
+        },
+        regions,
+    })
+}
+
+/// Copy an image into its region with its outermost texels repeated once around it.
+fn copy_with_extruded_edges(
+    image: &TextureData,
+    region: &AtlasRegion,
+    texels: &mut [u8],
+    atlas_width: u32,
+) {
+    if region.width == 0 || region.height == 0 {
+        return;
+    }
+    let pixels = &image.levels[0];
+    for y in -1..=region.height as i32 {
+        let source_y = y.clamp(0, region.height as i32 - 1) as u32;
+        for x in -1..=region.width as i32 {
+            let source_x = x.clamp(0, region.width as i32 - 1) as u32;
+            let source = ((source_y * image.width + source_x) * 4) as usize;
+            let target_x = (region.x as i32 + x) as u32;
+            let target_y = (region.y as i32 + y) as u32;
+            let target = ((target_y * atlas_width + target_x) * 4) as usize;
+            texels[target..target + 4].copy_from_slice(&pixels[source..source + 4]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32) -> TextureData {
+        TextureData {
+            width,
+            height,
+            format: TextureFormat::Rgba8UnormSrgb,
+            levels: vec![vec![255; (width * height * 4) as usize]],
+        }
+    }
+
+    fn overlap(a: &AtlasRegion, b: &AtlasRegion, gap: u32) -> bool {
+        a.x < b.x + b.width + gap
+            && b.x < a.x + a.width + gap
+            && a.y < b.y + b.height + gap
+            && b.y < a.y + a.height + gap
+    }
+
+    #[test]
+    fn shelves_do_not_overlap_and_fit_the_width() {
+        let sizes = [
+            (30, 10),
+            (50, 40),
+            (20, 20),
+            (64, 5),
+            (10, 33),
+            (40, 40),
+            (7, 7),
+        ];
+        let (positions, height) = pack_shelves(&sizes, 64);
+        assert!(height.is_power_of_two());
+
+        let regions: Vec<AtlasRegion> = positions
+            .iter()
+            .zip(sizes)
+            .map(|(&(x, y), (width, height))| AtlasRegion {
+                x,
+                y,
+                width,
+                height,
+            })
+            .collect();
+        for (index, a) in regions.iter().enumerate() {
+            assert!(a.x + a.width <= 64 && a.y + a.height <= height, "{a:?}");
+            for b in &regions[index + 1..] {
+                assert!(!overlap(a, b, 0), "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn regions_keep_the_padding_between_them_and_to_the_edges() {
+        let names = ["a", "b", "c", "d", "e"];
+        let sizes = [(16, 16), (8, 30), (40, 4), (1, 1), (20, 20)];
+        let images = names
+            .iter()
+            .zip(sizes)
+            .map(|(name, (width, height))| (name.to_string(), image(width, height)))
+            .collect();
+        let atlas = pack_texture_atlas(images).unwrap();
+        assert!(atlas.texture.width.is_power_of_two());
+        assert!(atlas.texture.height.is_power_of_two());
+
+        let regions: Vec<AtlasRegion> = names
+            .iter()
+            .map(|name| atlas.region(name).unwrap())
+            .collect();
+        for (index, (a, (width, height))) in regions.iter().zip(sizes).enumerate() {
+            assert_eq!((a.width, a.height), (width, height));
+            assert!(a.x >= ATLAS_PADDING && a.y >= ATLAS_PADDING, "{a:?}");
+            assert!(
+                a.x + a.width + ATLAS_PADDING <= atlas.texture.width,
+                "{a:?}"
+            );
+            assert!(
+                a.y + a.height + ATLAS_PADDING <= atlas.texture.height,
+                "{a:?}"
+            );
+            for b in &regions[index + 1..] {
+                assert!(
+                    !overlap(a, b, 2 * ATLAS_PADDING),
+                    "{a:?} is too close to {b:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn images_with_the_same_name_are_an_error() {
+        let images = vec![
+            ("grass".to_string(), image(4, 4)),
+            ("stone".to_string(), image(4, 4)),
+            ("grass".to_string(), image(8, 8)),
+        ];
+        let error = pack_texture_atlas(images).unwrap_err();
+        assert!(error.to_string().contains("grass"), "{error}");
+    }
+}
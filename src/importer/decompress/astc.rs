@@ -0,0 +1,820 @@
+//! Decoder for 2D LDR ASTC blocks. Every block is 128 bits, covering between 4x4 and 12x12
+//! texels. HDR content is not supported and decodes to the error color like LDR hardware does.
+
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+fn read_bits(bits: u128, start: u32, count: u32) -> u32 {
+    ((bits >> start) & ((1u128 << count) - 1)) as u32
+}
+
+/// Number of trits, quints and plain bits used by each quantization level.
+#[derive(Clone, Copy)]
+struct Quantization {
+    levels: u32,
+    trits: bool,
+    quints: bool,
+    bits: u32,
+}
+
+const fn quantization(levels: u32, trits: bool, quints: bool, bits: u32) -> Quantization {
+    Quantization {
+        levels,
+        trits,
+        quints,
+        bits,
+    }
+}
+
+const QUANTIZATIONS: [Quantization; 21] = [
+    quantization(2, false, false, 1),
+    quantization(3, true, false, 0),
+    quantization(4, false, false, 2),
+    quantization(5, false, true, 0),
+    quantization(6, true, false, 1),
+    quantization(8, false, false, 3),
+    quantization(10, false, true, 1),
+    quantization(12, true, false, 2),
+    quantization(16, false, false, 4),
+    quantization(20, false, true, 2),
+    quantization(24, true, false, 3),
+    quantization(32, false, false, 5),
+    quantization(40, false, true, 3),
+    quantization(48, true, false, 4),
+    quantization(64, false, false, 6),
+    quantization(80, false, true, 4),
+    quantization(96, true, false, 5),
+    quantization(128, false, false, 7),
+    quantization(160, false, true, 5),
+    quantization(192, true, false, 6),
+    quantization(256, false, false, 8),
+];
+
+impl Quantization {
+    fn sequence_bit_count(&self, count: u32) -> u32 {
+        let mut bit_count = self.bits * count;
+        if self.trits {
+            bit_count += (8 * count).div_ceil(5);
+        }
+        if self.quints {
+            bit_count += (7 * count).div_ceil(3);
+        }
+        bit_count
+    }
+
+    /// Decode a bounded integer sequence of `count` values starting at bit `start`.
+    fn decode_sequence(&self, bits: u128, start: u32, count: usize) -> Vec<u32> {
+        // a partial trit or quint group at the end reads zeros past the sequence
+        let end = start + self.sequence_bit_count(count as u32);
+        let bits = if end < 128 {
+            bits & ((1u128 << end) - 1)
+        } else {
+            bits
+        };
+
+        let mut values = Vec::with_capacity(count + 5);
+        let mut position = start;
+        let n = self.bits;
+
+        if self.trits {
+            while values.len() < count {
+                let mut m = [0u32; 5];
+                let mut t = 0;
+                m[0] = read_bits(bits, position, n);
+                t |= read_bits(bits, position + n, 2);
+                m[1] = read_bits(bits, position + n + 2, n);
+                t |= read_bits(bits, position + 2 * n + 2, 2) << 2;
+                m[2] = read_bits(bits, position + 2 * n + 4, n);
+                t |= read_bits(bits, position + 3 * n + 4, 1) << 4;
+                m[3] = read_bits(bits, position + 3 * n + 5, n);
+                t |= read_bits(bits, position + 4 * n + 5, 2) << 5;
+                m[4] = read_bits(bits, position + 4 * n + 7, n);
+                t |= read_bits(bits, position + 5 * n + 7, 1) << 7;
+                position += 5 * n + 8;
+
+                for (trit, m) in decode_trits(t).into_iter().zip(m) {
+                    values.push((trit << n) | m);
+                }
+            }
+        } else if self.quints {
+            while values.len() < count {
+                let mut m = [0u32; 3];
+                let mut q = 0;
+                m[0] = read_bits(bits, position, n);
+                q |= read_bits(bits, position + n, 3);
+                m[1] = read_bits(bits, position + n + 3, n);
+                q |= read_bits(bits, position + 2 * n + 3, 2) << 3;
+                m[2] = read_bits(bits, position + 2 * n + 5, n);
+                q |= read_bits(bits, position + 3 * n + 5, 2) << 5;
+                position += 3 * n + 7;
+
+                for (quint, m) in decode_quints(q).into_iter().zip(m) {
+                    values.push((quint << n) | m);
+                }
+            }
+        } else {
+            for _ in 0..count {
+                values.push(read_bits(bits, position, n));
+                position += n;
+            }
+        }
+
+        values.truncate(count);
+        values
+    }
+
+    /// Unquantize a color endpoint value to 0..=255.
+    fn unquantize_color(&self, value: u32) -> u32 {
+        let n = self.bits;
+        if !self.trits && !self.quints {
+            return replicate(value, n, 8);
+        }
+
+        let m = value & ((1 << n) - 1);
+        let d = value >> n;
+        let a = if m & 1 == 1 { 0x1ff } else { 0 };
+        let x = m >> 1;
+        let (b, c) = match (self.trits, n) {
+            (true, 1) => (0, 204),
+            (true, 2) => ((x << 8) | (x << 4) | (x << 2) | (x << 1), 93),
+            (true, 3) => ((x << 7) | (x << 2) | x, 44),
+            (true, 4) => ((x << 6) | x, 22),
+            (true, 5) => ((x << 5) | (x >> 2), 11),
+            (true, _) => ((x << 4) | (x >> 4), 5),
+            (false, 1) => (0, 113),
+            (false, 2) => ((x << 8) | (x << 3) | (x << 2), 54),
+            (false, 3) => ((x << 7) | (x << 1) | (x >> 1), 26),
+            (false, 4) => ((x << 6) | (x >> 1), 13),
+            (false, _) => ((x << 5) | (x >> 3), 6),
+        };
+
+        let t = (d * c + b) ^ a;
+        (a & 0x80) | (t >> 2)
+    }
+
+    /// Unquantize a weight to 0..=64.
+    fn unquantize_weight(&self, value: u32) -> u32 {
+        let n = self.bits;
+        let result = if !self.trits && !self.quints {
+            replicate(value, n, 6)
+        } else if n == 0 {
+            match self.trits {
+                true => [0, 32, 63][value as usize],
+                false => [0, 16, 32, 47, 63][value as usize],
+            }
+        } else {
+            let m = value & ((1 << n) - 1);
+            let d = value >> n;
+            let a = if m & 1 == 1 { 0x7f } else { 0 };
+            let x = m >> 1;
+            let (b, c) = match (self.trits, n) {
+                (true, 1) => (0, 50),
+                (true, 2) => ((x << 6) | (x << 2) | x, 23),
+                (true, _) => ((x << 5) | x, 11),
+                (false, 1) => (0, 28),
+                (false, _) => ((x << 6) | (x << 1), 13),
+            };
+
+            let t = (d * c + b) ^ a;
+            (a & 0x20) | (t >> 2)
+        };
+
+        if result > 32 {
+            result + 1
+        } else {
+            result
+        }
+    }
+}
+
+/// Replicate the `from` bit wide `value` until it is `to` bits wide.
+fn replicate(value: u32, from: u32, to: u32) -> u32 {
+    if from == 0 {
+        return 0;
+    }
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to {
+        let shift = to as i32 - filled as i32 - from as i32;
+        result |= if shift >= 0 {
+            value << shift
+        } else {
+            value >> -shift
+        };
+        filled += from;
+    }
+    result
+}
+
+fn decode_trits(t: u32) -> [u32; 5] {
+    let bit = |value: u32, index: u32| (value >> index) & 1;
+    let (c, t4, t3);
+    if (t >> 2) & 7 == 7 {
+        c = (((t >> 5) & 7) << 2) | (t & 3);
+        t4 = 2;
+        t3 = 2;
+    } else {
+        c = t & 31;
+        if (t >> 5) & 3 == 3 {
+            t4 = 2;
+            t3 = bit(t, 7);
+        } else {
+            t4 = bit(t, 7);
+            t3 = (t >> 5) & 3;
+        }
+    }
+
+    let (t2, t1, t0);
+    if c & 3 == 3 {
+        t2 = 2;
+        t1 = bit(c, 4);
+        t0 = (bit(c, 3) << 1) | (bit(c, 2) & !bit(c, 3) & 1);
+    } else if (c >> 2) & 3 == 3 {
+        t2 = 2;
+        t1 = 2;
+        t0 = c & 3;
+    } else {
+        t2 = bit(c, 4);
+        t1 = (c >> 2) & 3;
+        t0 = (bit(c, 1) << 1) | (bit(c, 0) & !bit(c, 1) & 1);
+    }
+
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |value: u32, index: u32| (value >> index) & 1;
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let q2 = (bit(q, 0) << 2) | ((bit(q, 4) & !bit(q, 0) & 1) << 1) | (bit(q, 3) & !bit(q, 0) & 1);
+        return [4, 4, q2];
+    }
+
+    let (q2, c);
+    if (q >> 1) & 3 == 3 {
+        q2 = 4;
+        c = (((q >> 3) & 3) << 3) | ((!(q >> 5) & 3) << 1) | bit(q, 0);
+    } else {
+        q2 = (q >> 5) & 3;
+        c = q & 31;
+    }
+
+    if c & 7 == 5 {
+        [(c >> 3) & 3, 4, q2]
+    } else {
+        [c & 7, (c >> 3) & 3, q2]
+    }
+}
+
+struct BlockMode {
+    weights_x: usize,
+    weights_y: usize,
+    dual_plane: bool,
+    weight_quantization: Quantization,
+}
+
+fn decode_block_mode(mode: u32) -> Option<BlockMode> {
+    let mut quantization = (mode >> 4) & 1;
+    let mut high_precision = (mode >> 9) & 1;
+    let mut dual_plane = (mode >> 10) & 1;
+    let a = (mode >> 5) & 3;
+
+    let (weights_x, weights_y);
+    if mode & 3 != 0 {
+        quantization |= (mode & 3) << 1;
+        let b = (mode >> 7) & 3;
+        match (mode >> 2) & 3 {
+            0 => (weights_x, weights_y) = (b + 4, a + 2),
+            1 => (weights_x, weights_y) = (b + 8, a + 2),
+            2 => (weights_x, weights_y) = (a + 2, b + 8),
+            _ if mode & 0x100 != 0 => (weights_x, weights_y) = ((b & 1) + 2, a + 2),
+            _ => (weights_x, weights_y) = (a + 2, (b & 1) + 6),
+        }
+    } else {
+        quantization |= ((mode >> 2) & 3) << 1;
+        if (mode >> 2) & 3 == 0 {
+            return None;
+        }
+        let b = (mode >> 9) & 3;
+        match (mode >> 7) & 3 {
+            0 => (weights_x, weights_y) = (12, a + 2),
+            1 => (weights_x, weights_y) = (a + 2, 12),
+            2 => {
+                (weights_x, weights_y) = (a + 6, b + 6);
+                dual_plane = 0;
+                high_precision = 0;
+            }
+            _ => match a {
+                0 => (weights_x, weights_y) = (6, 10),
+                1 => (weights_x, weights_y) = (10, 6),
+                _ => return None,
+            },
+        }
+    }
+
+    // weights use the first twelve ranges, 2 up to 32 levels
+    let quantization_index = (quantization - 2 + 6 * high_precision) as usize;
+    Some(BlockMode {
+        weights_x: weights_x as usize,
+        weights_y: weights_y as usize,
+        dual_plane: dual_plane == 1,
+        weight_quantization: QUANTIZATIONS[quantization_index],
+    })
+}
+
+fn hash52(seed: u32) -> u32 {
+    let mut p = seed;
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
+
+fn select_partition(
+    seed: u32,
+    mut x: u32,
+    mut y: u32,
+    partition_count: u32,
+    small_block: bool,
+) -> usize {
+    if small_block {
+        x <<= 1;
+        y <<= 1;
+    }
+    let seed = seed + (partition_count - 1) * 1024;
+    let random = hash52(seed);
+
+    let mut seeds = [0u32; 12];
+    for (i, shift) in [0, 4, 8, 12, 16, 20, 24, 28, 18, 22, 26].into_iter().enumerate() {
+        seeds[i] = (random >> shift) & 0xf;
+    }
+    seeds[11] = random.rotate_left(2) & 0xf;
+    for seed in seeds.iter_mut() {
+        *seed *= *seed;
+    }
+
+    let (sh1, sh2) = if seed & 1 == 1 {
+        (
+            if seed & 2 == 2 { 4 } else { 5 },
+            if partition_count == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partition_count == 3 { 6 } else { 5 },
+            if seed & 2 == 2 { 4 } else { 5 },
+        )
+    };
+    for (i, seed) in seeds.iter_mut().enumerate().take(8) {
+        *seed >>= if i % 2 == 0 { sh1 } else { sh2 };
+    }
+
+    // the z coordinate is always 0 for 2D blocks, so seeds 9 to 12 drop out
+    let a = (seeds[0] * x + seeds[1] * y + (random >> 14)) & 0x3f;
+    let b = (seeds[2] * x + seeds[3] * y + (random >> 10)) & 0x3f;
+    let mut c = (seeds[4] * x + seeds[5] * y + (random >> 6)) & 0x3f;
+    let mut d = (seeds[6] * x + seeds[7] * y + (random >> 2)) & 0x3f;
+    if partition_count < 4 {
+        d = 0;
+    }
+    if partition_count < 3 {
+        c = 0;
+    }
+
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+    let b = (b >> 1) | (a & 0x80);
+    let mut a = (a >> 1) & 0x3f;
+    if a & 0x20 != 0 {
+        a -= 0x40;
+    }
+    (a, b)
+}
+
+fn blue_contract(color: [i32; 4]) -> [i32; 4] {
+    [
+        (color[0] + color[2]) >> 1,
+        (color[1] + color[2]) >> 1,
+        color[2],
+        color[3],
+    ]
+}
+
+
+/// Turn the unquantized values of one partition into its two endpoints.
+fn decode_endpoints(mode: u32, v: &[i32]) -> Option<[[i32; 4]; 2]> {
+    let endpoints = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xc0);
+            let l1 = (l0 + (v[1] & 0x3f)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let (v1, v0) = bit_transfer_signed(v[1], v[0]);
+            let (v3, v2) = bit_transfer_signed(v[3], v[2]);
+            [
+                [v0, v0, v0, v2],
+                [v0 + v1, v0 + v1, v0 + v1, v2 + v3],
+            ]
+        }
+        6 => [
+            [
+                (v[0] * v[3]) >> 8,
+                (v[1] * v[3]) >> 8,
+                (v[2] * v[3]) >> 8,
+                255,
+            ],
+            [v[0], v[1], v[2], 255],
+        ],
+        8 | 12 => {
+            let alpha = if mode == 12 { [v[6], v[7]] } else { [255, 255] };
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [[v[0], v[2], v[4], alpha[0]], [v[1], v[3], v[5], alpha[1]]]
+            } else {
+                [
+                    blue_contract([v[1], v[3], v[5], alpha[1]]),
+                    blue_contract([v[0], v[2], v[4], alpha[0]]),
+                ]
+            }
+        }
+        9 | 13 => {
+            let (v1, v0) = bit_transfer_signed(v[1], v[0]);
+            let (v3, v2) = bit_transfer_signed(v[3], v[2]);
+            let (v5, v4) = bit_transfer_signed(v[5], v[4]);
+            let (v7, v6) = if mode == 13 {
+                bit_transfer_signed(v[7], v[6])
+            } else {
+                (0, 255)
+            };
+            if v1 + v3 + v5 >= 0 {
+                [
+                    [v0, v2, v4, v6],
+                    [v0 + v1, v2 + v3, v4 + v5, v6 + v7],
+                ]
+            } else {
+                [
+                    blue_contract([v0 + v1, v2 + v3, v4 + v5, v6 + v7]),
+                    blue_contract([v0, v2, v4, v6]),
+                ]
+            }
+        }
+        10 => [
+            [
+                (v[0] * v[3]) >> 8,
+                (v[1] * v[3]) >> 8,
+                (v[2] * v[3]) >> 8,
+                v[4],
+            ],
+            [v[0], v[1], v[2], v[5]],
+        ],
+        // HDR endpoint modes
+        _ => return None,
+    };
+
+    // offsets can leave the unorm8 range, blue contraction happens before clamping
+    Some(endpoints.map(|endpoint| endpoint.map(|value| value.clamp(0, 255))))
+}
+
+/// Bilinearly infill the weight grid up to the block size, as described by the
+/// ASTC specification. `plane` selects the plane of a dual plane weight grid.
+fn infill_weights(
+    weights: &[u32],
+    block_mode: &BlockMode,
+    block_width: usize,
+    block_height: usize,
+    plane: usize,
+) -> Vec<u32> {
+    let planes = if block_mode.dual_plane { 2 } else { 1 };
+    let grid_width = block_mode.weights_x;
+    let grid_height = block_mode.weights_y;
+    let weight_at = |index: usize| -> u32 {
+        weights
+            .get(index * planes + plane)
+            .copied()
+            .unwrap_or(0)
+    };
+
+    let ds = (1024 + block_width / 2) / (block_width - 1);
+    let dt = (1024 + block_height / 2) / (block_height - 1);
+
+    let mut result = Vec::with_capacity(block_width * block_height);
+    for t in 0..block_height {
+        for s in 0..block_width {
+            let gs = (ds * s * (grid_width - 1) + 32) >> 6;
+            let gt = (dt * t * (grid_height - 1) + 32) >> 6;
+            let js = gs >> 4;
+            let fs = (gs & 15) as u32;
+            let jt = gt >> 4;
+            let ft = (gt & 15) as u32;
+
+            let v0 = js + jt * grid_width;
+            let w11 = (fs * ft + 8) >> 4;
+            let w10 = ft - w11;
+            let w01 = fs - w11;
+            let w00 = 16 + w11 - fs - ft;
+
+            let p00 = weight_at(v0);
+            let p01 = if w01 > 0 { weight_at(v0 + 1) } else { 0 };
+            let p10 = if w10 > 0 { weight_at(v0 + grid_width) } else { 0 };
+            let p11 = if w11 > 0 {
+                weight_at(v0 + grid_width + 1)
+            } else {
+                0
+            };
+
+            result.push((p00 * w00 + p01 * w01 + p10 * w10 + p11 * w11 + 8) >> 4);
+        }
+    }
+    result
+}
+
+fn decode_void_extent(bits: u128, texels: &mut [[u8; 4]]) {
+    let hdr = read_bits(bits, 9, 1) == 1;
+
+    let s_low = read_bits(bits, 12, 13);
+    let s_high = read_bits(bits, 25, 13);
+    let t_low = read_bits(bits, 38, 13);
+    let t_high = read_bits(bits, 51, 13);
+    let all_ones = [s_low, s_high, t_low, t_high]
+        .into_iter()
+        .all(|value| value == 0x1fff);
+    let invalid_extent = !all_ones && (s_low >= s_high || t_low >= t_high);
+
+    let color = if hdr || invalid_extent {
+        ERROR_COLOR
+    } else {
+        [
+            (read_bits(bits, 64, 16) >> 8) as u8,
+            (read_bits(bits, 80, 16) >> 8) as u8,
+            (read_bits(bits, 96, 16) >> 8) as u8,
+            (read_bits(bits, 112, 16) >> 8) as u8,
+        ]
+    };
+    texels.iter_mut().for_each(|texel| *texel = color);
+}
+
+pub fn decode_astc(block: &[u8], block_width: usize, block_height: usize, texels: &mut [[u8; 4]]) {
+    let bits = u128::from_le_bytes(block[0..16].try_into().unwrap());
+    if !decode_astc_block(bits, block_width, block_height, texels) {
+        texels.iter_mut().for_each(|texel| *texel = ERROR_COLOR);
+    }
+}
+
+/// Returns false for blocks which have to decode to the error color.
+fn decode_astc_block(
+    bits: u128,
+    block_width: usize,
+    block_height: usize,
+    texels: &mut [[u8; 4]],
+) -> bool {
+    if read_bits(bits, 0, 9) == 0x1fc {
+        decode_void_extent(bits, texels);
+        return true;
+    }
+
+    let Some(block_mode) = decode_block_mode(read_bits(bits, 0, 11)) else {
+        return false;
+    };
+    if block_mode.weights_x > block_width || block_mode.weights_y > block_height {
+        return false;
+    }
+
+    let planes = if block_mode.dual_plane { 2 } else { 1 };
+    let weight_count = block_mode.weights_x * block_mode.weights_y * planes;
+    let weight_bits = block_mode
+        .weight_quantization
+        .sequence_bit_count(weight_count as u32);
+    if weight_count > 64 || !(24..=96).contains(&weight_bits) {
+        return false;
+    }
+
+    let partition_count = read_bits(bits, 11, 2) + 1;
+    if block_mode.dual_plane && partition_count == 4 {
+        return false;
+    }
+
+    let mut below_weights = 128 - weight_bits;
+    let mut endpoint_modes = [0u32; 4];
+    let partition_seed;
+    let color_start;
+    if partition_count == 1 {
+        partition_seed = 0;
+        endpoint_modes[0] = read_bits(bits, 13, 4);
+        color_start = 17;
+    } else {
+        partition_seed = read_bits(bits, 13, 10);
+        color_start = 29;
+        let mut encoded = read_bits(bits, 23, 6);
+        if encoded & 3 == 0 {
+            endpoint_modes = [encoded >> 2; 4];
+        } else {
+            let high_size = 3 * partition_count - 4;
+            below_weights -= high_size;
+            encoded |= read_bits(bits, below_weights, high_size) << 6;
+
+            let base_class = (encoded & 3) - 1;
+            let partitions = partition_count as usize;
+            for (partition, endpoint_mode) in endpoint_modes.iter_mut().enumerate().take(partitions) {
+                let class = (encoded >> (2 + partition)) & 1;
+                let mode = (encoded >> (2 + partitions + 2 * partition)) & 3;
+                *endpoint_mode = ((base_class + class) << 2) | mode;
+            }
+        }
+    }
+
+    let mut plane2_component = None;
+    if block_mode.dual_plane {
+        below_weights -= 2;
+        plane2_component = Some(read_bits(bits, below_weights, 2) as usize);
+    }
+
+    let value_counts = endpoint_modes.map(|mode| 2 * ((mode >> 2) + 1) as usize);
+    let value_count: usize = value_counts.iter().take(partition_count as usize).sum();
+    if value_count > 18 || below_weights < color_start {
+        return false;
+    }
+
+    let available_bits = below_weights - color_start;
+    let Some(color_quantization) = QUANTIZATIONS
+        .iter()
+        .rev()
+        .find(|quantization| quantization.sequence_bit_count(value_count as u32) <= available_bits)
+    else {
+        return false;
+    };
+    if color_quantization.levels < 6 {
+        return false;
+    }
+
+    let values: Vec<i32> = color_quantization
+        .decode_sequence(bits, color_start, value_count)
+        .into_iter()
+        .map(|value| color_quantization.unquantize_color(value) as i32)
+        .collect();
+
+    let mut endpoints = [[[0i32; 4]; 2]; 4];
+    let mut offset = 0;
+    for partition in 0..partition_count as usize {
+        let partition_values = &values[offset..offset + value_counts[partition]];
+        offset += value_counts[partition];
+        match decode_endpoints(endpoint_modes[partition], partition_values) {
+            Some(decoded) => endpoints[partition] = decoded,
+            None => return false,
+        }
+    }
+
+    let weight_quantization = block_mode.weight_quantization;
+    let weights: Vec<u32> = weight_quantization
+        .decode_sequence(bits.reverse_bits(), 0, weight_count)
+        .into_iter()
+        .map(|value| weight_quantization.unquantize_weight(value))
+        .collect();
+    let plane_weights = [
+        infill_weights(&weights, &block_mode, block_width, block_height, 0),
+        if block_mode.dual_plane {
+            infill_weights(&weights, &block_mode, block_width, block_height, 1)
+        } else {
+            vec![]
+        },
+    ];
+
+    let small_block = block_width * block_height < 31;
+    for y in 0..block_height {
+        for x in 0..block_width {
+            let texel = y * block_width + x;
+            let partition = if partition_count > 1 {
+                select_partition(
+                    partition_seed,
+                    x as u32,
+                    y as u32,
+                    partition_count,
+                    small_block,
+                )
+            } else {
+                0
+            };
+            let [e0, e1] = endpoints[partition];
+
+            let mut color = [0u8; 4];
+            for channel in 0..4 {
+                let weight = match plane2_component {
+                    Some(component) if component == channel => plane_weights[1][texel],
+                    _ => plane_weights[0][texel],
+                } as i32;
+                let c0 = (e0[channel] << 8) | e0[channel];
+                let c1 = (e1[channel] << 8) | e1[channel];
+                let c = (c0 * (64 - weight) + c1 * weight + 32) >> 6;
+                color[channel] = (c >> 8) as u8;
+            }
+            texels[texel] = color;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bits: u128, block_width: usize, block_height: usize) -> Vec<[u8; 4]> {
+        let mut texels = vec![[0; 4]; block_width * block_height];
+        decode_astc(&bits.to_le_bytes(), block_width, block_height, &mut texels);
+        texels
+    }
+
+    fn void_extent(color: [u16; 4]) -> u128 {
+        let mut bits = 0x1fc | (3 << 10) | (((1u128 << 52) - 1) << 12);
+        for (channel, value) in color.into_iter().enumerate() {
+            bits |= (value as u128) << (64 + 16 * channel);
+        }
+        bits
+    }
+
+    #[test]
+    fn void_extent_is_a_solid_color() {
+        let texels = decode(void_extent([0xffff, 0x8000, 0, 0xffff]), 4, 4);
+        assert!(texels.iter().all(|texel| *texel == [255, 128, 0, 255]));
+
+        let texels = decode(void_extent([0x1234, 0, 0xff00, 0x8080]), 8, 6);
+        assert!(texels.iter().all(|texel| *texel == [0x12, 0, 0xff, 0x80]));
+    }
+
+    #[test]
+    fn hdr_void_extent_is_the_error_color() {
+        let texels = decode(void_extent([0xffff; 4]) | (1 << 9), 4, 4);
+        assert!(texels.iter().all(|texel| *texel == ERROR_COLOR));
+    }
+
+    #[test]
+    fn reserved_block_mode_is_the_error_color() {
+        let texels = decode(0, 4, 4);
+        assert!(texels.iter().all(|texel| *texel == ERROR_COLOR));
+    }
+
+    /// A single partition luminance block from black to white with a 4x4 grid of 2 bit
+    /// weights. The weights are stored from the top of the block down, each with its
+    /// lowest bit first.
+    fn luminance_block(weights: [u128; 16]) -> u128 {
+        // block mode: 4x4 weights with 4 levels
+        let mut bits = 2 | (2 << 5);
+        // luminance endpoints 0 and 255 in 8 bits each
+        bits |= 255 << 25;
+        for (i, weight) in weights.into_iter().enumerate() {
+            bits |= (weight & 1) << (127 - 2 * i);
+            bits |= (weight >> 1) << (126 - 2 * i);
+        }
+        bits
+    }
+
+    #[test]
+    fn single_partition_luminance_weights() {
+        let texels = decode(luminance_block([3; 16]), 4, 4);
+        assert!(texels.iter().all(|texel| *texel == [255, 255, 255, 255]));
+
+        let mut weights = [0; 16];
+        weights[0] = 1;
+        weights[15] = 2;
+        let texels = decode(luminance_block(weights), 4, 4);
+        assert_eq!(texels[0], [84, 84, 84, 255]);
+        assert_eq!(texels[15], [171, 171, 171, 255]);
+        assert!(texels[1..15].iter().all(|texel| *texel == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn weight_grid_is_infilled_to_the_block_size() {
+        // the corners of the grid land on the corners of the block
+        let mut weights = [0; 16];
+        weights[3] = 3;
+        let texels = decode(luminance_block(weights), 6, 6);
+        assert_eq!(texels[5], [255, 255, 255, 255]);
+        assert_eq!(texels[0], [0, 0, 0, 255]);
+        assert_eq!(texels[35], [0, 0, 0, 255]);
+        // and the texels between them blend
+        assert!(texels[4][0] > 0 && texels[4][0] < 255);
+    }
+
+    #[test]
+    fn weight_grid_larger_than_the_block_is_the_error_color() {
+        // block mode: 4x10 weights with 4 levels
+        let bits = (luminance_block([3; 16]) & !0x7ff) | 2 | (2 << 2) | (2 << 5) | (2 << 7);
+        let texels = decode(bits, 4, 4);
+        assert!(texels.iter().all(|texel| *texel == ERROR_COLOR));
+    }
+}
@@ -0,0 +1,526 @@
+//! Decoders for the BCn (S3TC, RGTC and BPTC) block formats. Every block covers 4x4 texels.
+
+fn rgb565(color: u16) -> [u32; 3] {
+    let r = ((color >> 11) & 31) as u32;
+    let g = ((color >> 5) & 63) as u32;
+    let b = (color & 31) as u32;
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+/// The color half shared by BC1, BC2 and BC3. BC2 and BC3 always use the four color mode.
+fn decode_color_block(block: &[u8], texels: &mut [[u8; 4]], four_color_only: bool) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let e0 = rgb565(c0);
+    let e1 = rgb565(c1);
+
+    let mut palette = [[0u8, 0, 0, 255]; 4];
+    for channel in 0..3 {
+        palette[0][channel] = e0[channel] as u8;
+        palette[1][channel] = e1[channel] as u8;
+        if c0 > c1 || four_color_only {
+            palette[2][channel] = ((2 * e0[channel] + e1[channel]) / 3) as u8;
+            palette[3][channel] = ((e0[channel] + 2 * e1[channel]) / 3) as u8;
+        } else {
+            palette[2][channel] = ((e0[channel] + e1[channel]) / 2) as u8;
+        }
+    }
+    if !(c0 > c1 || four_color_only) {
+        palette[3] = [0, 0, 0, 0];
+    }
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for (i, texel) in texels.iter_mut().enumerate().take(16) {
+        *texel = palette[((indices >> (2 * i)) & 3) as usize];
+    }
+}
+
+/// The interpolated 8 bit channel of BC3 alpha, BC4 and BC5.
+fn decode_channel_block(block: &[u8]) -> [u8; 16] {
+    let a0 = block[0] as u32;
+    let a1 = block[1] as u32;
+
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        }
+    }
+
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let bits = u64::from_le_bytes(bits);
+
+    let mut result = [0u8; 16];
+    for (i, value) in result.iter_mut().enumerate() {
+        *value = palette[((bits >> (3 * i)) & 7) as usize] as u8;
+    }
+    result
+}
+
+pub fn decode_bc1(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    decode_color_block(block, texels, false);
+}
+
+pub fn decode_bc2(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    decode_color_block(&block[8..16], texels, true);
+    let alpha = u64::from_le_bytes(block[0..8].try_into().unwrap());
+    for (i, texel) in texels.iter_mut().enumerate().take(16) {
+        texel[3] = ((alpha >> (4 * i)) & 15) as u8 * 17;
+    }
+}
+
+pub fn decode_bc3(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    decode_color_block(&block[8..16], texels, true);
+    let alpha = decode_channel_block(&block[0..8]);
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+}
+
+pub fn decode_bc4(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    let red = decode_channel_block(&block[0..8]);
+    for (texel, red) in texels.iter_mut().zip(red) {
+        *texel = [red, 0, 0, 255];
+    }
+}
+
+pub fn decode_bc5(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    let red = decode_channel_block(&block[0..8]);
+    let green = decode_channel_block(&block[8..16]);
+    for (i, texel) in texels.iter_mut().enumerate().take(16) {
+        *texel = [red[i], green[i], 0, 255];
+    }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index2_bits: u32,
+}
+
+#[allow(clippy::too_many_arguments)]
+const fn bc7_mode(
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index2_bits: u32,
+) -> Bc7Mode {
+    Bc7Mode {
+        subsets,
+        partition_bits,
+        rotation_bits,
+        index_selection_bits,
+        color_bits,
+        alpha_bits,
+        endpoint_pbits,
+        shared_pbits,
+        index_bits,
+        index2_bits,
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+    bc7_mode(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+    bc7_mode(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+    bc7_mode(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+    bc7_mode(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+    bc7_mode(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+    bc7_mode(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+    bc7_mode(2, 6, 0, 0, 5, 5, true, false, 2, 0),
+];
+
+/// Subset of every texel for the 64 two subset partitions, one bit per texel.
+const BC7_PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80,
+    0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310,
+    0x3100, 0x8cce, 0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa,
+    0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8, 0x324c, 0x3bdc,
+    0x6996, 0xc33c, 0x9966, 0x0660, 0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6,
+    0x639c, 0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// Subset of every texel for the 64 three subset partitions, two bits per texel.
+const BC7_PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0,
+    0x5a5a5050, 0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4,
+    0xa9a59450, 0x2a0a4250, 0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454,
+    0x6a6a4040, 0xa4a45000, 0x1a1a0500, 0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400,
+    0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200, 0xa9a58000, 0x5090a0a8, 0xa8a09050,
+    0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50, 0x500aa550, 0xaaaa4444,
+    0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600, 0xaa444444,
+    0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44,
+    0x2a4a5254,
+];
+
+/// Texels of the second subset which store their index with one bit less.
+const BC7_ANCHORS_2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2,
+    8, 2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15,
+    2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+const BC7_ANCHORS_3_SECOND: [usize; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8,
+    5, 15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10,
+    5, 10, 8, 13, 15, 12, 3, 3,
+];
+
+const BC7_ANCHORS_3_THIRD: [usize; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15,
+    6, 10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15,
+    15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+struct BitReader {
+    bits: u128,
+    position: u32,
+}
+
+impl BitReader {
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.bits >> self.position) & ((1u128 << count) - 1);
+        self.position += count;
+        value as u32
+    }
+}
+
+fn bc7_unquantize(value: u32, precision: u32) -> u32 {
+    if precision >= 8 {
+        value
+    } else {
+        (value << (8 - precision)) | (value >> (2 * precision - 8))
+    }
+}
+
+fn bc7_interpolate(e0: u32, e1: u32, index: u32, index_bits: u32) -> u8 {
+    let weight = match index_bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize],
+    };
+    (((64 - weight) * e0 + weight * e1 + 32) >> 6) as u8
+}
+
+pub fn decode_bc7(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    let bits = u128::from_le_bytes(block[0..16].try_into().unwrap());
+    let mode_index = (bits as u8).trailing_zeros() as usize;
+    if mode_index >= 8 {
+        // reserved mode, decoders output transparent black
+        texels.iter_mut().for_each(|texel| *texel = [0, 0, 0, 0]);
+        return;
+    }
+
+    let mode = &BC7_MODES[mode_index];
+    let mut reader = BitReader {
+        bits,
+        position: mode_index as u32 + 1,
+    };
+
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = reader.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = reader.read(mode.alpha_bits);
+    }
+
+    let mut color_precision = mode.color_bits;
+    let mut alpha_precision = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let mut pbits = [0u32; 6];
+        if mode.endpoint_pbits {
+            for pbit in pbits.iter_mut().take(endpoint_count) {
+                *pbit = reader.read(1);
+            }
+        } else {
+            for subset in 0..mode.subsets {
+                let pbit = reader.read(1);
+                pbits[2 * subset] = pbit;
+                pbits[2 * subset + 1] = pbit;
+            }
+        }
+        for (endpoint, pbit) in endpoints.iter_mut().zip(pbits) {
+            for value in endpoint.iter_mut() {
+                *value = (*value << 1) | pbit;
+            }
+        }
+        color_precision += 1;
+        if mode.alpha_bits > 0 {
+            alpha_precision += 1;
+        }
+    }
+
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for value in endpoint.iter_mut().take(3) {
+            *value = bc7_unquantize(*value, color_precision);
+        }
+        endpoint[3] = if mode.alpha_bits > 0 {
+            bc7_unquantize(endpoint[3], alpha_precision)
+        } else {
+            255
+        };
+    }
+
+    let subset_of = |texel: usize| -> usize {
+        match mode.subsets {
+            2 => ((BC7_PARTITIONS_2[partition] >> texel) & 1) as usize,
+            3 => ((BC7_PARTITIONS_3[partition] >> (2 * texel)) & 3) as usize,
+            _ => 0,
+        }
+    };
+    let is_anchor = |texel: usize| -> bool {
+        texel == 0
+            || match mode.subsets {
+                2 => texel == BC7_ANCHORS_2[partition],
+                3 => {
+                    texel == BC7_ANCHORS_3_SECOND[partition]
+                        || texel == BC7_ANCHORS_3_THIRD[partition]
+                }
+                _ => false,
+            }
+    };
+
+    let mut indices = [0u32; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        *index = reader.read(mode.index_bits - is_anchor(texel) as u32);
+    }
+    let mut indices2 = [0u32; 16];
+    if mode.index2_bits > 0 {
+        for (texel, index) in indices2.iter_mut().enumerate() {
+            *index = reader.read(mode.index2_bits - (texel == 0) as u32);
+        }
+    }
+
+    for (texel, output) in texels.iter_mut().enumerate().take(16) {
+        let subset = subset_of(texel);
+        let e0 = endpoints[2 * subset];
+        let e1 = endpoints[2 * subset + 1];
+
+        let (color_index, color_bits, alpha_index, alpha_bits) = if mode.index2_bits == 0 {
+            (indices[texel], mode.index_bits, indices[texel], mode.index_bits)
+        } else if index_selection == 0 {
+            (indices[texel], mode.index_bits, indices2[texel], mode.index2_bits)
+        } else {
+            (indices2[texel], mode.index2_bits, indices[texel], mode.index_bits)
+        };
+
+        let mut color = [0u8; 4];
+        for channel in 0..3 {
+            color[channel] = bc7_interpolate(e0[channel], e1[channel], color_index, color_bits);
+        }
+        color[3] = bc7_interpolate(e0[3], e1[3], alpha_index, alpha_bits);
+
+        match rotation {
+            1 => color.swap(0, 3),
+            2 => color.swap(1, 3),
+            3 => color.swap(2, 3),
+            _ => {}
+        }
+
+        *output = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(decoder: fn(&[u8], usize, usize, &mut [[u8; 4]]), block: &[u8]) -> [[u8; 4]; 16] {
+        let mut texels = [[0; 4]; 16];
+        decoder(block, 4, 4, &mut texels);
+        texels
+    }
+
+    /// Pack `(value, bit count)` fields into a block, least significant bit first.
+    fn pack_bits(fields: &[(u32, u32)]) -> [u8; 16] {
+        let mut bits = 0u128;
+        let mut position = 0;
+        for &(value, count) in fields {
+            bits |= (value as u128) << position;
+            position += count;
+        }
+        assert_eq!(position, 128);
+        bits.to_le_bytes()
+    }
+
+    const WHITE_COLOR_BLOCK: [u8; 8] = [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+
+    #[test]
+    fn bc1_solid_color() {
+        let texels = decode(decode_bc1, &[0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0]);
+        assert!(texels.iter().all(|texel| *texel == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn bc1_four_color_palette() {
+        // white before black, the first row uses the indices 0 to 3
+        let texels = decode(
+            decode_bc1,
+            &[0xff, 0xff, 0x00, 0x00, 0b11_10_01_00, 0, 0, 0],
+        );
+        assert_eq!(
+            texels[0..4],
+            [
+                [255, 255, 255, 255],
+                [0, 0, 0, 255],
+                [170, 170, 170, 255],
+                [85, 85, 85, 255],
+            ]
+        );
+        assert!(texels[4..]
+            .iter()
+            .all(|texel| *texel == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn bc1_three_color_palette_with_transparent_black() {
+        // black before white selects the three color mode
+        let texels = decode(
+            decode_bc1,
+            &[0x00, 0x00, 0xff, 0xff, 0b11_10_01_00, 0, 0, 0],
+        );
+        assert_eq!(
+            texels[0..4],
+            [
+                [0, 0, 0, 255],
+                [255, 255, 255, 255],
+                [127, 127, 127, 255],
+                [0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn bc2_explicit_alpha() {
+        let mut block = [0u8; 16];
+        block[0] = 0xf0;
+        block[1] = 0x08;
+        block[8..16].copy_from_slice(&WHITE_COLOR_BLOCK);
+        let texels = decode(decode_bc2, &block);
+        assert_eq!(
+            texels[0..4]
+                .iter()
+                .map(|texel| texel[3])
+                .collect::<Vec<_>>(),
+            [0, 255, 136, 0]
+        );
+        assert!(texels.iter().all(|texel| texel[0..3] == [255, 255, 255]));
+    }
+
+    #[test]
+    fn bc3_eight_value_alpha() {
+        // indices 0, 1, 2 and 7 for the first row
+        let mut block = [255, 0, 0x88, 0x0e, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        block[8..16].copy_from_slice(&WHITE_COLOR_BLOCK);
+        let texels = decode(decode_bc3, &block);
+        assert_eq!(
+            texels[0..4]
+                .iter()
+                .map(|texel| texel[3])
+                .collect::<Vec<_>>(),
+            [255, 0, 218, 36]
+        );
+        assert!(texels[4..]
+            .iter()
+            .all(|texel| *texel == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn bc4_six_value_red_with_extremes() {
+        // indices 6, 7, 2 and 0 for the first row
+        let texels = decode(decode_bc4, &[0, 255, 0xbe, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            texels[0..4],
+            [
+                [0, 0, 0, 255],
+                [255, 0, 0, 255],
+                [51, 0, 0, 255],
+                [0, 0, 0, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn bc5_solid_red_and_green() {
+        let texels = decode(
+            decode_bc5,
+            &[200, 200, 0, 0, 0, 0, 0, 0, 100, 100, 0, 0, 0, 0, 0, 0],
+        );
+        assert!(texels.iter().all(|texel| *texel == [200, 100, 0, 255]));
+    }
+
+    #[test]
+    fn bc7_mode_6() {
+        // one subset from black to white with 4 bit indices, alpha follows the color
+        let mut fields = vec![(1 << 6, 7)];
+        for _ in 0..4 {
+            fields.extend([(0, 7), (127, 7)]);
+        }
+        fields.extend([(0, 1), (1, 1)]);
+        fields.extend([(0, 3), (15, 4), (8, 4)]);
+        fields.extend([(0, 4); 13]);
+        let texels = decode(decode_bc7, &pack_bits(&fields));
+        assert_eq!(
+            texels[0..3],
+            [[0, 0, 0, 0], [255, 255, 255, 255], [135, 135, 135, 135]]
+        );
+        assert!(texels[3..].iter().all(|texel| *texel == [0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn bc7_mode_4_rotation_and_separate_alpha_indices() {
+        // rotation 1 swaps red and alpha, index selection 0 takes the alpha from the
+        // 3 bit indices
+        let mut fields = vec![(1 << 4, 5), (1, 2), (0, 1)];
+        // red, green and blue from 0 to 31, alpha from 63 to 0
+        for _ in 0..3 {
+            fields.extend([(0, 5), (31, 5)]);
+        }
+        fields.extend([(63, 6), (0, 6)]);
+        // the first index of each set has one bit less
+        fields.push((0, 1));
+        fields.extend([(3, 2); 15]);
+        fields.push((0, 2));
+        fields.extend([(7, 3); 15]);
+        let texels = decode(decode_bc7, &pack_bits(&fields));
+        assert_eq!(texels[0], [255, 0, 0, 0]);
+        assert!(texels[1..].iter().all(|texel| *texel == [0, 255, 255, 255]));
+    }
+
+    #[test]
+    fn bc7_reserved_mode_is_transparent_black() {
+        let texels = decode(decode_bc7, &[0; 16]);
+        assert!(texels.iter().all(|texel| *texel == [0, 0, 0, 0]));
+    }
+}
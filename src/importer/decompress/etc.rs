@@ -0,0 +1,487 @@
+//! Decoders for the ETC2 and EAC block formats. Every block covers 4x4 texels and is
+//! stored big endian, with texels addressed column by column.
+
+const ETC1_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+const ETC2_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn bits(block: u64, high: u32, low: u32) -> i32 {
+    ((block >> low) & ((1 << (high - low + 1)) - 1)) as i32
+}
+
+fn extend_4(value: i32) -> i32 {
+    (value << 4) | value
+}
+
+fn extend_5(value: i32) -> i32 {
+    (value << 3) | (value >> 2)
+}
+
+fn extend_6(value: i32) -> i32 {
+    (value << 2) | (value >> 4)
+}
+
+fn extend_7(value: i32) -> i32 {
+    (value << 1) | (value >> 6)
+}
+
+fn clamp_color(color: [i32; 3]) -> [u8; 4] {
+    [
+        color[0].clamp(0, 255) as u8,
+        color[1].clamp(0, 255) as u8,
+        color[2].clamp(0, 255) as u8,
+        255,
+    ]
+}
+
+fn offset_color(color: [i32; 3], offset: i32) -> [i32; 3] {
+    [color[0] + offset, color[1] + offset, color[2] + offset]
+}
+
+/// Row-major position of the `texel`th texel in the column-major block order.
+fn texel_at(texel: usize) -> usize {
+    (texel % 4) * 4 + texel / 4
+}
+
+fn pixel_index(block: u64, texel: usize) -> usize {
+    let msb = (block >> (16 + texel)) & 1;
+    let lsb = (block >> texel) & 1;
+    ((msb << 1) | lsb) as usize
+}
+
+fn decode_etc2_color(block: u64, texels: &mut [[u8; 4]], punchthrough: bool) {
+    let differential = bits(block, 33, 33) == 1;
+    // punch-through alpha reuses the differential bit as the opaque bit
+    let opaque = !punchthrough || differential;
+    let transparent = [0u8; 4];
+
+    if !punchthrough && !differential {
+        let base = [
+            [
+                extend_4(bits(block, 63, 60)),
+                extend_4(bits(block, 55, 52)),
+                extend_4(bits(block, 47, 44)),
+            ],
+            [
+                extend_4(bits(block, 59, 56)),
+                extend_4(bits(block, 51, 48)),
+                extend_4(bits(block, 43, 40)),
+            ],
+        ];
+        decode_etc1_subblocks(block, base, texels, true);
+        return;
+    }
+
+    let r = bits(block, 63, 59);
+    let g = bits(block, 55, 51);
+    let b = bits(block, 47, 43);
+    let dr = (bits(block, 58, 56) << 29) >> 29;
+    let dg = (bits(block, 50, 48) << 29) >> 29;
+    let db = (bits(block, 42, 40) << 29) >> 29;
+
+    if !(0..32).contains(&(r + dr)) {
+        // T mode
+        let c1 = [
+            extend_4((bits(block, 60, 59) << 2) | bits(block, 57, 56)),
+            extend_4(bits(block, 55, 52)),
+            extend_4(bits(block, 51, 48)),
+        ];
+        let c2 = [
+            extend_4(bits(block, 47, 44)),
+            extend_4(bits(block, 43, 40)),
+            extend_4(bits(block, 39, 36)),
+        ];
+        let distance = ETC2_DISTANCES[((bits(block, 35, 34) << 1) | bits(block, 32, 32)) as usize];
+        let paint = [
+            clamp_color(c1),
+            clamp_color(offset_color(c2, distance)),
+            clamp_color(c2),
+            clamp_color(offset_color(c2, -distance)),
+        ];
+        for texel in 0..16 {
+            let index = pixel_index(block, texel);
+            texels[texel_at(texel)] = if !opaque && index == 2 {
+                transparent
+            } else {
+                paint[index]
+            };
+        }
+    } else if !(0..32).contains(&(g + dg)) {
+        // H mode
+        let c1_bits = [
+            bits(block, 62, 59),
+            (bits(block, 58, 56) << 1) | bits(block, 52, 52),
+            (bits(block, 51, 51) << 3) | bits(block, 49, 47),
+        ];
+        let c2_bits = [
+            bits(block, 46, 43),
+            bits(block, 42, 39),
+            bits(block, 38, 35),
+        ];
+        let c1_value = (c1_bits[0] << 8) | (c1_bits[1] << 4) | c1_bits[2];
+        let c2_value = (c2_bits[0] << 8) | (c2_bits[1] << 4) | c2_bits[2];
+        let distance = ETC2_DISTANCES[((bits(block, 34, 34) << 2)
+            | (bits(block, 32, 32) << 1)
+            | (c1_value >= c2_value) as i32) as usize];
+        let c1 = c1_bits.map(extend_4);
+        let c2 = c2_bits.map(extend_4);
+        let paint = [
+            clamp_color(offset_color(c1, distance)),
+            clamp_color(offset_color(c1, -distance)),
+            clamp_color(offset_color(c2, distance)),
+            clamp_color(offset_color(c2, -distance)),
+        ];
+        for texel in 0..16 {
+            let index = pixel_index(block, texel);
+            texels[texel_at(texel)] = if !opaque && index == 2 {
+                transparent
+            } else {
+                paint[index]
+            };
+        }
+    } else if !(0..32).contains(&(b + db)) {
+        // planar mode, always opaque
+        let origin = [
+            extend_6(bits(block, 62, 57)),
+            extend_7((bits(block, 56, 56) << 6) | bits(block, 54, 49)),
+            extend_6((bits(block, 48, 48) << 5) | (bits(block, 44, 43) << 3) | bits(block, 41, 39)),
+        ];
+        let horizontal = [
+            extend_6((bits(block, 38, 34) << 1) | bits(block, 32, 32)),
+            extend_7(bits(block, 31, 25)),
+            extend_6(bits(block, 24, 19)),
+        ];
+        let vertical = [
+            extend_6(bits(block, 18, 13)),
+            extend_7(bits(block, 12, 6)),
+            extend_6(bits(block, 5, 0)),
+        ];
+        for y in 0..4 {
+            for x in 0..4 {
+                let mut color = [0; 3];
+                for channel in 0..3 {
+                    color[channel] = (x * (horizontal[channel] - origin[channel])
+                        + y * (vertical[channel] - origin[channel])
+                        + 4 * origin[channel]
+                        + 2)
+                        >> 2;
+                }
+                texels[(y * 4 + x) as usize] = clamp_color(color);
+            }
+        }
+    } else {
+        let base = [
+            [extend_5(r), extend_5(g), extend_5(b)],
+            [extend_5(r + dr), extend_5(g + dg), extend_5(b + db)],
+        ];
+        decode_etc1_subblocks(block, base, texels, opaque);
+    }
+}
+
+fn decode_etc1_subblocks(block: u64, base: [[i32; 3]; 2], texels: &mut [[u8; 4]], opaque: bool) {
+    let tables = [bits(block, 39, 37) as usize, bits(block, 36, 34) as usize];
+    let flip = bits(block, 32, 32) == 1;
+
+    for texel in 0..16 {
+        let x = texel / 4;
+        let y = texel % 4;
+        let subblock = if flip { y / 2 } else { x / 2 };
+        let modifiers = ETC1_MODIFIERS[tables[subblock]];
+        let index = pixel_index(block, texel);
+
+        let modifier = match index {
+            0 if opaque => modifiers[0],
+            0 => 0,
+            1 => modifiers[1],
+            2 => modifiers[0],
+            _ => modifiers[1],
+        };
+        texels[texel_at(texel)] = if !opaque && index == 2 {
+            [0; 4]
+        } else if index >= 2 {
+            clamp_color(offset_color(base[subblock], -modifier))
+        } else {
+            clamp_color(offset_color(base[subblock], modifier))
+        };
+    }
+}
+
+/// Decode an EAC block into 11 bit values, in row-major order.
+fn decode_eac_block(block: &[u8]) -> [i32; 16] {
+    let block = u64::from_be_bytes(block[0..8].try_into().unwrap());
+    let base = bits(block, 63, 56);
+    let multiplier = bits(block, 55, 52);
+    let modifiers = EAC_MODIFIERS[bits(block, 51, 48) as usize];
+
+    let mut result = [0; 16];
+    for texel in 0..16 {
+        let modifier = modifiers[((block >> (45 - 3 * texel)) & 7) as usize];
+        let value = if multiplier == 0 {
+            base * 8 + 4 + modifier
+        } else {
+            base * 8 + 4 + modifier * multiplier * 8
+        };
+        result[texel_at(texel)] = value.clamp(0, 2047);
+    }
+    result
+}
+
+/// Decode an EAC block holding 8 bit alpha, in row-major order.
+fn decode_eac_alpha_block(block: &[u8]) -> [u8; 16] {
+    let block = u64::from_be_bytes(block[0..8].try_into().unwrap());
+    let base = bits(block, 63, 56);
+    let multiplier = bits(block, 55, 52);
+    let modifiers = EAC_MODIFIERS[bits(block, 51, 48) as usize];
+
+    let mut result = [0; 16];
+    for texel in 0..16 {
+        let modifier = modifiers[((block >> (45 - 3 * texel)) & 7) as usize];
+        result[texel_at(texel)] = (base + modifier * multiplier).clamp(0, 255) as u8;
+    }
+    result
+}
+
+fn eac_to_unorm8(value: i32) -> u8 {
+    ((value * 255 + 1023) / 2047) as u8
+}
+
+pub fn decode_etc2_rgb8(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    decode_etc2_color(u64::from_be_bytes(block[0..8].try_into().unwrap()), texels, false);
+}
+
+pub fn decode_etc2_rgb8a1(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    decode_etc2_color(u64::from_be_bytes(block[0..8].try_into().unwrap()), texels, true);
+}
+
+pub fn decode_etc2_rgba8(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    decode_etc2_color(
+        u64::from_be_bytes(block[8..16].try_into().unwrap()),
+        texels,
+        false,
+    );
+    let alpha = decode_eac_alpha_block(&block[0..8]);
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+}
+
+pub fn decode_eac_r11(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    let red = decode_eac_block(&block[0..8]);
+    for (texel, red) in texels.iter_mut().zip(red) {
+        *texel = [eac_to_unorm8(red), 0, 0, 255];
+    }
+}
+
+pub fn decode_eac_rg11(block: &[u8], _: usize, _: usize, texels: &mut [[u8; 4]]) {
+    let red = decode_eac_block(&block[0..8]);
+    let green = decode_eac_block(&block[8..16]);
+    for (i, texel) in texels.iter_mut().enumerate().take(16) {
+        *texel = [eac_to_unorm8(red[i]), eac_to_unorm8(green[i]), 0, 255];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(decoder: fn(&[u8], usize, usize, &mut [[u8; 4]]), block: &[u8]) -> [[u8; 4]; 16] {
+        let mut texels = [[0; 4]; 16];
+        decoder(block, 4, 4, &mut texels);
+        texels
+    }
+
+    /// `value` placed in the bits from `high` down to `low` of a block.
+    fn field(value: u64, high: u32, low: u32) -> u64 {
+        assert!(value < 1 << (high - low + 1));
+        value << low
+    }
+
+    /// The pixel index bits with every texel set to `index`.
+    fn indices(index: u64) -> u64 {
+        field((index >> 1) * 0xffff, 31, 16) | field((index & 1) * 0xffff, 15, 0)
+    }
+
+    /// An individual mode block with the same base color in both halves.
+    fn individual_block(color: [u64; 3], index: u64) -> u64 {
+        let mut block = 0;
+        for (channel, value) in color.into_iter().enumerate() {
+            let high = 63 - 8 * channel as u32;
+            block |= field(value, high, high - 3) | field(value, high - 4, high - 7);
+        }
+        block | indices(index)
+    }
+
+    #[test]
+    fn etc2_individual_mode() {
+        let block = individual_block([8, 4, 0], 0);
+        let texels = decode(decode_etc2_rgb8, &block.to_be_bytes());
+        assert!(texels.iter().all(|texel| *texel == [138, 70, 2, 255]));
+
+        let block = individual_block([8, 4, 0], 3);
+        let texels = decode(decode_etc2_rgb8, &block.to_be_bytes());
+        assert!(texels.iter().all(|texel| *texel == [128, 60, 0, 255]));
+    }
+
+    #[test]
+    fn etc2_texels_are_stored_column_by_column() {
+        // the second texel of the block is the first one of the second row
+        let block = individual_block([8, 4, 0], 0) | field(1, 1, 1);
+        let texels = decode(decode_etc2_rgb8, &block.to_be_bytes());
+        assert_eq!(texels[4], [144, 76, 8, 255]);
+        assert!(texels
+            .iter()
+            .enumerate()
+            .all(|(i, texel)| i == 4 || *texel == [138, 70, 2, 255]));
+    }
+
+    #[test]
+    fn etc2_differential_mode() {
+        // red is one step brighter in the right half
+        let block = field(16, 63, 59) | field(1, 58, 56) | field(8, 55, 51) | field(1, 33, 33);
+        let texels = decode(decode_etc2_rgb8, &block.to_be_bytes());
+        for (i, texel) in texels.iter().enumerate() {
+            let expected = if i % 4 < 2 {
+                [134, 68, 2, 255]
+            } else {
+                [142, 68, 2, 255]
+            };
+            assert_eq!(*texel, expected, "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn etc2_t_mode() {
+        // red overflowing in the differential bits selects T mode, the first color is
+        // green and the second a gray that the distance moves up and down
+        let block = field(1, 58, 58)
+            | field(15, 55, 52)
+            | field(8, 47, 44)
+            | field(8, 43, 40)
+            | field(8, 39, 36)
+            | field(1, 33, 33)
+            | field(1, 0, 0)
+            | field(1, 17, 17)
+            | field(1, 18, 18)
+            | field(1, 2, 2);
+        let texels = decode(decode_etc2_rgb8, &block.to_be_bytes());
+        assert_eq!(texels[0], [139, 139, 139, 255]);
+        assert_eq!(texels[4], [136, 136, 136, 255]);
+        assert_eq!(texels[8], [133, 133, 133, 255]);
+        assert!(texels
+            .iter()
+            .enumerate()
+            .all(|(i, texel)| [0, 4, 8].contains(&i) || *texel == [0, 255, 0, 255]));
+    }
+
+    /// A planar mode block, blue overflowing in the differential bits selects it.
+    fn planar_block(origin: [u64; 3], horizontal: [u64; 3], vertical: [u64; 3]) -> u64 {
+        field(origin[0], 62, 57)
+            | field(origin[1] >> 6, 56, 56)
+            | field(origin[1] & 63, 54, 49)
+            | field(origin[2] >> 5, 48, 48)
+            | field((origin[2] >> 3) & 3, 44, 43)
+            | field(origin[2] & 7, 41, 39)
+            | field(horizontal[0] >> 1, 38, 34)
+            | field(horizontal[0] & 1, 32, 32)
+            | field(horizontal[1], 31, 25)
+            | field(horizontal[2], 24, 19)
+            | field(vertical[0], 18, 13)
+            | field(vertical[1], 12, 6)
+            | field(vertical[2], 5, 0)
+            | field(1, 42, 42)
+            | field(1, 33, 33)
+    }
+
+    #[test]
+    fn etc2_planar_mode() {
+        let block = planar_block([32, 64, 0], [32, 64, 0], [32, 64, 0]);
+        let texels = decode(decode_etc2_rgb8, &block.to_be_bytes());
+        assert!(texels.iter().all(|texel| *texel == [130, 129, 0, 255]));
+
+        // red rises to the right
+        let block = planar_block([32, 64, 0], [63, 64, 0], [32, 64, 0]);
+        let texels = decode(decode_etc2_rgb8, &block.to_be_bytes());
+        for row in texels.chunks(4) {
+            assert_eq!(
+                row.iter().map(|texel| texel[0]).collect::<Vec<_>>(),
+                [130, 161, 193, 224]
+            );
+        }
+    }
+
+    #[test]
+    fn etc2_punchthrough_alpha() {
+        // without the opaque bit index 2 is transparent and index 0 the base color
+        let block = field(16, 63, 59) | field(8, 55, 51) | field(1, 16, 16);
+        let texels = decode(decode_etc2_rgb8a1, &block.to_be_bytes());
+        assert_eq!(texels[0], [0, 0, 0, 0]);
+        assert!(texels[1..].iter().all(|texel| *texel == [132, 66, 0, 255]));
+    }
+
+    /// An EAC block with the first texel at index `first` and every other one at 4.
+    fn eac_block(base: u64, multiplier: u64, first: u64) -> u64 {
+        let mut block = field(base, 63, 56) | field(multiplier, 55, 52) | field(first, 47, 45);
+        for texel in 1..16 {
+            block |= field(4, 47 - 3 * texel, 45 - 3 * texel);
+        }
+        block
+    }
+
+    #[test]
+    fn eac_r11() {
+        let texels = decode(decode_eac_r11, &eac_block(128, 1, 3).to_be_bytes());
+        assert_eq!(texels[0], [113, 0, 0, 255]);
+        assert!(texels[1..].iter().all(|texel| *texel == [130, 0, 0, 255]));
+
+        // a multiplier of 0 still moves the value by the modifier, in 11 bit steps
+        let texels = decode(decode_eac_r11, &eac_block(255, 0, 4).to_be_bytes());
+        assert!(texels.iter().all(|texel| *texel == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn eac_rg11() {
+        let mut block = [0u8; 16];
+        block[0..8].copy_from_slice(&eac_block(128, 1, 4).to_be_bytes());
+        block[8..16].copy_from_slice(&eac_block(0, 1, 3).to_be_bytes());
+        let texels = decode(decode_eac_rg11, &block);
+        assert_eq!(texels[0], [130, 0, 0, 255]);
+        assert_eq!(texels[1], [130, 2, 0, 255]);
+    }
+
+    #[test]
+    fn etc2_rgba8_alpha() {
+        let mut block = [0u8; 16];
+        block[0..8].copy_from_slice(&eac_block(200, 1, 4).to_be_bytes());
+        block[8..16].copy_from_slice(&individual_block([8, 4, 0], 0).to_be_bytes());
+        let texels = decode(decode_etc2_rgba8, &block);
+        assert!(texels.iter().all(|texel| *texel == [138, 70, 2, 202]));
+    }
+}
@@ -0,0 +1,166 @@
+//! CPU decoders for block compressed texture formats, used when the adapter cannot
+//! sample the format directly.
+
+use std::error::Error;
+
+use wgpu::{AstcChannel, TextureFormat};
+
+use super::texture::{LoadTextureError, TextureData};
+
+pub mod astc;
+pub mod bc;
+pub mod etc;
+
+/// Decodes one block into `block_width * block_height` RGBA8 texels in row-major order.
+type BlockDecoder = fn(&[u8], usize, usize, &mut [[u8; 4]]);
+
+fn block_decoder(format: TextureFormat) -> Option<BlockDecoder> {
+    use TextureFormat::*;
+
+    let decoder: BlockDecoder = match format {
+        Bc1RgbaUnorm | Bc1RgbaUnormSrgb => bc::decode_bc1,
+        Bc2RgbaUnorm | Bc2RgbaUnormSrgb => bc::decode_bc2,
+        Bc3RgbaUnorm | Bc3RgbaUnormSrgb => bc::decode_bc3,
+        Bc4RUnorm => bc::decode_bc4,
+        Bc5RgUnorm => bc::decode_bc5,
+        Bc7RgbaUnorm | Bc7RgbaUnormSrgb => bc::decode_bc7,
+        Etc2Rgb8Unorm | Etc2Rgb8UnormSrgb => etc::decode_etc2_rgb8,
+        Etc2Rgb8A1Unorm | Etc2Rgb8A1UnormSrgb => etc::decode_etc2_rgb8a1,
+        Etc2Rgba8Unorm | Etc2Rgba8UnormSrgb => etc::decode_etc2_rgba8,
+        EacR11Unorm => etc::decode_eac_r11,
+        EacRg11Unorm => etc::decode_eac_rg11,
+        Astc {
+            channel: AstcChannel::Unorm | AstcChannel::UnormSrgb,
+            ..
+        } => astc::decode_astc,
+        _ => return None,
+    };
+
+    Some(decoder)
+}
+
+/// Decode every mip level of a block compressed texture into RGBA8, keeping sRGB
+/// formats sRGB. Signed, HDR and float formats are not supported.
+pub fn decompress(texture_data: &TextureData) -> Result<TextureData, Box<dyn Error>> {
+    let format = texture_data.format;
+    let decoder = block_decoder(format).ok_or_else(|| {
+        LoadTextureError(format!("cannot decompress {:?} on the CPU", format))
+    })?;
+
+    let (block_width, block_height) = format.block_dimensions();
+    let block_width = block_width as usize;
+    let block_height = block_height as usize;
+    let block_size = format.block_copy_size(None).unwrap() as usize;
+
+    let mut levels = Vec::with_capacity(texture_data.levels.len());
+    let mut texels = vec![[0u8; 4]; block_width * block_height];
+    for (level, data) in texture_data.levels.iter().enumerate() {
+        let (width, height) = texture_data.level_size(level);
+        let (width, height) = (width as usize, height as usize);
+        let blocks_x = width.div_ceil(block_width);
+        let blocks_y = height.div_ceil(block_height);
+        if data.len() < blocks_x * blocks_y * block_size {
+            return Err(Box::new(LoadTextureError(format!(
+                "mip level {} is truncated",
+                level
+            ))));
+        }
+
+        let mut decoded = vec![0u8; width * height * 4];
+        for (i, block) in data.chunks_exact(block_size).take(blocks_x * blocks_y).enumerate() {
+            decoder(block, block_width, block_height, &mut texels);
+
+            // blocks on the right and bottom edges can hang over the image
+            let origin_x = (i % blocks_x) * block_width;
+            let origin_y = (i / blocks_x) * block_height;
+            for y in 0..block_height.min(height - origin_y) {
+                for x in 0..block_width.min(width - origin_x) {
+                    let offset = ((origin_y + y) * width + origin_x + x) * 4;
+                    decoded[offset..offset + 4].copy_from_slice(&texels[y * block_width + x]);
+                }
+            }
+        }
+        levels.push(decoded);
+    }
+
+    let format = if format.is_srgb() {
+        TextureFormat::Rgba8UnormSrgb
+    } else {
+        TextureFormat::Rgba8Unorm
+    };
+
+    Ok(TextureData {
+        width: texture_data.width,
+        height: texture_data.height,
+        format,
+        levels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A BC1 block with both endpoints set to the 565 `color`.
+    fn bc1_block(color: u16) -> [u8; 8] {
+        let [low, high] = color.to_le_bytes();
+        [low, high, low, high, 0, 0, 0, 0]
+    }
+
+    #[test]
+    fn blocks_over_the_edge_are_cropped() {
+        // 6x6 texels take 2x2 blocks, the right and bottom ones only half used
+        let colors = [0xf800, 0x07e0, 0x001f, 0xffff];
+        let texture_data = TextureData {
+            width: 6,
+            height: 6,
+            format: TextureFormat::Bc1RgbaUnormSrgb,
+            levels: vec![colors.iter().flat_map(|color| bc1_block(*color)).collect()],
+        };
+        let decompressed = decompress(&texture_data).unwrap();
+        assert_eq!(decompressed.format, TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(decompressed.levels[0].len(), 6 * 6 * 4);
+
+        let texel = |x: usize, y: usize| &decompressed.levels[0][(y * 6 + x) * 4..][..4];
+        assert_eq!(texel(3, 3), [255, 0, 0, 255]);
+        assert_eq!(texel(4, 3), [0, 255, 0, 255]);
+        assert_eq!(texel(3, 4), [0, 0, 255, 255]);
+        assert_eq!(texel(5, 5), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn every_mip_level_is_decoded() {
+        let texture_data = TextureData {
+            width: 8,
+            height: 4,
+            format: TextureFormat::Bc1RgbaUnorm,
+            levels: vec![[bc1_block(0xffff); 2].concat(), bc1_block(0).to_vec()],
+        };
+        let decompressed = decompress(&texture_data).unwrap();
+        assert_eq!(decompressed.format, TextureFormat::Rgba8Unorm);
+        assert_eq!(decompressed.levels[0], [255; 8 * 4 * 4]);
+        assert_eq!(decompressed.levels[1], [0, 0, 0, 255].repeat(4 * 2));
+    }
+
+    #[test]
+    fn truncated_levels_are_an_error() {
+        let texture_data = TextureData {
+            width: 8,
+            height: 8,
+            format: TextureFormat::Bc1RgbaUnorm,
+            levels: vec![bc1_block(0).to_vec()],
+        };
+        assert!(decompress(&texture_data).is_err());
+    }
+
+    #[test]
+    fn float_formats_are_not_decompressed() {
+        let texture_data = TextureData {
+            width: 4,
+            height: 4,
+            format: TextureFormat::Bc6hRgbUfloat,
+            levels: vec![vec![0; 16]],
+        };
+        assert!(decompress(&texture_data).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use ab_glyph::{Font as _, FontVec, GlyphId, PxScale, ScaleFont};
+
+use super::atlas::pack_shelves;
+
+/// Pixel size the glyphs are rasterized at. Text of any size is drawn from the same
+/// atlas, the distance field keeps the edges sharp.
+pub const SDF_FONT_SIZE: f32 = 48.0;
+/// How far from the outline the distance field reaches, in atlas pixels.
+pub const SDF_SPREAD: u32 = 6;
+const ATLAS_WIDTH: u32 = 512;
+
+#[derive(Debug)]
+pub struct LoadFontError(pub String);
+
+impl fmt::Display for LoadFontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error loading the font: {}", self.0)
+    }
+}
+
+impl Error for LoadFontError {}
+
+/// Where a glyph is in the atlas and how it sits on the baseline, in pixels at
+/// `SDF_FONT_SIZE`.
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasGlyph {
+    pub id: GlyphId,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// top left corner of the atlas rectangle relative to the pen position on the
+    /// baseline, y down
+    pub offset: (f32, f32),
+    pub advance: f32,
+}
+
+/// A TTF or OTF font with a signed distance field atlas of its glyphs.
+pub struct Font {
+    /// unique among all loaded fonts, the renderer keeps the atlas textures by it
+    pub id: u32,
+    font: FontVec,
+    pub glyphs: HashMap<char, AtlasGlyph>,
+    pub atlas_width: u32,
+    pub atlas_height: u32,
+    /// one byte per texel, 128 at the outline and growing towards the inside
+    pub atlas: Vec<u8>,
+}
+
+impl fmt::Debug for Font {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Font")
+            .field("id", &self.id)
+            .field("glyphs", &self.glyphs.len())
+            .finish()
+    }
+}
+
+/// Printable ASCII and Latin-1 characters.
+pub fn default_characters() -> impl Iterator<Item = char> {
+    (' '..='~').chain('\u{a0}'..='ÿ')
+}
+
+pub fn load_font(path: impl AsRef<Path>) -> Result<Font, Box<dyn Error>> {
+    load_font_with_characters(path, default_characters())
+}
+
+/// Load a font with an atlas of the given characters. Characters that are not in the
+/// atlas are left out when laying out text.
+pub fn load_font_with_characters(
+    path: impl AsRef<Path>,
+    characters: impl IntoIterator<Item = char>,
+) -> Result<Font, Box<dyn Error>> {
+    let data = std::fs::read(path)?;
+    let font = FontVec::try_from_vec(data).map_err(|e| LoadFontError(e.to_string()))?;
+    Ok(Font::new(font, characters))
+}
+
+impl Font {
+    fn new(font: FontVec, characters: impl IntoIterator<Item = char>) -> Font {
+        static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+        let scaled = font.as_scaled(PxScale::from(SDF_FONT_SIZE));
+
+        let mut bitmaps = vec![];
+        let mut glyphs = HashMap::new();
+        for character in characters {
+            let id = font.glyph_id(character);
+            if id.0 == 0 || glyphs.contains_key(&character) {
+                continue;
+            }
+            let (bitmap, glyph) = match font.outline_glyph(scaled.scaled_glyph(character)) {
+                Some(outlined) => {
+                    let bounds = outlined.px_bounds();
+                    let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+                    let mut coverage = vec![0.0; (width * height) as usize];
+                    outlined.draw(|x, y, c| coverage[(y * width + x) as usize] = c);
+                    let glyph = AtlasGlyph {
+                        id,
+                        x: 0,
+                        y: 0,
+                        width: width + 2 * SDF_SPREAD,
+                        height: height + 2 * SDF_SPREAD,
+                        offset: (
+                            bounds.min.x - SDF_SPREAD as f32,
+                            bounds.min.y - SDF_SPREAD as f32,
+                        ),
+                        advance: scaled.h_advance(id),
+                    };
+                    (signed_distance_field(&coverage, width, height), glyph)
+                }
+                // whitespace has nothing to draw
+                None => (
+                    vec![],
+                    AtlasGlyph {
+                        id,
+                        x: 0,
+                        y: 0,
+                        width: 0,
+                        height: 0,
+                        offset: (0.0, 0.0),
+                        advance: scaled.h_advance(id),
+                    },
+                ),
+            };
+            bitmaps.push((character, bitmap));
+            glyphs.insert(character, glyph);
+        }
+
+        let sizes: Vec<(u32, u32)> = bitmaps
+            .iter()
+            .map(|(character, _)| (glyphs[character].width, glyphs[character].height))
+            .collect();
+        let (positions, atlas_height) = pack_shelves(&sizes, ATLAS_WIDTH);
+        for ((character, _), (x, y)) in bitmaps.iter().zip(positions) {
+            let glyph = glyphs.get_mut(character).unwrap();
+            glyph.x = x;
+            glyph.y = y;
+        }
+        let mut atlas = vec![0u8; (ATLAS_WIDTH * atlas_height) as usize];
+        for (character, bitmap) in bitmaps {
+            let glyph = &glyphs[&character];
+            for (row, texels) in bitmap.chunks(glyph.width.max(1) as usize).enumerate() {
+                let start = ((glyph.y + row as u32) * ATLAS_WIDTH + glyph.x) as usize;
+                atlas[start..start + texels.len()].copy_from_slice(texels);
+            }
+        }
+
+        Font {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            font,
+            glyphs,
+            atlas_width: ATLAS_WIDTH,
+            atlas_height,
+            atlas,
+        }
+    }
+
+    /// Distance from the baseline to the top of the line, in pixels at `size`.
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.font.as_scaled(PxScale::from(size)).ascent()
+    }
+
+    /// Distance between the baselines of two lines, in pixels at `size`.
+    pub fn line_height(&self, size: f32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        scaled.height() + scaled.line_gap()
+    }
+
+    /// Kerning between two glyphs, in pixels at `size`.
+    pub fn kern(&self, first: GlyphId, second: GlyphId, size: f32) -> f32 {
+        self.font.as_scaled(PxScale::from(size)).kern(first, second)
+    }
+}
+
+/// Turn the coverage of a glyph into a distance field with `SDF_SPREAD` pixels of
+/// padding around it.
+fn signed_distance_field(coverage: &[f32], width: u32, height: u32) -> Vec<u8> {
+    let padded_width = (width + 2 * SDF_SPREAD) as usize;
+    let padded_height = (height + 2 * SDF_SPREAD) as usize;
+
+    // squared distances to the closest pixel inside and outside the glyph
+    let mut to_inside = vec![f32::MAX; padded_width * padded_height];
+    let mut to_outside = vec![0.0; padded_width * padded_height];
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            if coverage[y * width as usize + x] >= 0.5 {
+                let index = (y + SDF_SPREAD as usize) * padded_width + x + SDF_SPREAD as usize;
+                to_inside[index] = 0.0;
+                to_outside[index] = f32::MAX;
+            }
+        }
+    }
+    distance_transform(&mut to_inside, padded_width, padded_height);
+    distance_transform(&mut to_outside, padded_width, padded_height);
+
+    to_inside
+        .iter()
+        .zip(&to_outside)
+        .map(|(to_inside, to_outside)| {
+            let distance = to_outside.sqrt() - to_inside.sqrt();
+            let value = 0.5 + distance / (2.0 * SDF_SPREAD as f32);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+/// Squared euclidean distance transform of a grid where 0 marks the features, see
+/// Felzenszwalb and Huttenlocher, "Distance Transforms of Sampled Functions".
+fn distance_transform(grid: &mut [f32], width: usize, height: usize) {
+    let mut line = vec![0.0; width.max(height)];
+    let mut result = vec![0.0; width.max(height)];
+
+    for x in 0..width {
+        for y in 0..height {
+            line[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&line[..height], &mut result[..height]);
+        for y in 0..height {
+            grid[y * width + x] = result[y];
+        }
+    }
+    for y in 0..height {
+        line[..width].copy_from_slice(&grid[y * width..(y + 1) * width]);
+        distance_transform_1d(&line[..width], &mut result[..width]);
+        grid[y * width..(y + 1) * width].copy_from_slice(&result[..width]);
+    }
+}
+
+fn distance_transform_1d(f: &[f32], d: &mut [f32]) {
+    // positions of the parabolas in the lower envelope and where they start
+    let mut v = vec![0usize; f.len()];
+    let mut z = vec![0.0f32; f.len() + 1];
+    let mut k = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2.0 * q as f32 - 2.0 * p as f32)
+    };
+    for q in 1..f.len() {
+        if f[q] == f32::MAX {
+            continue;
+        }
+        if f[v[k]] == f32::MAX {
+            v[k] = q;
+            continue;
+        }
+        let mut s = intersection(q, v[k]);
+        while k > 0 && s <= z[k] {
+            k -= 1;
+            s = intersection(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for (q, distance) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let p = v[k];
+        *distance = match f[p] == f32::MAX {
+            true => f32::MAX,
+            false => (q as f32 - p as f32).powi(2) + f[p],
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(f: &[f32]) -> Vec<f32> {
+        let mut d = vec![0.0; f.len()];
+        distance_transform_1d(f, &mut d);
+        d
+    }
+
+    #[test]
+    fn squared_distances_to_the_closest_feature() {
+        let m = f32::MAX;
+        assert_eq!(
+            transform(&[m, m, 0.0, m, m, m, 0.0]),
+            [4.0, 1.0, 0.0, 1.0, 4.0, 1.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn values_of_the_first_pass_are_added_to_the_distances() {
+        let m = f32::MAX;
+        assert_eq!(transform(&[m, 1.0, m, m]), [2.0, 1.0, 2.0, 5.0]);
+        assert_eq!(transform(&[0.0, m, 9.0, 0.0]), [0.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn lines_without_features_stay_empty() {
+        let m = f32::MAX;
+        assert_eq!(transform(&[m, m, m]), [m, m, m]);
+    }
+}
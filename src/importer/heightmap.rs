@@ -0,0 +1,114 @@
+use std::{error::Error, fmt, path::Path};
+
+#[derive(Debug)]
+pub struct LoadHeightmapError(pub String);
+
+impl fmt::Display for LoadHeightmapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error loading the heightmap: {}", self.0)
+    }
+}
+
+impl Error for LoadHeightmapError {}
+
+/// Heights sampled on a grid, from 0 at black to 1 at white.
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    pub width: u32,
+    pub height: u32,
+    /// row-major, the rows go along x and there is one row for each z
+    pub heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn new(width: u32, height: u32, heights: Vec<f32>) -> Self {
+        assert_eq!(heights.len(), (width * height) as usize);
+        Heightmap {
+            width,
+            height,
+            heights,
+        }
+    }
+
+    /// The height at this sample, samples outside the map take the closest edge.
+    pub fn sample(&self, x: i64, z: i64) -> f32 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let z = z.clamp(0, self.height as i64 - 1) as usize;
+        self.heights[z * self.width as usize + x]
+    }
+}
+
+/// Load a grayscale image as a heightmap. 16-bit PNGs keep their precision, colored
+/// images are converted to their luminance.
+pub fn load_heightmap(path: impl AsRef<Path>) -> Result<Heightmap, Box<dyn Error>> {
+    let image = image::open(path)?.into_luma16();
+    if image.width() < 2 || image.height() < 2 {
+        return Err(Box::new(LoadHeightmapError(format!(
+            "a heightmap needs at least 2x2 samples, this one is {}x{}",
+            image.width(),
+            image.height()
+        ))));
+    }
+    Ok(Heightmap::new(
+        image.width(),
+        image.height(),
+        image
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_png(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("heightmap-{}-{name}.png", std::process::id()))
+    }
+
+    #[test]
+    fn loads_8_bit_png() {
+        let path = temp_png("8-bit");
+        image::GrayImage::from_fn(3, 2, |x, y| image::Luma([(x * 100 + y * 20) as u8]))
+            .save(&path)
+            .unwrap();
+        let heightmap = load_heightmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((heightmap.width, heightmap.height), (3, 2));
+        let expected = [0, 100, 200, 20, 120, 220].map(|value| value as f32 / 255.0);
+        for (height, expected) in heightmap.heights.iter().zip(expected) {
+            assert!((height - expected).abs() < 1e-6, "{height} != {expected}");
+        }
+    }
+
+    #[test]
+    fn loads_16_bit_png_at_full_precision() {
+        let path = temp_png("16-bit");
+        let values = [0, 1, 0x1234, u16::MAX];
+        image::ImageBuffer::<image::Luma<u16>, _>::from_fn(2, 2, |x, y| {
+            image::Luma([values[(y * 2 + x) as usize]])
+        })
+        .save(&path)
+        .unwrap();
+        let heightmap = load_heightmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((heightmap.width, heightmap.height), (2, 2));
+        let expected = values.map(|value| value as f32 / u16::MAX as f32);
+        assert_eq!(heightmap.heights, expected);
+    }
+
+    #[test]
+    fn rejects_maps_smaller_than_2x2() {
+        let path = temp_png("too-small");
+        image::GrayImage::new(1, 4).save(&path).unwrap();
+        let result = load_heightmap(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let error = result.unwrap_err();
+        assert!(error.is::<LoadHeightmapError>(), "{error}");
+    }
+}
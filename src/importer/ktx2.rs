@@ -0,0 +1,158 @@
+use std::{io::Read, result::Result};
+
+use ktx2::{Format, Reader, SupercompressionScheme};
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+use super::texture::{LoadTextureError, TextureData};
+
+fn err(message: String) -> Box<LoadTextureError> {
+    Box::new(LoadTextureError(message))
+}
+
+fn astc(block: AstcBlock, srgb: bool) -> TextureFormat {
+    let channel = if srgb {
+        AstcChannel::UnormSrgb
+    } else {
+        AstcChannel::Unorm
+    };
+    TextureFormat::Astc { block, channel }
+}
+
+/// Map a Vulkan format from the KTX2 header to the matching wgpu format.
+fn ktx2_format_to_texture_format(format: Format) -> Option<TextureFormat> {
+    let texture_format = match format {
+        Format::R8_UNORM => TextureFormat::R8Unorm,
+        Format::R8G8_UNORM => TextureFormat::Rg8Unorm,
+        Format::R8G8B8A8_UNORM => TextureFormat::Rgba8Unorm,
+        Format::R8G8B8A8_SRGB => TextureFormat::Rgba8UnormSrgb,
+        Format::B8G8R8A8_UNORM => TextureFormat::Bgra8Unorm,
+        Format::B8G8R8A8_SRGB => TextureFormat::Bgra8UnormSrgb,
+        Format::R16G16B16A16_SFLOAT => TextureFormat::Rgba16Float,
+        Format::R32G32B32A32_SFLOAT => TextureFormat::Rgba32Float,
+        // wgpu has no opaque-only BC1. The colors come out the same, but in blocks with
+        // color0 <= color1 the texels with index 3 are transparent black here where the
+        // RGB format makes them opaque black. Materials only use the color of a texture.
+        Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGBA_UNORM_BLOCK => TextureFormat::Bc1RgbaUnorm,
+        Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_SRGB_BLOCK => {
+            TextureFormat::Bc1RgbaUnormSrgb
+        }
+        Format::BC2_UNORM_BLOCK => TextureFormat::Bc2RgbaUnorm,
+        Format::BC2_SRGB_BLOCK => TextureFormat::Bc2RgbaUnormSrgb,
+        Format::BC3_UNORM_BLOCK => TextureFormat::Bc3RgbaUnorm,
+        Format::BC3_SRGB_BLOCK => TextureFormat::Bc3RgbaUnormSrgb,
+        Format::BC4_UNORM_BLOCK => TextureFormat::Bc4RUnorm,
+        Format::BC4_SNORM_BLOCK => TextureFormat::Bc4RSnorm,
+        Format::BC5_UNORM_BLOCK => TextureFormat::Bc5RgUnorm,
+        Format::BC5_SNORM_BLOCK => TextureFormat::Bc5RgSnorm,
+        Format::BC6H_UFLOAT_BLOCK => TextureFormat::Bc6hRgbUfloat,
+        Format::BC6H_SFLOAT_BLOCK => TextureFormat::Bc6hRgbFloat,
+        Format::BC7_UNORM_BLOCK => TextureFormat::Bc7RgbaUnorm,
+        Format::BC7_SRGB_BLOCK => TextureFormat::Bc7RgbaUnormSrgb,
+        Format::ETC2_R8G8B8_UNORM_BLOCK => TextureFormat::Etc2Rgb8Unorm,
+        Format::ETC2_R8G8B8_SRGB_BLOCK => TextureFormat::Etc2Rgb8UnormSrgb,
+        Format::ETC2_R8G8B8A1_UNORM_BLOCK => TextureFormat::Etc2Rgb8A1Unorm,
+        Format::ETC2_R8G8B8A1_SRGB_BLOCK => TextureFormat::Etc2Rgb8A1UnormSrgb,
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => TextureFormat::Etc2Rgba8Unorm,
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK => TextureFormat::Etc2Rgba8UnormSrgb,
+        Format::EAC_R11_UNORM_BLOCK => TextureFormat::EacR11Unorm,
+        Format::EAC_R11_SNORM_BLOCK => TextureFormat::EacR11Snorm,
+        Format::EAC_R11G11_UNORM_BLOCK => TextureFormat::EacRg11Unorm,
+        Format::EAC_R11G11_SNORM_BLOCK => TextureFormat::EacRg11Snorm,
+        Format::ASTC_4x4_UNORM_BLOCK => astc(AstcBlock::B4x4, false),
+        Format::ASTC_4x4_SRGB_BLOCK => astc(AstcBlock::B4x4, true),
+        Format::ASTC_5x4_UNORM_BLOCK => astc(AstcBlock::B5x4, false),
+        Format::ASTC_5x4_SRGB_BLOCK => astc(AstcBlock::B5x4, true),
+        Format::ASTC_5x5_UNORM_BLOCK => astc(AstcBlock::B5x5, false),
+        Format::ASTC_5x5_SRGB_BLOCK => astc(AstcBlock::B5x5, true),
+        Format::ASTC_6x5_UNORM_BLOCK => astc(AstcBlock::B6x5, false),
+        Format::ASTC_6x5_SRGB_BLOCK => astc(AstcBlock::B6x5, true),
+        Format::ASTC_6x6_UNORM_BLOCK => astc(AstcBlock::B6x6, false),
+        Format::ASTC_6x6_SRGB_BLOCK => astc(AstcBlock::B6x6, true),
+        Format::ASTC_8x5_UNORM_BLOCK => astc(AstcBlock::B8x5, false),
+        Format::ASTC_8x5_SRGB_BLOCK => astc(AstcBlock::B8x5, true),
+        Format::ASTC_8x6_UNORM_BLOCK => astc(AstcBlock::B8x6, false),
+        Format::ASTC_8x6_SRGB_BLOCK => astc(AstcBlock::B8x6, true),
+        Format::ASTC_8x8_UNORM_BLOCK => astc(AstcBlock::B8x8, false),
+        Format::ASTC_8x8_SRGB_BLOCK => astc(AstcBlock::B8x8, true),
+        Format::ASTC_10x5_UNORM_BLOCK => astc(AstcBlock::B10x5, false),
+        Format::ASTC_10x5_SRGB_BLOCK => astc(AstcBlock::B10x5, true),
+        Format::ASTC_10x6_UNORM_BLOCK => astc(AstcBlock::B10x6, false),
+        Format::ASTC_10x6_SRGB_BLOCK => astc(AstcBlock::B10x6, true),
+        Format::ASTC_10x8_UNORM_BLOCK => astc(AstcBlock::B10x8, false),
+        Format::ASTC_10x8_SRGB_BLOCK => astc(AstcBlock::B10x8, true),
+        Format::ASTC_10x10_UNORM_BLOCK => astc(AstcBlock::B10x10, false),
+        Format::ASTC_10x10_SRGB_BLOCK => astc(AstcBlock::B10x10, true),
+        Format::ASTC_12x10_UNORM_BLOCK => astc(AstcBlock::B12x10, false),
+        Format::ASTC_12x10_SRGB_BLOCK => astc(AstcBlock::B12x10, true),
+        Format::ASTC_12x12_UNORM_BLOCK => astc(AstcBlock::B12x12, false),
+        Format::ASTC_12x12_SRGB_BLOCK => astc(AstcBlock::B12x12, true),
+        _ => return None,
+    };
+
+    Some(texture_format)
+}
+
+fn decompress_level(
+    level: &[u8],
+    scheme: Option<SupercompressionScheme>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match scheme {
+        None => Ok(level.to_vec()),
+        Some(SupercompressionScheme::Zstandard) => {
+            let mut source = level;
+            let mut decoder = ruzstd::StreamingDecoder::new(&mut source)
+                .map_err(|e| err(format!("invalid zstd stream: {}", e)))?;
+            let mut result = vec![];
+            decoder.read_to_end(&mut result)?;
+            Ok(result)
+        }
+        Some(SupercompressionScheme::ZLIB) => miniz_oxide::inflate::decompress_to_vec_zlib(level)
+            .map_err(|e| err(format!("invalid zlib stream: {:?}", e)) as Box<dyn std::error::Error>),
+        Some(scheme) => Err(err(format!(
+            "supercompression scheme {:?} is not supported",
+            scheme
+        ))),
+    }
+}
+
+/// Parse a KTX2 container holding a single 2D image. All mip levels stored in the
+/// file are kept, the payload stays in its original (possibly block compressed) format.
+pub fn load_ktx2(bytes: Vec<u8>) -> Result<TextureData, Box<dyn std::error::Error>> {
+    let reader = Reader::new(bytes)?;
+    let header = reader.header();
+
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count != 1 {
+        return Err(err(
+            "only single 2D images are supported, not volumes, arrays or cube maps".to_string(),
+        ));
+    }
+
+    let Some(ktx2_format) = header.format else {
+        // VK_FORMAT_UNDEFINED is what Basis Universal (ETC1S and UASTC) payloads use,
+        // there is no transcoder for them yet
+        let payload = match header.supercompression_scheme {
+            Some(SupercompressionScheme::BasisLZ) => "ETC1S",
+            _ => "UASTC",
+        };
+        return Err(err(format!(
+            "Basis Universal {} textures are not supported yet, encode them to a GPU \
+             format such as BC7, ETC2 or ASTC instead",
+            payload
+        )));
+    };
+
+    let format = ktx2_format_to_texture_format(ktx2_format)
+        .ok_or_else(|| err(format!("format {:?} is not supported", ktx2_format)))?;
+
+    let levels = reader
+        .levels()
+        .map(|level| decompress_level(level, header.supercompression_scheme))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(TextureData {
+        width: header.pixel_width,
+        height: header.pixel_height.max(1),
+        format,
+        levels,
+    })
+}
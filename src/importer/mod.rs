@@ -1,3 +1,9 @@
 pub mod obj;
 pub mod helper;
-pub mod defaults;
\ No newline at end of file
+pub mod defaults;
+pub mod texture;
+pub mod ktx2;
+pub mod decompress;
+pub mod font;
+pub mod atlas;
+pub mod heightmap;
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::{error::Error, fmt, path::Path, result::Result};
+
+use wgpu::TextureFormat;
+
+use crate::importer::ktx2::load_ktx2;
+
+type LoadTextureErrorBranchType = Box<dyn Error>;
+type LoadTextureResult = Result<TextureData, LoadTextureErrorBranchType>;
+
+#[derive(Debug)]
+pub struct LoadTextureError(pub String);
+
+impl fmt::Display for LoadTextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error loading the texture: {}", self.0)
+    }
+}
+
+impl Error for LoadTextureError {}
+
+/// A texture decoded on the CPU, ready to be uploaded.
+///
+/// `levels` holds one tightly packed buffer per mip level, largest first. For block
+/// compressed formats each buffer holds whole blocks in row-major order.
+#[derive(Debug)]
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureData {
+    /// A single texel texture, used in place of textures which fail to load.
+    pub fn solid_color(color: [u8; 4]) -> Self {
+        TextureData {
+            width: 1,
+            height: 1,
+            format: TextureFormat::Rgba8UnormSrgb,
+            levels: vec![color.to_vec()],
+        }
+    }
+
+    pub fn level_size(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+}
+
+/// Load a texture from a file. KTX2 containers keep their format and mip levels,
+/// everything else is decoded into a single level of sRGB RGBA8.
+pub fn load_texture<P>(filename: P) -> LoadTextureResult
+where
+    P: AsRef<Path>,
+{
+    let filename = filename.as_ref();
+    let is_ktx2 = filename
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ktx2"));
+
+    if is_ktx2 {
+        load_ktx2(std::fs::read(filename)?)
+    } else {
+        let image = image::open(filename)?.to_rgba8();
+        Ok(TextureData {
+            width: image.width(),
+            height: image.height(),
+            format: TextureFormat::Rgba8UnormSrgb,
+            levels: vec![image.into_raw()],
+        })
+    }
+}
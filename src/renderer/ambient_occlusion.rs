@@ -0,0 +1,414 @@
+use std::num::NonZeroU64;
+
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BlendComponent, BlendState, Buffer,
+    BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, Device, Extent3d, FragmentState,
+    MultisampleState, PipelineLayout, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPipelineDescriptor, ShaderModule, ShaderStages, SurfaceCapabilities, Texture,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, VertexState,
+};
+
+use super::{
+    deferred::GBuffer,
+    depth_texture::DepthTexture,
+    light::Light,
+    material::MaterialManager,
+    settings::AmbientOcclusion,
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
+    stats::{GpuTimer, RenderStats},
+    viewport::PixelRect,
+};
+
+/// How much of the ambient light reaches each pixel.
+const OCCLUSION_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct AmbientOcclusionData {
+    radius: f32,
+    intensity: f32,
+    sample_count: u32,
+    blur_radius: u32,
+}
+
+unsafe impl bytemuck::Zeroable for AmbientOcclusionData {}
+unsafe impl bytemuck::Pod for AmbientOcclusionData {}
+
+pub trait AmbientOcclusionManager {
+    fn add_ambient_occlusion_pipelines(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    );
+}
+
+impl AmbientOcclusionManager for MaterialManager {
+    fn add_ambient_occlusion_pipelines(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    ) {
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let view_bind_group_layout = self.deferred_view_bind_group_layout.as_ref().unwrap();
+        let create_pipeline = |label,
+                               layout: &PipelineLayout,
+                               shader_module: &ShaderModule,
+                               vertex_entry_point,
+                               fragment_entry_point,
+                               target| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: VertexState {
+                    module: shader_module,
+                    entry_point: vertex_entry_point,
+                    buffers: &[],
+                },
+                fragment: Some(FragmentState {
+                    module: shader_module,
+                    entry_point: fragment_entry_point,
+                    targets: &[Some(target)],
+                }),
+                primitive: PrimitiveState::default(),
+                multisample: MultisampleState::default(),
+                depth_stencil: None,
+                multiview: None,
+            })
+        };
+
+        // Bind Groups
+        // Group 0 Binding 1: DeferredViewData
+        // Group 1 Binding 0: AmbientOcclusionData
+        // Group 1 Binding 1, 2: Depth and Occlusion Textures
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("AmbientOcclusionBindGroupLayout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(
+                            std::mem::size_of::<AmbientOcclusionData>() as u64,
+                        ),
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+            ],
+        });
+        let shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/ambient-occlusion.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("AmbientOcclusionPipelineLayout"),
+            bind_group_layouts: &[view_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        self.ambient_occlusion_pipelines = [
+            ("AmbientOcclusionRenderPipeline", "fragment_occlusion"),
+            (
+                "AmbientOcclusionHorizontalBlurPipeline",
+                "fragment_blur_horizontal",
+            ),
+            (
+                "AmbientOcclusionVerticalBlurPipeline",
+                "fragment_blur_vertical",
+            ),
+        ]
+        .into_iter()
+        .map(|(label, fragment_entry_point)| {
+            create_pipeline(
+                label,
+                &pipeline_layout,
+                &shader_module,
+                "vertex_main",
+                fragment_entry_point,
+                OCCLUSION_FORMAT.into(),
+            )
+        })
+        .collect();
+
+        // Bind Groups
+        // Group 0 Binding 1: DeferredViewData
+        // Group 1 Binding 9, 13, 14: Ambient G-Buffer Texture, Depth and Occlusion
+        // Group 2 Binding 0, 1: PointLightData and ViewInfoData
+        let apply_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("AmbientOcclusionApplyBindGroupLayout"),
+            entries: &[texture_entry(9), texture_entry(13), texture_entry(14)],
+        });
+        let phong_shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/phong-model.wgsl"));
+        let apply_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("AmbientOcclusionApplyPipelineLayout"),
+            bind_group_layouts: &[
+                view_bind_group_layout,
+                &apply_bind_group_layout,
+                self.point_light_bind_group_layout.as_ref().unwrap(),
+            ],
+            push_constant_ranges: &[],
+        });
+        // the destination less the occluded ambient light
+        self.ambient_occlusion_apply_pipeline = Some(create_pipeline(
+            "AmbientOcclusionApplyPipeline",
+            &apply_pipeline_layout,
+            &phong_shader_module,
+            "vertex_fullscreen",
+            "fragment_ambient_occlusion",
+            ColorTargetState {
+                format: surface_capabilities.formats[0],
+                blend: Some(BlendState {
+                    color: BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::ReverseSubtract,
+                    },
+                    alpha: BlendComponent {
+                        src_factor: wgpu::BlendFactor::Zero,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                }),
+                write_mask: ColorWrites::ALL,
+            },
+        ));
+        self.ambient_occlusion_bind_group_layout = Some(bind_group_layout);
+        self.ambient_occlusion_apply_bind_group_layout = Some(apply_bind_group_layout);
+    }
+}
+
+/// The occlusion texture, the texture the blur goes through between its passes and the
+/// settings buffer, sized like the G-buffer they belong to.
+pub struct AmbientOcclusionTargets {
+    views: [TextureView; 2],
+    settings_buffer: Buffer,
+    _textures: [Texture; 2],
+}
+
+impl AmbientOcclusionTargets {
+    pub fn new(device: &Device, size: (u32, u32)) -> Self {
+        let textures = ["AmbientOcclusionTexture", "AmbientOcclusionBlurTexture"].map(|label| {
+            device.create_texture(&TextureDescriptor {
+                label: Some(label),
+                size: Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: OCCLUSION_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT.union(TextureUsages::TEXTURE_BINDING),
+                view_formats: &[],
+            })
+        });
+        let views = [0, 1].map(|index| textures[index].create_view(&Default::default()));
+        let settings_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("AmbientOcclusionSettingsBuffer"),
+            size: std::mem::size_of::<AmbientOcclusionData>() as u64,
+            usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+            mapped_at_creation: false,
+        });
+
+        AmbientOcclusionTargets {
+            views,
+            settings_buffer,
+            _textures: textures,
+        }
+    }
+
+    // The depth is read by every pass, along with the occlusion texture they do not
+    // draw to.
+    fn create_bind_group(
+        &self,
+        device: &Device,
+        layout: &BindGroupLayout,
+        depth_texture: &DepthTexture,
+        read_view: &TextureView,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("AmbientOcclusionBindGroup"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: self.settings_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(read_view),
+                },
+            ],
+        })
+    }
+}
+
+/// Find the ambient occlusion in `rect` from the depth texture, blur it, and take the
+/// occluded ambient light out of `texture_view`. The G-buffer has to hold the frame,
+/// the ambient light of the surfaces is read from it.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_ambient_occlusion_passes(
+    settings: &AmbientOcclusion,
+    lights: &[Light],
+    stats: &mut RenderStats,
+    material_manager: &MaterialManager,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+    device: &Device,
+    mut gpu_timer: Option<&mut GpuTimer>,
+    texture_view: &TextureView,
+    depth_texture: &DepthTexture,
+    g_buffer: &mut GBuffer,
+    rect: PixelRect,
+) {
+    // the view info the fog needs is bound with the lights
+    let Some(first_light) = lights.first().map(Light::as_point_light) else {
+        return;
+    };
+    let size = (
+        depth_texture.texture.width(),
+        depth_texture.texture.height(),
+    );
+    let targets = g_buffer
+        .ambient_occlusion
+        .get_or_insert_with(|| AmbientOcclusionTargets::new(device, size));
+    staging_belt.write_buffer(
+        &targets.settings_buffer,
+        0,
+        bytemuck::cast_slice(&[AmbientOcclusionData {
+            radius: settings.radius,
+            intensity: settings.intensity,
+            sample_count: settings.sample_count,
+            blur_radius: settings.blur_radius,
+        }]),
+        device,
+    );
+
+    let bind_group_layout = material_manager
+        .ambient_occlusion_bind_group_layout
+        .as_ref()
+        .unwrap();
+    let [occlusion_view, blur_view] = &targets.views;
+    let read_blur_bind_group =
+        targets.create_bind_group(device, bind_group_layout, depth_texture, blur_view);
+    let read_occlusion_bind_group =
+        targets.create_bind_group(device, bind_group_layout, depth_texture, occlusion_view);
+
+    // the occlusion, then the blur there and back between the two textures
+    for ((label, pipeline), (view, bind_group)) in [
+        "AmbientOcclusionRenderPass",
+        "AmbientOcclusionHorizontalBlurRenderPass",
+        "AmbientOcclusionVerticalBlurRenderPass",
+    ]
+    .into_iter()
+    .zip(&material_manager.ambient_occlusion_pipelines)
+    .zip([
+        (occlusion_view, &read_blur_bind_group),
+        (blur_view, &read_occlusion_bind_group),
+        (occlusion_view, &read_blur_bind_group),
+    ]) {
+        let timestamp_writes = gpu_timer
+            .as_deref_mut()
+            .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes(label));
+        let mut render_pass =
+            staging_belt
+                .command_encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(label),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes,
+                    occlusion_query_set: None,
+                });
+
+        rect.set_viewport(&mut render_pass);
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &g_buffer.view_bind_group, &[]);
+        render_pass.set_bind_group(1, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 2;
+        stats.draw_calls += 1;
+        stats.triangles += 1;
+    }
+
+    let apply_bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("AmbientOcclusionApplyBindGroup"),
+        layout: material_manager
+            .ambient_occlusion_apply_bind_group_layout
+            .as_ref()
+            .unwrap(),
+        entries: &[
+            BindGroupEntry {
+                binding: 9,
+                resource: wgpu::BindingResource::TextureView(&g_buffer.views[0]),
+            },
+            BindGroupEntry {
+                binding: 13,
+                resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+            },
+            BindGroupEntry {
+                binding: 14,
+                resource: wgpu::BindingResource::TextureView(occlusion_view),
+            },
+        ],
+    });
+    let timestamp_writes = gpu_timer.and_then(|gpu_timer| {
+        gpu_timer.render_pass_timestamp_writes("AmbientOcclusionApplyRenderPass")
+    });
+    let mut render_pass =
+        staging_belt
+            .command_encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("AmbientOcclusionApplyRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+    rect.set_viewport(&mut render_pass);
+    render_pass.set_pipeline(
+        material_manager
+            .ambient_occlusion_apply_pipeline
+            .as_ref()
+            .unwrap(),
+    );
+    render_pass.set_bind_group(0, &g_buffer.view_bind_group, &[]);
+    render_pass.set_bind_group(1, &apply_bind_group, &[]);
+    render_pass.set_bind_group(2, first_light.bind_group.as_ref().unwrap(), &[]);
+    render_pass.draw(0..3, 0..1);
+    stats.pipeline_switches += 1;
+    stats.bind_group_switches += 3;
+    stats.draw_calls += 1;
+    stats.triangles += 1;
+}
@@ -12,7 +12,12 @@ impl<'a> BindMaterial<'a> for RenderPass<'a> {
             Material::PhongMaterial(mat) => {
                 self.set_bind_group(1, mat.bind_group.as_ref().unwrap(), &[]);
             }
-            Material::PhongMaterialWithTexture(_mat) => todo!(),
+            Material::PhongMaterialWithTexture(mat) => {
+                self.set_bind_group(1, mat.bind_group.as_ref().unwrap(), &[]);
+            }
+            Material::TerrainMaterial(mat) => {
+                self.set_bind_group(1, mat.bind_group.as_ref().unwrap(), &[]);
+            }
         }
     }
 }
@@ -0,0 +1,230 @@
+use std::{error::Error, fmt, path::PathBuf};
+
+use image::RgbaImage;
+use wgpu::{
+    include_wgsl, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device,
+    Extent3d, FragmentState, ImageCopyBuffer, ImageDataLayout, MapMode, MultisampleState,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPipelineDescriptor, ShaderStages,
+    SurfaceCapabilities, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, VertexState, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+use super::{material::MaterialManager, wgpu_handles::WgpuHandles};
+
+#[derive(Debug)]
+pub struct CaptureError(pub String);
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error capturing the frame: {}", self.0)
+    }
+}
+
+impl Error for CaptureError {}
+
+pub trait CaptureManager {
+    fn add_capture_blit_pipeline(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    );
+}
+
+impl CaptureManager for MaterialManager {
+    fn add_capture_blit_pipeline(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    ) {
+        // Bind Groups
+        // Group 0 Binding 0: Captured Frame Texture
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("CaptureBlitBindGroupLayout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/capture-blit.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("CaptureBlitPipelineLayout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        self.capture_blit_pipeline =
+            Some(device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("CaptureBlitRenderPipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: VertexState {
+                    module: &shader_module,
+                    entry_point: "vertex_main",
+                    buffers: &[],
+                },
+                fragment: Some(FragmentState {
+                    module: &shader_module,
+                    entry_point: "fragment_main",
+                    targets: &[Some(surface_capabilities.formats[0].into())],
+                }),
+                primitive: PrimitiveState::default(),
+                multisample: MultisampleState::default(),
+                depth_stencil: None,
+                multiview: None,
+            }));
+        self.capture_blit_bind_group_layout = Some(bind_group_layout);
+    }
+}
+
+/// Texture to draw a frame into when the surface texture cannot be copied from. It is
+/// drawn onto the surface afterwards with `blit_capture_texture`.
+pub fn create_capture_texture(device: &Device, size: (u32, u32), format: TextureFormat) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("CaptureTexture"),
+        size: Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::COPY_SRC
+            | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+/// Draw a frame from a capture texture onto a surface texture of the same size.
+pub fn blit_capture_texture(wgpu_handles: &WgpuHandles, texture: &Texture, target: &TextureView) {
+    let material_manager = &wgpu_handles.material_manager;
+    let texture_view = texture.create_view(&Default::default());
+    let bind_group = wgpu_handles.device.create_bind_group(&BindGroupDescriptor {
+        label: Some("CaptureBlitBindGroup"),
+        layout: material_manager
+            .capture_blit_bind_group_layout
+            .as_ref()
+            .unwrap(),
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&texture_view),
+        }],
+    });
+
+    let mut encoder = wgpu_handles
+        .device
+        .create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("CaptureBlitCommandEncoder"),
+        });
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("CaptureBlitRenderPass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    render_pass.set_pipeline(material_manager.capture_blit_pipeline.as_ref().unwrap());
+    render_pass.set_bind_group(0, &bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+    drop(render_pass);
+    wgpu_handles.queue.submit(Some(encoder.finish()));
+}
+
+/// Copy a texture with 8 bit RGBA or BGRA texels to the CPU. This waits for the GPU.
+pub fn read_texture_to_image(
+    wgpu_handles: &WgpuHandles,
+    texture: &Texture,
+) -> Result<RgbaImage, Box<dyn Error>> {
+    let swap_red_blue = match texture.format() {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+        format => {
+            return Err(Box::new(CaptureError(format!(
+                "{:?} textures are not supported",
+                format
+            ))))
+        }
+    };
+    let (width, height) = (texture.width(), texture.height());
+
+    // rows of texture copies have to be aligned
+    let unpadded_bytes_per_row = width * 4;
+    let bytes_per_row = unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT)
+        * COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer = wgpu_handles.device.create_buffer(&BufferDescriptor {
+        label: Some("CaptureReadbackBuffer"),
+        size: (bytes_per_row * height) as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = wgpu_handles
+        .device
+        .create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("CaptureCommandEncoder"),
+        });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    wgpu_handles.queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    wgpu_handles.device.poll(wgpu::Maintain::Wait);
+    receiver.recv()??;
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    for row in slice
+        .get_mapped_range()
+        .chunks(bytes_per_row as usize)
+        .take(height as usize)
+    {
+        pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+    }
+    buffer.unmap();
+
+    if swap_red_blue {
+        pixels.chunks_mut(4).for_each(|pixel| pixel.swap(0, 2));
+    }
+    // surfaces are presented opaque whatever they have in alpha
+    pixels.chunks_mut(4).for_each(|pixel| pixel[3] = 255);
+
+    RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| Box::new(CaptureError("image size mismatch".to_string())) as Box<dyn Error>)
+}
+
+/// Encode and write the PNG on another thread so the frame loop is not held up.
+pub fn save_png(image: RgbaImage, path: PathBuf) {
+    std::thread::spawn(move || {
+        if let Err(e) = image.save_with_format(&path, image::ImageFormat::Png) {
+            eprintln!("{} ({})", e, path.display());
+        }
+    });
+}
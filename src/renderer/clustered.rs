@@ -0,0 +1,333 @@
+use glm::GenSquareMat;
+
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, Buffer, BufferDescriptor, BufferUsages,
+    ComputePassDescriptor, ComputePipelineDescriptor, Device, PipelineLayoutDescriptor,
+    ShaderStages,
+};
+
+use super::{
+    light::{ConeData, Light, PointLightData},
+    material::MaterialManager,
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
+    stats::{GpuTimer, RenderStats},
+    viewport::PixelRect,
+};
+use crate::{scene::camera::Camera, util::MuckableMatrix};
+
+/// Clusters along the width, height and depth of the view.
+pub const CLUSTER_COUNTS: [u32; 3] = [16, 9, 24];
+/// Lights a cluster holds at most, the ones after them are left out of it. The shaders
+/// have their own copy.
+pub const MAX_CLUSTER_LIGHTS: u32 = 128;
+const WORKGROUP_SIZE: u32 = 4;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ClusterData {
+    world_to_view: MuckableMatrix,
+    clip_to_view: MuckableMatrix,
+    /// x, y, width and height in pixels
+    viewport: [f32; 4],
+    /// the cluster counts and the number of lights
+    counts: [u32; 4],
+    /// near and far
+    depth_range: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for ClusterData {}
+unsafe impl bytemuck::Pod for ClusterData {}
+
+/// A light in the light list of the clusters.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ClusterLightData {
+    data: PointLightData,
+    layers: u32,
+    _padding: [u32; 3],
+    cone: ConeData,
+}
+
+unsafe impl bytemuck::Zeroable for ClusterLightData {}
+unsafe impl bytemuck::Pod for ClusterLightData {}
+
+fn buffer_entry(
+    binding: u32,
+    visibility: ShaderStages,
+    ty: wgpu::BufferBindingType,
+) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+pub trait ClusteredManager {
+    fn get_light_clusters_bind_group_layout(&self, device: &Device) -> BindGroupLayout;
+    fn add_light_assignment_pipeline(&mut self, device: &Device);
+}
+
+impl ClusteredManager for MaterialManager {
+    /// Takes the place of the light of the forward path for the clustered pipelines.
+    fn get_light_clusters_bind_group_layout(&self, device: &Device) -> BindGroupLayout {
+        let storage = wgpu::BufferBindingType::Storage { read_only: true };
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("LightClustersBindGroupLayout"),
+            entries: &[
+                buffer_entry(1, ShaderStages::FRAGMENT, wgpu::BufferBindingType::Uniform),
+                buffer_entry(2, ShaderStages::FRAGMENT, storage),
+                buffer_entry(3, ShaderStages::FRAGMENT, storage),
+                buffer_entry(4, ShaderStages::FRAGMENT, wgpu::BufferBindingType::Uniform),
+            ],
+        })
+    }
+
+    fn add_light_assignment_pipeline(&mut self, device: &Device) {
+        let shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/light-clusters.wgsl"));
+
+        // Bind Groups
+        // Group 0 Binding 0: Lights
+        // Group 0 Binding 1: Light Indices of the Clusters
+        // Group 0 Binding 2: ClusterData
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("LightAssignmentBindGroupLayout"),
+            entries: &[
+                buffer_entry(
+                    0,
+                    ShaderStages::COMPUTE,
+                    wgpu::BufferBindingType::Storage { read_only: true },
+                ),
+                buffer_entry(
+                    1,
+                    ShaderStages::COMPUTE,
+                    wgpu::BufferBindingType::Storage { read_only: false },
+                ),
+                buffer_entry(2, ShaderStages::COMPUTE, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("LightAssignmentPipelineLayout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        self.light_assignment_pipeline =
+            Some(device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("LightAssignmentPipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: "assign_lights",
+            }));
+        self.light_assignment_bind_group_layout = Some(bind_group_layout);
+    }
+}
+
+/// The light list, the lights of each cluster and the cluster settings of the clustered
+/// path. The light list grows with the lights of the scene.
+pub struct LightClusters {
+    light_capacity: usize,
+    lights_buffer: Buffer,
+    indices_buffer: Buffer,
+    cluster_buffer: Buffer,
+    assignment_bind_group: BindGroup,
+}
+
+impl LightClusters {
+    pub fn new(
+        device: &Device,
+        assignment_layout: &BindGroupLayout,
+        light_capacity: usize,
+    ) -> Self {
+        let lights_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("ClusterLightsBuffer"),
+            size: (light_capacity * std::mem::size_of::<ClusterLightData>()) as u64,
+            usage: BufferUsages::STORAGE.union(BufferUsages::COPY_DST),
+            mapped_at_creation: false,
+        });
+        let cluster_count = CLUSTER_COUNTS.iter().product::<u32>();
+        let indices_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("ClusterLightIndicesBuffer"),
+            size: (cluster_count * (MAX_CLUSTER_LIGHTS + 1)) as u64 * 4,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let cluster_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("ClusterDataBuffer"),
+            size: std::mem::size_of::<ClusterData>() as u64,
+            usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+            mapped_at_creation: false,
+        });
+        let assignment_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("LightAssignmentBindGroup"),
+            layout: assignment_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: indices_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: cluster_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        LightClusters {
+            light_capacity,
+            lights_buffer,
+            indices_buffer,
+            cluster_buffer,
+            assignment_bind_group,
+        }
+    }
+
+    /// Get the light clusters in `slot`, recreating them with room for twice the lights
+    /// if there are more than they hold.
+    pub fn get_or_grow<'a>(
+        slot: &'a mut Option<LightClusters>,
+        device: &Device,
+        assignment_layout: &BindGroupLayout,
+        light_count: usize,
+    ) -> &'a LightClusters {
+        if slot
+            .as_ref()
+            .is_some_and(|clusters| clusters.light_capacity < light_count)
+        {
+            *slot = None;
+        }
+        slot.get_or_insert_with(|| {
+            LightClusters::new(device, assignment_layout, (light_count * 2).max(16))
+        })
+    }
+}
+
+/// Assign the lights of the scene to the clusters of the view of `camera` in `rect`,
+/// and return the bind group the clustered pipelines light the objects with. There is
+/// none without lights, like the forward path nothing is drawn then.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_light_assignment(
+    lights: &[Light],
+    camera: &Camera,
+    stats: &mut RenderStats,
+    material_manager: &MaterialManager,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+    device: &Device,
+    gpu_timer: Option<&mut GpuTimer>,
+    slot: &mut Option<LightClusters>,
+    rect: PixelRect,
+) -> Option<BindGroup> {
+    // the view info the fog needs is kept with the lights
+    let first_light = lights.first()?.as_point_light();
+    let light_clusters = LightClusters::get_or_grow(
+        slot,
+        device,
+        material_manager
+            .light_assignment_bind_group_layout
+            .as_ref()
+            .unwrap(),
+        lights.len(),
+    );
+
+    let light_data: Vec<ClusterLightData> = lights
+        .iter()
+        .map(|light| ClusterLightData {
+            data: light.as_point_light().data,
+            layers: light.as_point_light().layers,
+            _padding: [0; 3],
+            cone: light.cone_data(),
+        })
+        .collect();
+    staging_belt.write_buffer(
+        &light_clusters.lights_buffer,
+        0,
+        bytemuck::cast_slice(&light_data),
+        device,
+    );
+    let (near, far) = camera.depth_range();
+    let [x, y, z] = CLUSTER_COUNTS;
+    staging_belt.write_buffer(
+        &light_clusters.cluster_buffer,
+        0,
+        bytemuck::cast_slice(&[ClusterData {
+            world_to_view: MuckableMatrix(*camera.world_to_local()),
+            clip_to_view: MuckableMatrix(camera.get_projection_matrix().inverse().unwrap()),
+            viewport: [
+                rect.x as f32,
+                rect.y as f32,
+                rect.width as f32,
+                rect.height as f32,
+            ],
+            counts: [x, y, z, lights.len() as u32],
+            // the slices are spread logarithmically, which needs a near plane in front
+            // of the camera
+            depth_range: [near.max(1e-3), far.max(near.max(1e-3) * 2.0), 0.0, 0.0],
+        }]),
+        device,
+    );
+
+    let timestamp_writes = gpu_timer
+        .and_then(|gpu_timer| gpu_timer.compute_pass_timestamp_writes("LightAssignmentPass"));
+    let mut compute_pass =
+        staging_belt
+            .command_encoder
+            .begin_compute_pass(&ComputePassDescriptor {
+                label: Some("LightAssignmentPass"),
+                timestamp_writes,
+            });
+    compute_pass.set_pipeline(material_manager.light_assignment_pipeline.as_ref().unwrap());
+    compute_pass.set_bind_group(0, &light_clusters.assignment_bind_group, &[]);
+    compute_pass.dispatch_workgroups(
+        x.div_ceil(WORKGROUP_SIZE),
+        y.div_ceil(WORKGROUP_SIZE),
+        z.div_ceil(WORKGROUP_SIZE),
+    );
+    drop(compute_pass);
+    stats.pipeline_switches += 1;
+    stats.bind_group_switches += 1;
+    stats.compute_dispatches += 1;
+
+    // the view info buffer belongs to the first light, which can change between frames
+    Some(
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("LightClustersBindGroup"),
+            layout: material_manager
+                .light_clusters_bind_group_layout
+                .as_ref()
+                .unwrap(),
+            entries: &[
+                BindGroupEntry {
+                    binding: 1,
+                    resource: first_light
+                        .view_info
+                        .view_info_buffer
+                        .as_ref()
+                        .unwrap()
+                        .as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: light_clusters.lights_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: light_clusters.indices_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: light_clusters.cluster_buffer.as_entire_binding(),
+                },
+            ],
+        }),
+    )
+}
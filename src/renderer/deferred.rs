@@ -0,0 +1,477 @@
+use std::num::NonZeroU64;
+
+use glm::GenSquareMat;
+
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BlendComponent, BlendState, Buffer,
+    BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, Device, Extent3d, FragmentState,
+    MultisampleState, PipelineLayoutDescriptor, PrimitiveState, RenderPipelineDescriptor,
+    ShaderStages, SurfaceCapabilities, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, VertexState,
+};
+
+use super::{
+    ambient_occlusion::AmbientOcclusionTargets,
+    depth_texture::DepthTexture,
+    draw_impl::DrawObject3D,
+    draw_state::DrawState,
+    light::Light,
+    material::{Deformation, MaterialManager, MaterialType},
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
+    stats::GpuTimer,
+    viewport::PixelRect,
+};
+use crate::{
+    scene::{camera::Camera, object3d::Object3D},
+    util::MuckableMatrix,
+};
+
+/// Ambient color, diffuse color, specular color with the shininess, and normal. The
+/// colors are the ones of the material weighed by the color of the surface.
+const G_BUFFER_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// The layers of the object, which the lights are tested against. A float target can't
+/// hold all 32 bits of the mask.
+const G_BUFFER_LAYERS_FORMAT: TextureFormat = TextureFormat::R32Uint;
+/// Label, format and binding of each target. The depth texture is bound at 13.
+const G_BUFFER_TARGETS: [(&str, TextureFormat, u32); 5] = [
+    ("GBufferAmbientTexture", G_BUFFER_FORMAT, 9),
+    ("GBufferDiffuseTexture", G_BUFFER_FORMAT, 10),
+    ("GBufferSpecularTexture", G_BUFFER_FORMAT, 11),
+    ("GBufferNormalTexture", G_BUFFER_FORMAT, 12),
+    ("GBufferLayersTexture", G_BUFFER_LAYERS_FORMAT, 15),
+];
+
+/// Color targets of the pipelines drawing into the G-buffer.
+pub fn g_buffer_color_targets() -> [Option<ColorTargetState>; 5] {
+    G_BUFFER_TARGETS.map(|(_, format, _)| Some(format.into()))
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct DeferredViewData {
+    world_to_clip: MuckableMatrix,
+    clip_to_world: MuckableMatrix,
+    /// x, y, width and height in pixels
+    viewport: [f32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for DeferredViewData {}
+unsafe impl bytemuck::Pod for DeferredViewData {}
+
+pub trait DeferredManager {
+    fn add_deferred_pipelines(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    );
+}
+
+impl DeferredManager for MaterialManager {
+    fn add_deferred_pipelines(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    ) {
+        let shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/phong-model.wgsl"));
+
+        let view_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("DeferredViewBindGroupLayout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(
+                        std::mem::size_of::<DeferredViewData>() as u64
+                    ),
+                },
+                count: None,
+            }],
+        });
+        let texture_entry = |binding, sample_type| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let unfiltered = wgpu::TextureSampleType::Float { filterable: false };
+        let g_buffer_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("GBufferBindGroupLayout"),
+                entries: &[
+                    texture_entry(9, unfiltered),
+                    texture_entry(10, unfiltered),
+                    texture_entry(11, unfiltered),
+                    texture_entry(12, unfiltered),
+                    texture_entry(13, unfiltered),
+                    texture_entry(15, wgpu::TextureSampleType::Uint),
+                ],
+            });
+
+        // Bind Groups
+        // Group 0 Binding 1: DeferredViewData
+        // Group 1 Binding 9 - 13, 15: G-Buffer Textures and Depth
+        // Group 2 Binding 0, 1: PointLightData and ViewInfoData
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("DeferredLightingPipelineLayout"),
+            bind_group_layouts: &[
+                &view_bind_group_layout,
+                &g_buffer_bind_group_layout,
+                self.point_light_bind_group_layout.as_ref().unwrap(),
+            ],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, vertex_entry_point, fragment_entry_point, topology, blend| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: VertexState {
+                    module: &shader_module,
+                    entry_point: vertex_entry_point,
+                    buffers: &[],
+                },
+                fragment: Some(FragmentState {
+                    module: &shader_module,
+                    entry_point: fragment_entry_point,
+                    targets: &[Some(ColorTargetState {
+                        format: surface_capabilities.formats[0],
+                        blend,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: PrimitiveState {
+                    topology,
+                    ..Default::default()
+                },
+                multisample: MultisampleState::default(),
+                depth_stencil: None,
+                multiview: None,
+            })
+        };
+
+        // the ambient light and the fog are drawn over the surfaces like the first light
+        // of the forward path does, the lights are added to them
+        self.deferred_ambient_pipeline = Some(create_pipeline(
+            "DeferredAmbientRenderPipeline",
+            "vertex_fullscreen",
+            "fragment_deferred_ambient",
+            wgpu::PrimitiveTopology::TriangleList,
+            None,
+        ));
+        self.deferred_light_pipeline = Some(create_pipeline(
+            "DeferredLightRenderPipeline",
+            "vertex_light_volume",
+            "fragment_deferred_light",
+            wgpu::PrimitiveTopology::TriangleStrip,
+            Some(BlendState {
+                color: BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: BlendComponent::REPLACE,
+            }),
+        ));
+        self.deferred_view_bind_group_layout = Some(view_bind_group_layout);
+        self.g_buffer_bind_group_layout = Some(g_buffer_bind_group_layout);
+    }
+}
+
+/// Targets of the geometry pass of the deferred path, sized like the render target. The
+/// depth goes to the depth texture of the scene, where the passes after it test against.
+pub struct GBuffer {
+    size: (u32, u32),
+    /// in the order of `G_BUFFER_TARGETS`
+    pub views: Vec<TextureView>,
+    view_buffer: Buffer,
+    /// the `DeferredViewData` of the frame
+    pub view_bind_group: BindGroup,
+    /// created by the first frame with ambient occlusion
+    pub ambient_occlusion: Option<AmbientOcclusionTargets>,
+    _textures: Vec<Texture>,
+}
+
+impl GBuffer {
+    pub fn new(
+        device: &Device,
+        view_bind_group_layout: &BindGroupLayout,
+        size: (u32, u32),
+    ) -> Self {
+        let textures: Vec<Texture> = G_BUFFER_TARGETS
+            .iter()
+            .map(|(label, format, _)| {
+                device.create_texture(&TextureDescriptor {
+                    label: Some(label),
+                    size: Extent3d {
+                        width: size.0,
+                        height: size.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: *format,
+                    usage: TextureUsages::RENDER_ATTACHMENT.union(TextureUsages::TEXTURE_BINDING),
+                    view_formats: &[],
+                })
+            })
+            .collect();
+        let views = textures
+            .iter()
+            .map(|texture| texture.create_view(&Default::default()))
+            .collect();
+        let view_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("DeferredViewBuffer"),
+            size: std::mem::size_of::<DeferredViewData>() as u64,
+            usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+            mapped_at_creation: false,
+        });
+        let view_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("DeferredViewBindGroup"),
+            layout: view_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 1,
+                resource: view_buffer.as_entire_binding(),
+            }],
+        });
+
+        GBuffer {
+            size,
+            views,
+            view_buffer,
+            view_bind_group,
+            ambient_occlusion: None,
+            _textures: textures,
+        }
+    }
+
+    /// Get the G-buffer in `slot`, recreating it if the render target was resized.
+    pub fn get_or_resize<'a>(
+        slot: &'a mut Option<GBuffer>,
+        device: &Device,
+        view_bind_group_layout: &BindGroupLayout,
+        size: (u32, u32),
+    ) -> &'a mut GBuffer {
+        if slot.as_ref().is_some_and(|g_buffer| g_buffer.size != size) {
+            *slot = None;
+        }
+        slot.get_or_insert_with(|| GBuffer::new(device, view_bind_group_layout, size))
+    }
+
+    // The depth texture is recreated apart from the G-buffer, so the textures are bound
+    // anew for each frame.
+    fn create_texture_bind_group(
+        &self,
+        device: &Device,
+        layout: &BindGroupLayout,
+        depth_texture: &DepthTexture,
+    ) -> BindGroup {
+        let mut entries: Vec<BindGroupEntry> = self
+            .views
+            .iter()
+            .zip(G_BUFFER_TARGETS)
+            .map(|(view, (_, _, binding))| BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .collect();
+        entries.push(BindGroupEntry {
+            binding: 13,
+            resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+        });
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("GBufferBindGroup"),
+            layout,
+            entries: &entries,
+        })
+    }
+}
+
+/// Draw the objects into the G-buffer, then light them into `rect` of `texture_view`:
+/// the ambient light and the fog over all of it, and each light over the part of the
+/// screen it reaches. Like the forward path, nothing is drawn without lights.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_deferred_passes(
+    lights: &[Light],
+    camera: &Camera,
+    drawn_root: Option<&Object3D>,
+    deformations: &[Deformation],
+    draw_state: &mut DrawState,
+    material_manager: &MaterialManager,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+    device: &Device,
+    mut gpu_timer: Option<&mut GpuTimer>,
+    texture_view: &TextureView,
+    depth_texture: &DepthTexture,
+    g_buffer: &GBuffer,
+    rect: PixelRect,
+) {
+    encode_g_buffer_pass(
+        lights,
+        camera,
+        drawn_root,
+        deformations,
+        draw_state,
+        material_manager,
+        staging_belt,
+        device,
+        gpu_timer.as_deref_mut(),
+        depth_texture,
+        g_buffer,
+        rect,
+    );
+
+    let light_bind_groups: Vec<&BindGroup> = lights
+        .iter()
+        .map(|light| light.as_point_light().bind_group.as_ref().unwrap())
+        .collect();
+    let Some(first_light_bind_group) = light_bind_groups.first() else {
+        return;
+    };
+    let texture_bind_group = g_buffer.create_texture_bind_group(
+        device,
+        material_manager
+            .g_buffer_bind_group_layout
+            .as_ref()
+            .unwrap(),
+        depth_texture,
+    );
+    let timestamp_writes = gpu_timer
+        .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("DeferredLightingRenderPass"));
+    let mut render_pass =
+        staging_belt
+            .command_encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("DeferredLightingRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+    rect.set_viewport(&mut render_pass);
+    render_pass.set_bind_group(0, &g_buffer.view_bind_group, &[]);
+    render_pass.set_bind_group(1, &texture_bind_group, &[]);
+    render_pass.set_bind_group(2, first_light_bind_group, &[]);
+    render_pass.set_pipeline(material_manager.deferred_ambient_pipeline.as_ref().unwrap());
+    render_pass.draw(0..3, 0..1);
+    draw_state.stats.pipeline_switches += 1;
+    draw_state.stats.bind_group_switches += 3;
+    draw_state.stats.draw_calls += 1;
+    draw_state.stats.triangles += 1;
+
+    render_pass.set_pipeline(material_manager.deferred_light_pipeline.as_ref().unwrap());
+    draw_state.stats.pipeline_switches += 1;
+    for light_bind_group in light_bind_groups {
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        render_pass.draw(0..4, 0..1);
+        draw_state.stats.bind_group_switches += 1;
+        draw_state.stats.draw_calls += 1;
+        draw_state.stats.triangles += 2;
+    }
+}
+
+/// Draw the objects into the G-buffer and the depth texture, in `rect`. The view data
+/// the passes reading the G-buffer need is written here too.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_g_buffer_pass(
+    lights: &[Light],
+    camera: &Camera,
+    drawn_root: Option<&Object3D>,
+    deformations: &[Deformation],
+    draw_state: &mut DrawState,
+    material_manager: &MaterialManager,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+    device: &Device,
+    gpu_timer: Option<&mut GpuTimer>,
+    depth_texture: &DepthTexture,
+    g_buffer: &GBuffer,
+    rect: PixelRect,
+) {
+    let world_to_clip = camera.get_inverse_matrix();
+    staging_belt.write_buffer(
+        &g_buffer.view_buffer,
+        0,
+        bytemuck::cast_slice(&[DeferredViewData {
+            world_to_clip: MuckableMatrix(world_to_clip),
+            clip_to_world: MuckableMatrix(world_to_clip.inverse().unwrap()),
+            viewport: [
+                rect.x as f32,
+                rect.y as f32,
+                rect.width as f32,
+                rect.height as f32,
+            ],
+        }]),
+        device,
+    );
+    let color_attachments = g_buffer.views.iter().map(|view| {
+        Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        })
+    });
+    let timestamp_writes =
+        gpu_timer.and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("GBufferRenderPass"));
+    let mut render_pass =
+        staging_belt
+            .command_encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("GBufferRenderPass"),
+                color_attachments: &color_attachments.collect::<Vec<_>>(),
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+    rect.set_viewport(&mut render_pass);
+
+    // the lights are not used, but the pipelines share their layout with the forward ones
+    if let (Some(drawn_root), Some(point_light)) =
+        (drawn_root, lights.first().map(Light::as_point_light))
+    {
+        render_pass.set_bind_group(2, point_light.bind_group.as_ref().unwrap(), &[]);
+        draw_state.stats.bind_group_switches += 1;
+        for material_type in &[
+            MaterialType::PhongMaterial,
+            MaterialType::PhongMaterialWithTexture,
+            MaterialType::TerrainMaterial,
+        ] {
+            draw_state.current_material = material_type.to_owned();
+
+            for deformation in deformations {
+                draw_state.deformation = *deformation;
+                render_pass.set_pipeline(
+                    material_manager
+                        .get_g_buffer_pipeline_for_material_type(material_type, *deformation),
+                );
+                draw_state.stats.pipeline_switches += 1;
+                render_pass.draw_object_3d(draw_state, drawn_root);
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use wgpu::{
+    CompareFunction, DepthStencilState, Device, Extent3d, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView,
+};
+
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Depth attachment of the scene render pass, sized like the render target.
+pub struct DepthTexture {
+    pub size: (u32, u32),
+    pub texture: Texture,
+    pub view: TextureView,
+}
+
+impl DepthTexture {
+    pub fn new(device: &Device, size: (u32, u32)) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("SceneDepthTexture"),
+            size: Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            // the deferred path reads it back to find the positions of the surfaces
+            usage: TextureUsages::RENDER_ATTACHMENT.union(TextureUsages::TEXTURE_BINDING),
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+
+        DepthTexture {
+            size,
+            texture,
+            view,
+        }
+    }
+
+    /// Get the depth texture in `slot`, recreating it if the render target was resized.
+    pub fn get_or_resize<'a>(
+        slot: &'a mut Option<DepthTexture>,
+        device: &Device,
+        size: (u32, u32),
+    ) -> &'a DepthTexture {
+        if slot.as_ref().is_some_and(|depth| depth.size != size) {
+            *slot = None;
+        }
+        slot.get_or_insert_with(|| DepthTexture::new(device, size))
+    }
+}
+
+/// Depth state for pipelines drawing into the scene render pass. Objects are drawn
+/// once per light, so equal depths have to pass.
+pub fn scene_depth_stencil_state(depth_write_enabled: bool) -> DepthStencilState {
+    DepthStencilState {
+        format: DEPTH_FORMAT,
+        depth_write_enabled,
+        depth_compare: CompareFunction::LessEqual,
+        stencil: Default::default(),
+        bias: Default::default(),
+    }
+}
@@ -1,49 +1,131 @@
 use super::bind_material::BindMaterial;
-use crate::renderer::draw_state::DrawState;
-use crate::scene::{mesh::Mesh, object3d::Object3D};
-use wgpu::{self, RenderPass};
+use crate::renderer::{draw_state::DrawState, material::Deformation, stats::RenderStats};
+use crate::scene::{mesh::Mesh, object3d::Object3D, skin::SkinVertex};
+use wgpu::{self, vertex_attr_array, RenderPass, VertexAttribute, VertexBufferLayout};
+
+const GEOMETRY_ATTRIBUTES: [VertexAttribute; 1] = vertex_attr_array![0 => Float32x4];
+const SKIN_ATTRIBUTES: [VertexAttribute; 2] = vertex_attr_array![3 => Uint32x4, 4 => Float32x4];
 
 pub trait DrawObject3D<'a> {
+    /// Draw the meshes of the subtree that match the draw state. Particles, grids and
+    /// labels are drawn in their own passes after the scene.
     fn draw_object_3d(&mut self, draw_state: &mut DrawState, mesh: &'a Object3D);
 }
 
 impl<'a> DrawObject3D<'a> for RenderPass<'a> {
     fn draw_object_3d(&mut self, draw_state: &mut DrawState, object3d: &'a Object3D) {
-        if object3d.is_drawable() {
-            self.set_bind_group(0, object3d.matrix_bind_group.as_ref().unwrap(), &[]);
-        }
-
-        match &object3d.object {
-            crate::scene::object3d::Object3DObject::Empty => {}
-            crate::scene::object3d::Object3DObject::Scene => {}
-            crate::scene::object3d::Object3DObject::Mesh(mesh) => self.draw_mesh(draw_state, mesh),
-        };
-
-        for child in &object3d.children {
-            self.draw_object_3d(draw_state, child);
-        }
+        let layers = draw_state.layers;
+        object3d.for_each_drawn_mesh(layers, &mut |object3d, mesh| {
+            self.draw_mesh(draw_state, object3d, mesh)
+        });
     }
 }
 
 trait DrawMesh<'a> {
-    fn draw_mesh(&mut self, draw_state: &mut DrawState, mesh: &'a Mesh);
+    fn draw_mesh(&mut self, draw_state: &mut DrawState, object3d: &'a Object3D, mesh: &'a Mesh);
 }
 
 impl<'a> DrawMesh<'a> for RenderPass<'a> {
-    fn draw_mesh(&mut self, draw_state: &mut DrawState, mesh: &'a Mesh) {
-        if mesh.material.is_of_type(&draw_state.current_material) {
+    fn draw_mesh(&mut self, draw_state: &mut DrawState, object3d: &'a Object3D, mesh: &'a Mesh) {
+        // a texture cannot be sampled in the pass that draws to it
+        let samples_target = draw_state.render_texture.is_some()
+            && mesh.material.render_texture_name() == draw_state.render_texture.as_deref();
+        if mesh.material.is_of_type(&draw_state.current_material)
+            && !samples_target
+            && mesh.deformation() == draw_state.deformation
+        {
+            self.set_bind_group(0, object3d.matrix_bind_group.as_ref().unwrap(), &[]);
+            draw_state.stats.bind_group_switches += 1;
             self.bind_material(&mesh.material);
+            if let Some(deformation_bind_group) = &mesh.deformation_bind_group {
+                self.set_bind_group(3, deformation_bind_group, &[]);
+                draw_state.stats.bind_group_switches += 1;
+            }
+            if let Some(skin) = &mesh.skin {
+                self.set_vertex_buffer(1, skin.vertex_buffer.as_ref().unwrap().slice(..));
+            }
+            let index_count =
+                (std::mem::size_of_val(&*(mesh.index_array)) / std::mem::size_of::<u32>()) as u32;
             self.set_vertex_buffer(0, mesh.vertex_buffer.as_ref().unwrap().slice(..));
             self.set_index_buffer(
                 mesh.index_buffer.as_ref().unwrap().slice(..),
                 wgpu::IndexFormat::Uint32,
             );
-            self.draw_indexed(
-                0..((std::mem::size_of_val(&*(mesh.index_array)) / std::mem::size_of::<u32>())
-                    as u32),
-                0,
-                0..1,
-            );
+            self.draw_indexed(0..index_count, 0, 0..1);
+
+            draw_state.stats.bind_group_switches += 1;
+            draw_state.stats.draw_calls += 1;
+            draw_state.stats.triangles += index_count as u64 / 3;
         }
     }
 }
+
+/// Vertex buffers of the pipelines `draw_mesh_geometry` draws with: the positions, and
+/// the joints and weights of skinned meshes.
+pub fn geometry_vertex_buffers(deformation: Deformation) -> Vec<VertexBufferLayout<'static>> {
+    let mut buffers = vec![VertexBufferLayout {
+        array_stride: 48,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &GEOMETRY_ATTRIBUTES,
+    }];
+    if deformation.is_skinned() {
+        buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<SkinVertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &SKIN_ATTRIBUTES,
+        });
+    }
+    buffers
+}
+
+/// The vertex shader of those pipelines for each `Deformation`.
+pub fn geometry_vertex_entry_point(deformation: Deformation) -> &'static str {
+    match deformation {
+        Deformation::None => "vertex_main",
+        Deformation::Skinned => "vertex_main_skinned",
+        Deformation::Morphed => "vertex_main_morphed",
+        Deformation::SkinnedAndMorphed => "vertex_main_skinned_morphed",
+    }
+}
+
+pub trait DrawMeshGeometry<'a> {
+    /// Draw a mesh with the pipeline that is set, which only takes the object matrix and
+    /// the vertex positions, deformed like in the scene pass. The pipeline has to be the
+    /// one for the `Deformation` of the mesh, which binds the joints and morph targets
+    /// right after the matrix. Used by the passes that draw ids and masks.
+    fn draw_mesh_geometry(
+        &mut self,
+        stats: &mut RenderStats,
+        object3d: &'a Object3D,
+        mesh: &'a Mesh,
+    );
+}
+
+impl<'a> DrawMeshGeometry<'a> for RenderPass<'a> {
+    fn draw_mesh_geometry(
+        &mut self,
+        stats: &mut RenderStats,
+        object3d: &'a Object3D,
+        mesh: &'a Mesh,
+    ) {
+        let index_count = mesh.index_array.len() as u32;
+        self.set_bind_group(0, object3d.matrix_bind_group.as_ref().unwrap(), &[]);
+        if let Some(deformation_bind_group) = &mesh.deformation_bind_group {
+            self.set_bind_group(1, deformation_bind_group, &[]);
+            stats.bind_group_switches += 1;
+        }
+        if let Some(skin) = &mesh.skin {
+            self.set_vertex_buffer(1, skin.vertex_buffer.as_ref().unwrap().slice(..));
+        }
+        self.set_vertex_buffer(0, mesh.vertex_buffer.as_ref().unwrap().slice(..));
+        self.set_index_buffer(
+            mesh.index_buffer.as_ref().unwrap().slice(..),
+            wgpu::IndexFormat::Uint32,
+        );
+        self.draw_indexed(0..index_count, 0, 0..1);
+
+        stats.bind_group_switches += 1;
+        stats.draw_calls += 1;
+        stats.triangles += index_count as u64 / 3;
+    }
+}
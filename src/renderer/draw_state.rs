@@ -1,25 +1,47 @@
 use std::sync::Arc;
 
-use super::material::{MaterialManager, MaterialType};
+use super::{
+    material::{Deformation, MaterialManager, MaterialType},
+    stats::RenderStats,
+};
+use crate::scene::object3d::ALL_LAYERS;
 
 pub struct DrawState {
+    /// world matrices of the objects being written
     pub matrix_stack: Vec<glm::Matrix4<f32>>,
+    /// world to clip space of the camera the objects are drawn with
+    pub view_projection: glm::Matrix4<f32>,
     pub current_material: MaterialType,
     pub material_manager: Arc<MaterialManager>,
+    pub stats: RenderStats,
+    /// render texture being drawn, materials sampling it are left out
+    pub render_texture: Option<String>,
+    /// only meshes deformed this way are drawn
+    pub deformation: Deformation,
+    /// the range of the dither pattern the objects being written are drawn in
+    pub fade: [f32; 2],
+    /// only objects on one of these layers are drawn
+    pub layers: u32,
 }
 
 impl DrawState {
     pub fn new(current_material: MaterialType, material_manager: Arc<MaterialManager>) -> Self {
         DrawState {
             matrix_stack: vec![crate::util::identity_matrix()],
+            view_projection: crate::util::identity_matrix(),
             current_material,
             material_manager,
+            stats: RenderStats::default(),
+            render_texture: None,
+            deformation: Deformation::None,
+            fade: [0.0, 1.0],
+            layers: ALL_LAYERS,
         }
     }
 
     pub fn transform(&mut self, matrix: &glm::Matrix4<f32>) {
         let edited = self.matrix_stack.pop().unwrap();
-        let multiplied = matrix.mul_m(&edited);
+        let multiplied = edited.mul_m(matrix);
         self.matrix_stack.push(multiplied);
     }
 
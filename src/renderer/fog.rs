@@ -0,0 +1,84 @@
+/// Fog of the scene, applied by the material shaders. Distance fog is applied first and
+/// height fog on top of it.
+#[derive(Default)]
+pub struct Fog {
+    pub distance: Option<DistanceFog>,
+    pub height: Option<HeightFog>,
+}
+
+pub struct DistanceFog {
+    pub falloff: DistanceFogFalloff,
+    pub color: glm::Vec3,
+}
+
+/// How the amount of fog grows with the distance from the camera. Orthographic cameras
+/// measure the distance along their view direction.
+pub enum DistanceFogFalloff {
+    /// no fog before `start`, only fog after `end` and growing linearly in between,
+    /// clamp((distance - start) / (end - start), 0, 1)
+    Linear { start: f32, end: f32 },
+    /// 1 - e^(-density * distance)
+    Exponential { density: f32 },
+    /// 1 - e^(-(density * distance)^2)
+    ExponentialSquared { density: f32 },
+}
+
+/// Fog that is densest at `height` and thins out above it.
+pub struct HeightFog {
+    pub color: glm::Vec3,
+    /// world space y, whatever the transforms of the fogged objects
+    pub height: f32,
+    pub density: f32,
+    /// how quickly the density drops above `height`
+    pub falloff: f32,
+}
+
+/// Fog settings as laid out in the view info uniform.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct FogData {
+    distance_color: glm::Vec3,
+    /// 0 for no distance fog, then linear, exponential and exponential squared
+    distance_mode: u32,
+    /// start, end and density
+    distance_params: glm::Vec4,
+    height_color: glm::Vec3,
+    height_enabled: u32,
+    /// height, density and falloff
+    height_params: glm::Vec4,
+}
+
+unsafe impl bytemuck::Zeroable for FogData {}
+unsafe impl bytemuck::Pod for FogData {}
+
+impl Default for FogData {
+    fn default() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+}
+
+impl Fog {
+    pub fn to_data(&self) -> FogData {
+        let mut data = FogData::default();
+
+        if let Some(distance) = &self.distance {
+            data.distance_color = distance.color;
+            (data.distance_mode, data.distance_params) = match distance.falloff {
+                DistanceFogFalloff::Linear { start, end } => (1, glm::vec4(start, end, 0.0, 0.0)),
+                DistanceFogFalloff::Exponential { density } => {
+                    (2, glm::vec4(0.0, 0.0, density, 0.0))
+                }
+                DistanceFogFalloff::ExponentialSquared { density } => {
+                    (3, glm::vec4(0.0, 0.0, density, 0.0))
+                }
+            };
+        }
+        if let Some(height) = &self.height {
+            data.height_color = height.color;
+            data.height_enabled = 1;
+            data.height_params = glm::vec4(height.height, height.density, height.falloff, 0.0);
+        }
+
+        data
+    }
+}
@@ -0,0 +1,290 @@
+use glm::GenSquareMat;
+use wgpu::{
+    include_wgsl, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BlendState, BufferDescriptor, BufferUsages, ColorTargetState,
+    ColorWrites, Device, FragmentState, MultisampleState, PipelineLayout, PipelineLayoutDescriptor,
+    PrimitiveState, RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderStages,
+    SurfaceCapabilities, TextureView, VertexState,
+};
+
+use super::{
+    depth_texture::scene_depth_stencil_state, material::MaterialManager,
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder, viewport::PixelRect,
+    wgpu_handles::WgpuHandles,
+};
+use crate::{
+    scene::{
+        camera::Camera,
+        grid::Grid,
+        object3d::{Object3D, Object3DObject},
+        scene::Scene,
+    },
+    util::{identity_matrix, MuckableMatrix},
+};
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct GridData {
+    local_to_clip: MuckableMatrix,
+    clip_to_local: MuckableMatrix,
+    eye: glm::Vec4,
+    minor_color: glm::Vec4,
+    major_color: glm::Vec4,
+    /// x, y, width and height in pixels
+    viewport: glm::Vec4,
+    /// cell size, lines per major line, line width and axis width
+    params: glm::Vec4,
+    /// fade distance and whether the axes are shown
+    fade: glm::Vec4,
+}
+
+unsafe impl bytemuck::Zeroable for GridData {}
+unsafe impl bytemuck::Pod for GridData {}
+
+pub trait GridManager {
+    fn add_grid_pipelines(&mut self, device: &Device, surface_capabilities: &SurfaceCapabilities);
+}
+
+impl GridManager for MaterialManager {
+    fn add_grid_pipelines(&mut self, device: &Device, surface_capabilities: &SurfaceCapabilities) {
+        let shader_module = device.create_shader_module(include_wgsl!("./shaders/grid.wgsl"));
+
+        // Bind Groups
+        // Group 0 Binding 0: GridData
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("GridBindGroupLayout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("GridPipelineLayout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        self.grid_pipeline = Some(create_grid_pipeline(
+            device,
+            surface_capabilities,
+            &shader_module,
+            &pipeline_layout,
+            "GridRenderPipeline",
+            "fragment_main",
+        ));
+        self.grid_y_axis_pipeline = Some(create_grid_pipeline(
+            device,
+            surface_capabilities,
+            &shader_module,
+            &pipeline_layout,
+            "GridYAxisRenderPipeline",
+            "fragment_y_axis",
+        ));
+        self.grid_bind_group_layout = Some(bind_group_layout);
+    }
+}
+
+fn create_grid_pipeline(
+    device: &Device,
+    surface_capabilities: &SurfaceCapabilities,
+    shader_module: &ShaderModule,
+    pipeline_layout: &PipelineLayout,
+    label: &str,
+    fragment_entry_point: &str,
+) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(pipeline_layout),
+        vertex: VertexState {
+            module: shader_module,
+            entry_point: "vertex_main",
+            buffers: &[],
+        },
+        fragment: Some(FragmentState {
+            module: shader_module,
+            entry_point: fragment_entry_point,
+            targets: &[Some(ColorTargetState {
+                format: surface_capabilities.formats[0],
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState::default(),
+        multisample: MultisampleState::default(),
+        // the shader writes the depth of the grid, which hides it behind the scene but
+        // does not hide the particles drawn after it
+        depth_stencil: Some(scene_depth_stencil_state(false)),
+        multiview: None,
+    })
+}
+
+/// The visible grids on the layers of the camera, with their world matrices.
+fn collect_grids<'a>(
+    object3d: &'a mut Object3D,
+    parent_matrix: &glm::Mat4,
+    layers: u32,
+    grids: &mut Vec<(&'a mut Grid, glm::Mat4)>,
+) {
+    if !object3d.visible {
+        return;
+    }
+    let matrix: glm::Mat4 = object3d.matrix.into();
+    let matrix = parent_matrix.mul_m(&matrix);
+
+    let is_seen = object3d.is_on_layers(layers);
+    if let (true, Object3DObject::Grid(grid)) = (is_seen, &mut object3d.object) {
+        grids.push((grid, matrix));
+    }
+    for child in &mut object3d.children {
+        collect_grids(child, &matrix, layers, grids);
+    }
+}
+
+fn create_grid_buffer(grid: &mut Grid, device: &Device, material_manager: &MaterialManager) {
+    let grid_buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("GridBuffer"),
+        size: std::mem::size_of::<GridData>() as u64,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    grid.bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
+        label: Some("GridBindGroup"),
+        layout: material_manager.grid_bind_group_layout.as_ref().unwrap(),
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: grid_buffer.as_entire_binding(),
+        }],
+    }));
+    grid.grid_buffer = Some(grid_buffer);
+}
+
+fn grid_data(grid: &Grid, world_matrix: &glm::Mat4, camera: &Camera, rect: PixelRect) -> GridData {
+    let local_to_clip = camera.get_inverse_matrix().mul_m(world_matrix);
+    let world_to_grid = world_matrix.inverse().unwrap();
+    let eye = world_to_grid * camera.get_view_info().camera_position;
+    // orthographic cameras see every part of the grid alike
+    let fade_distance = match camera {
+        Camera::PerspectiveCamera { .. } => grid.fade_distance * eye.y.abs().max(grid.cell_size),
+        Camera::OrthographicCamera { .. } => 0.0,
+    };
+
+    GridData {
+        local_to_clip: MuckableMatrix(local_to_clip),
+        clip_to_local: MuckableMatrix(local_to_clip.inverse().unwrap()),
+        eye,
+        minor_color: grid.minor_color,
+        major_color: grid.major_color,
+        viewport: glm::vec4(
+            rect.x as f32,
+            rect.y as f32,
+            rect.width as f32,
+            rect.height as f32,
+        ),
+        params: glm::vec4(
+            grid.cell_size,
+            grid.major_every as f32,
+            grid.line_width,
+            grid.axis_width,
+        ),
+        fade: glm::vec4(
+            fade_distance,
+            if grid.show_axes { 1.0 } else { 0.0 },
+            0.0,
+            0.0,
+        ),
+    }
+}
+
+/// Draw the grids of the scene as seen by the scene camera in `rect`, behind the objects
+/// in the depth texture of the scene pass that was just encoded.
+pub fn encode_grid_pass(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &MaterialManager,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+    texture_view: &TextureView,
+    window: usize,
+    rect: PixelRect,
+) {
+    let mut grids = vec![];
+    let layers = scene.camera.layers();
+    collect_grids(&mut scene.root, &identity_matrix(), layers, &mut grids);
+    // a grid scaled down to nothing has no plane to draw
+    grids.retain(|(_, matrix)| matrix.determinant() != 0.0);
+    if grids.is_empty() {
+        return;
+    }
+
+    let device = wgpu_handles.device.clone();
+    for (grid, world_matrix) in &mut grids {
+        if grid.grid_buffer.is_none() {
+            create_grid_buffer(grid, &device, material_manager);
+        }
+        let grid_data = grid_data(grid, world_matrix, &scene.camera, rect);
+        staging_belt.write_buffer(
+            grid.grid_buffer.as_ref().unwrap(),
+            0,
+            bytemuck::cast_slice(&[grid_data]),
+            &device,
+        );
+    }
+
+    let timestamp_writes = wgpu_handles
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("GridRenderPass"));
+    let depth_texture = wgpu_handles.window_targets[&window]
+        .depth_texture
+        .as_ref()
+        .unwrap();
+    let mut render_pass =
+        staging_belt
+            .command_encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("GridRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+    rect.set_viewport(&mut render_pass);
+    let stats = &mut scene.render_stats;
+    for (grid, _) in &grids {
+        render_pass.set_bind_group(0, grid.bind_group.as_ref().unwrap(), &[]);
+        stats.bind_group_switches += 1;
+
+        let mut pipelines = vec![material_manager.grid_pipeline.as_ref().unwrap()];
+        if grid.show_axes {
+            pipelines.push(material_manager.grid_y_axis_pipeline.as_ref().unwrap());
+        }
+        for pipeline in pipelines {
+            render_pass.set_pipeline(pipeline);
+            render_pass.draw(0..3, 0..1);
+
+            stats.pipeline_switches += 1;
+            stats.draw_calls += 1;
+            stats.triangles += 1;
+        }
+    }
+}
@@ -5,27 +5,88 @@ use wgpu::{
     BindGroupLayoutEntry, BufferBinding, BufferDescriptor, BufferUsages, Device, ShaderStages,
 };
 
-use crate::renderer::wgpu_handles::WgpuHandles;
+use crate::{renderer::wgpu_handles::WgpuHandles, scene::object3d::ALL_LAYERS};
 
 use super::{
-    material::MaterialManager, staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
+    fog::FogData, material::MaterialManager,
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
 };
 
 pub enum Light {
     PointLight(PointLight),
+    SpotLight(SpotLight),
 }
 
 impl Light {
     pub fn point_light(position: glm::Vec4, color: glm::Vec3) -> Light {
-        Light::PointLight(PointLight {
-            data: PointLightData { position, color },
-            buffer: None,
-            bind_group: None,
-            view_info: ViewInfo {
-                view_info_buffer: None,
-            },
+        Self::point_light_with_range(position, color, 0.0)
+    }
+
+    /// A point light that fades out towards `range` and does not reach beyond it. The
+    /// deferred path only draws it over the part of the screen it reaches.
+    pub fn point_light_with_range(position: glm::Vec4, color: glm::Vec3, range: f32) -> Light {
+        Light::PointLight(PointLight::new(position, color, range))
+    }
+
+    /// A light that shines from `position` towards `direction` in a cone. It is at full
+    /// strength within `inner_angle` of the direction and fades out towards
+    /// `outer_angle`, both half angles in radians. Like a point light it does not reach
+    /// beyond a `range` above 0.
+    pub fn spot_light(
+        position: glm::Vec4,
+        direction: glm::Vec3,
+        color: glm::Vec3,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Light {
+        Light::SpotLight(SpotLight {
+            point_light: PointLight::new(position, color, range),
+            direction,
+            inner_angle,
+            outer_angle,
         })
     }
+
+    /// The position, color, range and GPU resources every kind of light has.
+    pub fn as_point_light(&self) -> &PointLight {
+        match self {
+            Light::PointLight(light) => light,
+            Light::SpotLight(light) => &light.point_light,
+        }
+    }
+
+    pub fn as_point_light_mut(&mut self) -> &mut PointLight {
+        match self {
+            Light::PointLight(light) => light,
+            Light::SpotLight(light) => &mut light.point_light,
+        }
+    }
+
+    /// The cone the shaders limit the light to, none for point lights.
+    pub(crate) fn cone_data(&self) -> ConeData {
+        match self {
+            Light::PointLight(_) => bytemuck::Zeroable::zeroed(),
+            Light::SpotLight(light) => {
+                let outer_angle = light.outer_angle.clamp(0.0, std::f32::consts::PI);
+                let inner_angle = light.inner_angle.clamp(0.0, outer_angle);
+                let direction = glm::normalize(light.direction);
+                ConeData {
+                    direction: glm::vec4(direction.x, direction.y, direction.z, 1.0),
+                    cone: glm::vec4(inner_angle.cos(), outer_angle.cos(), outer_angle.sin(), 0.0),
+                }
+            }
+        }
+    }
+
+    /// Let go of the buffers and bind group of the light, they are created again when
+    /// the lights are next written.
+    pub fn drop_gpu_resources(&mut self) {
+        let light = self.as_point_light_mut();
+        light.buffer = None;
+        light.bind_group = None;
+        light.view_info.view_info_buffer = None;
+    }
 }
 
 fn set_bind_group(
@@ -33,38 +94,35 @@ fn set_bind_group(
     point_light_bind_group_layout: &Option<BindGroupLayout>,
     wgpu_handles: &WgpuHandles,
 ) {
-    match light {
-        Light::PointLight(light) => {
-            if light.bind_group.is_none()
-                && light.buffer.is_some()
-                && light.view_info.view_info_buffer.is_some()
-            {
-                let _ = light
-                    .bind_group
-                    .insert(wgpu_handles.device.create_bind_group(&BindGroupDescriptor {
-                        label: Some("SomePhongMaterialBindGroup"),
-                        layout: point_light_bind_group_layout.as_ref().unwrap(),
-                        entries: &[
-                            BindGroupEntry {
-                                binding: 0,
-                                resource: wgpu::BindingResource::Buffer(BufferBinding {
-                                    buffer: light.buffer.as_ref().unwrap(),
-                                    offset: 0,
-                                    size: None,
-                                }),
-                            },
-                            BindGroupEntry {
-                                binding: 1,
-                                resource: wgpu::BindingResource::Buffer(BufferBinding {
-                                    buffer: light.view_info.view_info_buffer.as_ref().unwrap(),
-                                    offset: 0,
-                                    size: None,
-                                }),
-                            },
-                        ],
-                    }));
-            }
-        }
+    let light = light.as_point_light_mut();
+    if light.bind_group.is_none()
+        && light.buffer.is_some()
+        && light.view_info.view_info_buffer.is_some()
+    {
+        let _ = light
+            .bind_group
+            .insert(wgpu_handles.device.create_bind_group(&BindGroupDescriptor {
+                label: Some("SomePhongMaterialBindGroup"),
+                layout: point_light_bind_group_layout.as_ref().unwrap(),
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(BufferBinding {
+                            buffer: light.buffer.as_ref().unwrap(),
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Buffer(BufferBinding {
+                            buffer: light.view_info.view_info_buffer.as_ref().unwrap(),
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                ],
+            }));
     }
 }
 
@@ -73,6 +131,7 @@ pub trait LightManager {
     fn write_light(
         &self,
         light: &mut Light,
+        is_first: bool,
         wgpu_handles: &WgpuHandles,
         staging_buffer: &mut StagingBeltAndCommandEncoder,
     );
@@ -96,17 +155,17 @@ impl LightManager for MaterialManager {
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: NonZeroU64::new(8 * 4),
+                        min_binding_size: NonZeroU64::new(POINT_LIGHT_UNIFORM_SIZE),
                     },
                     count: None,
                 },
                 BindGroupLayoutEntry {
                     binding: 1,
-                    visibility: ShaderStages::VERTEX,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: NonZeroU64::new(4 * 4),
+                        min_binding_size: NonZeroU64::new(VIEW_INFO_DATA_SIZE),
                     },
                     count: None,
                 },
@@ -117,27 +176,32 @@ impl LightManager for MaterialManager {
     fn write_light(
         &self,
         light: &mut Light,
+        is_first: bool,
         wgpu_handles: &WgpuHandles,
         staging_buffer: &mut StagingBeltAndCommandEncoder,
     ) {
-        match light {
-            Light::PointLight(light) => {
-                let buffer = light.buffer.get_or_insert_with(|| {
-                    wgpu_handles.device.create_buffer(&BufferDescriptor {
-                        label: Some("SomePointLightBuffer"),
-                        size: 40,
-                        usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
-                        mapped_at_creation: false,
-                    })
-                });
-                staging_buffer.write_buffer(
-                    buffer,
-                    0,
-                    bytemuck::cast_slice(&[light.data]),
-                    &wgpu_handles.device,
-                );
-            }
-        }
+        let cone = light.cone_data();
+        let point_light = light.as_point_light_mut();
+        let buffer = point_light.buffer.get_or_insert_with(|| {
+            wgpu_handles.device.create_buffer(&BufferDescriptor {
+                label: Some("SomePointLightBuffer"),
+                size: POINT_LIGHT_UNIFORM_SIZE,
+                usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+                mapped_at_creation: false,
+            })
+        });
+        staging_buffer.write_buffer(
+            buffer,
+            0,
+            bytemuck::cast_slice(&[PointLightUniform {
+                data: point_light.data,
+                is_first: is_first as u32,
+                layers: point_light.layers,
+                _padding: [0; 2],
+                cone,
+            }]),
+            &wgpu_handles.device,
+        );
 
         set_bind_group(light, &self.point_light_bind_group_layout, wgpu_handles);
     }
@@ -149,24 +213,24 @@ impl LightManager for MaterialManager {
         wgpu_handles: &WgpuHandles,
         staging_buffer: &mut StagingBeltAndCommandEncoder,
     ) {
-        match light {
-            Light::PointLight(light) => {
-                let buffer = light.view_info.view_info_buffer.get_or_insert_with(|| {
-                    wgpu_handles.device.create_buffer(&BufferDescriptor {
-                        label: Some("SomeViewInfoBuffer"),
-                        size: 16,
-                        usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
-                        mapped_at_creation: false,
-                    })
-                });
-                staging_buffer.write_buffer(
-                    buffer,
-                    0,
-                    bytemuck::cast_slice(&[*view_info]),
-                    &wgpu_handles.device,
-                );
-            }
-        }
+        let buffer = light
+            .as_point_light_mut()
+            .view_info
+            .view_info_buffer
+            .get_or_insert_with(|| {
+                wgpu_handles.device.create_buffer(&BufferDescriptor {
+                    label: Some("SomeViewInfoBuffer"),
+                    size: VIEW_INFO_DATA_SIZE,
+                    usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+                    mapped_at_creation: false,
+                })
+            });
+        staging_buffer.write_buffer(
+            buffer,
+            0,
+            bytemuck::cast_slice(&[*view_info]),
+            &wgpu_handles.device,
+        );
 
         set_bind_group(light, &self.point_light_bind_group_layout, wgpu_handles);
     }
@@ -181,6 +245,37 @@ pub struct PointLight {
     pub buffer: Option<wgpu::Buffer>,
     pub bind_group: Option<wgpu::BindGroup>,
     pub view_info: ViewInfo,
+    /// bitmask of the layers of the objects it lights
+    pub layers: u32,
+}
+
+impl PointLight {
+    fn new(position: glm::Vec4, color: glm::Vec3, range: f32) -> PointLight {
+        PointLight {
+            data: PointLightData {
+                position,
+                color,
+                range,
+            },
+            buffer: None,
+            bind_group: None,
+            view_info: ViewInfo {
+                view_info_buffer: None,
+            },
+            layers: ALL_LAYERS,
+        }
+    }
+}
+
+/// A point light that only shines into a cone around `direction`.
+pub struct SpotLight {
+    pub point_light: PointLight,
+    /// the direction the cone opens towards, in world space
+    pub direction: glm::Vec3,
+    /// half angle in radians within which the light is at full strength
+    pub inner_angle: f32,
+    /// half angle in radians beyond which the light does not reach
+    pub outer_angle: f32,
 }
 
 #[repr(C)]
@@ -188,14 +283,49 @@ pub struct PointLight {
 pub struct PointLightData {
     pub position: glm::Vec4,
     pub color: glm::Vec3,
+    /// how far the light reaches, 0 for everywhere
+    pub range: f32,
+}
+
+/// What the shaders see of a point light.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct PointLightUniform {
+    data: PointLightData,
+    /// the first light of the scene draws the ambient light and the fog in the forward
+    /// path, the lights after it are added on top
+    is_first: u32,
+    layers: u32,
+    _padding: [u32; 2],
+    cone: ConeData,
+}
+
+/// The cone of a spot light as the shaders see it.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct ConeData {
+    /// the direction the cone opens towards, w is 1 for spot lights and 0 for point
+    /// lights, which are not limited to a cone
+    pub direction: glm::Vec4,
+    /// cosines of the inner and outer angle, and the sine of the outer angle
+    pub cone: glm::Vec4,
 }
 
+const POINT_LIGHT_UNIFORM_SIZE: u64 = std::mem::size_of::<PointLightUniform>() as u64;
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct ViewInfoData {
+    /// camera position, or the direction towards the camera with w = 0 for
+    /// orthographic cameras
     pub position: glm::Vec4,
+    /// camera position for both kinds of cameras, fog distances are measured from it
+    pub camera_position: glm::Vec4,
+    pub fog: FogData,
 }
 
+const VIEW_INFO_DATA_SIZE: u64 = std::mem::size_of::<ViewInfoData>() as u64;
+
 unsafe impl bytemuck::Zeroable for PointLightData {
     fn zeroed() -> Self {
         unsafe { core::mem::zeroed() }
@@ -204,6 +334,17 @@ unsafe impl bytemuck::Zeroable for PointLightData {
 
 unsafe impl bytemuck::Pod for PointLightData {}
 
+unsafe impl bytemuck::Zeroable for ConeData {
+    fn zeroed() -> Self {
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+unsafe impl bytemuck::Pod for ConeData {}
+
+unsafe impl bytemuck::Zeroable for PointLightUniform {}
+unsafe impl bytemuck::Pod for PointLightUniform {}
+
 unsafe impl bytemuck::Zeroable for ViewInfoData {
     fn zeroed() -> Self {
         unsafe { core::mem::zeroed() }
@@ -211,3 +352,31 @@ unsafe impl bytemuck::Zeroable for ViewInfoData {
 }
 
 unsafe impl bytemuck::Pod for ViewInfoData {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_spot_lights_have_a_cone() {
+        let point_light =
+            Light::point_light(glm::vec4(0.0, 1.0, 0.0, 1.0), glm::vec3(1.0, 1.0, 1.0));
+        assert_eq!(point_light.cone_data().direction.w, 0.0);
+
+        let spot_light = Light::spot_light(
+            glm::vec4(0.0, 1.0, 0.0, 1.0),
+            glm::vec3(0.0, -2.0, 0.0),
+            glm::vec3(1.0, 1.0, 1.0),
+            5.0,
+            0.8,
+            0.5,
+        );
+        let cone = spot_light.cone_data();
+        assert_eq!(cone.direction.w, 1.0);
+        assert_eq!(cone.direction.y, -1.0);
+        // the inner angle is kept within the outer one
+        assert_eq!(cone.cone.x, cone.cone.y);
+        assert!((cone.cone.y - 0.5f32.cos()).abs() < 1e-6);
+        assert!((cone.cone.z - 0.5f32.sin()).abs() < 1e-6);
+    }
+}
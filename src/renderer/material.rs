@@ -1,41 +1,141 @@
-use std::{collections::HashMap, num::NonZeroU64};
+use std::{
+    collections::HashMap,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
 use glm::Vector3;
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 use wgpu::{
-    include_wgsl, vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry,
-    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, Buffer, BufferBinding,
-    BufferDescriptor, BufferUsages, Device, Face, FragmentState, MultisampleState,
-    PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor,
-    ShaderModule, ShaderStages, SurfaceCapabilities, Texture, VertexBufferLayout, VertexState,
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BlendComponent, BlendFactor, BlendOperation,
+    BlendState, Buffer, BufferBinding, BufferDescriptor, BufferUsages, ColorTargetState,
+    ColorWrites, ComputePipeline, Device, Face, FragmentState, MultisampleState,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor, Sampler,
+    ShaderModule, ShaderStages, SurfaceCapabilities, Texture, TextureFormat, VertexBufferLayout,
+    VertexState,
 };
 
 use super::{
-    light::LightManager, staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
+    ambient_occlusion::AmbientOcclusionManager,
+    capture::CaptureManager,
+    clustered::ClusteredManager,
+    deferred::{g_buffer_color_targets, DeferredManager},
+    depth_texture::scene_depth_stencil_state,
+    grid::GridManager,
+    light::LightManager,
+    outline::OutlineManager,
+    particles::ParticleManager,
+    picking::PickingManager,
+    sprite::SpriteManager,
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
+    text::TextManager,
+    texture::TextureManager,
+    viewport::ViewportManager,
     wgpu_handles::WgpuHandles,
 };
+use crate::{
+    importer::texture::{load_texture, TextureData},
+    scene::{
+        morph::MorphWeightData,
+        object3d::MATRIX_DATA_SIZE,
+        skin::{SkinVertex, MAX_JOINTS},
+    },
+};
 
 pub struct MaterialManager {
     pub shaders: Vec<ShaderModule>,
     pub render_pipelines: Vec<RenderPipeline>,
+    /// `render_pipelines` for each `Deformation` after `Deformation::None`
+    pub deformed_render_pipelines: Vec<Vec<RenderPipeline>>,
+    /// for each material, the pipelines drawing into the G-buffer for each `Deformation`
+    pub g_buffer_pipelines: Vec<Vec<RenderPipeline>>,
+    /// for each material, the pipelines lighting with the light clusters for each
+    /// `Deformation`
+    pub clustered_pipelines: Vec<Vec<RenderPipeline>>,
     pub material_bind_group_layouts: Vec<BindGroupLayout>,
     pub matrix_bind_group_layout: Option<BindGroupLayout>,
     pub point_light_bind_group_layout: Option<BindGroupLayout>,
+    pub light_clusters_bind_group_layout: Option<BindGroupLayout>,
+    /// for each `Deformation` after `Deformation::None`
+    pub deformation_bind_group_layouts: Vec<BindGroupLayout>,
     pub view_info_buffer: Option<Buffer>,
     pub view_info_bind_group: Option<BindGroup>,
     pub textures: HashMap<String, Texture>,
+    pub texture_sampler: Option<Sampler>,
+    /// for each `Deformation`
+    pub picking_pipelines: Vec<RenderPipeline>,
+    /// for each `Deformation`
+    pub outline_mask_pipelines: Vec<RenderPipeline>,
+    pub outline_edge_pipeline: Option<RenderPipeline>,
+    pub outline_bind_group_layout: Option<BindGroupLayout>,
+    pub clear_rect_pipeline: Option<RenderPipeline>,
+    pub capture_blit_pipeline: Option<RenderPipeline>,
+    pub capture_blit_bind_group_layout: Option<BindGroupLayout>,
+    pub sprite_pipeline: Option<RenderPipeline>,
+    pub sprite_camera_bind_group_layout: Option<BindGroupLayout>,
+    pub sprite_atlas_bind_group_layout: Option<BindGroupLayout>,
+    pub text_pipeline: Option<RenderPipeline>,
+    pub text_bind_group_layout: Option<BindGroupLayout>,
+    pub particle_compute_pipeline: Option<ComputePipeline>,
+    pub particle_compute_bind_group_layout: Option<BindGroupLayout>,
+    pub particle_additive_pipeline: Option<RenderPipeline>,
+    pub particle_alpha_pipeline: Option<RenderPipeline>,
+    pub particle_camera_bind_group_layout: Option<BindGroupLayout>,
+    pub grid_pipeline: Option<RenderPipeline>,
+    pub grid_y_axis_pipeline: Option<RenderPipeline>,
+    pub grid_bind_group_layout: Option<BindGroupLayout>,
+    pub deferred_ambient_pipeline: Option<RenderPipeline>,
+    pub deferred_light_pipeline: Option<RenderPipeline>,
+    pub deferred_view_bind_group_layout: Option<BindGroupLayout>,
+    pub g_buffer_bind_group_layout: Option<BindGroupLayout>,
+    /// the occlusion and its horizontal and vertical blur
+    pub ambient_occlusion_pipelines: Vec<RenderPipeline>,
+    pub ambient_occlusion_bind_group_layout: Option<BindGroupLayout>,
+    pub ambient_occlusion_apply_pipeline: Option<RenderPipeline>,
+    pub ambient_occlusion_apply_bind_group_layout: Option<BindGroupLayout>,
+    pub light_assignment_pipeline: Option<ComputePipeline>,
+    pub light_assignment_bind_group_layout: Option<BindGroupLayout>,
+    /// format the material pipelines draw to, offscreen targets are created with it
+    pub color_format: Option<TextureFormat>,
 }
 
 #[derive(Debug)]
 pub enum Material {
     PhongMaterial(PhongMaterial),
     PhongMaterialWithTexture(PhongMaterialWithTexture),
+    TerrainMaterial(TerrainMaterial),
 }
 
 #[derive(Clone, EnumIter)]
 pub enum MaterialType {
     PhongMaterial,
     PhongMaterialWithTexture,
+    TerrainMaterial,
+}
+
+/// How the vertex shader moves the vertices of a mesh before it is drawn. Each has its
+/// own pipelines, and the last bind group holds what it needs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum Deformation {
+    None,
+    Skinned,
+    Morphed,
+    SkinnedAndMorphed,
+}
+
+impl Deformation {
+    pub fn is_skinned(&self) -> bool {
+        matches!(self, Deformation::Skinned | Deformation::SkinnedAndMorphed)
+    }
+
+    pub fn is_morphed(&self) -> bool {
+        matches!(self, Deformation::Morphed | Deformation::SkinnedAndMorphed)
+    }
 }
 
 impl Material {
@@ -43,9 +143,60 @@ impl Material {
         match (self, compare_to) {
             (Material::PhongMaterial(_), MaterialType::PhongMaterial) => true,
             (Material::PhongMaterialWithTexture(_), MaterialType::PhongMaterialWithTexture) => true,
+            (Material::TerrainMaterial(_), MaterialType::TerrainMaterial) => true,
             _ => false,
         }
     }
+
+    /// A material with the same settings and textures for another mesh, it is uploaded
+    /// again when that mesh is drawn.
+    pub fn duplicate(&self) -> Material {
+        match self {
+            Material::PhongMaterial(mat) => Material::PhongMaterial(PhongMaterial {
+                data: mat.data,
+                buffer: None,
+                bind_group: None,
+            }),
+            Material::PhongMaterialWithTexture(mat) => {
+                Material::PhongMaterialWithTexture(PhongMaterialWithTexture {
+                    data: mat.data,
+                    map_kd: mat.map_kd.clone(),
+                    texture_loaded: false,
+                    texture: None,
+                    bound_texture: None,
+                    buffer: None,
+                    bind_group: None,
+                })
+            }
+            Material::TerrainMaterial(mat) => Material::TerrainMaterial(TerrainMaterial {
+                data: mat.data,
+                layers: mat.layers.clone(),
+                buffer: None,
+                bind_group: None,
+            }),
+        }
+    }
+
+    /// Let go of the buffers, bind groups and textures of the material, they are
+    /// uploaded again when it is next drawn. Terrain layers are uploaded again for all
+    /// the chunks that share them.
+    pub fn drop_gpu_resources(&mut self) {
+        if let Material::TerrainMaterial(mat) = self {
+            *mat.layers.uploaded.lock().unwrap() = None;
+        }
+        *self = self.duplicate();
+    }
+
+    /// Name of the render texture this material samples, if any.
+    pub fn render_texture_name(&self) -> Option<&str> {
+        match self {
+            Material::PhongMaterialWithTexture(PhongMaterialWithTexture {
+                map_kd: TextureSource::RenderTexture(name),
+                ..
+            }) => Some(name),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -69,11 +220,21 @@ pub struct PhongMaterialData {
 unsafe impl bytemuck::Zeroable for PhongMaterialData {}
 unsafe impl bytemuck::Pod for PhongMaterialData {}
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextureSource {
+    File(PathBuf),
+    /// the offscreen texture of the `RenderTexture` with this name
+    RenderTexture(String),
+}
+
 #[derive(Debug)]
 pub struct PhongMaterialWithTexture {
     pub data: PhongMaterialData,
-    map_kd: String,
+    map_kd: TextureSource,
     texture_loaded: bool,
+    /// loaded from a file, or white while a render texture is not drawn yet
+    pub texture: Option<Texture>,
+    bound_texture: Option<wgpu::Id<Texture>>,
     pub buffer: Option<Buffer>,
     pub bind_group: Option<BindGroup>,
 }
@@ -90,6 +251,65 @@ impl PartialEq for PhongMaterialWithTexture {
 
 impl Eq for PhongMaterialWithTexture {}
 
+/// Up to four textures blended across a terrain by the channels of a splat map, red
+/// weighs the first layer and alpha the fourth. The chunks of a terrain share one, so the
+/// textures are uploaded once for all of them.
+#[derive(Debug)]
+pub struct TerrainLayers {
+    /// stretched across the whole terrain, a splat map that fails to load shows the first
+    /// layer everywhere
+    pub splat_map: PathBuf,
+    /// the channels of the splat map past the last layer are ignored
+    pub layer_maps: Vec<PathBuf>,
+    /// how many times each layer repeats across the terrain, read when the layers are
+    /// uploaded
+    pub tiling: [f32; 4],
+    uploaded: Mutex<Option<Arc<UploadedTerrainLayers>>>,
+}
+
+#[derive(Debug)]
+struct UploadedTerrainLayers {
+    splat_map: Texture,
+    /// always four, the missing layers are white
+    layer_maps: Vec<Texture>,
+    buffer: Buffer,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TerrainLayerData {
+    tiling: [f32; 4],
+    layer_count: u32,
+    _padding: [u32; 3],
+}
+
+unsafe impl bytemuck::Zeroable for TerrainLayerData {}
+unsafe impl bytemuck::Pod for TerrainLayerData {}
+
+impl TerrainLayers {
+    pub fn new(splat_map: impl Into<PathBuf>, layer_maps: Vec<PathBuf>) -> Self {
+        assert!(
+            layer_maps.len() <= 4,
+            "a terrain can blend four layers at most"
+        );
+        TerrainLayers {
+            splat_map: splat_map.into(),
+            layer_maps,
+            tiling: [16.0; 4],
+            uploaded: Mutex::new(None),
+        }
+    }
+}
+
+/// Phong shading with the color blended from the layers of a terrain.
+#[derive(Debug)]
+pub struct TerrainMaterial {
+    pub data: PhongMaterialData,
+    pub layers: Arc<TerrainLayers>,
+    pub buffer: Option<Buffer>,
+    pub bind_group: Option<BindGroup>,
+}
+
 impl PhongMaterial {
     pub fn new_without_texture(
         ka: Vector3<f32>,
@@ -116,7 +336,7 @@ impl PhongMaterial {
         kd: Vector3<f32>,
         ks: Vector3<f32>,
         shininess: f32,
-        map_kd: &String,
+        map_kd: impl Into<PathBuf>,
     ) -> PhongMaterialWithTexture {
         PhongMaterialWithTexture {
             data: PhongMaterialData {
@@ -127,8 +347,40 @@ impl PhongMaterial {
                 _padding1: 0,
                 _padding2: 0,
             },
-            map_kd: map_kd.to_owned(),
+            map_kd: TextureSource::File(map_kd.into()),
             texture_loaded: false,
+            texture: None,
+            bound_texture: None,
+            buffer: None,
+            bind_group: None,
+        }
+    }
+
+    /// Textured material sampling a render texture of the scene.
+    pub fn new_with_render_texture(
+        ka: Vector3<f32>,
+        kd: Vector3<f32>,
+        ks: Vector3<f32>,
+        shininess: f32,
+        render_texture_name: impl Into<String>,
+    ) -> PhongMaterialWithTexture {
+        PhongMaterialWithTexture {
+            map_kd: TextureSource::RenderTexture(render_texture_name.into()),
+            ..Self::new_with_texture(ka, kd, ks, shininess, PathBuf::new())
+        }
+    }
+
+    /// Material for the chunks of a terrain, `layers` is shared between them.
+    pub fn new_terrain(
+        ka: Vector3<f32>,
+        kd: Vector3<f32>,
+        ks: Vector3<f32>,
+        shininess: f32,
+        layers: Arc<TerrainLayers>,
+    ) -> TerrainMaterial {
+        TerrainMaterial {
+            data: Self::new_without_texture(ka, kd, ks, shininess).data,
+            layers,
             buffer: None,
             bind_group: None,
         }
@@ -140,12 +392,49 @@ impl MaterialManager {
         MaterialManager {
             shaders: vec![],
             render_pipelines: vec![],
+            deformed_render_pipelines: vec![],
+            g_buffer_pipelines: vec![],
+            clustered_pipelines: vec![],
             material_bind_group_layouts: vec![],
             matrix_bind_group_layout: None,
             point_light_bind_group_layout: None,
+            light_clusters_bind_group_layout: None,
+            deformation_bind_group_layouts: vec![],
             view_info_buffer: None,
             view_info_bind_group: None,
             textures: HashMap::new(),
+            texture_sampler: None,
+            picking_pipelines: vec![],
+            outline_mask_pipelines: vec![],
+            outline_edge_pipeline: None,
+            outline_bind_group_layout: None,
+            clear_rect_pipeline: None,
+            capture_blit_pipeline: None,
+            capture_blit_bind_group_layout: None,
+            sprite_pipeline: None,
+            sprite_camera_bind_group_layout: None,
+            sprite_atlas_bind_group_layout: None,
+            text_pipeline: None,
+            text_bind_group_layout: None,
+            particle_compute_pipeline: None,
+            particle_compute_bind_group_layout: None,
+            particle_additive_pipeline: None,
+            particle_alpha_pipeline: None,
+            particle_camera_bind_group_layout: None,
+            grid_pipeline: None,
+            grid_y_axis_pipeline: None,
+            grid_bind_group_layout: None,
+            deferred_ambient_pipeline: None,
+            deferred_light_pipeline: None,
+            deferred_view_bind_group_layout: None,
+            g_buffer_bind_group_layout: None,
+            ambient_occlusion_pipelines: vec![],
+            ambient_occlusion_bind_group_layout: None,
+            ambient_occlusion_apply_pipeline: None,
+            ambient_occlusion_apply_bind_group_layout: None,
+            light_assignment_pipeline: None,
+            light_assignment_bind_group_layout: None,
+            color_format: None,
         }
     }
 
@@ -153,55 +442,358 @@ impl MaterialManager {
         let shader_module =
             device.create_shader_module(include_wgsl!("./shaders/phong-model.wgsl"));
 
-        let matrix_bind_group_layout = self.matrix_bind_group_layout.as_ref().unwrap();
+        let material_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("PhongMaterialBindGroupLayout"),
+                entries: &[phong_material_data_layout_entry()],
+            });
+
         let point_light_bind_group_layout = self.point_light_bind_group_layout.as_ref().unwrap();
+        let light_clusters_bind_group_layout =
+            self.light_clusters_bind_group_layout.as_ref().unwrap();
+        let targets = [Some(forward_color_target(surface_capabilities))];
+        let render_pipeline = self.create_phong_pipeline(
+            device,
+            &targets,
+            &shader_module,
+            &material_bind_group_layout,
+            point_light_bind_group_layout,
+            "fragment_main",
+            Deformation::None,
+        );
+        let deformed_render_pipelines = Deformation::iter()
+            .skip(1)
+            .map(|deformation| {
+                self.create_phong_pipeline(
+                    device,
+                    &targets,
+                    &shader_module,
+                    &material_bind_group_layout,
+                    point_light_bind_group_layout,
+                    "fragment_main",
+                    deformation,
+                )
+            })
+            .collect();
+        let g_buffer_pipelines = Deformation::iter()
+            .map(|deformation| {
+                self.create_phong_pipeline(
+                    device,
+                    &g_buffer_color_targets(),
+                    &shader_module,
+                    &material_bind_group_layout,
+                    point_light_bind_group_layout,
+                    "fragment_main_g_buffer",
+                    deformation,
+                )
+            })
+            .collect();
+        let clustered_pipelines = Deformation::iter()
+            .map(|deformation| {
+                self.create_phong_pipeline(
+                    device,
+                    &targets,
+                    &shader_module,
+                    &material_bind_group_layout,
+                    light_clusters_bind_group_layout,
+                    "fragment_main_clustered",
+                    deformation,
+                )
+            })
+            .collect();
+
+        self.shaders.push(shader_module);
+        self.render_pipelines.push(render_pipeline);
+        self.deformed_render_pipelines
+            .push(deformed_render_pipelines);
+        self.g_buffer_pipelines.push(g_buffer_pipelines);
+        self.clustered_pipelines.push(clustered_pipelines);
+        self.material_bind_group_layouts
+            .push(material_bind_group_layout);
+    }
+
+    fn add_phong_material_with_texture(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    ) {
+        let shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/phong-model.wgsl"));
 
         let material_bind_group_layout =
             device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("PhongMaterialBindGroupLayout"),
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: NonZeroU64::new(16 * 4),
+                label: Some("PhongMaterialWithTextureBindGroupLayout"),
+                entries: &[
+                    phong_material_data_layout_entry(),
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let point_light_bind_group_layout = self.point_light_bind_group_layout.as_ref().unwrap();
+        let light_clusters_bind_group_layout =
+            self.light_clusters_bind_group_layout.as_ref().unwrap();
+        let targets = [Some(forward_color_target(surface_capabilities))];
+        let render_pipeline = self.create_phong_pipeline(
+            device,
+            &targets,
+            &shader_module,
+            &material_bind_group_layout,
+            point_light_bind_group_layout,
+            "fragment_main_textured",
+            Deformation::None,
+        );
+        let deformed_render_pipelines = Deformation::iter()
+            .skip(1)
+            .map(|deformation| {
+                self.create_phong_pipeline(
+                    device,
+                    &targets,
+                    &shader_module,
+                    &material_bind_group_layout,
+                    point_light_bind_group_layout,
+                    "fragment_main_textured",
+                    deformation,
+                )
+            })
+            .collect();
+        let g_buffer_pipelines = Deformation::iter()
+            .map(|deformation| {
+                self.create_phong_pipeline(
+                    device,
+                    &g_buffer_color_targets(),
+                    &shader_module,
+                    &material_bind_group_layout,
+                    point_light_bind_group_layout,
+                    "fragment_main_textured_g_buffer",
+                    deformation,
+                )
+            })
+            .collect();
+        let clustered_pipelines = Deformation::iter()
+            .map(|deformation| {
+                self.create_phong_pipeline(
+                    device,
+                    &targets,
+                    &shader_module,
+                    &material_bind_group_layout,
+                    light_clusters_bind_group_layout,
+                    "fragment_main_textured_clustered",
+                    deformation,
+                )
+            })
+            .collect();
+
+        self.shaders.push(shader_module);
+        self.render_pipelines.push(render_pipeline);
+        self.deformed_render_pipelines
+            .push(deformed_render_pipelines);
+        self.g_buffer_pipelines.push(g_buffer_pipelines);
+        self.clustered_pipelines.push(clustered_pipelines);
+        self.material_bind_group_layouts
+            .push(material_bind_group_layout);
+    }
+
+    fn add_terrain_material(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    ) {
+        let shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/phong-model.wgsl"));
+
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let material_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("TerrainMaterialBindGroupLayout"),
+                entries: &[
+                    phong_material_data_layout_entry(),
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    texture_entry(3),
+                    texture_entry(4),
+                    texture_entry(5),
+                    texture_entry(6),
+                    texture_entry(7),
+                    BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: NonZeroU64::new(
+                                std::mem::size_of::<TerrainLayerData>() as u64,
+                            ),
+                        },
+                        count: None,
+                    },
+                ],
             });
 
+        let point_light_bind_group_layout = self.point_light_bind_group_layout.as_ref().unwrap();
+        let light_clusters_bind_group_layout =
+            self.light_clusters_bind_group_layout.as_ref().unwrap();
+        let targets = [Some(forward_color_target(surface_capabilities))];
+        let render_pipeline = self.create_phong_pipeline(
+            device,
+            &targets,
+            &shader_module,
+            &material_bind_group_layout,
+            point_light_bind_group_layout,
+            "fragment_main_terrain",
+            Deformation::None,
+        );
+        let deformed_render_pipelines = Deformation::iter()
+            .skip(1)
+            .map(|deformation| {
+                self.create_phong_pipeline(
+                    device,
+                    &targets,
+                    &shader_module,
+                    &material_bind_group_layout,
+                    point_light_bind_group_layout,
+                    "fragment_main_terrain",
+                    deformation,
+                )
+            })
+            .collect();
+        let g_buffer_pipelines = Deformation::iter()
+            .map(|deformation| {
+                self.create_phong_pipeline(
+                    device,
+                    &g_buffer_color_targets(),
+                    &shader_module,
+                    &material_bind_group_layout,
+                    point_light_bind_group_layout,
+                    "fragment_main_terrain_g_buffer",
+                    deformation,
+                )
+            })
+            .collect();
+        let clustered_pipelines = Deformation::iter()
+            .map(|deformation| {
+                self.create_phong_pipeline(
+                    device,
+                    &targets,
+                    &shader_module,
+                    &material_bind_group_layout,
+                    light_clusters_bind_group_layout,
+                    "fragment_main_terrain_clustered",
+                    deformation,
+                )
+            })
+            .collect();
+
+        self.shaders.push(shader_module);
+        self.render_pipelines.push(render_pipeline);
+        self.deformed_render_pipelines
+            .push(deformed_render_pipelines);
+        self.g_buffer_pipelines.push(g_buffer_pipelines);
+        self.clustered_pipelines.push(clustered_pipelines);
+        self.material_bind_group_layouts
+            .push(material_bind_group_layout);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_phong_pipeline(
+        &self,
+        device: &Device,
+        targets: &[Option<ColorTargetState>],
+        shader_module: &ShaderModule,
+        material_bind_group_layout: &BindGroupLayout,
+        lights_bind_group_layout: &BindGroupLayout,
+        fragment_entry_point: &str,
+        deformation: Deformation,
+    ) -> RenderPipeline {
+        let matrix_bind_group_layout = self.matrix_bind_group_layout.as_ref().unwrap();
+
         // Bind Groups
         // Group 0 Binding 0: Object to Camera Matrix Uniform
         // Group 1 Binding 0: PhongMaterialData
-        // Group 2 Binding 0: PointLigthData
+        // Group 1 Binding 1, 2: Diffuse Texture and Sampler (textured only)
+        // Group 1 Binding 3 - 8: Terrain Layers, Splat Map and Tiling (terrain only)
+        // Group 2 Binding 0: PointLigthData (one light at a time only)
+        // Group 2 Binding 1: ViewInfoData
+        // Group 2 Binding 2 - 4: Lights, Light Indices and ClusterData (clustered only)
+        // Group 3 Binding 0: Joint Matrices (skinned only)
+        // Group 3 Binding 1, 2: Morph Deltas and Weights (morphed only)
+        let mut bind_group_layouts = vec![
+            matrix_bind_group_layout,
+            material_bind_group_layout,
+            lights_bind_group_layout,
+        ];
+        bind_group_layouts.extend(self.get_deformation_bind_group_layout(deformation));
         let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("PhongShadingPipelineLayout"),
-            bind_group_layouts: &[
-                matrix_bind_group_layout,
-                &material_bind_group_layout,
-                point_light_bind_group_layout,
-            ],
+            bind_group_layouts: &bind_group_layouts,
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("PhongShadingRenderPipeline"),
+        let vertex_attributes = vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Float32x4];
+        let skin_vertex_attributes = vertex_attr_array![3 => Uint32x4, 4 => Float32x4];
+        let mut buffers = vec![VertexBufferLayout {
+            array_stride: 48,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &vertex_attributes,
+        }];
+        // joints and weights come from the second vertex buffer
+        if deformation.is_skinned() {
+            buffers.push(VertexBufferLayout {
+                array_stride: std::mem::size_of::<SkinVertex>() as u64,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &skin_vertex_attributes,
+            });
+        }
+        let (label, entry_point) = match deformation {
+            Deformation::None => ("PhongShadingRenderPipeline", "vertex_main"),
+            Deformation::Skinned => ("SkinnedPhongShadingRenderPipeline", "vertex_main_skinned"),
+            Deformation::Morphed => ("MorphedPhongShadingRenderPipeline", "vertex_main_morphed"),
+            Deformation::SkinnedAndMorphed => (
+                "SkinnedMorphedPhongShadingRenderPipeline",
+                "vertex_main_skinned_morphed",
+            ),
+        };
+
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
             layout: Some(&render_pipeline_layout),
             vertex: VertexState {
-                module: &shader_module,
-                entry_point: "vertex_main",
-                buffers: &[VertexBufferLayout {
-                    array_stride: 48,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Float32x4],
-                }],
+                module: shader_module,
+                entry_point,
+                buffers: &buffers,
             },
             // TODO: I don't know if this will work or not.
             fragment: Some(FragmentState {
-                module: &shader_module,
-                entry_point: "fragment_main",
-                targets: &[Some(surface_capabilities.formats[0].into())],
+                module: shader_module,
+                entry_point: fragment_entry_point,
+                targets,
             }),
             primitive: PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
@@ -213,13 +805,9 @@ impl MaterialManager {
                 conservative: false,
             },
             multisample: MultisampleState::default(),
-            depth_stencil: None,
+            depth_stencil: Some(scene_depth_stencil_state(true)),
             multiview: None,
-        });
-
-        self.render_pipelines.push(render_pipeline);
-        self.material_bind_group_layouts
-            .push(material_bind_group_layout);
+        })
     }
 
     fn create_common_bind_group_layouts(&mut self, device: &Device) {
@@ -232,24 +820,118 @@ impl MaterialManager {
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: NonZeroU64::new(2 * 16 * 4),
+                        min_binding_size: NonZeroU64::new(MATRIX_DATA_SIZE),
                     },
                     count: None,
                 }],
             }));
 
         self.point_light_bind_group_layout = Some(self.get_point_light_bind_group_layout(device));
+        self.light_clusters_bind_group_layout =
+            Some(self.get_light_clusters_bind_group_layout(device));
+        self.deformation_bind_group_layouts = Deformation::iter()
+            .skip(1)
+            .map(|deformation| {
+                let mut entries = vec![];
+                if deformation.is_skinned() {
+                    entries.push(joint_matrices_layout_entry());
+                }
+                if deformation.is_morphed() {
+                    entries.extend(morph_layout_entries());
+                }
+                device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some("DeformationBindGroupLayout"),
+                    entries: &entries,
+                })
+            })
+            .collect();
+        self.texture_sampler = Some(self.create_texture_sampler(device));
     }
 
     pub fn populate(&mut self, device: &Device, surface_capabilities: &SurfaceCapabilities) {
+        self.color_format = Some(surface_capabilities.formats[0]);
         self.create_common_bind_group_layouts(device);
         self.add_phong_material(device, surface_capabilities);
+        self.add_phong_material_with_texture(device, surface_capabilities);
+        self.add_terrain_material(device, surface_capabilities);
+        self.add_deferred_pipelines(device, surface_capabilities);
+        self.add_ambient_occlusion_pipelines(device, surface_capabilities);
+        self.add_light_assignment_pipeline(device);
+        self.picking_pipelines = self.create_picking_pipelines(device);
+        self.add_outline_pipelines(device, surface_capabilities);
+        self.clear_rect_pipeline =
+            Some(self.create_clear_rect_pipeline(device, surface_capabilities));
+        self.add_capture_blit_pipeline(device, surface_capabilities);
+        self.add_sprite_pipeline(device, surface_capabilities);
+        self.add_text_pipeline(device, surface_capabilities);
+        self.add_particle_pipelines(device, surface_capabilities);
+        self.add_grid_pipelines(device, surface_capabilities);
     }
 
     pub fn get_pipeline_for_material_type(&self, material_type: &MaterialType) -> &RenderPipeline {
         match material_type {
             MaterialType::PhongMaterial => self.render_pipelines.get(0).unwrap(),
-            MaterialType::PhongMaterialWithTexture => todo!(),
+            MaterialType::PhongMaterialWithTexture => self.render_pipelines.get(1).unwrap(),
+            MaterialType::TerrainMaterial => self.render_pipelines.get(2).unwrap(),
+        }
+    }
+
+    /// The pipeline for meshes with materials of this type that are deformed this way.
+    pub fn get_deformed_pipeline_for_material_type(
+        &self,
+        material_type: &MaterialType,
+        deformation: Deformation,
+    ) -> &RenderPipeline {
+        let material_index = match material_type {
+            MaterialType::PhongMaterial => 0,
+            MaterialType::PhongMaterialWithTexture => 1,
+            MaterialType::TerrainMaterial => 2,
+        };
+        match deformation {
+            Deformation::None => self.get_pipeline_for_material_type(material_type),
+            _ => &self.deformed_render_pipelines[material_index][deformation as usize - 1],
+        }
+    }
+
+    /// The pipeline drawing meshes with materials of this type that are deformed this
+    /// way into the G-buffer.
+    pub fn get_g_buffer_pipeline_for_material_type(
+        &self,
+        material_type: &MaterialType,
+        deformation: Deformation,
+    ) -> &RenderPipeline {
+        let material_index = match material_type {
+            MaterialType::PhongMaterial => 0,
+            MaterialType::PhongMaterialWithTexture => 1,
+            MaterialType::TerrainMaterial => 2,
+        };
+        &self.g_buffer_pipelines[material_index][deformation as usize]
+    }
+
+    /// The pipeline drawing meshes with materials of this type that are deformed this
+    /// way with all the lights of their clusters at once.
+    pub fn get_clustered_pipeline_for_material_type(
+        &self,
+        material_type: &MaterialType,
+        deformation: Deformation,
+    ) -> &RenderPipeline {
+        let material_index = match material_type {
+            MaterialType::PhongMaterial => 0,
+            MaterialType::PhongMaterialWithTexture => 1,
+            MaterialType::TerrainMaterial => 2,
+        };
+        &self.clustered_pipelines[material_index][deformation as usize]
+    }
+
+    pub fn get_deformation_bind_group_layout(
+        &self,
+        deformation: Deformation,
+    ) -> Option<&BindGroupLayout> {
+        match deformation {
+            Deformation::None => None,
+            _ => self
+                .deformation_bind_group_layouts
+                .get(deformation as usize - 1),
         }
     }
 
@@ -290,7 +972,281 @@ impl MaterialManager {
                     &wgpu_handles.device,
                 );
             }
-            Material::PhongMaterialWithTexture(_) => todo!(),
+            Material::PhongMaterialWithTexture(mat) => {
+                let texture = match &mat.map_kd {
+                    TextureSource::File(map_kd) => {
+                        if !mat.texture_loaded {
+                            mat.texture_loaded = true;
+                            mat.texture = Some(self.upload_texture_file(
+                                map_kd,
+                                [255, 255, 255, 255],
+                                false,
+                                wgpu_handles,
+                            ));
+                        }
+                        mat.texture.as_ref().unwrap()
+                    }
+                    TextureSource::RenderTexture(name) => {
+                        match wgpu_handles.offscreen_targets.get(name) {
+                            Some(target) => &target.texture,
+                            None => mat.texture.get_or_insert_with(|| {
+                                self.upload_texture(
+                                    &TextureData::solid_color([255, 255, 255, 255]),
+                                    wgpu_handles,
+                                )
+                                .unwrap()
+                            }),
+                        }
+                    }
+                };
+                // offscreen targets are recreated when they are resized
+                if mat.bound_texture != Some(texture.global_id()) {
+                    mat.bound_texture = Some(texture.global_id());
+                    mat.bind_group = None;
+                }
+
+                let buffer = mat.buffer.get_or_insert_with(|| {
+                    wgpu_handles.device.create_buffer(&BufferDescriptor {
+                        label: Some("SomePhongMaterialWithTextureBuffer"),
+                        size: 64,
+                        usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+                        mapped_at_creation: false,
+                    })
+                });
+                mat.bind_group.get_or_insert_with(|| {
+                    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                    wgpu_handles.device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("SomePhongMaterialWithTextureBindGroup"),
+                        layout: self.material_bind_group_layouts.get(1).unwrap(),
+                        entries: &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: wgpu::BindingResource::Buffer(BufferBinding {
+                                    buffer,
+                                    offset: 0,
+                                    size: None,
+                                }),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: wgpu::BindingResource::TextureView(&texture_view),
+                            },
+                            BindGroupEntry {
+                                binding: 2,
+                                resource: wgpu::BindingResource::Sampler(
+                                    self.texture_sampler.as_ref().unwrap(),
+                                ),
+                            },
+                        ],
+                    })
+                });
+                staging_belt.write_buffer(
+                    buffer,
+                    0,
+                    bytemuck::cast_slice(&[mat.data]),
+                    &wgpu_handles.device,
+                );
+            }
+            Material::TerrainMaterial(mat) => {
+                let layers = mat
+                    .layers
+                    .uploaded
+                    .lock()
+                    .unwrap()
+                    .get_or_insert_with(|| {
+                        Arc::new(self.upload_terrain_layers(&mat.layers, wgpu_handles))
+                    })
+                    .clone();
+
+                let buffer = mat.buffer.get_or_insert_with(|| {
+                    wgpu_handles.device.create_buffer(&BufferDescriptor {
+                        label: Some("SomeTerrainMaterialBuffer"),
+                        size: 64,
+                        usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+                        mapped_at_creation: false,
+                    })
+                });
+                mat.bind_group.get_or_insert_with(|| {
+                    let views: Vec<_> = layers
+                        .layer_maps
+                        .iter()
+                        .chain([&layers.splat_map])
+                        .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+                        .collect();
+                    let mut entries = vec![
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Buffer(BufferBinding {
+                                buffer,
+                                offset: 0,
+                                size: None,
+                            }),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Sampler(
+                                self.texture_sampler.as_ref().unwrap(),
+                            ),
+                        },
+                        BindGroupEntry {
+                            binding: 8,
+                            resource: layers.buffer.as_entire_binding(),
+                        },
+                    ];
+                    // the four layers and then the splat map
+                    entries.extend(views.iter().zip(3..).map(|(view, binding)| BindGroupEntry {
+                        binding,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    }));
+                    wgpu_handles.device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("SomeTerrainMaterialBindGroup"),
+                        layout: self.material_bind_group_layouts.get(2).unwrap(),
+                        entries: &entries,
+                    })
+                });
+                staging_belt.write_buffer(
+                    buffer,
+                    0,
+                    bytemuck::cast_slice(&[mat.data]),
+                    &wgpu_handles.device,
+                );
+            }
         }
     }
+
+    /// Load a texture file and upload it, or a texture of a single color if it fails.
+    /// `linear` is for textures that hold data rather than colors.
+    fn upload_texture_file(
+        &self,
+        path: &Path,
+        fallback_color: [u8; 4],
+        linear: bool,
+        wgpu_handles: &WgpuHandles,
+    ) -> Texture {
+        // a missing texture should not take the whole scene down with it
+        let mut texture_data = load_texture(path).unwrap_or_else(|e| {
+            log::error!("{} ({})", e, path.display());
+            TextureData::solid_color(fallback_color)
+        });
+        if linear && texture_data.format == TextureFormat::Rgba8UnormSrgb {
+            texture_data.format = TextureFormat::Rgba8Unorm;
+        }
+        self.upload_texture(&texture_data, wgpu_handles)
+            .or_else(|e| {
+                log::error!("{} ({})", e, path.display());
+                self.upload_texture(&TextureData::solid_color(fallback_color), wgpu_handles)
+            })
+            .unwrap()
+    }
+
+    fn upload_terrain_layers(
+        &self,
+        layers: &TerrainLayers,
+        wgpu_handles: &WgpuHandles,
+    ) -> UploadedTerrainLayers {
+        let layer_maps = (0..4)
+            .map(|index| match layers.layer_maps.get(index) {
+                Some(path) => {
+                    self.upload_texture_file(path, [255, 255, 255, 255], false, wgpu_handles)
+                }
+                None => self
+                    .upload_texture(
+                        &TextureData::solid_color([255, 255, 255, 255]),
+                        wgpu_handles,
+                    )
+                    .unwrap(),
+            })
+            .collect();
+        let data = TerrainLayerData {
+            tiling: layers.tiling,
+            layer_count: layers.layer_maps.len() as u32,
+            _padding: [0; 3],
+        };
+        UploadedTerrainLayers {
+            splat_map: self.upload_texture_file(
+                &layers.splat_map,
+                [255, 0, 0, 0],
+                true,
+                wgpu_handles,
+            ),
+            layer_maps,
+            buffer: wgpu_handles
+                .device
+                .create_buffer_init(&BufferInitDescriptor {
+                    label: Some("TerrainLayerBuffer"),
+                    contents: bytemuck::cast_slice(&[data]),
+                    usage: BufferUsages::UNIFORM,
+                }),
+        }
+    }
+}
+
+fn joint_matrices_layout_entry() -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(
+                (MAX_JOINTS * std::mem::size_of::<glm::Mat4>()) as u64,
+            ),
+        },
+        count: None,
+    }
+}
+
+fn morph_layout_entries() -> [BindGroupLayoutEntry; 2] {
+    [
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(std::mem::size_of::<MorphWeightData>() as u64),
+            },
+            count: None,
+        },
+    ]
+}
+
+fn phong_material_data_layout_entry() -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: NonZeroU64::new(16 * 4),
+        },
+        count: None,
+    }
+}
+
+/// The first light of the scene replaces what was drawn before and the lights after it
+/// are added on top. The blend constant is 0 while the first light is drawn and 1 after.
+fn forward_color_target(surface_capabilities: &SurfaceCapabilities) -> ColorTargetState {
+    ColorTargetState {
+        format: surface_capabilities.formats[0],
+        blend: Some(BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::Constant,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent::REPLACE,
+        }),
+        write_mask: ColorWrites::ALL,
+    }
 }
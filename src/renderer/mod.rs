@@ -1,8 +1,26 @@
+pub mod ambient_occlusion;
 pub mod bind_material;
+pub mod capture;
+pub mod clustered;
+pub mod deferred;
+pub mod depth_texture;
 pub mod draw_impl;
 pub mod draw_state;
+pub mod fog;
+pub mod grid;
 pub mod light;
 pub mod material;
+pub mod offscreen_target;
+pub mod outline;
+pub mod particles;
+pub mod picking;
 pub mod render;
+pub mod settings;
+pub mod sprite;
 pub mod staging_belt_and_command_encoder;
+pub mod stats;
+pub mod text;
+pub mod texture;
+pub mod ui;
+pub mod viewport;
 pub mod wgpu_handles;
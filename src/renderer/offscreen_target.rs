@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use wgpu::{
+    Device, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+
+use super::{deferred::GBuffer, depth_texture::DepthTexture};
+
+/// Texture a `RenderTexture` of the scene is drawn to, owned by the renderer so that
+/// materials can sample it.
+pub struct OffscreenTarget {
+    pub size: (u32, u32),
+    pub texture: Texture,
+    pub depth_texture: Option<DepthTexture>,
+    pub g_buffer: Option<GBuffer>,
+}
+
+impl OffscreenTarget {
+    pub fn new(device: &Device, size: (u32, u32), format: TextureFormat) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("OffscreenTargetTexture"),
+            size: Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        OffscreenTarget {
+            size,
+            texture,
+            depth_texture: None,
+            g_buffer: None,
+        }
+    }
+
+    /// Get the target with the given name, creating it or recreating it if the size
+    /// changed. Materials notice the new texture and bind it again.
+    pub fn get_or_resize<'a>(
+        targets: &'a mut HashMap<String, OffscreenTarget>,
+        device: &Device,
+        name: &str,
+        size: (u32, u32),
+        format: TextureFormat,
+    ) -> &'a mut OffscreenTarget {
+        if targets.get(name).is_some_and(|target| target.size != size) {
+            targets.remove(name);
+        }
+        targets
+            .entry(name.to_string())
+            .or_insert_with(|| OffscreenTarget::new(device, size, format))
+    }
+}
@@ -0,0 +1,390 @@
+use std::{collections::HashSet, num::NonZeroU64};
+
+use wgpu::{
+    include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BlendState, Buffer, BufferDescriptor,
+    BufferUsages, ColorTargetState, ColorWrites, Device, Extent3d, Face, FragmentState,
+    MultisampleState, PipelineLayoutDescriptor, PrimitiveState, RenderPipelineDescriptor,
+    ShaderStages, SurfaceCapabilities, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, VertexState,
+};
+
+use strum::IntoEnumIterator;
+
+use super::{
+    depth_texture::scene_depth_stencil_state,
+    draw_impl::{geometry_vertex_buffers, geometry_vertex_entry_point, DrawMeshGeometry},
+    material::{Deformation, MaterialManager},
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
+    viewport::PixelRect,
+    wgpu_handles::WgpuHandles,
+};
+use crate::scene::{object3d::Object3D, scene::Scene};
+
+const MASK_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+/// The edge pass samples a (2 * width + 1)² square per pixel, so wider outlines are clamped to this
+pub const MAX_OUTLINE_WIDTH: u32 = 8;
+
+/// Objects to draw an outline around. Highlighting an object highlights its children too.
+pub struct Outline {
+    /// ids of the highlighted objects, see `Object3D::id`
+    pub highlighted: HashSet<u32>,
+    pub color: glm::Vec4,
+    /// in pixels, clamped to `MAX_OUTLINE_WIDTH`
+    pub width: u32,
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Outline {
+            highlighted: HashSet::new(),
+            color: glm::vec4(1.0, 0.6, 0.0, 1.0),
+            width: 3,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct OutlineSettingsData {
+    color: glm::Vec4,
+    width: u32,
+    _padding: [u32; 3],
+}
+
+unsafe impl bytemuck::Zeroable for OutlineSettingsData {}
+unsafe impl bytemuck::Pod for OutlineSettingsData {}
+
+pub trait OutlineManager {
+    fn add_outline_pipelines(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    );
+}
+
+impl OutlineManager for MaterialManager {
+    fn add_outline_pipelines(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    ) {
+        let primitive = PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        };
+
+        // Bind Groups
+        // Group 0 Binding 0: Object to Camera Matrix Uniform
+        // Group 1 Binding 0 - 2: Joint Matrices, Morph Deltas and Weights (deformed only)
+        let mask_shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/outline-mask.wgsl"));
+        self.outline_mask_pipelines = Deformation::iter()
+            .map(|deformation| {
+                let mut bind_group_layouts = vec![self.matrix_bind_group_layout.as_ref().unwrap()];
+                bind_group_layouts.extend(self.get_deformation_bind_group_layout(deformation));
+                let mask_pipeline_layout =
+                    device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                        label: Some("OutlineMaskPipelineLayout"),
+                        bind_group_layouts: &bind_group_layouts,
+                        push_constant_ranges: &[],
+                    });
+                device.create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some("OutlineMaskRenderPipeline"),
+                    layout: Some(&mask_pipeline_layout),
+                    vertex: VertexState {
+                        module: &mask_shader_module,
+                        entry_point: geometry_vertex_entry_point(deformation),
+                        buffers: &geometry_vertex_buffers(deformation),
+                    },
+                    fragment: Some(FragmentState {
+                        module: &mask_shader_module,
+                        entry_point: "fragment_main",
+                        targets: &[Some(MASK_FORMAT.into())],
+                    }),
+                    primitive,
+                    multisample: MultisampleState::default(),
+                    // only the visible parts of the objects get outlined
+                    depth_stencil: Some(scene_depth_stencil_state(false)),
+                    multiview: None,
+                })
+            })
+            .collect();
+
+        // Bind Groups
+        // Group 0 Binding 0: Mask Texture
+        // Group 0 Binding 1: OutlineSettingsData
+        let edge_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("OutlineEdgeBindGroupLayout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(
+                            std::mem::size_of::<OutlineSettingsData>() as u64,
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let edge_shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/outline-edge.wgsl"));
+        let edge_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("OutlineEdgePipelineLayout"),
+            bind_group_layouts: &[&edge_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        self.outline_edge_pipeline =
+            Some(device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("OutlineEdgeRenderPipeline"),
+                layout: Some(&edge_pipeline_layout),
+                vertex: VertexState {
+                    module: &edge_shader_module,
+                    entry_point: "vertex_main",
+                    buffers: &[],
+                },
+                fragment: Some(FragmentState {
+                    module: &edge_shader_module,
+                    entry_point: "fragment_main",
+                    targets: &[Some(ColorTargetState {
+                        format: surface_capabilities.formats[0],
+                        blend: Some(BlendState::ALPHA_BLENDING),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: PrimitiveState {
+                    cull_mode: None,
+                    ..primitive
+                },
+                multisample: MultisampleState::default(),
+                depth_stencil: None,
+                multiview: None,
+            }));
+        self.outline_bind_group_layout = Some(edge_bind_group_layout);
+    }
+}
+
+/// Mask texture and settings buffer of the outline passes, sized like the render target.
+pub struct OutlineTargets {
+    size: (u32, u32),
+    mask_view: TextureView,
+    settings_buffer: Buffer,
+    bind_group: BindGroup,
+    _mask_texture: Texture,
+}
+
+impl OutlineTargets {
+    pub fn new(device: &Device, bind_group_layout: &BindGroupLayout, size: (u32, u32)) -> Self {
+        let mask_texture = device.create_texture(&TextureDescriptor {
+            label: Some("OutlineMaskTexture"),
+            size: Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: MASK_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT.union(TextureUsages::TEXTURE_BINDING),
+            view_formats: &[],
+        });
+        let mask_view = mask_texture.create_view(&Default::default());
+        let settings_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("OutlineSettingsBuffer"),
+            size: std::mem::size_of::<OutlineSettingsData>() as u64,
+            usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("OutlineEdgeBindGroup"),
+            layout: bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&mask_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: settings_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        OutlineTargets {
+            size,
+            mask_view,
+            settings_buffer,
+            bind_group,
+            _mask_texture: mask_texture,
+        }
+    }
+}
+
+/// Draw the outline of the highlighted objects on top of the rendered scene. The depth
+/// texture of the scene pass is used so that hidden parts are not outlined.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_outline_passes(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &MaterialManager,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+    texture_view: &TextureView,
+    target_size: (u32, u32),
+    window: usize,
+    rect: PixelRect,
+) {
+    if scene.outline.highlighted.is_empty() {
+        return;
+    }
+
+    let window_targets = wgpu_handles.window_targets.entry(window).or_default();
+    if window_targets
+        .outline_targets
+        .as_ref()
+        .is_some_and(|targets| targets.size != target_size)
+    {
+        window_targets.outline_targets = None;
+    }
+    let device = &wgpu_handles.device;
+    let targets = window_targets.outline_targets.get_or_insert_with(|| {
+        OutlineTargets::new(
+            device,
+            material_manager.outline_bind_group_layout.as_ref().unwrap(),
+            target_size,
+        )
+    });
+
+    staging_belt.write_buffer(
+        &targets.settings_buffer,
+        0,
+        bytemuck::cast_slice(&[OutlineSettingsData {
+            color: scene.outline.color,
+            width: scene.outline.width.min(MAX_OUTLINE_WIDTH),
+            _padding: [0; 3],
+        }]),
+        device,
+    );
+
+    let timestamp_writes = wgpu_handles
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("OutlineMaskRenderPass"));
+    let mut render_pass =
+        staging_belt
+            .command_encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("OutlineMaskRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &targets.mask_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &window_targets.depth_texture.as_ref().unwrap().view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+    rect.set_viewport(&mut render_pass);
+    let mut highlighted = HashSet::new();
+    collect_highlighted(
+        &scene.root,
+        &scene.outline.highlighted,
+        false,
+        &mut highlighted,
+    );
+    let stats = &mut scene.render_stats;
+    for deformation in Deformation::iter() {
+        render_pass.set_pipeline(&material_manager.outline_mask_pipelines[deformation as usize]);
+        stats.pipeline_switches += 1;
+        scene
+            .root
+            .for_each_drawn_mesh(scene.camera.layers(), &mut |object3d, mesh| {
+                if highlighted.contains(&object3d.id) && mesh.deformation() == deformation {
+                    render_pass.draw_mesh_geometry(stats, object3d, mesh);
+                }
+            });
+    }
+    drop(render_pass);
+
+    let timestamp_writes = wgpu_handles
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("OutlineEdgeRenderPass"));
+    let targets = wgpu_handles.window_targets[&window]
+        .outline_targets
+        .as_ref()
+        .unwrap();
+    let mut render_pass =
+        staging_belt
+            .command_encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("OutlineEdgeRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+    rect.set_viewport(&mut render_pass);
+    render_pass.set_pipeline(material_manager.outline_edge_pipeline.as_ref().unwrap());
+    render_pass.set_bind_group(0, &targets.bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+    scene.render_stats.pipeline_switches += 1;
+    scene.render_stats.bind_group_switches += 1;
+    scene.render_stats.draw_calls += 1;
+    scene.render_stats.triangles += 1;
+}
+
+/// Add the ids of the highlighted objects and of everything below them to `ids`.
+fn collect_highlighted(
+    object3d: &Object3D,
+    highlighted: &HashSet<u32>,
+    parent_highlighted: bool,
+    ids: &mut HashSet<u32>,
+) {
+    let is_highlighted = parent_highlighted || highlighted.contains(&object3d.id);
+    if is_highlighted {
+        ids.insert(object3d.id);
+    }
+    for child in &object3d.children {
+        collect_highlighted(child, highlighted, is_highlighted, ids);
+    }
+}
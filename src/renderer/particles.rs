@@ -0,0 +1,511 @@
+use wgpu::{
+    include_wgsl, vertex_attr_array, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BlendComponent, BlendFactor, BlendOperation,
+    BlendState, Buffer, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites,
+    CommandEncoderDescriptor, ComputePassDescriptor, ComputePipelineDescriptor, Device,
+    FragmentState, MultisampleState, PipelineLayoutDescriptor, PrimitiveState, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, ShaderStages, SurfaceCapabilities, TextureView,
+    VertexBufferLayout, VertexState,
+};
+
+use super::{
+    depth_texture::scene_depth_stencil_state, material::MaterialManager,
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder, stats::RenderStats,
+    viewport::PixelRect, wgpu_handles::WgpuHandles,
+};
+use crate::{
+    scene::{
+        object3d::{Object3D, Object3DObject},
+        particles::{ParticleBlend, ParticleEmitter},
+        scene::Scene,
+    },
+    util::{identity_matrix, MuckableMatrix},
+};
+
+/// Size of a particle in the particle buffer, see `particles-simulate.wgsl`.
+const PARTICLE_SIZE: u64 = 64;
+const WORKGROUP_SIZE: u32 = 64;
+/// longer frames are simulated as this long, so particles do not jump after a stall
+const MAX_TIME_STEP: f32 = 0.1;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct EmitterData {
+    world_matrix: MuckableMatrix,
+    velocity: glm::Vec4,
+    velocity_spread: glm::Vec4,
+    /// drag in w
+    gravity: glm::Vec4,
+    start_color: glm::Vec4,
+    end_color: glm::Vec4,
+    /// start size, end size, lifetime, time step
+    params: glm::Vec4,
+    /// first spawned particle, spawn count, particle count, frame
+    spawn: [u32; 4],
+}
+
+unsafe impl bytemuck::Zeroable for EmitterData {}
+unsafe impl bytemuck::Pod for EmitterData {}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ParticleCameraData {
+    view_projection: MuckableMatrix,
+    right: glm::Vec4,
+    up: glm::Vec4,
+}
+
+unsafe impl bytemuck::Zeroable for ParticleCameraData {}
+unsafe impl bytemuck::Pod for ParticleCameraData {}
+
+pub trait ParticleManager {
+    fn add_particle_pipelines(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    );
+}
+
+impl ParticleManager for MaterialManager {
+    fn add_particle_pipelines(
+        &mut self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    ) {
+        let simulate_shader_module =
+            device.create_shader_module(include_wgsl!("./shaders/particles-simulate.wgsl"));
+        let shader_module = device.create_shader_module(include_wgsl!("./shaders/particles.wgsl"));
+
+        // Bind Groups
+        // Group 0 Binding 0, 1: Emitter Data and Particles
+        let compute_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("ParticleComputeBindGroupLayout"),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let compute_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("ParticleComputePipelineLayout"),
+            bind_group_layouts: &[&compute_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        self.particle_compute_pipeline =
+            Some(device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("ParticleComputePipeline"),
+                layout: Some(&compute_pipeline_layout),
+                module: &simulate_shader_module,
+                entry_point: "compute_main",
+            }));
+
+        // Group 0 Binding 0: Particle Camera
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("ParticleCameraBindGroupLayout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("ParticlePipelineLayout"),
+            bind_group_layouts: &[&camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let additive_blend = BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::SrcAlpha,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent {
+                src_factor: BlendFactor::Zero,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+        };
+        self.particle_additive_pipeline = Some(create_particle_pipeline(
+            device,
+            surface_capabilities,
+            &shader_module,
+            &pipeline_layout,
+            "ParticleAdditiveRenderPipeline",
+            additive_blend,
+        ));
+        self.particle_alpha_pipeline = Some(create_particle_pipeline(
+            device,
+            surface_capabilities,
+            &shader_module,
+            &pipeline_layout,
+            "ParticleAlphaRenderPipeline",
+            BlendState::ALPHA_BLENDING,
+        ));
+        self.particle_compute_bind_group_layout = Some(compute_bind_group_layout);
+        self.particle_camera_bind_group_layout = Some(camera_bind_group_layout);
+    }
+}
+
+fn create_particle_pipeline(
+    device: &Device,
+    surface_capabilities: &SurfaceCapabilities,
+    shader_module: &ShaderModule,
+    pipeline_layout: &wgpu::PipelineLayout,
+    label: &str,
+    blend: BlendState,
+) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(pipeline_layout),
+        vertex: VertexState {
+            module: shader_module,
+            entry_point: "vertex_main",
+            // the particle buffer the compute pass writes, one billboard per particle
+            buffers: &[VertexBufferLayout {
+                array_stride: PARTICLE_SIZE,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Float32x4, 3 => Float32x4],
+            }],
+        },
+        fragment: Some(FragmentState {
+            module: shader_module,
+            entry_point: "fragment_main",
+            targets: &[Some(ColorTargetState {
+                format: surface_capabilities.formats[0],
+                blend: Some(blend),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState::default(),
+        multisample: MultisampleState::default(),
+        // hidden behind the scene, but not sorted among themselves
+        depth_stencil: Some(scene_depth_stencil_state(false)),
+        multiview: None,
+    })
+}
+
+/// Camera uniform of the particle pass, created when particles are first drawn.
+pub struct ParticleCamera {
+    buffer: Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ParticleCamera {
+    fn new(device: &Device, camera_bind_group_layout: &BindGroupLayout) -> Self {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("ParticleCameraBuffer"),
+            size: std::mem::size_of::<ParticleCameraData>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("ParticleCameraBindGroup"),
+            layout: camera_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        ParticleCamera { buffer, bind_group }
+    }
+}
+
+/// The emitters in the subtree with their world matrices. With `seen_by`, only those
+/// that are visible and on one of its layers, hidden emitters keep moving regardless.
+fn collect_emitters<'a>(
+    object3d: &'a mut Object3D,
+    parent_matrix: &glm::Mat4,
+    seen_by: Option<u32>,
+    emitters: &mut Vec<(&'a mut ParticleEmitter, glm::Mat4)>,
+) {
+    if seen_by.is_some() && !object3d.visible {
+        return;
+    }
+    let matrix: glm::Mat4 = object3d.matrix.into();
+    let matrix = parent_matrix.mul_m(&matrix);
+
+    let is_seen = seen_by.is_none_or(|layers| object3d.is_on_layers(layers));
+    if let (true, Object3DObject::ParticleEmitter(emitter)) = (is_seen, &mut object3d.object) {
+        emitters.push((emitter, matrix));
+    }
+    for child in &mut object3d.children {
+        collect_emitters(child, &matrix, seen_by, emitters);
+    }
+}
+
+fn create_emitter_buffers(
+    emitter: &mut ParticleEmitter,
+    device: &Device,
+    material_manager: &MaterialManager,
+) {
+    let particle_buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("ParticleBuffer"),
+        size: emitter.max_particles as u64 * PARTICLE_SIZE,
+        usage: BufferUsages::STORAGE | BufferUsages::VERTEX,
+        // zeroed particles are dead, their age is not less than their lifetime
+        mapped_at_creation: false,
+    });
+    let emitter_buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("ParticleEmitterBuffer"),
+        size: std::mem::size_of::<EmitterData>() as u64,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    emitter.compute_bind_group = Some(
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("ParticleComputeBindGroup"),
+            layout: material_manager
+                .particle_compute_bind_group_layout
+                .as_ref()
+                .unwrap(),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: emitter_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+            ],
+        }),
+    );
+    emitter.particle_buffer = Some(particle_buffer);
+    emitter.emitter_buffer = Some(emitter_buffer);
+}
+
+/// Advance the particles of every emitter in the scene to `time`, in seconds like the
+/// time given to the render loop. Call this once per frame before the frame is
+/// rendered, however many windows and views show the particles. Returns the figures of
+/// the simulation, which `render_window_to_texture_view` does not count.
+pub fn simulate_particles(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &MaterialManager,
+    time: f64,
+) -> RenderStats {
+    let mut stats = RenderStats::default();
+    let mut emitters = vec![];
+    collect_emitters(&mut scene.root, &identity_matrix(), None, &mut emitters);
+    if emitters.is_empty() {
+        return stats;
+    }
+
+    let device = wgpu_handles.device.clone();
+    for (emitter, _) in &mut emitters {
+        if emitter.max_particles > 0 && emitter.particle_buffer.is_none() {
+            create_emitter_buffers(emitter, &device, material_manager);
+        }
+    }
+
+    let timestamp_writes = wgpu_handles
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.compute_pass_timestamp_writes("ParticleComputePass"));
+
+    // queue writes land before the commands submitted after them
+    let mut dispatches = vec![];
+    for (emitter, world_matrix) in emitters {
+        if emitter.max_particles == 0 {
+            continue;
+        }
+        let time_step = emitter
+            .last_update
+            .map_or(0.0, |last_update| (time - last_update).max(0.0) as f32)
+            .min(MAX_TIME_STEP);
+        emitter.last_update = Some(time);
+
+        let spawns = emitter.spawn_remainder + emitter.spawn_rate.max(0.0) * time_step;
+        let spawn_count = (spawns.floor() as u32).min(emitter.max_particles);
+        emitter.spawn_remainder = spawns.fract();
+        let first_particle = emitter.next_particle;
+        emitter.next_particle = (first_particle + spawn_count) % emitter.max_particles;
+        emitter.frame = emitter.frame.wrapping_add(1);
+
+        let emitter_data = EmitterData {
+            world_matrix: MuckableMatrix(world_matrix),
+            velocity: emitter.velocity.extend(0.0),
+            velocity_spread: emitter.velocity_spread.extend(0.0),
+            gravity: emitter.gravity.extend(emitter.drag),
+            start_color: emitter.start_color,
+            end_color: emitter.end_color,
+            params: glm::vec4(
+                emitter.start_size,
+                emitter.end_size,
+                emitter.lifetime,
+                time_step,
+            ),
+            spawn: [
+                first_particle,
+                spawn_count,
+                emitter.max_particles,
+                emitter.frame,
+            ],
+        };
+        wgpu_handles.queue.write_buffer(
+            emitter.emitter_buffer.as_ref().unwrap(),
+            0,
+            bytemuck::cast_slice(&[emitter_data]),
+        );
+        dispatches.push((
+            emitter.compute_bind_group.as_ref().unwrap(),
+            emitter.max_particles.div_ceil(WORKGROUP_SIZE),
+        ));
+    }
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("ParticleCommandEncoder"),
+    });
+    let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+        label: Some("ParticleComputePass"),
+        timestamp_writes,
+    });
+    compute_pass.set_pipeline(material_manager.particle_compute_pipeline.as_ref().unwrap());
+    stats.pipeline_switches += 1;
+    for (bind_group, workgroups) in dispatches {
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        stats.bind_group_switches += 1;
+        stats.compute_dispatches += 1;
+    }
+    drop(compute_pass);
+    wgpu_handles.queue.submit(Some(encoder.finish()));
+    stats
+}
+
+/// Draw the particles of the scene as seen by the scene camera in `rect`. This needs the
+/// depth texture of the scene pass that was just encoded.
+pub fn encode_particle_pass(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &MaterialManager,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+    texture_view: &TextureView,
+    window: usize,
+    rect: PixelRect,
+) {
+    let mut emitters = vec![];
+    let layers = scene.camera.layers();
+    collect_emitters(
+        &mut scene.root,
+        &identity_matrix(),
+        Some(layers),
+        &mut emitters,
+    );
+    emitters.retain(|(emitter, _)| emitter.particle_buffer.is_some());
+    if emitters.is_empty() {
+        return;
+    }
+
+    let device = wgpu_handles.device.clone();
+    let particle_camera = wgpu_handles.particle_camera.get_or_insert_with(|| {
+        ParticleCamera::new(
+            &device,
+            material_manager
+                .particle_camera_bind_group_layout
+                .as_ref()
+                .unwrap(),
+        )
+    });
+    // the rows of the view matrix are the screen axes in world space
+    let world_to_local = scene.camera.world_to_local();
+    let camera_data = ParticleCameraData {
+        view_projection: MuckableMatrix(scene.camera.get_inverse_matrix()),
+        right: glm::vec4(
+            world_to_local.c0.x,
+            world_to_local.c1.x,
+            world_to_local.c2.x,
+            0.0,
+        ),
+        up: glm::vec4(
+            world_to_local.c0.y,
+            world_to_local.c1.y,
+            world_to_local.c2.y,
+            0.0,
+        ),
+    };
+    staging_belt.write_buffer(
+        &particle_camera.buffer,
+        0,
+        bytemuck::cast_slice(&[camera_data]),
+        &device,
+    );
+
+    let timestamp_writes = wgpu_handles
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("ParticleRenderPass"));
+    let particle_camera = wgpu_handles.particle_camera.as_ref().unwrap();
+    let depth_texture = wgpu_handles.window_targets[&window]
+        .depth_texture
+        .as_ref()
+        .unwrap();
+    let mut render_pass =
+        staging_belt
+            .command_encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("ParticleRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+    rect.set_viewport(&mut render_pass);
+    render_pass.set_bind_group(0, &particle_camera.bind_group, &[]);
+    let stats = &mut scene.render_stats;
+    stats.bind_group_switches += 1;
+    for (emitter, _) in emitters {
+        let pipeline = match emitter.blend {
+            ParticleBlend::Additive => &material_manager.particle_additive_pipeline,
+            ParticleBlend::Alpha => &material_manager.particle_alpha_pipeline,
+        };
+        render_pass.set_pipeline(pipeline.as_ref().unwrap());
+        render_pass.set_vertex_buffer(0, emitter.particle_buffer.as_ref().unwrap().slice(..));
+        render_pass.draw(0..6, 0..emitter.max_particles);
+
+        stats.pipeline_switches += 1;
+        stats.draw_calls += 1;
+        stats.triangles += 2 * emitter.max_particles as u64;
+    }
+}
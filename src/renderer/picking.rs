@@ -0,0 +1,356 @@
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+use wgpu::{
+    include_wgsl, Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, ColorTargetState,
+    CommandEncoder, CompareFunction, DepthStencilState, Device, Extent3d, Face, FragmentState,
+    ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, MapMode, MultisampleState, Origin3d,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModule, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, VertexState,
+};
+
+use strum::IntoEnumIterator;
+
+use super::{
+    draw_impl::{geometry_vertex_buffers, geometry_vertex_entry_point, DrawMeshGeometry},
+    material::{Deformation, MaterialManager},
+    viewport::PixelRect,
+    wgpu_handles::WgpuHandles,
+};
+use crate::{
+    engine::picking_service::{PickHit, PickResult, PickingService},
+    scene::scene::Scene,
+};
+
+const ID_FORMAT: TextureFormat = TextureFormat::R32Uint;
+const DEPTH_FORMAT: TextureFormat = TextureFormat::R32Float;
+const DEPTH_STENCIL_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+// copies into buffers need their offsets aligned
+const DEPTH_READBACK_OFFSET: u64 = 256;
+
+pub trait PickingManager {
+    /// One for each `Deformation`, in its order.
+    fn create_picking_pipelines(&self, device: &Device) -> Vec<RenderPipeline>;
+}
+
+impl PickingManager for MaterialManager {
+    fn create_picking_pipelines(&self, device: &Device) -> Vec<RenderPipeline> {
+        let shader_module = device.create_shader_module(include_wgsl!("./shaders/picking.wgsl"));
+
+        Deformation::iter()
+            .map(|deformation| self.create_picking_pipeline(device, &shader_module, deformation))
+            .collect()
+    }
+}
+
+impl MaterialManager {
+    fn create_picking_pipeline(
+        &self,
+        device: &Device,
+        shader_module: &ShaderModule,
+        deformation: Deformation,
+    ) -> RenderPipeline {
+        // Bind Groups
+        // Group 0 Binding 0: Object to Camera Matrix Uniform with the object id
+        // Group 1 Binding 0 - 2: Joint Matrices, Morph Deltas and Weights (deformed only)
+        let mut bind_group_layouts = vec![self.matrix_bind_group_layout.as_ref().unwrap()];
+        bind_group_layouts.extend(self.get_deformation_bind_group_layout(deformation));
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("PickingPipelineLayout"),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("PickingRenderPipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: VertexState {
+                module: shader_module,
+                entry_point: geometry_vertex_entry_point(deformation),
+                buffers: &geometry_vertex_buffers(deformation),
+            },
+            fragment: Some(FragmentState {
+                module: shader_module,
+                entry_point: "fragment_main",
+                targets: &[
+                    Some(ColorTargetState::from(ID_FORMAT)),
+                    Some(ColorTargetState::from(DEPTH_FORMAT)),
+                ],
+            }),
+            primitive: PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            multisample: MultisampleState::default(),
+            depth_stencil: Some(DepthStencilState {
+                format: DEPTH_STENCIL_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Less,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multiview: None,
+        })
+    }
+}
+
+/// Render targets and the readback buffer for the picking pass. Only one pick is read
+/// back at a time, further requests wait in the `PickingService` until it is done.
+pub struct Picker {
+    size: (u32, u32),
+    id_texture: Texture,
+    depth_texture: Texture,
+    depth_stencil_texture: Texture,
+    readback_buffer: Buffer,
+    readback_pixel: Option<(u32, u32)>,
+    readback_requested: bool,
+    /// the result of mapping the readback buffer, while it is pending
+    readback_mapping: Option<Receiver<Result<(), BufferAsyncError>>>,
+}
+
+impl Picker {
+    pub fn new(device: &Device, size: (u32, u32)) -> Self {
+        let create_target = |label, format| {
+            device.create_texture(&TextureDescriptor {
+                label: Some(label),
+                size: Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT.union(TextureUsages::COPY_SRC),
+                view_formats: &[],
+            })
+        };
+
+        Picker {
+            size,
+            id_texture: create_target("PickingIdTexture", ID_FORMAT),
+            depth_texture: create_target("PickingDepthTexture", DEPTH_FORMAT),
+            depth_stencil_texture: create_target(
+                "PickingDepthStencilTexture",
+                DEPTH_STENCIL_FORMAT,
+            ),
+            readback_buffer: device.create_buffer(&BufferDescriptor {
+                label: Some("PickingReadbackBuffer"),
+                size: DEPTH_READBACK_OFFSET + 4,
+                usage: BufferUsages::MAP_READ.union(BufferUsages::COPY_DST),
+                mapped_at_creation: false,
+            }),
+            readback_pixel: None,
+            readback_requested: false,
+            readback_mapping: None,
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.readback_pixel.is_some()
+    }
+
+    fn copy_pixel(
+        &self,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        pixel: (u32, u32),
+        offset: u64,
+    ) {
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: pixel.0,
+                    y: pixel.1,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: ImageDataLayout {
+                    offset,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Map the readback buffer after the frame is submitted, and hand the result to
+    /// the `PickingService` if an earlier pick arrived.
+    pub fn after_submit(&mut self, device: &Device) {
+        if self.readback_requested {
+            self.readback_requested = false;
+            let (sender, receiver) = channel();
+            self.readback_buffer
+                .slice(..)
+                .map_async(MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            self.readback_mapping = Some(receiver);
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+        let Some(readback_mapping) = &self.readback_mapping else {
+            return;
+        };
+        match readback_mapping.try_recv() {
+            Ok(Ok(())) => self.readback_mapping = None,
+            Err(TryRecvError::Empty) => return,
+            // the pick is lost, the next request is served again
+            Ok(Err(_)) | Err(TryRecvError::Disconnected) => {
+                self.readback_mapping = None;
+                self.readback_pixel = None;
+                return;
+            }
+        }
+
+        let (object_id, depth) = {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let offset = DEPTH_READBACK_OFFSET as usize;
+            (
+                bytemuck::pod_read_unaligned::<u32>(&data[0..4]),
+                bytemuck::pod_read_unaligned::<f32>(&data[offset..offset + 4]),
+            )
+        };
+        self.readback_buffer.unmap();
+
+        let (x, y) = self.readback_pixel.take().unwrap();
+        PickingService::handle_pick_result(PickResult {
+            x,
+            y,
+            hit: (object_id != 0).then_some(PickHit { object_id, depth }),
+        });
+    }
+}
+
+/// Take the pending pick request, unless the last pick is not read yet.
+pub fn take_pick_request(wgpu_handles: &WgpuHandles) -> Option<(u32, u32)> {
+    let picker = wgpu_handles
+        .window_targets
+        .get(&0)
+        .and_then(|targets| targets.picker.as_ref());
+    if picker.is_some_and(Picker::is_busy) {
+        return None;
+    }
+    PickingService::get_mut().take_request()
+}
+
+/// Report a pick request at a pixel that no view draws to.
+pub fn report_pick_miss(pixel: (u32, u32)) {
+    PickingService::handle_pick_result(PickResult {
+        x: pixel.0,
+        y: pixel.1,
+        hit: None,
+    });
+}
+
+/// Render object ids of the view in `rect` and copy the requested pixel for reading
+/// back.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_picking_pass(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &MaterialManager,
+    encoder: &mut CommandEncoder,
+    target_size: (u32, u32),
+    window: usize,
+    rect: PixelRect,
+    pixel: (u32, u32),
+) {
+    let window_targets = wgpu_handles.window_targets.entry(window).or_default();
+    if window_targets
+        .picker
+        .as_ref()
+        .is_some_and(|picker| picker.size != target_size)
+    {
+        window_targets.picker = None;
+    }
+    let device = &wgpu_handles.device;
+    let picker = window_targets
+        .picker
+        .get_or_insert_with(|| Picker::new(device, target_size));
+
+    let id_view = picker.id_texture.create_view(&Default::default());
+    let depth_view = picker.depth_texture.create_view(&Default::default());
+    let depth_stencil_view = picker
+        .depth_stencil_texture
+        .create_view(&Default::default());
+
+    let timestamp_writes = wgpu_handles
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("PickingRenderPass"));
+
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("PickingRenderPass"),
+        color_attachments: &[
+            Some(cleared_attachment(&id_view)),
+            Some(cleared_attachment(&depth_view)),
+        ],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &depth_stencil_view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: wgpu::StoreOp::Discard,
+            }),
+            stencil_ops: None,
+        }),
+        timestamp_writes,
+        occlusion_query_set: None,
+    });
+
+    rect.set_viewport(&mut render_pass);
+    // what the camera does not see cannot be picked either
+    let layers = PickingService::get().layers & scene.camera.layers();
+    let stats = &mut scene.render_stats;
+    for deformation in Deformation::iter() {
+        render_pass.set_pipeline(&material_manager.picking_pipelines[deformation as usize]);
+        stats.pipeline_switches += 1;
+        scene
+            .root
+            .for_each_drawn_mesh(layers, &mut |object3d, mesh| {
+                if mesh.deformation() == deformation {
+                    render_pass.draw_mesh_geometry(stats, object3d, mesh)
+                }
+            });
+    }
+    drop(render_pass);
+
+    let picker = wgpu_handles
+        .window_targets
+        .get_mut(&window)
+        .and_then(|targets| targets.picker.as_mut())
+        .unwrap();
+    picker.copy_pixel(encoder, &picker.id_texture, pixel, 0);
+    picker.copy_pixel(encoder, &picker.depth_texture, pixel, DEPTH_READBACK_OFFSET);
+    picker.readback_pixel = Some(pixel);
+    picker.readback_requested = true;
+}
+
+/// Both targets are cleared to zero, id 0 means there is no object at the pixel and
+/// its depth is not read.
+fn cleared_attachment(view: &TextureView) -> wgpu::RenderPassColorAttachment<'_> {
+    wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            store: wgpu::StoreOp::Store,
+        },
+    }
+}
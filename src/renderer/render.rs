@@ -1,25 +1,116 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
+use strum::IntoEnumIterator;
 use wgpu::{util::StagingBelt, CommandEncoderDescriptor, TextureView};
 
 use super::{
+    ambient_occlusion::encode_ambient_occlusion_passes,
+    clustered::encode_light_assignment,
+    deferred::{encode_deferred_passes, encode_g_buffer_pass, GBuffer},
+    depth_texture::DepthTexture,
     draw_impl::DrawObject3D,
     draw_state::DrawState,
-    material::{MaterialManager, MaterialType},
+    grid::encode_grid_pass,
+    material::{Deformation, MaterialManager, MaterialType},
+    offscreen_target::OffscreenTarget,
+    outline::encode_outline_passes,
+    particles::encode_particle_pass,
+    picking::{encode_picking_pass, report_pick_miss, take_pick_request},
+    settings::RenderPath,
+    sprite::encode_sprite_pass,
     staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
+    stats::RenderStats,
+    text::{encode_label_pass, encode_overlay_pass},
+    viewport::{encode_clear, encode_clear_rect, PixelRect},
     wgpu_handles::WgpuHandles,
 };
-use crate::{
-    renderer::light::Light,
-    scene::{object3d::Object3DManager, scene::Scene},
+use crate::scene::{
+    lod::update_lod_groups, morph::write_morphs, object3d::Object3DManager,
+    render_texture::RenderTexture, scene::Scene, scene_view::ViewKey, skin::write_skins,
 };
 
+/// Size of the texture behind the texture view that is rendered to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderTargetSize {
+    pub width: u32,
+    pub height: u32,
+    /// physical pixels per logical pixel, 1 for targets that are not shown in a window
+    pub scale_factor: f64,
+}
+
+/// Render a frame of the main window and keep its statistics in `scene.render_stats`.
+/// Pick requests are served here too.
 pub fn render_to_texture_view(
     scene: &mut Scene,
     wgpu_handles: &mut WgpuHandles,
     texture_view: &TextureView,
+    render_target_size: RenderTargetSize,
+    material_manager: &mut Arc<MaterialManager>,
+) {
+    scene.render_stats.start_frame();
+    render_textures_to_offscreen_targets(scene, wgpu_handles, material_manager);
+    render_window_to_texture_view(
+        scene,
+        wgpu_handles,
+        texture_view,
+        render_target_size,
+        0,
+        material_manager,
+    );
+}
+
+/// Draw the render textures of the scene into their offscreen targets and add the
+/// figures to `scene.render_stats`. Call this once per frame before the windows are
+/// rendered, so that their views can sample the render textures.
+pub fn render_textures_to_offscreen_targets(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
     material_manager: &mut Arc<MaterialManager>,
 ) {
+    if scene.render_textures.is_empty() {
+        return;
+    }
+    let encoder = wgpu_handles
+        .device
+        .create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("RenderTextureCommandEncoder"),
+        });
+    let staging_belt = StagingBelt::new(128);
+    let mut staging_belt = StagingBeltAndCommandEncoder::new(staging_belt, encoder);
+
+    let mut render_textures = std::mem::take(&mut scene.render_textures);
+    for render_texture in &mut render_textures {
+        encode_render_texture(
+            scene,
+            render_texture,
+            wgpu_handles,
+            material_manager,
+            &mut staging_belt,
+        );
+    }
+    scene.render_textures = render_textures;
+    scene.render_stats.staging_bytes += staging_belt.bytes_written;
+
+    staging_belt.staging_belt.finish();
+    wgpu_handles
+        .queue
+        .submit(Some(staging_belt.command_encoder.finish()));
+    staging_belt.staging_belt.recall();
+}
+
+/// Render the views of the scene that belong to the given window, or the scene camera
+/// if this is the main window and there are no views. The figures are added to
+/// `scene.render_stats`, which is cleared once per frame before the first window.
+pub fn render_window_to_texture_view(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    texture_view: &TextureView,
+    render_target_size: RenderTargetSize,
+    window: usize,
+    material_manager: &mut Arc<MaterialManager>,
+) {
+    let target_size = (render_target_size.width, render_target_size.height);
+
     let encoder = wgpu_handles
         .device
         .create_command_encoder(&CommandEncoderDescriptor {
@@ -27,30 +118,306 @@ pub fn render_to_texture_view(
         });
     let staging_belt = StagingBelt::new(128);
     let mut staging_belt = StagingBeltAndCommandEncoder::new(staging_belt, encoder);
-    render(
-        scene,
-        wgpu_handles,
+
+    // picking is done in the main window, where the mouse service positions are from
+    let mut pick_pixel = match window {
+        0 => take_pick_request(wgpu_handles),
+        _ => None,
+    };
+
+    encode_clear(
+        &mut staging_belt.command_encoder,
         texture_view,
-        material_manager,
-        &mut staging_belt,
+        scene.clear_color,
     );
+
+    let mut views = std::mem::take(&mut scene.views);
+    if views.is_empty() && window == 0 {
+        if scene.fit_camera_to_target {
+            scene.camera.fit_to_render_target(render_target_size);
+        }
+        encode_view(
+            scene,
+            wgpu_handles,
+            texture_view,
+            render_target_size,
+            window,
+            &ViewKey::SceneCamera,
+            PixelRect::full(target_size),
+            &mut pick_pixel,
+            material_manager,
+            &mut staging_belt,
+        );
+    }
+    for (index, view) in views
+        .iter_mut()
+        .enumerate()
+        .filter(|(_, view)| view.window == window)
+    {
+        let rect = view.rect.to_pixels(target_size);
+        if rect.is_empty() {
+            continue;
+        }
+        if let Some(color) = view.clear_color {
+            encode_clear_rect(
+                material_manager,
+                &mut staging_belt.command_encoder,
+                texture_view,
+                rect,
+                color,
+            );
+        }
+        if scene.fit_camera_to_target {
+            view.camera.fit_to_render_target(RenderTargetSize {
+                width: rect.width,
+                height: rect.height,
+                ..render_target_size
+            });
+        }
+
+        // the scene is drawn with the camera of the view
+        std::mem::swap(&mut scene.camera, &mut view.camera);
+        encode_view(
+            scene,
+            wgpu_handles,
+            texture_view,
+            render_target_size,
+            window,
+            &ViewKey::View(index),
+            rect,
+            &mut pick_pixel,
+            material_manager,
+            &mut staging_belt,
+        );
+        std::mem::swap(&mut scene.camera, &mut view.camera);
+    }
+    scene.views = views;
+
+    if window == 0 {
+        encode_sprite_pass(
+            scene,
+            wgpu_handles,
+            material_manager,
+            &mut staging_belt,
+            texture_view,
+            render_target_size,
+        );
+        encode_overlay_pass(
+            scene,
+            wgpu_handles,
+            material_manager,
+            &mut staging_belt.command_encoder,
+            texture_view,
+            render_target_size,
+            window,
+        );
+    }
+    if let Some(pixel) = pick_pixel {
+        report_pick_miss(pixel);
+    }
+    scene.render_stats.staging_bytes += staging_belt.bytes_written;
+
+    if let Some(gpu_timer) = &mut wgpu_handles.gpu_timer {
+        gpu_timer.resolve(&mut staging_belt.command_encoder);
+    }
     staging_belt.staging_belt.finish();
     wgpu_handles
         .queue
         .submit(Some(staging_belt.command_encoder.finish()));
     staging_belt.staging_belt.recall();
+
+    if let Some(gpu_timer) = &mut wgpu_handles.gpu_timer {
+        gpu_timer.after_submit(&wgpu_handles.device, &wgpu_handles.queue);
+        scene.render_stats.gpu_pass_times = gpu_timer.pass_times.clone();
+    }
+    let pickers = wgpu_handles
+        .window_targets
+        .values_mut()
+        .filter_map(|targets| targets.picker.as_mut());
+    for picker in pickers {
+        picker.after_submit(&wgpu_handles.device);
+    }
 }
 
+/// Render the scene with its current camera into `rect`, followed by the grids, the
+/// particles, the outline, the object labels and the picking pass if the pick request
+/// falls into it.
+#[allow(clippy::too_many_arguments)]
+fn encode_view(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    texture_view: &TextureView,
+    render_target_size: RenderTargetSize,
+    window: usize,
+    view: &ViewKey,
+    rect: PixelRect,
+    pick_pixel: &mut Option<(u32, u32)>,
+    material_manager: &mut Arc<MaterialManager>,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+) {
+    let target_size = (render_target_size.width, render_target_size.height);
+    render(
+        scene,
+        wgpu_handles,
+        texture_view,
+        target_size,
+        rect,
+        window,
+        view,
+        None,
+        material_manager,
+        staging_belt,
+    );
+    encode_grid_pass(
+        scene,
+        wgpu_handles,
+        material_manager,
+        staging_belt,
+        texture_view,
+        window,
+        rect,
+    );
+    encode_particle_pass(
+        scene,
+        wgpu_handles,
+        material_manager,
+        staging_belt,
+        texture_view,
+        window,
+        rect,
+    );
+    encode_outline_passes(
+        scene,
+        wgpu_handles,
+        material_manager,
+        staging_belt,
+        texture_view,
+        target_size,
+        window,
+        rect,
+    );
+    encode_label_pass(
+        scene,
+        wgpu_handles,
+        material_manager,
+        &mut staging_belt.command_encoder,
+        texture_view,
+        render_target_size,
+        window,
+        rect,
+    );
+    if let Some(pixel) = pick_pixel.take_if(|pixel| rect.contains(*pixel)) {
+        encode_picking_pass(
+            scene,
+            wgpu_handles,
+            material_manager,
+            &mut staging_belt.command_encoder,
+            target_size,
+            window,
+            rect,
+            pixel,
+        );
+    }
+}
+
+/// Draw a render texture into its offscreen target with its own camera, either from
+/// `scene` or from the scene the render texture brings.
+fn encode_render_texture(
+    scene: &mut Scene,
+    render_texture: &mut RenderTexture,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &mut Arc<MaterialManager>,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+) {
+    let size = (render_texture.width, render_texture.height);
+    if size.0 == 0 || size.1 == 0 {
+        return;
+    }
+    let target = OffscreenTarget::get_or_resize(
+        &mut wgpu_handles.offscreen_targets,
+        &wgpu_handles.device,
+        &render_texture.name,
+        size,
+        material_manager.color_format.unwrap(),
+    );
+    let texture_view = target.texture.create_view(&Default::default());
+    encode_clear(
+        &mut staging_belt.command_encoder,
+        &texture_view,
+        render_texture.clear_color,
+    );
+
+    let mut own_scene = render_texture.scene.take();
+    let drawn_scene = match &mut own_scene {
+        Some(own_scene) => {
+            own_scene.render_stats = RenderStats::default();
+            own_scene.time = scene.time;
+            own_scene
+        }
+        None => &mut *scene,
+    };
+    if drawn_scene.fit_camera_to_target {
+        render_texture
+            .camera
+            .fit_to_render_target(RenderTargetSize {
+                width: size.0,
+                height: size.1,
+                scale_factor: 1.0,
+            });
+    }
+
+    std::mem::swap(&mut drawn_scene.camera, &mut render_texture.camera);
+    render(
+        drawn_scene,
+        wgpu_handles,
+        &texture_view,
+        size,
+        PixelRect::full(size),
+        // the targets of the render texture are used instead of the ones of a window
+        0,
+        &ViewKey::RenderTexture(render_texture.name.clone()),
+        Some(render_texture),
+        material_manager,
+        staging_belt,
+    );
+    std::mem::swap(&mut drawn_scene.camera, &mut render_texture.camera);
+
+    if let Some(own_scene) = own_scene {
+        scene.render_stats.accumulate(&own_scene.render_stats);
+        render_texture.scene = Some(own_scene);
+    }
+}
+
+/// Draw the scene with its camera into `rect`, using the depth and G-buffer targets of
+/// `window` unless it draws into a render texture. Drawing into a render texture leaves
+/// out the objects that sample it, and only draws its subtree if it has one. The level
+/// of detail groups are drawn at the levels they have in `view`.
+#[allow(clippy::too_many_arguments)]
 pub fn render(
     scene: &mut Scene,
     wgpu_handles: &mut WgpuHandles,
     texture_view: &TextureView,
+    target_size: (u32, u32),
+    rect: PixelRect,
+    window: usize,
+    view: &ViewKey,
+    render_texture: Option<&RenderTexture>,
     material_manager: &mut Arc<MaterialManager>,
     staging_belt: &mut StagingBeltAndCommandEncoder,
 ) {
     let mut draw_state = DrawState::new(MaterialType::PhongMaterial, material_manager.clone());
-    draw_state.transform(&scene.camera.get_inverse_matrix());
+    draw_state.view_projection = scene.camera.get_inverse_matrix();
+    draw_state.render_texture = render_texture.map(|render_texture| render_texture.name.clone());
+    draw_state.layers = scene.camera.layers();
 
+    let phase_start = Instant::now();
+    update_lod_groups(
+        &mut scene.root,
+        scene.camera.world_to_local(),
+        &scene.camera.get_projection_matrix(),
+        view,
+        scene.time,
+    );
     // objects are only written once by policy
     wgpu_handles
         .material_manager
@@ -60,12 +427,140 @@ pub fn render(
     scene
         .root
         .write_matrices(wgpu_handles, &mut draw_state, staging_belt);
+    write_skins(&scene.root, wgpu_handles, staging_belt);
+    write_morphs(&scene.root, wgpu_handles, staging_belt);
+    draw_state.stats.cpu_times.matrices = phase_start.elapsed();
+
+    let phase_start = Instant::now();
     scene
         .root
         .write_materials(wgpu_handles, material_manager, staging_belt);
+    draw_state.stats.cpu_times.materials = phase_start.elapsed();
 
+    let phase_start = Instant::now();
     scene.write_lights(wgpu_handles, material_manager, staging_belt);
     scene.write_view_info(wgpu_handles, material_manager, staging_belt);
+    draw_state.stats.cpu_times.lights = phase_start.elapsed();
+
+    let phase_start = Instant::now();
+    let (depth_texture_slot, g_buffer_slot) = match render_texture {
+        Some(render_texture) => {
+            let target = wgpu_handles
+                .offscreen_targets
+                .get_mut(&render_texture.name)
+                .unwrap();
+            (&mut target.depth_texture, &mut target.g_buffer)
+        }
+        None => {
+            let targets = wgpu_handles.window_targets.entry(window).or_default();
+            (&mut targets.depth_texture, &mut targets.g_buffer)
+        }
+    };
+    let depth_texture =
+        DepthTexture::get_or_resize(depth_texture_slot, &wgpu_handles.device, target_size);
+
+    let drawn_root = match render_texture.and_then(|render_texture| render_texture.subtree) {
+        Some(id) => scene.root.find_by_id(id),
+        None => Some(&scene.root),
+    };
+
+    // deformed meshes are drawn with their own pipelines after the others
+    let deformations: Vec<Deformation> = Deformation::iter()
+        .filter(|deformation| {
+            *deformation == Deformation::None || scene.root.has_deformation(*deformation)
+        })
+        .collect();
+
+    // ambient occlusion reads the depth and the ambient light of the surfaces from the
+    // G-buffer, the forward path fills it first when it is on
+    let ambient_occlusion = scene.render_settings.ambient_occlusion.as_ref();
+    let g_buffer = match (scene.render_settings.path, ambient_occlusion) {
+        (RenderPath::Forward | RenderPath::Clustered, None) => None,
+        _ => Some(GBuffer::get_or_resize(
+            g_buffer_slot,
+            &wgpu_handles.device,
+            material_manager
+                .deferred_view_bind_group_layout
+                .as_ref()
+                .unwrap(),
+            target_size,
+        )),
+    };
+
+    if scene.render_settings.path == RenderPath::Deferred {
+        let g_buffer = g_buffer.unwrap();
+        encode_deferred_passes(
+            &scene.lights,
+            &scene.camera,
+            drawn_root,
+            &deformations,
+            &mut draw_state,
+            material_manager,
+            staging_belt,
+            &wgpu_handles.device,
+            wgpu_handles.gpu_timer.as_mut(),
+            texture_view,
+            depth_texture,
+            g_buffer,
+            rect,
+        );
+        if let Some(settings) = ambient_occlusion {
+            encode_ambient_occlusion_passes(
+                settings,
+                &scene.lights,
+                &mut draw_state.stats,
+                material_manager,
+                staging_belt,
+                &wgpu_handles.device,
+                wgpu_handles.gpu_timer.as_mut(),
+                texture_view,
+                depth_texture,
+                g_buffer,
+                rect,
+            );
+        }
+        draw_state.stats.cpu_times.encoding = phase_start.elapsed();
+        scene.render_stats.accumulate(&draw_state.stats);
+        return;
+    }
+
+    if let Some(g_buffer) = &g_buffer {
+        encode_g_buffer_pass(
+            &scene.lights,
+            &scene.camera,
+            drawn_root,
+            &deformations,
+            &mut draw_state,
+            material_manager,
+            staging_belt,
+            &wgpu_handles.device,
+            wgpu_handles.gpu_timer.as_mut(),
+            depth_texture,
+            g_buffer,
+            rect,
+        );
+    }
+
+    // the clustered path draws each object once with all of its lights
+    let light_clusters = match scene.render_settings.path {
+        RenderPath::Clustered => encode_light_assignment(
+            &scene.lights,
+            &scene.camera,
+            &mut draw_state.stats,
+            material_manager,
+            staging_belt,
+            &wgpu_handles.device,
+            wgpu_handles.gpu_timer.as_mut(),
+            &mut wgpu_handles.light_clusters,
+            rect,
+        ),
+        _ => None,
+    };
+
+    let timestamp_writes = wgpu_handles
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("SceneRenderPass"));
 
     let mut render_pass =
         staging_belt
@@ -76,32 +571,90 @@ pub fn render(
                     view: texture_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 1.0,
-                            g: 0.6,
-                            b: 0.8,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Load,
                         store: wgpu::StoreOp::Store,
                     },
                 })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes,
                 occlusion_query_set: None,
             });
 
+    rect.set_viewport(&mut render_pass);
+
     // only a set of iterations for point light
-    for material_type in &[MaterialType::PhongMaterial] {
+    for material_type in &[
+        MaterialType::PhongMaterial,
+        MaterialType::PhongMaterialWithTexture,
+        MaterialType::TerrainMaterial,
+    ] {
         draw_state.current_material = material_type.to_owned();
 
-        render_pass.set_pipeline(material_manager.get_pipeline_for_material_type(material_type));
-        for light in &scene.lights {
-            match light {
-                Light::PointLight(point_light) => {
-                    render_pass.set_bind_group(2, point_light.bind_group.as_ref().unwrap(), &[]);
-                    render_pass.draw_object_3d(&mut draw_state, &scene.root);
+        for deformation in &deformations {
+            draw_state.deformation = *deformation;
+            if let Some(light_clusters) = &light_clusters {
+                render_pass.set_pipeline(
+                    material_manager
+                        .get_clustered_pipeline_for_material_type(material_type, *deformation),
+                );
+                draw_state.stats.pipeline_switches += 1;
+                // drawn like the first light, over what is behind
+                render_pass.set_blend_constant(wgpu::Color::TRANSPARENT);
+                render_pass.set_bind_group(2, light_clusters, &[]);
+                draw_state.stats.bind_group_switches += 1;
+                if let Some(drawn_root) = drawn_root {
+                    render_pass.draw_object_3d(&mut draw_state, drawn_root);
+                }
+                continue;
+            }
+            render_pass.set_pipeline(
+                material_manager
+                    .get_deformed_pipeline_for_material_type(material_type, *deformation),
+            );
+            draw_state.stats.pipeline_switches += 1;
+            for (index, light) in scene.lights.iter().enumerate() {
+                // see the blend state of the material pipelines
+                render_pass.set_blend_constant(match index {
+                    0 => wgpu::Color::TRANSPARENT,
+                    _ => wgpu::Color::WHITE,
+                });
+                render_pass.set_bind_group(
+                    2,
+                    light.as_point_light().bind_group.as_ref().unwrap(),
+                    &[],
+                );
+                draw_state.stats.bind_group_switches += 1;
+                if let Some(drawn_root) = drawn_root {
+                    render_pass.draw_object_3d(&mut draw_state, drawn_root);
                 }
             }
         }
     }
+    drop(render_pass);
+
+    if let (Some(settings), Some(g_buffer)) = (ambient_occlusion, g_buffer) {
+        encode_ambient_occlusion_passes(
+            settings,
+            &scene.lights,
+            &mut draw_state.stats,
+            material_manager,
+            staging_belt,
+            &wgpu_handles.device,
+            wgpu_handles.gpu_timer.as_mut(),
+            texture_view,
+            depth_texture,
+            g_buffer,
+            rect,
+        );
+    }
+    draw_state.stats.cpu_times.encoding = phase_start.elapsed();
+
+    scene.render_stats.accumulate(&draw_state.stats);
 }
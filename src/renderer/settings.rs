@@ -0,0 +1,48 @@
+/// How the objects of the scene are lit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderPath {
+    /// every object is drawn again for each light
+    #[default]
+    Forward,
+    /// the objects are drawn once into a G-buffer, and each light is drawn over the part
+    /// of the screen it reaches
+    Deferred,
+    /// a compute pass sorts the lights into clusters of the view, and every object is
+    /// drawn once with the lights of the clusters it is in
+    Clustered,
+}
+
+/// Choices of how the scene is rendered. The render paths look the same, the effects
+/// are added on top of either.
+#[derive(Clone, Debug, Default)]
+pub struct RenderSettings {
+    pub path: RenderPath,
+    /// none leaves the ambient light unoccluded
+    pub ambient_occlusion: Option<AmbientOcclusion>,
+}
+
+/// Screen-space ambient occlusion, darkening the ambient light in creases and where
+/// objects meet. It is found from the depth buffer, and the ambient light it takes away
+/// is read from the G-buffer, which the forward path fills first when it is on.
+#[derive(Clone, Debug)]
+pub struct AmbientOcclusion {
+    /// how far from a surface other surfaces occlude it, in world units
+    pub radius: f32,
+    /// 0 leaves the ambient light as it is, 1 removes it where a surface is fully occluded
+    pub intensity: f32,
+    /// points tested around each pixel, more is smoother and slower
+    pub sample_count: u32,
+    /// in pixels, of the blur that keeps to surfaces at the same depth
+    pub blur_radius: u32,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        AmbientOcclusion {
+            radius: 0.5,
+            intensity: 1.0,
+            sample_count: 16,
+            blur_radius: 2,
+        }
+    }
+}
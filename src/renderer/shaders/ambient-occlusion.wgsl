@@ -0,0 +1,163 @@
+struct DeferredView {
+    world_to_clip : mat4x4f,
+    clip_to_world : mat4x4f,
+    // x, y, width and height in pixels
+    viewport : vec4f,
+}
+
+struct AmbientOcclusionSettings {
+    radius : f32,
+    intensity : f32,
+    sample_count : u32,
+    blur_radius : u32,
+}
+
+@group(0) @binding(1)
+var<uniform> view : DeferredView;
+@group(1) @binding(0)
+var<uniform> settings : AmbientOcclusionSettings;
+@group(1) @binding(1)
+var depth_texture : texture_2d<f32>;
+// the unblurred occlusion for the blur passes
+@group(1) @binding(2)
+var occlusion : texture_2d<f32>;
+
+// a single triangle covering the whole target
+@vertex
+fn vertex_main(@builtin(vertex_index) index : u32) -> @builtin(position) vec4f {
+    var corner = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4f(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn clamp_to_viewport(pixel : vec2i) -> vec2i {
+    var first = vec2i(view.viewport.xy);
+    return clamp(pixel, first, first + vec2i(view.viewport.zw) - 1);
+}
+
+fn depth_at(pixel : vec2i) -> f32 {
+    return textureLoad(depth_texture, clamp_to_viewport(pixel), 0).r;
+}
+
+// The point with this depth that is seen through a position in the render target.
+fn world_position(frag_position : vec2f, depth : f32) -> vec3f {
+    var viewport = view.viewport;
+    var ndc = vec2f(frag_position.x - viewport.x, viewport.y + viewport.w - frag_position.y) / viewport.zw * 2.0 - 1.0;
+    var position = view.clip_to_world * vec4f(ndc, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+fn pixel_position(pixel : vec2i) -> vec3f {
+    return world_position(vec2f(pixel) + 0.5, depth_at(pixel));
+}
+
+// The step to the neighbour in `offset` or the one opposite to it, whichever is closer in
+// depth, so that the normals do not bend over the edges of objects.
+fn neighbour_step(pixel : vec2i, offset : vec2i, center : vec3f, depth : f32) -> vec3f {
+    if abs(depth_at(pixel + offset) - depth) < abs(depth_at(pixel - offset) - depth) {
+        return pixel_position(pixel + offset) - center;
+    }
+    return center - pixel_position(pixel - offset);
+}
+
+// The normal of the surface from the depth of the pixels around it, facing the camera.
+fn reconstructed_normal(frag_position : vec2f, center : vec3f, depth : f32) -> vec3f {
+    var pixel = vec2i(frag_position);
+    var normal = normalize(cross(
+        neighbour_step(pixel, vec2i(1, 0), center, depth),
+        neighbour_step(pixel, vec2i(0, 1), center, depth),
+    ));
+    var toward_camera = world_position(frag_position, 0.0) - world_position(frag_position, 0.5);
+    if dot(normal, toward_camera) < 0.0 {
+        return -normal;
+    }
+    return normal;
+}
+
+// How much of the ambient light reaches the surface. The points of a hemisphere around
+// its normal pick the pixels to look at, and the surfaces seen there occlude it the
+// more the higher they rise above it and the closer they are.
+@fragment
+fn fragment_occlusion(@builtin(position) frag_position : vec4f) -> @location(0) vec4f {
+    var depth = depth_at(vec2i(frag_position.xy));
+    if depth >= 1.0 {
+        return vec4f(1.0);
+    }
+    var center = world_position(frag_position.xy, depth);
+    var normal = reconstructed_normal(frag_position.xy, center, depth);
+    var helper = select(vec3f(1.0, 0.0, 0.0), vec3f(0.0, 1.0, 0.0), abs(normal.y) < 0.99);
+    var tangent = normalize(cross(helper, normal));
+    var bitangent = cross(normal, tangent);
+
+    // neighbouring pixels turn the samples differently, the blur smooths that out
+    var noise = fract(52.9829189 * fract(dot(frag_position.xy, vec2f(0.06711056, 0.00583715))));
+    var sample_count = max(settings.sample_count, 1u);
+    var occluded = 0.0;
+    for (var index = 0u; index < sample_count; index++) {
+        // a spiral over the hemisphere
+        var height = 1.0 - (f32(index) + 0.5) / f32(sample_count);
+        var angle = (f32(index) + noise) * 2.39996323;
+        var direction = (tangent * cos(angle) + bitangent * sin(angle)) * sqrt(1.0 - height * height) + normal * height;
+        // more of the samples close to the surface, where the contact shadows are
+        var scale = fract(noise + f32(index) * 0.618034);
+        var reach = settings.radius * mix(0.1, 1.0, scale * scale);
+
+        var clip = view.world_to_clip * vec4f(center + direction * reach, 1.0);
+        var ndc = clip.xy / clip.w;
+        if clip.w <= 0.0 || any(abs(ndc) > vec2f(1.0)) {
+            continue;
+        }
+        var sample_pixel = vec2i(view.viewport.xy + vec2f(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * view.viewport.zw);
+        var sample_depth = depth_at(sample_pixel);
+        if sample_depth >= 1.0 {
+            continue;
+        }
+        // surfaces in the same plane do not occlude each other, the small bias keeps
+        // the steps of the depth buffer from doing so
+        var to_occluder = world_position(vec2f(sample_pixel) + 0.5, sample_depth) - center;
+        var occluder_distance = max(length(to_occluder), 1e-4);
+        var rise = max(0.0, dot(to_occluder, normal) / occluder_distance - 0.1) / 0.9;
+        var falloff = max(0.0, 1.0 - occluder_distance * occluder_distance / (settings.radius * settings.radius));
+        occluded += rise * falloff;
+    }
+
+    var visibility = 1.0 - settings.intensity * occluded / f32(sample_count);
+    return vec4f(clamp(visibility, 0.0, 1.0), 0.0, 0.0, 1.0);
+}
+
+// Blur along one axis, leaving out the pixels of surfaces away from this one.
+fn blur(frag_position : vec2f, axis : vec2i) -> vec4f {
+    var pixel = vec2i(frag_position);
+    var depth = depth_at(pixel);
+    if depth >= 1.0 {
+        return vec4f(1.0);
+    }
+    var center = world_position(frag_position, depth);
+    var radius = i32(settings.blur_radius);
+
+    var total = 0.0;
+    var total_weight = 0.0;
+    for (var offset = -radius; offset <= radius; offset++) {
+        var tap = clamp_to_viewport(pixel + axis * offset);
+        var tap_depth = depth_at(tap);
+        if tap_depth >= 1.0 {
+            continue;
+        }
+        var spread = f32(offset) / f32(radius + 1);
+        var closeness = max(0.0, 1.0 - distance(center, pixel_position(tap)) / settings.radius);
+        var weight = exp(-2.0 * spread * spread) * closeness;
+        total += textureLoad(occlusion, tap, 0).r * weight;
+        total_weight += weight;
+    }
+
+    return vec4f(total / max(total_weight, 1e-4), 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn fragment_blur_horizontal(@builtin(position) frag_position : vec4f) -> @location(0) vec4f {
+    return blur(frag_position.xy, vec2i(1, 0));
+}
+
+@fragment
+fn fragment_blur_vertical(@builtin(position) frag_position : vec4f) -> @location(0) vec4f {
+    return blur(frag_position.xy, vec2i(0, 1));
+}
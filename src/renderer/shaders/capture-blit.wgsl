@@ -0,0 +1,15 @@
+@group(0) @binding(0)
+var frame : texture_2d<f32>;
+
+// a single triangle covering the whole target
+@vertex
+fn vertex_main(@builtin(vertex_index) index : u32) -> @builtin(position) vec4f {
+    var corner = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4f(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// the frame and the target have the same size, so each pixel copies one texel
+@fragment
+fn fragment_main(@builtin(position) position : vec4f) -> @location(0) vec4f {
+    return textureLoad(frame, vec2i(position.xy), 0);
+}
@@ -0,0 +1,12 @@
+// a single triangle covering the whole viewport, the blend constant multiplies the
+// white output into the clear color
+@vertex
+fn vertex_main(@builtin(vertex_index) index : u32) -> @builtin(position) vec4f {
+    var corner = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4f(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fragment_main() -> @location(0) vec4f {
+    return vec4f(1.0);
+}
@@ -0,0 +1,149 @@
+struct Grid {
+    local_to_clip : mat4x4f,
+    clip_to_local : mat4x4f,
+    // the camera in the space of the grid
+    eye : vec4f,
+    minor_color : vec4f,
+    major_color : vec4f,
+    // x, y, width and height in pixels
+    viewport : vec4f,
+    // cell size, lines per major line, line width and axis width
+    params : vec4f,
+    // distance the lines have faded out at, 0 for no fading, and 1 to show the axes
+    fade : vec4f,
+}
+
+struct GridOut {
+    @location(0) color : vec4f,
+    @builtin(frag_depth) depth : f32,
+}
+
+const X_AXIS_COLOR = vec4f(0.9, 0.2, 0.2, 1.0);
+const Y_AXIS_COLOR = vec4f(0.2, 0.8, 0.2, 1.0);
+const Z_AXIS_COLOR = vec4f(0.2, 0.4, 0.9, 1.0);
+// the cells of the finest level that is fully shown are at least this many pixels wide
+const MIN_CELL_PIXELS = 8.0;
+
+@group(0) @binding(0)
+var<uniform> grid : Grid;
+
+// a single triangle covering the whole viewport
+@vertex
+fn vertex_main(@builtin(vertex_index) index : u32) -> @builtin(position) vec4f {
+    var corner = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4f(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn to_ndc(frag_position : vec2f) -> vec2f {
+    var viewport = grid.viewport;
+    return vec2f(frag_position.x - viewport.x, viewport.y + viewport.w - frag_position.y) / viewport.zw * 2.0 - 1.0;
+}
+
+fn to_local(ndc : vec2f, depth : f32) -> vec3f {
+    var position = grid.clip_to_local * vec4f(ndc, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// how much of the pixel is covered by the lines `spacing` apart, `derivative` is the
+// size of the pixel on the plane
+fn line_coverage(coord : vec2f, derivative : vec2f, spacing : f32, width : f32) -> f32 {
+    var distance = abs(fract(coord / spacing + 0.5) - 0.5) * spacing / derivative;
+    var coverage = clamp(width * 0.5 + 0.5 - distance, vec2f(0.0), vec2f(1.0));
+    return max(coverage.x, coverage.y);
+}
+
+// `color` over `below`, both with straight alpha
+fn over(color : vec4f, below : vec4f) -> vec4f {
+    var alpha = color.a + below.a * (1.0 - color.a);
+    if alpha <= 0.0 {
+        return vec4f(0.0);
+    }
+    return vec4f((color.rgb * color.a + below.rgb * below.a * (1.0 - color.a)) / alpha, alpha);
+}
+
+fn depth_of(position : vec3f) -> f32 {
+    var clip = grid.local_to_clip * vec4f(position, 1.0);
+    return clip.z / clip.w;
+}
+
+// The view ray of the pixel meets the plane of the grid, the lines are drawn where it
+// does. Orthographic and perspective cameras are alike here.
+@fragment
+fn fragment_main(@builtin(position) frag_position : vec4f) -> GridOut {
+    var ndc = to_ndc(frag_position.xy);
+    var near = to_local(ndc, 0.0);
+    var far = to_local(ndc, 1.0);
+    var direction = far - near;
+    var t = -near.y / direction.y;
+    var position = near + direction * t;
+
+    // derivatives are taken before any pixel is discarded
+    var coord = position.xz;
+    var derivative = max(fwidth(coord), vec2f(1e-6));
+    var pixel_size = max(length(vec2f(dpdx(coord.x), dpdy(coord.x))), length(vec2f(dpdx(coord.y), dpdy(coord.y))));
+
+    var cell_size = grid.params.x;
+    var major_every = max(grid.params.y, 2.0);
+    var lod = max(log(pixel_size * MIN_CELL_PIXELS / cell_size) / log(major_every), 0.0);
+    var level_fade = fract(lod);
+    var spacing = cell_size * pow(major_every, floor(lod));
+
+    // the finest level fades out while the next one turns from major to minor lines
+    var width = grid.params.z;
+    var color = grid.minor_color;
+    color.a *= line_coverage(coord, derivative, spacing, width) * (1.0 - level_fade);
+    var middle = mix(grid.major_color, grid.minor_color, level_fade);
+    middle.a *= line_coverage(coord, derivative, spacing * major_every, width);
+    color = over(middle, color);
+    var major = grid.major_color;
+    major.a *= line_coverage(coord, derivative, spacing * major_every * major_every, width);
+    color = over(major, color);
+
+    if grid.fade.y > 0.5 {
+        var axis_width = grid.params.w;
+        var x_axis = X_AXIS_COLOR;
+        x_axis.a *= clamp(axis_width * 0.5 + 0.5 - abs(coord.y) / derivative.y, 0.0, 1.0);
+        var z_axis = Z_AXIS_COLOR;
+        z_axis.a *= clamp(axis_width * 0.5 + 0.5 - abs(coord.x) / derivative.x, 0.0, 1.0);
+        color = over(z_axis, over(x_axis, color));
+    }
+
+    var fade_distance = grid.fade.x;
+    if fade_distance > 0.0 {
+        color.a *= 1.0 - smoothstep(fade_distance * 0.5, fade_distance, distance(position, grid.eye.xyz));
+    }
+
+    // the ray misses the plane or meets it outside the depth range
+    if abs(direction.y) < 1e-6 || t < 0.0 || t > 1.0 || color.a <= 0.0 {
+        discard;
+    }
+    return GridOut(color, depth_of(position));
+}
+
+// The point of the y axis closest to the view ray of the pixel, drawn where it is less
+// than half the axis width away on the screen.
+@fragment
+fn fragment_y_axis(@builtin(position) frag_position : vec4f) -> GridOut {
+    var near = to_local(to_ndc(frag_position.xy), 0.0);
+    var direction = to_local(to_ndc(frag_position.xy), 1.0) - near;
+
+    var length_squared = dot(direction, direction);
+    var denominator = length_squared - direction.y * direction.y;
+    if denominator < 1e-6 * length_squared {
+        discard;
+    }
+    var height = (length_squared * near.y - direction.y * dot(direction, near)) / denominator;
+
+    var clip = grid.local_to_clip * vec4f(0.0, height, 0.0, 1.0);
+    if clip.w <= 0.0 {
+        discard;
+    }
+    var depth = clip.z / clip.w;
+    var viewport = grid.viewport;
+    var pixel = viewport.xy + (clip.xy / clip.w * vec2f(0.5, -0.5) + 0.5) * viewport.zw;
+    var coverage = clamp(grid.params.w * 0.5 + 0.5 - distance(pixel, frag_position.xy), 0.0, 1.0);
+    if depth < 0.0 || depth > 1.0 || coverage <= 0.0 {
+        discard;
+    }
+    return GridOut(vec4f(Y_AXIS_COLOR.rgb, Y_AXIS_COLOR.a * coverage), depth);
+}
@@ -0,0 +1,107 @@
+struct ClusterLight {
+    position : vec4f,
+    color : vec3f,
+    // 0 for lights that reach everywhere
+    range : f32,
+    // the objects it lights are on one of these layers
+    layers : u32,
+    // the direction of the cone of spot lights, w is 0 for point lights
+    direction : vec4f,
+    // cosines of the inner and outer angle of the cone, and the sine of the outer angle
+    cone : vec4f,
+}
+
+struct Clusters {
+    world_to_view : mat4x4f,
+    clip_to_view : mat4x4f,
+    // x, y, width and height in pixels
+    viewport : vec4f,
+    // clusters along the width, height and depth of the view, and the number of lights
+    counts : vec4u,
+    // view depths of the near and far plane the depth slices are spread between
+    depth_range : vec4f,
+}
+
+@group(0) @binding(0)
+var<storage, read> cluster_lights : array<ClusterLight>;
+// for each cluster, the number of its lights and their indices
+@group(0) @binding(1)
+var<storage, read_write> cluster_light_indices : array<u32>;
+@group(0) @binding(2)
+var<uniform> clusters : Clusters;
+
+// lights a cluster holds at most, see `MAX_CLUSTER_LIGHTS`
+const max_cluster_lights : u32 = 128u;
+
+// View depth of the front of a depth slice. The slices get deeper with the distance, so
+// that the clusters are about as deep as they are wide.
+fn slice_depth(slice : u32) -> f32 {
+    var near = clusters.depth_range.x;
+    var far = clusters.depth_range.y;
+    return near * pow(far / near, f32(slice) / f32(clusters.counts.z));
+}
+
+// The view space point seen through `ndc` at a view depth, for both kinds of cameras.
+fn view_point(ndc : vec2f, depth : f32) -> vec3f {
+    var near = clusters.clip_to_view * vec4f(ndc, 0.0, 1.0);
+    var middle = clusters.clip_to_view * vec4f(ndc, 0.5, 1.0);
+    var a = near.xyz / near.w;
+    var b = middle.xyz / middle.w;
+    return mix(a, b, (depth + a.z) / (a.z - b.z));
+}
+
+// Whether the cone of a spot light misses the sphere around a cluster, everything in
+// view space. Cones wider than a half space are only tested against their range.
+fn outside_cone(light : ClusterLight, apex : vec3f, center : vec3f, radius : f32) -> bool {
+    if light.direction.w <= 0.0 || light.cone.y <= 0.0 {
+        return false;
+    }
+    var direction = normalize((clusters.world_to_view * vec4f(light.direction.xyz, 0.0)).xyz);
+    var v = center - apex;
+    var along = dot(v, direction);
+    var across = sqrt(max(dot(v, v) - along * along, 0.0));
+    // distance from the center to the surface of the cone
+    var distance = light.cone.y * across - light.cone.z * along;
+    return distance > radius || along < -radius;
+}
+
+// One invocation for each cluster, collecting the lights whose range touches the box
+// around it, and for spot lights whose cone touches the sphere around the box.
+@compute @workgroup_size(4, 4, 4)
+fn assign_lights(@builtin(global_invocation_id) cluster : vec3u) {
+    var counts = clusters.counts;
+    if any(cluster >= counts.xyz) {
+        return;
+    }
+
+    // tiles are counted from the top left like pixels
+    var corner_min = vec2f(cluster.xy) / vec2f(counts.xy);
+    var corner_max = vec2f(cluster.xy + 1u) / vec2f(counts.xy);
+    var ndc_min = vec2f(corner_min.x, 1.0 - corner_max.y) * 2.0 - 1.0;
+    var ndc_max = vec2f(corner_max.x, 1.0 - corner_min.y) * 2.0 - 1.0;
+    var bounds_min = vec3f(1e30);
+    var bounds_max = vec3f(-1e30);
+    for (var corner = 0u; corner < 8u; corner++) {
+        var ndc = select(ndc_min, ndc_max, (vec2u(corner, corner >> 1u) & vec2u(1u)) == vec2u(1u));
+        var point = view_point(ndc, slice_depth(cluster.z + (corner >> 2u)));
+        bounds_min = min(bounds_min, point);
+        bounds_max = max(bounds_max, point);
+    }
+
+    var sphere_center = 0.5 * (bounds_min + bounds_max);
+    var sphere_radius = 0.5 * length(bounds_max - bounds_min);
+
+    var first = ((cluster.z * counts.y + cluster.y) * counts.x + cluster.x) * (max_cluster_lights + 1u);
+    var count = 0u;
+    for (var index = 0u; index < counts.w && count < max_cluster_lights; index++) {
+        var light = cluster_lights[index];
+        var center = (clusters.world_to_view * vec4f(light.position.xyz, 1.0)).xyz;
+        var offset = clamp(center, bounds_min, bounds_max) - center;
+        var in_range = light.range <= 0.0 || dot(offset, offset) <= light.range * light.range;
+        if in_range && !outside_cone(light, center, sphere_center, sphere_radius) {
+            cluster_light_indices[first + 1u + count] = index;
+            count++;
+        }
+    }
+    cluster_light_indices[first] = count;
+}
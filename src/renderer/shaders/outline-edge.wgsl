@@ -0,0 +1,44 @@
+struct OutlineSettings {
+    @location(0) color : vec4f,
+    @location(1) width : u32,
+}
+
+@group(0) @binding(0)
+var mask : texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> settings : OutlineSettings;
+
+// a single triangle covering the whole target
+@vertex
+fn vertex_main(@builtin(vertex_index) index : u32) -> @builtin(position) vec4f {
+    var corner = vec2f(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4f(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn is_masked(pixel : vec2i) -> bool {
+    var last = vec2i(textureDimensions(mask)) - 1;
+    return textureLoad(mask, clamp(pixel, vec2i(0), last), 0).r > 0.5;
+}
+
+@fragment
+fn fragment_main(@builtin(position) position : vec4f) -> @location(0) vec4f {
+    var center = vec2i(position.xy);
+    if is_masked(center) {
+        discard;
+    }
+
+    var width = i32(settings.width);
+    var found = false;
+    for (var y = -width; y <= width; y++) {
+        for (var x = -width; x <= width; x++) {
+            if x * x + y * y <= width * width && is_masked(center + vec2i(x, y)) {
+                found = true;
+            }
+        }
+    }
+
+    if !found {
+        discard;
+    }
+    return settings.color;
+}
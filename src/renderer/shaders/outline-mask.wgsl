@@ -0,0 +1,75 @@
+struct ProjectionMatrix {
+    @location(0) matrix: mat4x4f,
+    @location(1) matrix_inverse: mat4x4f,
+}
+
+// position and normal offsets of one vertex in a morph target
+struct MorphDelta {
+    position : vec4f,
+    normal : vec4f,
+}
+
+struct MorphWeights {
+    vertex_count : u32,
+    target_count : u32,
+    weights : array<vec4f, 4>,
+}
+
+@group(0) @binding(0)
+var<uniform> projection_matrix : ProjectionMatrix;
+
+// Deformed meshes only, the group the material pipelines bind at 3
+@group(1) @binding(0)
+var<uniform> joint_matrices : array<mat4x4f, 64>;
+@group(1) @binding(1)
+var<storage, read> morph_deltas : array<MorphDelta>;
+@group(1) @binding(2)
+var<uniform> morph_weights : MorphWeights;
+
+// invariant and deformed the way the scene pass does it, so depths match the ones it
+// wrote
+@vertex
+fn vertex_main(@location(0) position : vec4f) -> @builtin(position) @invariant vec4f {
+    return projection_matrix.matrix * position;
+}
+
+@vertex
+fn vertex_main_skinned(@location(0) position : vec4f, @location(3) joints : vec4u, @location(4) weights : vec4f) -> @builtin(position) @invariant vec4f {
+    return projection_matrix.matrix * (skin_matrix(joints, weights) * position);
+}
+
+@vertex
+fn vertex_main_morphed(@builtin(vertex_index) vertex_index : u32, @location(0) position : vec4f) -> @builtin(position) @invariant vec4f {
+    return projection_matrix.matrix * morph(vertex_index, position, vec4f()).position;
+}
+
+@vertex
+fn vertex_main_skinned_morphed(@builtin(vertex_index) vertex_index : u32, @location(0) position : vec4f, @location(3) joints : vec4u, @location(4) weights : vec4f) -> @builtin(position) @invariant vec4f {
+    let morphed = morph(vertex_index, position, vec4f());
+    return projection_matrix.matrix * (skin_matrix(joints, weights) * morphed.position);
+}
+
+fn skin_matrix(joints : vec4u, weights : vec4f) -> mat4x4f {
+    return weights.x * joint_matrices[joints.x]
+        + weights.y * joint_matrices[joints.y]
+        + weights.z * joint_matrices[joints.z]
+        + weights.w * joint_matrices[joints.w];
+}
+
+fn morph(vertex_index : u32, position : vec4f, normal : vec4f) -> MorphDelta {
+    var morphed = MorphDelta(position, normal);
+    for (var target_index = 0u; target_index < morph_weights.target_count; target_index++) {
+        let weight = morph_weights.weights[target_index / 4u][target_index % 4u];
+        if (weight != 0.0) {
+            let delta = morph_deltas[target_index * morph_weights.vertex_count + vertex_index];
+            morphed.position += weight * delta.position;
+            morphed.normal += weight * delta.normal;
+        }
+    }
+    return morphed;
+}
+
+@fragment
+fn fragment_main() -> @location(0) vec4f {
+    return vec4f(1.0);
+}
@@ -0,0 +1,72 @@
+struct Particle {
+    position : vec3f,
+    age : f32,
+    velocity : vec3f,
+    lifetime : f32,
+    color : vec4f,
+    size : f32,
+}
+
+struct Emitter {
+    world_matrix : mat4x4f,
+    velocity : vec4f,
+    velocity_spread : vec4f,
+    // drag in w
+    gravity : vec4f,
+    start_color : vec4f,
+    end_color : vec4f,
+    // start size, end size, lifetime, time step
+    params : vec4f,
+    // first spawned particle, spawn count, particle count, frame
+    spawn : vec4u,
+}
+
+@group(0) @binding(0)
+var<uniform> emitter : Emitter;
+@group(0) @binding(1)
+var<storage, read_write> particles : array<Particle>;
+
+// PCG hash, good enough for spreading particles around
+fn hash(value : u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// -1 to 1
+fn random(seed : u32) -> f32 {
+    return f32(hash(seed)) / 2147483647.5 - 1.0;
+}
+
+@compute @workgroup_size(64)
+fn compute_main(@builtin(global_invocation_id) id : vec3u) {
+    let count = emitter.spawn.z;
+    let index = id.x;
+    if (index >= count) {
+        return;
+    }
+    var particle = particles[index];
+    let time_step = emitter.params.w;
+
+    // distance from the first particle spawned this frame along the ring
+    let spawn_offset = (index + count - emitter.spawn.x) % count;
+    if (spawn_offset < emitter.spawn.y) {
+        let seed = hash(index ^ hash(emitter.spawn.w));
+        let spread = vec3f(random(seed), random(seed + 1u), random(seed + 2u));
+        let velocity = emitter.velocity.xyz + spread * emitter.velocity_spread.xyz;
+        particle.position = (emitter.world_matrix * vec4f(0.0, 0.0, 0.0, 1.0)).xyz;
+        particle.velocity = (emitter.world_matrix * vec4f(velocity, 0.0)).xyz;
+        particle.age = 0.0;
+        particle.lifetime = emitter.params.z;
+    } else if (particle.age < particle.lifetime) {
+        particle.velocity += emitter.gravity.xyz * time_step;
+        particle.velocity *= max(1.0 - emitter.gravity.w * time_step, 0.0);
+        particle.position += particle.velocity * time_step;
+        particle.age += time_step;
+    }
+
+    let life = clamp(particle.age / max(particle.lifetime, 0.0001), 0.0, 1.0);
+    particle.color = mix(emitter.start_color, emitter.end_color, life);
+    particle.size = mix(emitter.params.x, emitter.params.y, life);
+    particles[index] = particle;
+}
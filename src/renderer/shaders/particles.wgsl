@@ -0,0 +1,59 @@
+struct ParticleIn {
+    // position and age
+    @location(0) position_age : vec4f,
+    // velocity and lifetime
+    @location(1) velocity_lifetime : vec4f,
+    @location(2) color : vec4f,
+    // size and padding
+    @location(3) size : vec4f,
+}
+
+struct Camera {
+    view_projection : mat4x4f,
+    // world space directions of the screen axes
+    right : vec4f,
+    up : vec4f,
+}
+
+struct VertexOut {
+    @builtin(position) position : vec4f,
+    // -1 to 1 across the billboard
+    @location(0) corner : vec2f,
+    @location(1) color : vec4f,
+}
+
+@group(0) @binding(0)
+var<uniform> camera : Camera;
+
+@vertex
+fn vertex_main(@builtin(vertex_index) vertex_index : u32, input : ParticleIn) -> VertexOut {
+    var output : VertexOut;
+    var corners = array<vec2f, 6>(
+        vec2f(-1.0, -1.0),
+        vec2f(1.0, -1.0),
+        vec2f(-1.0, 1.0),
+        vec2f(-1.0, 1.0),
+        vec2f(1.0, -1.0),
+        vec2f(1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    // dead particles end up outside the clip volume
+    if (input.position_age.w >= input.velocity_lifetime.w) {
+        output.position = vec4f(2.0, 2.0, 2.0, 1.0);
+        return output;
+    }
+    let half_size = input.size.x * 0.5;
+    let offset = (camera.right.xyz * corner.x + camera.up.xyz * corner.y) * half_size;
+    output.position = camera.view_projection * vec4f(input.position_age.xyz + offset, 1.0);
+    output.corner = corner;
+    output.color = input.color;
+    return output;
+}
+
+@fragment
+fn fragment_main(input : VertexOut) -> @location(0) vec4f {
+    // soft round particles
+    let falloff = 1.0 - smoothstep(0.5, 1.0, length(input.corner));
+    return vec4f(input.color.rgb, input.color.a * falloff);
+}
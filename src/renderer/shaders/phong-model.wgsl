@@ -5,6 +5,14 @@ const camera_position: vec4f = vec4f(0, 0, 2, 1);
 struct ProjectionMatrix {
     @location(0) matrix: mat4x4f,
     @location(1) matrix_inverse: mat4x4f,
+    @location(2) object_id: u32,
+    @location(3) layers: u32,
+    // the range of the dither pattern that is drawn
+    @location(4) fade: vec2f,
+    // local to world, lighting and fog are done in world space
+    @location(5) model: mat4x4f,
+    // inverse transpose of the model matrix, for the normals
+    @location(6) normal_matrix: mat4x4f,
 }
 
 struct Material {
@@ -17,17 +25,109 @@ struct Material {
 struct PointLight {
     @location(0) position : vec4f,
     @location(1) color : vec3f,
+    // 0 for lights that reach everywhere
+    @location(2) range : f32,
+    // the first light also draws the ambient light and the fog, the others add to it
+    @location(3) is_first : u32,
+    // the objects it lights are on one of these layers
+    @location(4) layers : u32,
+    // the direction of the cone of spot lights, w is 1 for spot lights and 0 for point
+    // lights, which shine everywhere
+    @location(5) direction : vec4f,
+    // cosines of the inner and outer angle of the cone, and the sine of the outer angle
+    @location(6) cone : vec4f,
+}
+
+struct Fog {
+    @location(0) distance_color : vec3f,
+    // 0 for none, 1 linear, 2 exponential, 3 exponential squared
+    @location(1) distance_mode : u32,
+    // start, end, density
+    @location(2) distance_params : vec4f,
+    @location(3) height_color : vec3f,
+    @location(4) height_enabled : u32,
+    // height, density, falloff
+    @location(5) height_params : vec4f,
 }
 
 struct ViewInfo {
     @location(0) position : vec4f,
+    @location(1) camera_position : vec4f,
+    @location(2) fog : Fog,
+}
+
+// position and normal offsets of one vertex in a morph target
+struct MorphDelta {
+    position : vec4f,
+    normal : vec4f,
+}
+
+struct MorphWeights {
+    vertex_count : u32,
+    target_count : u32,
+    weights : array<vec4f, 4>,
+}
+
+struct TerrainLayers {
+    // how many times each layer repeats across the terrain
+    tiling : vec4f,
+    layer_count : u32,
+}
+
+struct DeferredView {
+    world_to_clip : mat4x4f,
+    clip_to_world : mat4x4f,
+    // x, y, width and height in pixels
+    viewport : vec4f,
+}
+
+// A light as the clustered path reads it from the light list, like `PointLight` without
+// `is_first`.
+struct ClusterLight {
+    position : vec4f,
+    color : vec3f,
+    range : f32,
+    layers : u32,
+    direction : vec4f,
+    cone : vec4f,
+}
+
+struct Clusters {
+    world_to_view : mat4x4f,
+    clip_to_view : mat4x4f,
+    // x, y, width and height in pixels
+    viewport : vec4f,
+    // clusters along the width, height and depth of the view, and the number of lights
+    counts : vec4u,
+    // view depths of the near and far plane the depth slices are spread between
+    depth_range : vec4f,
 }
 
 struct VertexOut {
-  @builtin(position) position : vec4f,
+  @builtin(position) @invariant position : vec4f,
   @location(1) normal : vec4f,
-  @location(2) view_relative : vec4f,
-  @location(3) light_relative : vec4f,
+  @location(4) uv : vec2f,
+  @location(5) world_position : vec4f,
+}
+
+// What the lights need to know about a point of a surface.
+struct Surface {
+    position : vec4f,
+    normal : vec3f,
+    ambient : vec3f,
+    diffuse : vec3f,
+    specular : vec3f,
+    shininess : f32,
+}
+
+struct GBufferOut {
+    @location(0) ambient : vec4f,
+    @location(1) diffuse : vec4f,
+    // shininess in alpha
+    @location(2) specular : vec4f,
+    @location(3) normal : vec4f,
+    // the lights only light the surface if they share one of these
+    @location(4) layers : u32,
 }
 
 // Object Bindings
@@ -35,35 +135,445 @@ struct VertexOut {
 var<uniform> projection_matrix : ProjectionMatrix;
 @group(1) @binding(0)
 var<uniform> material : Material;
+@group(1) @binding(1)
+var diffuse_texture : texture_2d<f32>;
+@group(1) @binding(2)
+var diffuse_sampler : sampler;
+@group(1) @binding(3)
+var terrain_layer_0 : texture_2d<f32>;
+@group(1) @binding(4)
+var terrain_layer_1 : texture_2d<f32>;
+@group(1) @binding(5)
+var terrain_layer_2 : texture_2d<f32>;
+@group(1) @binding(6)
+var terrain_layer_3 : texture_2d<f32>;
+@group(1) @binding(7)
+var splat_map : texture_2d<f32>;
+@group(1) @binding(8)
+var<uniform> terrain_layers : TerrainLayers;
 @group(2) @binding(0)
 var<uniform> light : PointLight;
 @group(2) @binding(1)
 var<uniform> view_info : ViewInfo;
+// Skinned meshes only
+@group(3) @binding(0)
+var<uniform> joint_matrices : array<mat4x4f, 64>;
+// Morphed meshes only
+@group(3) @binding(1)
+var<storage, read> morph_deltas : array<MorphDelta>;
+@group(3) @binding(2)
+var<uniform> morph_weights : MorphWeights;
+// Deferred lighting only
+@group(0) @binding(1)
+var<uniform> deferred_view : DeferredView;
+@group(1) @binding(9)
+var g_buffer_ambient : texture_2d<f32>;
+@group(1) @binding(10)
+var g_buffer_diffuse : texture_2d<f32>;
+@group(1) @binding(11)
+var g_buffer_specular : texture_2d<f32>;
+@group(1) @binding(12)
+var g_buffer_normal : texture_2d<f32>;
+// bound as a float texture, which every backend can load from
+@group(1) @binding(13)
+var g_buffer_depth : texture_2d<f32>;
+@group(1) @binding(15)
+var g_buffer_layers : texture_2d<u32>;
+@group(1) @binding(14)
+var ambient_occlusion : texture_2d<f32>;
+
+// Clustered Lighting Bindings
+@group(2) @binding(2)
+var<storage, read> cluster_lights : array<ClusterLight>;
+// for each cluster, the number of its lights and their indices
+@group(2) @binding(3)
+var<storage, read> cluster_light_indices : array<u32>;
+@group(2) @binding(4)
+var<uniform> clusters : Clusters;
+
+// lights a cluster holds at most, see `MAX_CLUSTER_LIGHTS`
+const max_cluster_lights : u32 = 128u;
 
 @vertex
 fn vertex_main(@location(0) position : vec4f, @location(1) normal : vec4f, @location(2) uv : vec4f) -> VertexOut {
+    return transform_vertex(position, normal, uv);
+}
+
+@vertex
+fn vertex_main_skinned(@location(0) position : vec4f, @location(1) normal : vec4f, @location(2) uv : vec4f, @location(3) joints : vec4u, @location(4) weights : vec4f) -> VertexOut {
+    let skin_matrix = skin_matrix(joints, weights);
+    return transform_vertex(skin_matrix * position, skin_matrix * normal, uv);
+}
+
+@vertex
+fn vertex_main_morphed(@builtin(vertex_index) vertex_index : u32, @location(0) position : vec4f, @location(1) normal : vec4f, @location(2) uv : vec4f) -> VertexOut {
+    let morphed = morph(vertex_index, position, normal);
+    return transform_vertex(morphed.position, morphed.normal, uv);
+}
+
+// morph targets are applied in the bind pose, before the joints move the vertex
+@vertex
+fn vertex_main_skinned_morphed(@builtin(vertex_index) vertex_index : u32, @location(0) position : vec4f, @location(1) normal : vec4f, @location(2) uv : vec4f, @location(3) joints : vec4u, @location(4) weights : vec4f) -> VertexOut {
+    let morphed = morph(vertex_index, position, normal);
+    let skin_matrix = skin_matrix(joints, weights);
+    return transform_vertex(skin_matrix * morphed.position, skin_matrix * morphed.normal, uv);
+}
+
+fn skin_matrix(joints : vec4u, weights : vec4f) -> mat4x4f {
+    return weights.x * joint_matrices[joints.x]
+        + weights.y * joint_matrices[joints.y]
+        + weights.z * joint_matrices[joints.z]
+        + weights.w * joint_matrices[joints.w];
+}
+
+fn morph(vertex_index : u32, position : vec4f, normal : vec4f) -> MorphDelta {
+    var morphed = MorphDelta(position, normal);
+    for (var target_index = 0u; target_index < morph_weights.target_count; target_index++) {
+        let weight = morph_weights.weights[target_index / 4u][target_index % 4u];
+        if (weight != 0.0) {
+            let delta = morph_deltas[target_index * morph_weights.vertex_count + vertex_index];
+            morphed.position += weight * delta.position;
+            morphed.normal += weight * delta.normal;
+        }
+    }
+    return morphed;
+}
+
+fn transform_vertex(position : vec4f, normal : vec4f, uv : vec4f) -> VertexOut {
     var output: VertexOut;
     output.position = projection_matrix.matrix * position;
-    output.normal = normalize(normal);
-    output.view_relative = view_info.position - position;
-    output.light_relative = light.position - position;
+    output.normal = vec4f(normalize((projection_matrix.normal_matrix * vec4f(normal.xyz, 0.0)).xyz), 0.0);
+    output.uv = uv.xy;
+    output.world_position = projection_matrix.model * position;
     return output;
 }
 
-@fragment
-fn fragment_main(frag_data: VertexOut) -> @location(0) vec4f {
-    var normal = normalize(frag_data.normal);
-    var view_direction = normalize(frag_data.view_relative);
-    var light_direction = normalize(frag_data.light_relative);
+fn material_surface(frag_data: VertexOut, diffuse_color: vec3f) -> Surface {
+    return Surface(
+        frag_data.world_position,
+        normalize(frag_data.normal.xyz),
+        material.ka * diffuse_color,
+        material.kd * diffuse_color,
+        material.ks,
+        material.shininess,
+    );
+}
+
+// The diffuse and specular light of a light, the ambient light is left out.
+fn phong(surface: Surface, light: PointLight) -> vec3f {
+    var normal = surface.normal;
+    // w is 0 for orthographic cameras, then the position is the direction to the camera
+    var view_direction = normalize((view_info.position - surface.position * view_info.position.w).xyz);
+    var light_relative = (light.position - surface.position).xyz;
+    var light_direction = normalize(light_relative);
     var reflection_direction = normalize(2 * normal * dot(light_direction, normal) - light_direction);
     // var halfway_direction = 0.5 * (light_direction + view_direction);
-    // var attenuation = 16.0 / (dot(frag_data.view_relative, frag_data.view_relative) * dot(frag_data.light_relative, frag_data.light_relative));
-    
-    var ambient = material.ka;
-    var lambertian_diffuse = max(0.0, dot(light_direction, normal)) * light.color * material.kd;
-    var phong_specular = pow(max(0.0, dot(reflection_direction, view_direction)), material.shininess) * light.color * material.ks;
+
+    var lambertian_diffuse = max(0.0, dot(light_direction, normal)) * light.color * surface.diffuse;
+    var phong_specular = pow(max(0.0, dot(reflection_direction, view_direction)), surface.shininess) * light.color * surface.specular;
     //var blinn_phong_specular = pow(max(0.0, dot(normal, halfway_direction)), material.shininess);
 
-    var result = ambient + lambertian_diffuse + phong_specular;
-    return vec4f(result, 1);
+    return (lambertian_diffuse + phong_specular) * range_falloff(length(light_relative), light.range) * cone_falloff(light, light_direction);
+}
+
+// Spot lights fade out between the inner and the outer angle of their cone.
+fn cone_falloff(light: PointLight, light_direction: vec3f) -> f32 {
+    if (light.direction.w <= 0.0) {
+        return 1.0;
+    }
+    var cos_angle = dot(-light_direction, normalize(light.direction.xyz));
+    return smoothstep(light.cone.y, max(light.cone.x, light.cone.y + 1e-4), cos_angle);
+}
+
+// Lights with a range fade out smoothly towards its end.
+fn range_falloff(distance: f32, range: f32) -> f32 {
+    if (range <= 0.0) {
+        return 1.0;
+    }
+    var ratio = distance / range;
+    var window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window;
+}
+
+// The color of a surface in the forward pass of the current light.
+fn shade(surface: Surface) -> vec4f {
+    var color = vec3f(0.0);
+    if ((light.layers & projection_matrix.layers) != 0u) {
+        color = phong(surface, light) * fog_transmittance(surface.position);
+    }
+    if (light.is_first != 0u) {
+        color += apply_fog(surface.position, surface.ambient);
+    }
+    return vec4f(color, 1);
+}
+
+// The color of a surface lit by the lights of its cluster at once.
+fn shade_clustered(surface: Surface, frag_position: vec4f) -> vec4f {
+    var first = cluster_index(frag_position) * (max_cluster_lights + 1u);
+    var color = vec3f(0.0);
+    for (var index = 0u; index < cluster_light_indices[first]; index++) {
+        var cluster_light = cluster_lights[cluster_light_indices[first + 1u + index]];
+        if ((cluster_light.layers & projection_matrix.layers) == 0u) {
+            continue;
+        }
+        color += phong(surface, PointLight(cluster_light.position, cluster_light.color, cluster_light.range, 0u, cluster_light.layers, cluster_light.direction, cluster_light.cone));
+    }
+    color = color * fog_transmittance(surface.position) + apply_fog(surface.position, surface.ambient);
+    return vec4f(color, 1);
+}
+
+// The cluster a fragment is in, the depth slices get deeper with the distance from the
+// camera like the light assignment spreads them.
+fn cluster_index(frag_position: vec4f) -> u32 {
+    var counts = clusters.counts;
+    var viewport = clusters.viewport;
+    var tile = clamp((frag_position.xy - viewport.xy) / viewport.zw, vec2f(0.0), vec2f(1.0)) * vec2f(counts.xy);
+    var ndc = vec2f(frag_position.x - viewport.x, viewport.y + viewport.w - frag_position.y) / viewport.zw * 2.0 - 1.0;
+    var view_position = clusters.clip_to_view * vec4f(ndc, frag_position.z, 1.0);
+    var depth = -view_position.z / view_position.w;
+    var near = clusters.depth_range.x;
+    var far = clusters.depth_range.y;
+    var slice = log(max(depth, near) / near) / log(far / near) * f32(counts.z);
+    var cluster = min(vec3u(vec3f(tile, slice)), counts.xyz - 1u);
+    return (cluster.z * counts.y + cluster.y) * counts.x + cluster.x;
+}
+
+fn g_buffer(surface: Surface) -> GBufferOut {
+    return GBufferOut(
+        vec4f(surface.ambient, 1.0),
+        vec4f(surface.diffuse, 1.0),
+        vec4f(surface.specular, surface.shininess),
+        vec4f(surface.normal, 1.0),
+        projection_matrix.layers,
+    );
+}
+
+// How much the distance fog and the height fog cover a point in world space.
+fn fog_amounts(world_position: vec4f) -> vec2f {
+    var fog = view_info.fog;
+    var to_camera = view_info.camera_position.xyz - world_position.xyz;
+    // orthographic cameras measure along the view direction like their depth does
+    var distance = length(to_camera);
+    if (view_info.position.w == 0.0) {
+        distance = dot(to_camera, view_info.position.xyz);
+    }
+
+    var distance_amount = 0.0;
+    var density = fog.distance_params.z;
+    switch fog.distance_mode {
+        case 1u: {
+            var start = fog.distance_params.x;
+            var end = max(fog.distance_params.y, start + 1e-4);
+            distance_amount = clamp((distance - start) / (end - start), 0.0, 1.0);
+        }
+        case 2u: {
+            distance_amount = 1.0 - exp(-density * distance);
+        }
+        case 3u: {
+            distance_amount = 1.0 - exp(-(density * distance) * (density * distance));
+        }
+        default: {}
+    }
+
+    var height_amount = 0.0;
+    if (fog.height_enabled != 0u) {
+        // the density at the fragment height, taken as the density all along the view ray
+        var above = max(0.0, world_position.y - fog.height_params.x);
+        var height_density = fog.height_params.y * exp(-fog.height_params.z * above);
+        height_amount = 1.0 - exp(-height_density * distance);
+    }
+
+    return clamp(vec2f(distance_amount, height_amount), vec2f(0.0), vec2f(1.0));
+}
+
+fn apply_fog(world_position: vec4f, color: vec3f) -> vec3f {
+    var amounts = fog_amounts(world_position);
+    var fogged = mix(color, view_info.fog.distance_color, amounts.x);
+    return mix(fogged, view_info.fog.height_color, amounts.y);
+}
+
+// The part of the light of a surface that makes it through the fog. Light added after
+// the fog was applied is scaled by it.
+fn fog_transmittance(world_position: vec4f) -> f32 {
+    var amounts = fog_amounts(world_position);
+    return (1.0 - amounts.x) * (1.0 - amounts.y);
+}
+
+// Two levels of detail fading into each other draw complementary parts of the pattern.
+fn dither_fade(frag_data: VertexOut) {
+    var noise = fract(52.9829189 * fract(dot(frag_data.position.xy, vec2f(0.06711056, 0.00583715))));
+    if (noise < projection_matrix.fade.x || noise >= projection_matrix.fade.y) {
+        discard;
+    }
+}
+
+@fragment
+fn fragment_main(frag_data: VertexOut) -> @location(0) vec4f {
+    dither_fade(frag_data);
+    return shade(material_surface(frag_data, vec3f(1.0)));
+}
+
+@fragment
+fn fragment_main_g_buffer(frag_data: VertexOut) -> GBufferOut {
+    dither_fade(frag_data);
+    return g_buffer(material_surface(frag_data, vec3f(1.0)));
+}
+
+@fragment
+fn fragment_main_clustered(frag_data: VertexOut) -> @location(0) vec4f {
+    dither_fade(frag_data);
+    return shade_clustered(material_surface(frag_data, vec3f(1.0)), frag_data.position);
+}
+
+@fragment
+fn fragment_main_textured(frag_data: VertexOut) -> @location(0) vec4f {
+    var texel = textureSample(diffuse_texture, diffuse_sampler, frag_data.uv);
+    dither_fade(frag_data);
+    return shade(material_surface(frag_data, texel.rgb));
+}
+
+@fragment
+fn fragment_main_textured_g_buffer(frag_data: VertexOut) -> GBufferOut {
+    var texel = textureSample(diffuse_texture, diffuse_sampler, frag_data.uv);
+    dither_fade(frag_data);
+    return g_buffer(material_surface(frag_data, texel.rgb));
+}
+
+@fragment
+fn fragment_main_textured_clustered(frag_data: VertexOut) -> @location(0) vec4f {
+    var texel = textureSample(diffuse_texture, diffuse_sampler, frag_data.uv);
+    dither_fade(frag_data);
+    return shade_clustered(material_surface(frag_data, texel.rgb), frag_data.position);
+}
+
+fn terrain_color(frag_data: VertexOut) -> vec3f {
+    var weights = textureSample(splat_map, diffuse_sampler, frag_data.uv);
+    // channels without a layer are ignored, the rest are weighed against each other
+    weights *= vec4f(vec4u(0u, 1u, 2u, 3u) < vec4u(terrain_layers.layer_count));
+    var total = weights.r + weights.g + weights.b + weights.a;
+    if (total <= 0.0) {
+        weights = vec4f(1.0, 0.0, 0.0, 0.0);
+        total = 1.0;
+    }
+    weights /= total;
+
+    var tiling = terrain_layers.tiling;
+    return textureSample(terrain_layer_0, diffuse_sampler, frag_data.uv * tiling.x).rgb * weights.r
+        + textureSample(terrain_layer_1, diffuse_sampler, frag_data.uv * tiling.y).rgb * weights.g
+        + textureSample(terrain_layer_2, diffuse_sampler, frag_data.uv * tiling.z).rgb * weights.b
+        + textureSample(terrain_layer_3, diffuse_sampler, frag_data.uv * tiling.w).rgb * weights.a;
+}
+
+@fragment
+fn fragment_main_terrain(frag_data: VertexOut) -> @location(0) vec4f {
+    var color = terrain_color(frag_data);
+    dither_fade(frag_data);
+    return shade(material_surface(frag_data, color));
+}
+
+@fragment
+fn fragment_main_terrain_g_buffer(frag_data: VertexOut) -> GBufferOut {
+    var color = terrain_color(frag_data);
+    dither_fade(frag_data);
+    return g_buffer(material_surface(frag_data, color));
+}
+
+@fragment
+fn fragment_main_terrain_clustered(frag_data: VertexOut) -> @location(0) vec4f {
+    var color = terrain_color(frag_data);
+    dither_fade(frag_data);
+    return shade_clustered(material_surface(frag_data, color), frag_data.position);
+}
+
+// A triangle covering the viewport.
+@vertex
+fn vertex_fullscreen(@builtin(vertex_index) vertex_index : u32) -> @builtin(position) vec4f {
+    var corner = vec2f(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4f(corner * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// A rectangle around the part of the viewport the current light reaches, drawn as a
+// triangle strip.
+@vertex
+fn vertex_light_volume(@builtin(vertex_index) vertex_index : u32) -> @builtin(position) vec4f {
+    var bounds = light_screen_bounds();
+    var corner = vec2f(f32(vertex_index & 1u), f32((vertex_index >> 1u) & 1u));
+    return vec4f(mix(bounds.xy, bounds.zw, corner), 0.0, 1.0);
+}
+
+// The corners of a box around the range of the light projected onto the screen, in
+// normalized device coordinates.
+fn light_screen_bounds() -> vec4f {
+    var full = vec4f(-1.0, -1.0, 1.0, 1.0);
+    if (light.range <= 0.0) {
+        return full;
+    }
+    var bounds = vec4f(1.0, 1.0, -1.0, -1.0);
+    for (var corner = 0u; corner < 8u; corner++) {
+        var offset = vec3f(vec3u(corner, corner >> 1u, corner >> 2u) & vec3u(1u)) * 2.0 - 1.0;
+        var clip = deferred_view.world_to_clip * vec4f(light.position.xyz + offset * light.range, 1.0);
+        // a corner behind the camera can be anywhere on the screen
+        if (clip.w <= 0.0) {
+            return full;
+        }
+        bounds = vec4f(min(bounds.xy, clip.xy / clip.w), max(bounds.zw, clip.xy / clip.w));
+    }
+    // lights off the screen get an empty rectangle
+    return clamp(bounds, full.xyxy, full.zwzw);
+}
+
+// The position of the surface drawn at this pixel, found from the depth.
+fn g_buffer_position(frag_position: vec4f) -> vec4f {
+    var depth = textureLoad(g_buffer_depth, vec2i(frag_position.xy), 0).r;
+    // nothing was drawn here
+    if (depth >= 1.0) {
+        discard;
+    }
+    var viewport = deferred_view.viewport;
+    var ndc = vec2f(frag_position.x - viewport.x, viewport.y + viewport.w - frag_position.y) / viewport.zw * 2.0 - 1.0;
+    var position = deferred_view.clip_to_world * vec4f(ndc, depth, 1.0);
+    return position / position.w;
+}
+
+// The surface written to the G-buffer at this pixel.
+fn g_buffer_surface(frag_position: vec4f) -> Surface {
+    var pixel = vec2i(frag_position.xy);
+    var position = g_buffer_position(frag_position);
+    var specular = textureLoad(g_buffer_specular, pixel, 0);
+
+    return Surface(
+        position,
+        normalize(textureLoad(g_buffer_normal, pixel, 0).xyz),
+        textureLoad(g_buffer_ambient, pixel, 0).rgb,
+        textureLoad(g_buffer_diffuse, pixel, 0).rgb,
+        specular.rgb,
+        specular.a,
+    );
+}
+
+@fragment
+fn fragment_deferred_ambient(@builtin(position) frag_position : vec4f) -> @location(0) vec4f {
+    var surface = g_buffer_surface(frag_position);
+    return vec4f(apply_fog(surface.position, surface.ambient), 1);
+}
+
+@fragment
+fn fragment_deferred_light(@builtin(position) frag_position : vec4f) -> @location(0) vec4f {
+    var layers = textureLoad(g_buffer_layers, vec2i(frag_position.xy), 0).r;
+    if ((light.layers & layers) == 0u) {
+        discard;
+    }
+    var surface = g_buffer_surface(frag_position);
+    return vec4f(phong(surface, light) * fog_transmittance(surface.position), 1);
+}
+
+// The ambient light that does not reach the occluded part of the surface, taken back
+// out of the lit scene. The fog over it stays.
+@fragment
+fn fragment_ambient_occlusion(@builtin(position) frag_position : vec4f) -> @location(0) vec4f {
+    var pixel = vec2i(frag_position.xy);
+    var position = g_buffer_position(frag_position);
+    var ambient = textureLoad(g_buffer_ambient, pixel, 0).rgb;
+    var occluded = 1.0 - textureLoad(ambient_occlusion, pixel, 0).r;
+    return vec4f(ambient * occluded * fog_transmittance(position), 0);
 }
\ No newline at end of file
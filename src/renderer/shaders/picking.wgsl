@@ -0,0 +1,93 @@
+struct ProjectionMatrix {
+    @location(0) matrix: mat4x4f,
+    @location(1) matrix_inverse: mat4x4f,
+    @location(2) object_id: u32,
+}
+
+// position and normal offsets of one vertex in a morph target
+struct MorphDelta {
+    position : vec4f,
+    normal : vec4f,
+}
+
+struct MorphWeights {
+    vertex_count : u32,
+    target_count : u32,
+    weights : array<vec4f, 4>,
+}
+
+struct VertexOut {
+  @builtin(position) position : vec4f,
+}
+
+struct PickingOut {
+  @location(0) object_id : u32,
+  @location(1) depth : f32,
+}
+
+@group(0) @binding(0)
+var<uniform> projection_matrix : ProjectionMatrix;
+
+// Deformed meshes only, the group the material pipelines bind at 3
+@group(1) @binding(0)
+var<uniform> joint_matrices : array<mat4x4f, 64>;
+@group(1) @binding(1)
+var<storage, read> morph_deltas : array<MorphDelta>;
+@group(1) @binding(2)
+var<uniform> morph_weights : MorphWeights;
+
+// the meshes are deformed like in the scene pass, so they are picked where they are seen
+@vertex
+fn vertex_main(@location(0) position : vec4f) -> VertexOut {
+    return transform_vertex(position);
+}
+
+@vertex
+fn vertex_main_skinned(@location(0) position : vec4f, @location(3) joints : vec4u, @location(4) weights : vec4f) -> VertexOut {
+    return transform_vertex(skin_matrix(joints, weights) * position);
+}
+
+@vertex
+fn vertex_main_morphed(@builtin(vertex_index) vertex_index : u32, @location(0) position : vec4f) -> VertexOut {
+    return transform_vertex(morph(vertex_index, position, vec4f()).position);
+}
+
+@vertex
+fn vertex_main_skinned_morphed(@builtin(vertex_index) vertex_index : u32, @location(0) position : vec4f, @location(3) joints : vec4u, @location(4) weights : vec4f) -> VertexOut {
+    let morphed = morph(vertex_index, position, vec4f());
+    return transform_vertex(skin_matrix(joints, weights) * morphed.position);
+}
+
+fn skin_matrix(joints : vec4u, weights : vec4f) -> mat4x4f {
+    return weights.x * joint_matrices[joints.x]
+        + weights.y * joint_matrices[joints.y]
+        + weights.z * joint_matrices[joints.z]
+        + weights.w * joint_matrices[joints.w];
+}
+
+fn morph(vertex_index : u32, position : vec4f, normal : vec4f) -> MorphDelta {
+    var morphed = MorphDelta(position, normal);
+    for (var target_index = 0u; target_index < morph_weights.target_count; target_index++) {
+        let weight = morph_weights.weights[target_index / 4u][target_index % 4u];
+        if (weight != 0.0) {
+            let delta = morph_deltas[target_index * morph_weights.vertex_count + vertex_index];
+            morphed.position += weight * delta.position;
+            morphed.normal += weight * delta.normal;
+        }
+    }
+    return morphed;
+}
+
+fn transform_vertex(position : vec4f) -> VertexOut {
+    var output: VertexOut;
+    output.position = projection_matrix.matrix * position;
+    return output;
+}
+
+@fragment
+fn fragment_main(frag_data: VertexOut) -> PickingOut {
+    var output: PickingOut;
+    output.object_id = projection_matrix.object_id;
+    output.depth = frag_data.position.z;
+    return output;
+}
@@ -0,0 +1,55 @@
+struct InstanceIn {
+    @location(0) position : vec2f,
+    @location(1) size : vec2f,
+    @location(2) pivot : vec2f,
+    @location(3) rotation : f32,
+    // left, top, right, bottom
+    @location(4) uv_rect : vec4f,
+    @location(5) tint : vec4f,
+}
+
+struct VertexOut {
+    @builtin(position) position : vec4f,
+    @location(0) uv : vec2f,
+    @location(1) tint : vec4f,
+}
+
+@group(0) @binding(0)
+var<uniform> camera : mat4x4f;
+
+@group(1) @binding(0)
+var atlas : texture_2d<f32>;
+@group(1) @binding(1)
+var atlas_sampler : sampler;
+
+@vertex
+fn vertex_main(@builtin(vertex_index) vertex_index : u32, input : InstanceIn) -> VertexOut {
+    var output : VertexOut;
+    // two triangles, the corners from the bottom left
+    var corners = array<vec2f, 6>(
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 0.0),
+        vec2f(0.0, 1.0),
+        vec2f(0.0, 1.0),
+        vec2f(1.0, 0.0),
+        vec2f(1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+    let local = (corner - input.pivot) * input.size;
+    let c = cos(input.rotation);
+    let s = sin(input.rotation);
+    let rotated = vec2f(local.x * c - local.y * s, local.x * s + local.y * c);
+    output.position = camera * vec4f(input.position + rotated, 0.0, 1.0);
+    // texture rows go down, the quad goes up
+    output.uv = vec2f(
+        mix(input.uv_rect.x, input.uv_rect.z, corner.x),
+        mix(input.uv_rect.w, input.uv_rect.y, corner.y),
+    );
+    output.tint = input.tint;
+    return output;
+}
+
+@fragment
+fn fragment_main(input : VertexOut) -> @location(0) vec4f {
+    return textureSample(atlas, atlas_sampler, input.uv) * input.tint;
+}
@@ -0,0 +1,38 @@
+struct VertexIn {
+    // clip space position the glyph offsets are measured from
+    @location(0) anchor : vec4f,
+    // from the anchor in normalized device coordinates
+    @location(1) offset : vec2f,
+    @location(2) uv : vec2f,
+    @location(3) color : vec4f,
+}
+
+struct VertexOut {
+    @builtin(position) position : vec4f,
+    @location(0) uv : vec2f,
+    @location(1) color : vec4f,
+}
+
+@group(0) @binding(0)
+var atlas : texture_2d<f32>;
+@group(0) @binding(1)
+var atlas_sampler : sampler;
+
+@vertex
+fn vertex_main(input : VertexIn) -> VertexOut {
+    var output : VertexOut;
+    // scaling the offset by w keeps the text the same size at any distance
+    output.position = input.anchor + vec4f(input.offset * input.anchor.w, 0.0, 0.0);
+    output.uv = input.uv;
+    output.color = input.color;
+    return output;
+}
+
+@fragment
+fn fragment_main(input : VertexOut) -> @location(0) vec4f {
+    // 0.5 is the outline, the smoothing covers about a pixel at any text size
+    var distance = textureSample(atlas, atlas_sampler, input.uv).r;
+    var smoothing = max(length(vec2f(dpdx(distance), dpdy(distance))) * 0.7071, 0.0001);
+    var alpha = smoothstep(0.5 - smoothing, 0.5 + smoothing, distance);
+    return vec4f(input.color.rgb, input.color.a * alpha);
+}
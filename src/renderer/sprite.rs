@@ -0,0 +1,325 @@
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use wgpu::{
+    include_wgsl, vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BlendState, Buffer, BufferDescriptor,
+    BufferUsages, ColorTargetState, ColorWrites, Device, FragmentState, MultisampleState,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPipelineDescriptor, ShaderStages,
+    SurfaceCapabilities, Texture, TextureView, VertexBufferLayout, VertexState,
+};
+
+use super::{
+    material::MaterialManager, render::RenderTargetSize,
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder, texture::TextureManager,
+    viewport::PixelRect, wgpu_handles::WgpuHandles,
+};
+use crate::{importer::atlas::TextureAtlas, scene::scene::Scene, util::MuckableMatrix};
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SpriteInstance {
+    position: glm::Vec2,
+    size: glm::Vec2,
+    pivot: glm::Vec2,
+    rotation: f32,
+    uv_rect: glm::Vec4,
+    tint: glm::Vec4,
+}
+
+unsafe impl bytemuck::Zeroable for SpriteInstance {}
+unsafe impl bytemuck::Pod for SpriteInstance {}
+
+pub trait SpriteManager {
+    fn add_sprite_pipeline(&mut self, device: &Device, surface_capabilities: &SurfaceCapabilities);
+}
+
+impl SpriteManager for MaterialManager {
+    fn add_sprite_pipeline(&mut self, device: &Device, surface_capabilities: &SurfaceCapabilities) {
+        let shader_module = device.create_shader_module(include_wgsl!("./shaders/sprite.wgsl"));
+
+        // Bind Groups
+        // Group 0 Binding 0: Sprite Camera Matrix
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("SpriteCameraBindGroupLayout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        // Group 1 Binding 0, 1: Atlas Texture and Sampler
+        let atlas_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("SpriteAtlasBindGroupLayout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("SpritePipelineLayout"),
+            bind_group_layouts: &[&camera_bind_group_layout, &atlas_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        self.sprite_pipeline = Some(device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("SpriteRenderPipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader_module,
+                entry_point: "vertex_main",
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<SpriteInstance>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x2, 3 => Float32, 4 => Float32x4, 5 => Float32x4],
+                }],
+            },
+            fragment: Some(FragmentState {
+                module: &shader_module,
+                entry_point: "fragment_main",
+                targets: &[Some(ColorTargetState {
+                    format: surface_capabilities.formats[0],
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            multisample: MultisampleState::default(),
+            // the draw order is the z order
+            depth_stencil: None,
+            multiview: None,
+        }));
+        self.sprite_camera_bind_group_layout = Some(camera_bind_group_layout);
+        self.sprite_atlas_bind_group_layout = Some(atlas_bind_group_layout);
+    }
+}
+
+struct AtlasTexture {
+    _texture: Texture,
+    bind_group: BindGroup,
+}
+
+/// GPU resources of the sprites, created when the first sprite is drawn.
+pub struct SpriteBatcher {
+    camera_buffer: Buffer,
+    camera_bind_group: BindGroup,
+    /// grows to fit the sprites of the largest frame
+    instance_buffer: Buffer,
+    /// by `TextureAtlas` id
+    atlases: HashMap<u32, AtlasTexture>,
+}
+
+impl SpriteBatcher {
+    fn new(device: &Device, material_manager: &MaterialManager) -> Self {
+        let camera_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("SpriteCameraBuffer"),
+            size: std::mem::size_of::<glm::Mat4>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("SpriteCameraBindGroup"),
+            layout: material_manager
+                .sprite_camera_bind_group_layout
+                .as_ref()
+                .unwrap(),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+
+        SpriteBatcher {
+            camera_buffer,
+            camera_bind_group,
+            instance_buffer: create_instance_buffer(device, 256),
+            atlases: HashMap::new(),
+        }
+    }
+}
+
+fn create_instance_buffer(device: &Device, capacity: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("SpriteInstanceBuffer"),
+        size: (capacity * std::mem::size_of::<SpriteInstance>()) as u64,
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_atlas_texture(
+    atlas: &TextureAtlas,
+    wgpu_handles: &WgpuHandles,
+    material_manager: &MaterialManager,
+) -> AtlasTexture {
+    // atlases are always uncompressed RGBA8, which every device can sample
+    let texture = material_manager
+        .upload_texture(&atlas.texture, wgpu_handles)
+        .unwrap();
+    let bind_group = wgpu_handles.device.create_bind_group(&BindGroupDescriptor {
+        label: Some("SpriteAtlasBindGroup"),
+        layout: material_manager
+            .sprite_atlas_bind_group_layout
+            .as_ref()
+            .unwrap(),
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &texture.create_view(&Default::default()),
+                ),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(
+                    material_manager.texture_sampler.as_ref().unwrap(),
+                ),
+            },
+        ],
+    });
+
+    AtlasTexture {
+        _texture: texture,
+        bind_group,
+    }
+}
+
+/// Draw the sprites of the scene over the whole render target with the sprite camera.
+/// Sprites are sorted by z, and consecutive sprites of the same atlas are one draw call.
+/// Sprites with the same z are grouped by atlas, so a layer of sprites costs one draw
+/// per atlas it uses.
+pub fn encode_sprite_pass(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &MaterialManager,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+    texture_view: &TextureView,
+    render_target_size: RenderTargetSize,
+) {
+    if scene.sprites.is_empty() {
+        return;
+    }
+    if scene.fit_camera_to_target {
+        scene.sprite_camera.fit_to_render_target(render_target_size);
+    }
+
+    let mut order: Vec<usize> = (0..scene.sprites.len()).collect();
+    order.sort_by_key(|index| (scene.sprites[*index].z, scene.sprites[*index].atlas.id));
+
+    let mut instances = Vec::with_capacity(order.len());
+    let mut runs: Vec<(Arc<TextureAtlas>, Range<u32>)> = vec![];
+    for index in order {
+        let sprite = &scene.sprites[index];
+        instances.push(SpriteInstance {
+            position: sprite.position,
+            size: sprite.size,
+            pivot: sprite.pivot,
+            rotation: sprite.rotation,
+            uv_rect: sprite.atlas.uv_rect(&sprite.region),
+            tint: sprite.tint,
+        });
+
+        let end = instances.len() as u32;
+        match runs.last_mut() {
+            Some((atlas, range)) if atlas.id == sprite.atlas.id => range.end = end,
+            _ => runs.push((sprite.atlas.clone(), end - 1..end)),
+        }
+    }
+
+    let device = wgpu_handles.device.clone();
+    wgpu_handles
+        .sprite_batcher
+        .get_or_insert_with(|| SpriteBatcher::new(&device, material_manager));
+    for (atlas, _) in &runs {
+        if !wgpu_handles
+            .sprite_batcher
+            .as_ref()
+            .unwrap()
+            .atlases
+            .contains_key(&atlas.id)
+        {
+            let atlas_texture = create_atlas_texture(atlas, wgpu_handles, material_manager);
+            let batcher = wgpu_handles.sprite_batcher.as_mut().unwrap();
+            batcher.atlases.insert(atlas.id, atlas_texture);
+        }
+    }
+
+    let batcher = wgpu_handles.sprite_batcher.as_mut().unwrap();
+    let instance_bytes = std::mem::size_of_val(instances.as_slice()) as u64;
+    if batcher.instance_buffer.size() < instance_bytes {
+        batcher.instance_buffer =
+            create_instance_buffer(&device, instances.len().next_power_of_two());
+    }
+    let camera_matrix = MuckableMatrix(scene.sprite_camera.get_inverse_matrix());
+    staging_belt.write_buffer(
+        &batcher.camera_buffer,
+        0,
+        bytemuck::cast_slice(&[camera_matrix]),
+        &device,
+    );
+    staging_belt.write_buffer(
+        &batcher.instance_buffer,
+        0,
+        bytemuck::cast_slice(&instances),
+        &device,
+    );
+
+    let timestamp_writes = wgpu_handles
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("SpriteRenderPass"));
+    let batcher = wgpu_handles.sprite_batcher.as_ref().unwrap();
+    let mut render_pass =
+        staging_belt
+            .command_encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SpriteRenderPass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+    PixelRect::full((render_target_size.width, render_target_size.height))
+        .set_viewport(&mut render_pass);
+    render_pass.set_pipeline(material_manager.sprite_pipeline.as_ref().unwrap());
+    render_pass.set_bind_group(0, &batcher.camera_bind_group, &[]);
+    render_pass.set_vertex_buffer(0, batcher.instance_buffer.slice(..instance_bytes));
+    let stats = &mut scene.render_stats;
+    stats.pipeline_switches += 1;
+    stats.bind_group_switches += 1;
+    for (atlas, range) in runs {
+        render_pass.set_bind_group(1, &batcher.atlases[&atlas.id].bind_group, &[]);
+        render_pass.draw(0..6, range.clone());
+
+        stats.bind_group_switches += 1;
+        stats.draw_calls += 1;
+        stats.triangles += 2 * range.len() as u64;
+    }
+}
@@ -5,6 +5,7 @@ use wgpu::{util::StagingBelt, Buffer, CommandEncoder, Device};
 pub struct StagingBeltAndCommandEncoder {
     pub staging_belt: StagingBelt,
     pub command_encoder: CommandEncoder,
+    pub bytes_written: u64,
 }
 
 impl StagingBeltAndCommandEncoder {
@@ -12,6 +13,7 @@ impl StagingBeltAndCommandEncoder {
         Self {
             staging_belt,
             command_encoder,
+            bytes_written: 0,
         }
     }
     pub fn write_buffer(&mut self, target: &Buffer, offset: u64, slice: &[u8], device: &Device) {
@@ -24,5 +26,6 @@ impl StagingBeltAndCommandEncoder {
                 device,
             )
             .copy_from_slice(slice);
+        self.bytes_written += slice.len() as u64;
     }
 }
@@ -0,0 +1,225 @@
+use std::{
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    time::Duration,
+};
+
+use wgpu::{
+    Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder,
+    ComputePassTimestampWrites, Device, Maintain, MapMode, QuerySet, QuerySetDescriptor, QueryType,
+    Queue, RenderPassTimestampWrites, QUERY_SIZE,
+};
+
+/// Figures collected while rendering a frame. The latest ones are kept in
+/// `Scene::render_stats` so game code can read them from the render loop.
+#[derive(Clone, Debug, Default)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    pub pipeline_switches: u32,
+    pub bind_group_switches: u32,
+    pub compute_dispatches: u32,
+    /// Bytes written through the staging belt, which is all per frame uploads.
+    pub staging_bytes: u64,
+    pub cpu_times: CpuPhaseTimes,
+    /// Only filled when the device has `TIMESTAMP_QUERY`. The timestamps are read back
+    /// asynchronously so these belong to a frame or two before the rest of the figures.
+    pub gpu_pass_times: Vec<GpuPassTime>,
+}
+
+impl RenderStats {
+    /// Clear the figures for a new frame. GPU times arrive later, the last ones are kept
+    /// until then.
+    pub fn start_frame(&mut self) {
+        *self = RenderStats {
+            gpu_pass_times: std::mem::take(&mut self.gpu_pass_times),
+            ..Default::default()
+        };
+    }
+
+    /// Add the figures of another view rendered in the same frame.
+    pub fn accumulate(&mut self, other: &RenderStats) {
+        self.draw_calls += other.draw_calls;
+        self.triangles += other.triangles;
+        self.pipeline_switches += other.pipeline_switches;
+        self.bind_group_switches += other.bind_group_switches;
+        self.compute_dispatches += other.compute_dispatches;
+        self.staging_bytes += other.staging_bytes;
+        self.cpu_times.matrices += other.cpu_times.matrices;
+        self.cpu_times.materials += other.cpu_times.materials;
+        self.cpu_times.lights += other.cpu_times.lights;
+        self.cpu_times.encoding += other.cpu_times.encoding;
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CpuPhaseTimes {
+    pub matrices: Duration,
+    pub materials: Duration,
+    pub lights: Duration,
+    pub encoding: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct GpuPassTime {
+    pub label: &'static str,
+    pub duration: Duration,
+}
+
+const MAX_TIMED_PASSES: u32 = 16;
+
+/// Records the start and end timestamps of render and compute passes and reads them back
+/// without stalling. Passes are not timed while the previous results are still being
+/// mapped.
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    frame_passes: Vec<&'static str>,
+    readback_passes: Vec<&'static str>,
+    readback_requested: bool,
+    /// the result of mapping the readback buffer, while it is pending
+    readback_mapping: Option<Receiver<Result<(), BufferAsyncError>>>,
+    pub pass_times: Vec<GpuPassTime>,
+}
+
+impl GpuTimer {
+    /// Returns `None` if the device was created without `TIMESTAMP_QUERY`.
+    pub fn new(device: &Device) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let size = (MAX_TIMED_PASSES * 2) as u64 * QUERY_SIZE as u64;
+
+        Some(GpuTimer {
+            query_set: device.create_query_set(&QuerySetDescriptor {
+                label: Some("GpuTimerQuerySet"),
+                ty: QueryType::Timestamp,
+                count: MAX_TIMED_PASSES * 2,
+            }),
+            resolve_buffer: device.create_buffer(&BufferDescriptor {
+                label: Some("GpuTimerResolveBuffer"),
+                size,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&BufferDescriptor {
+                label: Some("GpuTimerReadbackBuffer"),
+                size,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            frame_passes: vec![],
+            readback_passes: vec![],
+            readback_requested: false,
+            readback_mapping: None,
+            pass_times: vec![],
+        })
+    }
+
+    /// Timestamp writes to pass into a render pass descriptor, or `None` if the pass
+    /// cannot be timed this frame.
+    pub fn render_pass_timestamp_writes(
+        &mut self,
+        label: &'static str,
+    ) -> Option<RenderPassTimestampWrites<'_>> {
+        let index = self.next_pass_index(label)?;
+        Some(RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+
+    /// Like `render_pass_timestamp_writes`, for compute pass descriptors.
+    pub fn compute_pass_timestamp_writes(
+        &mut self,
+        label: &'static str,
+    ) -> Option<ComputePassTimestampWrites<'_>> {
+        let index = self.next_pass_index(label)?;
+        Some(ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+
+    fn next_pass_index(&mut self, label: &'static str) -> Option<u32> {
+        if !self.readback_passes.is_empty() || self.frame_passes.len() as u32 >= MAX_TIMED_PASSES {
+            return None;
+        }
+
+        self.frame_passes.push(label);
+        Some(self.frame_passes.len() as u32 - 1)
+    }
+
+    /// Copy the timestamps of this frame to the readback buffer. Call once all timed
+    /// passes are encoded.
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+        if self.frame_passes.is_empty() {
+            return;
+        }
+
+        let query_count = self.frame_passes.len() as u32 * 2;
+        encoder.resolve_query_set(&self.query_set, 0..query_count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            query_count as u64 * QUERY_SIZE as u64,
+        );
+        self.readback_passes = std::mem::take(&mut self.frame_passes);
+        self.readback_requested = true;
+    }
+
+    /// Map the readback buffer after the frame is submitted, and pick up the results
+    /// of an earlier frame if they arrived.
+    pub fn after_submit(&mut self, device: &Device, queue: &Queue) {
+        if self.readback_requested {
+            self.readback_requested = false;
+            let (sender, receiver) = channel();
+            self.readback_buffer
+                .slice(..)
+                .map_async(MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            self.readback_mapping = Some(receiver);
+        }
+
+        device.poll(Maintain::Poll);
+        let Some(readback_mapping) = &self.readback_mapping else {
+            return;
+        };
+        match readback_mapping.try_recv() {
+            Ok(Ok(())) => self.readback_mapping = None,
+            Err(TryRecvError::Empty) => return,
+            // the timestamps of this frame are lost, later frames are timed again
+            Ok(Err(_)) | Err(TryRecvError::Disconnected) => {
+                self.readback_mapping = None;
+                self.readback_passes.clear();
+                return;
+            }
+        }
+
+        let period = queue.get_timestamp_period() as f64;
+        {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            self.pass_times = self
+                .readback_passes
+                .iter()
+                .enumerate()
+                .map(|(i, label)| {
+                    let ticks = timestamps[i * 2 + 1].saturating_sub(timestamps[i * 2]);
+                    GpuPassTime {
+                        label,
+                        duration: Duration::from_nanos((ticks as f64 * period) as u64),
+                    }
+                })
+                .collect();
+        }
+        self.readback_buffer.unmap();
+        self.readback_passes.clear();
+    }
+}
@@ -0,0 +1,448 @@
+use std::{collections::HashMap, sync::Arc};
+
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt, TextureDataOrder},
+    vertex_attr_array, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry,
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BlendState, BufferUsages,
+    ColorTargetState, ColorWrites, CommandEncoder, Device, Extent3d, FilterMode, FragmentState,
+    MultisampleState, PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPipelineDescriptor,
+    SamplerDescriptor, ShaderStages, SurfaceCapabilities, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, VertexBufferLayout, VertexState,
+};
+
+use super::{
+    depth_texture::{scene_depth_stencil_state, DepthTexture},
+    material::MaterialManager,
+    render::RenderTargetSize,
+    stats::RenderStats,
+    viewport::PixelRect,
+    wgpu_handles::WgpuHandles,
+};
+use crate::{
+    importer::font::Font,
+    scene::{
+        object3d::Object3D,
+        scene::Scene,
+        text::{layout_text, Label},
+    },
+};
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TextVertex {
+    anchor: glm::Vec4,
+    offset: glm::Vec2,
+    uv: glm::Vec2,
+    color: glm::Vec4,
+}
+
+unsafe impl bytemuck::Zeroable for TextVertex {}
+unsafe impl bytemuck::Pod for TextVertex {}
+
+/// Vertices of the glyphs to draw, by font id. Each font is a single draw call.
+type TextBatches = HashMap<u32, (Arc<Font>, Vec<TextVertex>)>;
+
+pub trait TextManager {
+    fn add_text_pipeline(&mut self, device: &Device, surface_capabilities: &SurfaceCapabilities);
+}
+
+impl TextManager for MaterialManager {
+    fn add_text_pipeline(&mut self, device: &Device, surface_capabilities: &SurfaceCapabilities) {
+        let shader_module = device.create_shader_module(include_wgsl!("./shaders/text.wgsl"));
+
+        // Bind Groups
+        // Group 0 Binding 0, 1: Distance Field Atlas and Sampler
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("TextBindGroupLayout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("TextPipelineLayout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        self.text_pipeline = Some(device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("TextRenderPipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader_module,
+                entry_point: "vertex_main",
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<TextVertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &vertex_attr_array![0 => Float32x4, 1 => Float32x2, 2 => Float32x2, 3 => Float32x4],
+                }],
+            },
+            fragment: Some(FragmentState {
+                module: &shader_module,
+                entry_point: "fragment_main",
+                targets: &[Some(ColorTargetState {
+                    format: surface_capabilities.formats[0],
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            multisample: MultisampleState::default(),
+            // labels are hidden behind the scene, but do not hide each other
+            depth_stencil: Some(scene_depth_stencil_state(false)),
+            multiview: None,
+        }));
+        self.text_bind_group_layout = Some(bind_group_layout);
+    }
+}
+
+/// The atlas of a font on the GPU.
+pub struct FontTexture {
+    _texture: Texture,
+    bind_group: BindGroup,
+}
+
+impl FontTexture {
+    fn new(device: &Device, queue: &Queue, layout: &BindGroupLayout, font: &Font) -> Self {
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("FontAtlasTexture"),
+                size: Extent3d {
+                    width: font.atlas_width,
+                    height: font.atlas_height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            &font.atlas,
+        );
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("FontAtlasSampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("FontAtlasBindGroup"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(
+                        &texture.create_view(&Default::default()),
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        FontTexture {
+            _texture: texture,
+            bind_group,
+        }
+    }
+}
+
+/// Add the glyphs of a text to its batch. `origin` is where the top left of the text
+/// goes relative to the anchor, and `pixel_to_ndc` scales pixels to the viewport.
+#[allow(clippy::too_many_arguments)]
+fn push_text(
+    batches: &mut TextBatches,
+    font: &Arc<Font>,
+    text: &str,
+    size: f32,
+    max_width: Option<f32>,
+    color: glm::Vec4,
+    anchor: glm::Vec4,
+    origin: impl FnOnce(f32, f32) -> (f32, f32),
+    pixel_to_ndc: (f32, f32),
+) {
+    let layout = layout_text(font, text, size, max_width);
+    let origin = origin(layout.width, layout.height);
+    let vertices = &mut batches
+        .entry(font.id)
+        .or_insert_with(|| (font.clone(), vec![]))
+        .1;
+
+    for positioned in &layout.glyphs {
+        let glyph = &positioned.glyph;
+        if glyph.width == 0 {
+            continue;
+        }
+        let left = origin.0 + positioned.x;
+        let top = origin.1 + positioned.y;
+        let right = left + glyph.width as f32 * layout.scale;
+        let bottom = top + glyph.height as f32 * layout.scale;
+        let uv_left = glyph.x as f32 / font.atlas_width as f32;
+        let uv_top = glyph.y as f32 / font.atlas_height as f32;
+        let uv_right = (glyph.x + glyph.width) as f32 / font.atlas_width as f32;
+        let uv_bottom = (glyph.y + glyph.height) as f32 / font.atlas_height as f32;
+
+        let corner = |x: f32, y: f32, u: f32, v: f32| TextVertex {
+            anchor,
+            offset: glm::vec2(x * pixel_to_ndc.0, -y * pixel_to_ndc.1),
+            uv: glm::vec2(u, v),
+            color,
+        };
+        let top_left = corner(left, top, uv_left, uv_top);
+        let top_right = corner(right, top, uv_right, uv_top);
+        let bottom_left = corner(left, bottom, uv_left, uv_bottom);
+        let bottom_right = corner(right, bottom, uv_right, uv_bottom);
+        vertices.extend_from_slice(&[
+            top_left,
+            bottom_left,
+            top_right,
+            top_right,
+            bottom_left,
+            bottom_right,
+        ]);
+    }
+}
+
+/// Labels of the object and its children with their anchors in clip space. The matrices
+/// are multiplied in the same order as `Object3D::write_matrices` does.
+fn collect_labels<'a>(
+    object3d: &'a Object3D,
+    parent_matrix: &glm::Mat4,
+    layers: u32,
+    labels: &mut Vec<(&'a Label, glm::Vec4)>,
+) {
+    if !object3d.visible {
+        return;
+    }
+    let matrix: glm::Mat4 = object3d.matrix.into();
+    let matrix = parent_matrix.mul_m(&matrix);
+
+    if let (true, Some(label)) = (object3d.is_on_layers(layers), &object3d.label) {
+        let anchor = matrix * glm::vec4(label.offset.x, label.offset.y, label.offset.z, 1.0);
+        // behind the camera
+        if anchor.w > 0.0 {
+            labels.push((label, anchor));
+        }
+    }
+    for child in object3d.drawn_children() {
+        collect_labels(child, &matrix, layers, labels);
+    }
+}
+
+/// Draw the labels of the objects in the scene as seen by the scene camera in `rect`.
+/// This needs the depth texture of the scene pass that was just encoded.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_label_pass(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &MaterialManager,
+    encoder: &mut CommandEncoder,
+    texture_view: &TextureView,
+    render_target_size: RenderTargetSize,
+    window: usize,
+    rect: PixelRect,
+) {
+    let mut labels = vec![];
+    collect_labels(
+        &scene.root,
+        &scene.camera.get_inverse_matrix(),
+        scene.camera.layers(),
+        &mut labels,
+    );
+    if labels.is_empty() {
+        return;
+    }
+
+    let scale_factor = render_target_size.scale_factor as f32;
+    let pixel_to_ndc = (2.0 / rect.width as f32, 2.0 / rect.height as f32);
+    let mut batches = TextBatches::new();
+    for (label, anchor) in labels {
+        push_text(
+            &mut batches,
+            &label.font,
+            &label.text,
+            label.size * scale_factor,
+            label.max_width.map(|max_width| max_width * scale_factor),
+            label.color,
+            anchor,
+            |width, height| (-width / 2.0, -height),
+            pixel_to_ndc,
+        );
+    }
+
+    encode_text_pass(
+        &mut scene.render_stats,
+        wgpu_handles,
+        material_manager,
+        encoder,
+        texture_view,
+        render_target_size,
+        window,
+        rect,
+        batches,
+    );
+}
+
+/// Draw the screen space texts of the scene over the whole render target.
+pub fn encode_overlay_pass(
+    scene: &mut Scene,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &MaterialManager,
+    encoder: &mut CommandEncoder,
+    texture_view: &TextureView,
+    render_target_size: RenderTargetSize,
+    window: usize,
+) {
+    if scene.texts.is_empty() {
+        return;
+    }
+
+    let rect = PixelRect::full((render_target_size.width, render_target_size.height));
+    let scale_factor = render_target_size.scale_factor as f32;
+    let pixel_to_ndc = (2.0 / rect.width as f32, 2.0 / rect.height as f32);
+    // the top left corner of the target, in front of everything
+    let anchor = glm::vec4(-1.0, 1.0, 0.0, 1.0);
+    let mut batches = TextBatches::new();
+    for text in &scene.texts {
+        let position = (
+            text.position.0 * scale_factor,
+            text.position.1 * scale_factor,
+        );
+        push_text(
+            &mut batches,
+            &text.font,
+            &text.text,
+            text.size * scale_factor,
+            text.max_width.map(|max_width| max_width * scale_factor),
+            text.color,
+            anchor,
+            |_, _| position,
+            pixel_to_ndc,
+        );
+    }
+
+    encode_text_pass(
+        &mut scene.render_stats,
+        wgpu_handles,
+        material_manager,
+        encoder,
+        texture_view,
+        render_target_size,
+        window,
+        rect,
+        batches,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn encode_text_pass(
+    stats: &mut RenderStats,
+    wgpu_handles: &mut WgpuHandles,
+    material_manager: &MaterialManager,
+    encoder: &mut CommandEncoder,
+    texture_view: &TextureView,
+    render_target_size: RenderTargetSize,
+    window: usize,
+    rect: PixelRect,
+    batches: TextBatches,
+) {
+    let device = &wgpu_handles.device;
+    let batches: Vec<_> = batches
+        .into_iter()
+        .filter(|(_, (_, vertices))| !vertices.is_empty())
+        .map(|(font_id, (font, vertices))| {
+            wgpu_handles
+                .font_textures
+                .entry(font_id)
+                .or_insert_with(|| {
+                    FontTexture::new(
+                        device,
+                        &wgpu_handles.queue,
+                        material_manager.text_bind_group_layout.as_ref().unwrap(),
+                        &font,
+                    )
+                });
+            let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("TextVertexBuffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: BufferUsages::VERTEX,
+            });
+            (font_id, vertex_buffer, vertices.len() as u32)
+        })
+        .collect();
+    if batches.is_empty() {
+        return;
+    }
+
+    // the size matches unless only other windows have views of the scene
+    let depth_texture = DepthTexture::get_or_resize(
+        &mut wgpu_handles
+            .window_targets
+            .entry(window)
+            .or_default()
+            .depth_texture,
+        device,
+        (render_target_size.width, render_target_size.height),
+    );
+    let timestamp_writes = wgpu_handles
+        .gpu_timer
+        .as_mut()
+        .and_then(|gpu_timer| gpu_timer.render_pass_timestamp_writes("TextRenderPass"));
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("TextRenderPass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: texture_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &depth_texture.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }),
+        timestamp_writes,
+        occlusion_query_set: None,
+    });
+
+    rect.set_viewport(&mut render_pass);
+    render_pass.set_pipeline(material_manager.text_pipeline.as_ref().unwrap());
+    stats.pipeline_switches += 1;
+    for (font_id, vertex_buffer, vertex_count) in &batches {
+        render_pass.set_bind_group(0, &wgpu_handles.font_textures[font_id].bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..*vertex_count, 0..1);
+
+        stats.bind_group_switches += 1;
+        stats.draw_calls += 1;
+        stats.triangles += *vertex_count as u64 / 3;
+    }
+}
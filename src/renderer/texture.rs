@@ -0,0 +1,94 @@
+use std::error::Error;
+
+use wgpu::{
+    util::{DeviceExt, TextureDataOrder},
+    AddressMode, Device, Extent3d, FilterMode, Sampler, SamplerDescriptor, Texture,
+    TextureDescriptor, TextureDimension, TextureUsages,
+};
+
+use crate::importer::{decompress::decompress, texture::TextureData};
+
+use super::{material::MaterialManager, wgpu_handles::WgpuHandles};
+
+/// Features for the block compressed formats `load_texture` can return. Devices should
+/// request the ones their adapter has so those textures can be uploaded as they are.
+pub fn texture_compression_features() -> wgpu::Features {
+    wgpu::Features::TEXTURE_COMPRESSION_BC
+        | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+        | wgpu::Features::TEXTURE_COMPRESSION_ASTC
+}
+
+pub trait TextureManager {
+    fn create_texture_sampler(&self, device: &Device) -> Sampler;
+    fn upload_texture(
+        &self,
+        texture_data: &TextureData,
+        wgpu_handles: &WgpuHandles,
+    ) -> Result<Texture, Box<dyn Error>>;
+}
+
+impl TextureManager for MaterialManager {
+    fn create_texture_sampler(&self, device: &Device) -> Sampler {
+        device.create_sampler(&SamplerDescriptor {
+            label: Some("TextureSampler"),
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            address_mode_w: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        })
+    }
+
+    /// Create a texture with all mip levels of `texture_data`. Compressed formats the
+    /// device cannot sample are decompressed on the CPU first.
+    fn upload_texture(
+        &self,
+        texture_data: &TextureData,
+        wgpu_handles: &WgpuHandles,
+    ) -> Result<Texture, Box<dyn Error>> {
+        let device = &wgpu_handles.device;
+        let format = texture_data.format;
+
+        // wgpu wants the base level of compressed textures to be made of whole blocks
+        let (block_width, block_height) = format.block_dimensions();
+        let whole_blocks = texture_data.width.is_multiple_of(block_width)
+            && texture_data.height.is_multiple_of(block_height);
+
+        let decompressed;
+        let texture_data = if device.features().contains(format.required_features())
+            && whole_blocks
+        {
+            texture_data
+        } else {
+            log::debug!(
+                "Decompressing a {:?} texture on the CPU, the device cannot sample it",
+                format
+            );
+            decompressed = decompress(texture_data)?;
+            &decompressed
+        };
+
+        let data = texture_data.levels.concat();
+        Ok(device.create_texture_with_data(
+            &wgpu_handles.queue,
+            &TextureDescriptor {
+                label: Some("SomeTexture"),
+                size: Extent3d {
+                    width: texture_data.width,
+                    height: texture_data.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: texture_data.levels.len() as u32,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: texture_data.format,
+                usage: TextureUsages::TEXTURE_BINDING.union(TextureUsages::COPY_DST),
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            &data,
+        ))
+    }
+}
@@ -0,0 +1,116 @@
+use egui::{
+    epaint::{ClippedPrimitive, ImageDelta, TextureAtlas},
+    TextureId, TexturesDelta,
+};
+use egui_wgpu::{Renderer, ScreenDescriptor};
+use wgpu::{CommandEncoderDescriptor, TextureView};
+
+use super::{render::RenderTargetSize, wgpu_handles::WgpuHandles};
+use crate::engine::ui_service::UiService;
+
+/// The tessellated output of one egui frame.
+pub struct UiFrame {
+    pub paint_jobs: Vec<ClippedPrimitive>,
+    pub textures_delta: TexturesDelta,
+    pub pixels_per_point: f32,
+}
+
+/// egui's own renderer with the textures it asked for, created with the first UI frame.
+pub struct UiRenderer {
+    renderer: Renderer,
+    /// freed at the start of the next frame, the UI can be drawn more than once a frame
+    textures_to_free: Vec<TextureId>,
+}
+
+/// Apply the texture changes of a UI frame. This is done once per frame, before the UI
+/// is drawn.
+pub fn prepare_ui_frame(wgpu_handles: &mut WgpuHandles, ui_frame: &UiFrame) {
+    let color_format = wgpu_handles.material_manager.color_format.unwrap();
+    let device = &wgpu_handles.device;
+    let is_new = wgpu_handles.ui_renderer.is_none();
+    let ui_renderer = wgpu_handles.ui_renderer.get_or_insert_with(|| UiRenderer {
+        renderer: Renderer::new(device, color_format, None, 1),
+        textures_to_free: vec![],
+    });
+
+    // egui sends the whole font atlas only once, a renderer created for a new device
+    // after that has to be given it again
+    let has_font_atlas = ui_frame
+        .textures_delta
+        .set
+        .iter()
+        .any(|(id, image_delta)| *id == TextureId::default() && image_delta.is_whole());
+    if is_new && !has_font_atlas {
+        let font_image = UiService::context().fonts(|fonts| fonts.image());
+        ui_renderer.renderer.update_texture(
+            device,
+            &wgpu_handles.queue,
+            TextureId::default(),
+            &ImageDelta::full(font_image, TextureAtlas::texture_options()),
+        );
+    }
+
+    for id in ui_renderer.textures_to_free.drain(..) {
+        ui_renderer.renderer.free_texture(&id);
+    }
+    for (id, image_delta) in &ui_frame.textures_delta.set {
+        ui_renderer
+            .renderer
+            .update_texture(device, &wgpu_handles.queue, *id, image_delta);
+    }
+    ui_renderer
+        .textures_to_free
+        .extend_from_slice(&ui_frame.textures_delta.free);
+}
+
+/// Draw a prepared UI frame over the render target.
+pub fn render_ui_to_texture_view(
+    wgpu_handles: &mut WgpuHandles,
+    ui_frame: &UiFrame,
+    texture_view: &TextureView,
+    render_target_size: RenderTargetSize,
+) {
+    let Some(ui_renderer) = &mut wgpu_handles.ui_renderer else {
+        return;
+    };
+    let screen_descriptor = ScreenDescriptor {
+        size_in_pixels: [render_target_size.width, render_target_size.height],
+        pixels_per_point: ui_frame.pixels_per_point,
+    };
+
+    let mut encoder = wgpu_handles
+        .device
+        .create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("UiCommandEncoder"),
+        });
+    let callback_buffers = ui_renderer.renderer.update_buffers(
+        &wgpu_handles.device,
+        &wgpu_handles.queue,
+        &mut encoder,
+        &ui_frame.paint_jobs,
+        &screen_descriptor,
+    );
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("UiRenderPass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        ui_renderer
+            .renderer
+            .render(&mut render_pass, &ui_frame.paint_jobs, &screen_descriptor);
+    }
+
+    wgpu_handles
+        .queue
+        .submit(callback_buffers.into_iter().chain(Some(encoder.finish())));
+}
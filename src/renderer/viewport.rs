@@ -0,0 +1,150 @@
+use wgpu::{
+    include_wgsl, BlendComponent, BlendFactor, BlendOperation, BlendState, ColorTargetState,
+    ColorWrites, CommandEncoder, Device, FragmentState, MultisampleState, PipelineLayoutDescriptor,
+    PrimitiveState, RenderPass, RenderPipeline, RenderPipelineDescriptor, SurfaceCapabilities,
+    TextureView, VertexState,
+};
+
+use super::material::MaterialManager;
+
+/// Part of the render target that a view draws to, in physical pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect {
+    pub fn full(target_size: (u32, u32)) -> Self {
+        PixelRect {
+            x: 0,
+            y: 0,
+            width: target_size.0,
+            height: target_size.1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, pixel: (u32, u32)) -> bool {
+        (self.x..self.x + self.width).contains(&pixel.0)
+            && (self.y..self.y + self.height).contains(&pixel.1)
+    }
+
+    pub fn set_viewport(&self, render_pass: &mut RenderPass) {
+        render_pass.set_viewport(
+            self.x as f32,
+            self.y as f32,
+            self.width as f32,
+            self.height as f32,
+            0.0,
+            1.0,
+        );
+    }
+}
+
+pub trait ViewportManager {
+    fn create_clear_rect_pipeline(
+        &self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    ) -> RenderPipeline;
+}
+
+impl ViewportManager for MaterialManager {
+    fn create_clear_rect_pipeline(
+        &self,
+        device: &Device,
+        surface_capabilities: &SurfaceCapabilities,
+    ) -> RenderPipeline {
+        let shader_module = device.create_shader_module(include_wgsl!("./shaders/clear-rect.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("ClearRectPipelineLayout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        // the blend constant replaces whatever was there
+        let replace_with_constant = BlendComponent {
+            src_factor: BlendFactor::Constant,
+            dst_factor: BlendFactor::Zero,
+            operation: BlendOperation::Add,
+        };
+
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("ClearRectRenderPipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader_module,
+                entry_point: "vertex_main",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &shader_module,
+                entry_point: "fragment_main",
+                targets: &[Some(ColorTargetState {
+                    format: surface_capabilities.formats[0],
+                    blend: Some(BlendState {
+                        color: replace_with_constant,
+                        alpha: replace_with_constant,
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            multisample: MultisampleState::default(),
+            depth_stencil: None,
+            multiview: None,
+        })
+    }
+}
+
+/// Clear the whole render target.
+pub fn encode_clear(encoder: &mut CommandEncoder, texture_view: &TextureView, color: wgpu::Color) {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("ClearRenderPass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: texture_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(color),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+}
+
+/// Clear a part of the render target. Load operations always clear whole attachments,
+/// so this draws over the rectangle instead.
+pub fn encode_clear_rect(
+    material_manager: &MaterialManager,
+    encoder: &mut CommandEncoder,
+    texture_view: &TextureView,
+    rect: PixelRect,
+    color: wgpu::Color,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("ClearRectRenderPass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: texture_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    rect.set_viewport(&mut render_pass);
+    render_pass.set_pipeline(material_manager.clear_rect_pipeline.as_ref().unwrap());
+    render_pass.set_blend_constant(color);
+    render_pass.draw(0..3, 0..1);
+}
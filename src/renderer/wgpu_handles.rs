@@ -1,5 +1,22 @@
-use super::material::MaterialManager;
-use std::sync::Arc;
+use super::{
+    clustered::LightClusters, deferred::GBuffer, depth_texture::DepthTexture,
+    material::MaterialManager, offscreen_target::OffscreenTarget, outline::OutlineTargets,
+    particles::ParticleCamera, picking::Picker, sprite::SpriteBatcher, stats::GpuTimer,
+    text::FontTexture, ui::UiRenderer,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Targets sized like a window. Each window has its own, so that windows of different
+/// sizes do not recreate them for each other.
+#[derive(Default)]
+pub struct WindowTargets {
+    pub depth_texture: Option<DepthTexture>,
+    /// for the deferred path
+    pub g_buffer: Option<GBuffer>,
+    pub outline_targets: Option<OutlineTargets>,
+    /// only the main window picks
+    pub picker: Option<Picker>,
+}
 
 pub struct WgpuHandles {
     pub adapter: wgpu::Adapter,
@@ -7,4 +24,70 @@ pub struct WgpuHandles {
     pub device: Arc<wgpu::Device>,
     pub queue: wgpu::Queue,
     pub material_manager: Arc<MaterialManager>,
+    pub gpu_timer: Option<GpuTimer>,
+    /// by window index, see `SceneView::window`
+    pub window_targets: HashMap<usize, WindowTargets>,
+    /// for the clustered path, shared by all views
+    pub light_clusters: Option<LightClusters>,
+    /// by `RenderTexture` name
+    pub offscreen_targets: HashMap<String, OffscreenTarget>,
+    pub sprite_batcher: Option<SpriteBatcher>,
+    /// atlases by `Font` id
+    pub font_textures: HashMap<u32, FontTexture>,
+    pub ui_renderer: Option<UiRenderer>,
+    pub particle_camera: Option<ParticleCamera>,
+    /// counts the devices created in place of lost ones, a scene uploaded for an older
+    /// one has to be uploaded again
+    pub device_generation: u64,
+}
+
+impl WgpuHandles {
+    pub fn new(
+        adapter: wgpu::Adapter,
+        instance: wgpu::Instance,
+        device: Arc<wgpu::Device>,
+        queue: wgpu::Queue,
+        material_manager: Arc<MaterialManager>,
+    ) -> Self {
+        WgpuHandles {
+            adapter,
+            instance,
+            gpu_timer: GpuTimer::new(&device),
+            device,
+            queue,
+            material_manager,
+            window_targets: HashMap::new(),
+            light_clusters: None,
+            offscreen_targets: HashMap::new(),
+            sprite_batcher: None,
+            font_textures: HashMap::new(),
+            ui_renderer: None,
+            particle_camera: None,
+            device_generation: 0,
+        }
+    }
+
+    /// Move to a device created in place of a lost one. Everything kept for the old
+    /// device is dropped, it is created again on the new one as it is needed.
+    pub fn replace_device(
+        &mut self,
+        adapter: wgpu::Adapter,
+        device: Arc<wgpu::Device>,
+        queue: wgpu::Queue,
+        material_manager: Arc<MaterialManager>,
+    ) {
+        self.adapter = adapter;
+        self.gpu_timer = GpuTimer::new(&device);
+        self.device = device;
+        self.queue = queue;
+        self.material_manager = material_manager;
+        self.window_targets.clear();
+        self.light_clusters = None;
+        self.offscreen_targets.clear();
+        self.sprite_batcher = None;
+        self.font_textures.clear();
+        self.ui_renderer = None;
+        self.particle_camera = None;
+        self.device_generation += 1;
+    }
 }
@@ -1,38 +1,192 @@
 use glm::GenSquareMat;
 
-use crate::renderer::light::ViewInfoData;
+use crate::renderer::{fog::FogData, light::ViewInfoData, render::RenderTargetSize};
 
 pub enum Camera {
     PerspectiveCamera {
         world_to_local: glm::Mat4,
+        /// vertical field of view in degrees
+        fov: f32,
         near: f32,
         far: f32,
         aspect: f32,
+        /// bitmask of the layers of the objects it sees
+        layers: u32,
     },
+    OrthographicCamera {
+        world_to_local: glm::Mat4,
+        size: OrthographicSize,
+        near: f32,
+        far: f32,
+        aspect: f32,
+        /// bitmask of the layers of the objects it sees
+        layers: u32,
+    },
+}
+
+/// Visible area of an orthographic camera in camera space units.
+pub enum OrthographicSize {
+    Bounds {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+    },
+    /// centered on the camera, the width follows from the aspect ratio
+    Height(f32),
+    /// centered on the camera with one unit per logical pixel, the size is updated by
+    /// `Camera::fit_to_render_target`
+    LogicalPixels { width: f32, height: f32 },
+}
+
+/// glm builds projections for the OpenGL depth range of -1 to 1, wgpu clips at 0 to 1.
+fn depth_range_correction() -> glm::Mat4 {
+    glm::Matrix4::new(
+        glm::vec4(1.0, 0.0, 0.0, 0.0),
+        glm::vec4(0.0, 1.0, 0.0, 0.0),
+        glm::vec4(0.0, 0.0, 0.5, 0.0),
+        glm::vec4(0.0, 0.0, 0.5, 1.0),
+    )
+}
+
+/// Orthographic projection for the wgpu depth range, like `glm::ext::perspective` this
+/// looks down the negative z axis.
+fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> glm::Mat4 {
+    glm::Matrix4::new(
+        glm::vec4(2.0 / (right - left), 0.0, 0.0, 0.0),
+        glm::vec4(0.0, 2.0 / (top - bottom), 0.0, 0.0),
+        glm::vec4(0.0, 0.0, -1.0 / (far - near), 0.0),
+        glm::vec4(
+            -(right + left) / (right - left),
+            -(top + bottom) / (top - bottom),
+            -near / (far - near),
+            1.0,
+        ),
+    )
 }
 
 impl Camera {
-    pub fn get_inverse_matrix(&self) -> glm::Mat4 {
+    pub fn get_projection_matrix(&self) -> glm::Mat4 {
         match self {
             Camera::PerspectiveCamera {
-                world_to_local,
+                fov,
                 near,
                 far,
                 aspect,
-            } => glm::ext::perspective(
-                90.0f32.to_radians(),
+                ..
+            } => depth_range_correction().mul_m(&glm::ext::perspective(
+                fov.to_radians(),
                 aspect.to_owned(),
                 near.to_owned(),
                 far.to_owned(),
-            )
-            .mul_m(world_to_local),
+            )),
+            Camera::OrthographicCamera {
+                size,
+                near,
+                far,
+                aspect,
+                ..
+            } => match size {
+                OrthographicSize::Bounds {
+                    left,
+                    right,
+                    bottom,
+                    top,
+                } => orthographic(*left, *right, *bottom, *top, *near, *far),
+                OrthographicSize::LogicalPixels { width, height } => orthographic(
+                    -width / 2.0,
+                    width / 2.0,
+                    -height / 2.0,
+                    height / 2.0,
+                    *near,
+                    *far,
+                ),
+                OrthographicSize::Height(height) => {
+                    let half_height = height / 2.0;
+                    let half_width = half_height * aspect;
+                    orthographic(
+                        -half_width,
+                        half_width,
+                        -half_height,
+                        half_height,
+                        *near,
+                        *far,
+                    )
+                }
+            },
+        }
+    }
+
+    pub fn get_inverse_matrix(&self) -> glm::Mat4 {
+        self.get_projection_matrix().mul_m(self.world_to_local())
+    }
+
+    /// Update the aspect ratio, and the size for `OrthographicSize::LogicalPixels`, to
+    /// match the render target.
+    pub fn fit_to_render_target(&mut self, render_target_size: RenderTargetSize) {
+        if render_target_size.width == 0 || render_target_size.height == 0 {
+            return;
+        }
+        let new_aspect = render_target_size.width as f32 / render_target_size.height as f32;
+
+        match self {
+            Camera::PerspectiveCamera { aspect, .. } => *aspect = new_aspect,
+            Camera::OrthographicCamera { size, aspect, .. } => {
+                *aspect = new_aspect;
+                if let OrthographicSize::LogicalPixels { width, height } = size {
+                    let scale_factor = render_target_size.scale_factor as f32;
+                    *width = render_target_size.width as f32 / scale_factor;
+                    *height = render_target_size.height as f32 / scale_factor;
+                }
+            }
+        }
+    }
+
+    pub fn world_to_local(&self) -> &glm::Mat4 {
+        match self {
+            Camera::PerspectiveCamera { world_to_local, .. } => world_to_local,
+            Camera::OrthographicCamera { world_to_local, .. } => world_to_local,
+        }
+    }
+
+    pub fn layers(&self) -> u32 {
+        match self {
+            Camera::PerspectiveCamera { layers, .. } => *layers,
+            Camera::OrthographicCamera { layers, .. } => *layers,
+        }
+    }
+
+    /// Distances of the near and far plane in front of the camera.
+    pub fn depth_range(&self) -> (f32, f32) {
+        match self {
+            Camera::PerspectiveCamera { near, far, .. } => (*near, *far),
+            Camera::OrthographicCamera { near, far, .. } => (*near, *far),
         }
     }
 
+    pub fn world_to_local_mut(&mut self) -> &mut glm::Mat4 {
+        match self {
+            Camera::PerspectiveCamera { world_to_local, .. } => world_to_local,
+            Camera::OrthographicCamera { world_to_local, .. } => world_to_local,
+        }
+    }
+
+    /// View info without fog, which is a setting of the scene.
     pub fn get_view_info(&self) -> ViewInfoData {
+        let local_to_world = self.world_to_local().inverse().unwrap();
+        let camera_position = local_to_world * glm::vec4(0.0, 0.0, 0.0, 1.0);
+
         match self {
-            Camera::PerspectiveCamera { world_to_local, .. } => ViewInfoData {
-                position: world_to_local.inverse().unwrap() * glm::vec4(0.0, 0.0, 0.0, 1.0),
+            Camera::PerspectiveCamera { .. } => ViewInfoData {
+                position: camera_position,
+                camera_position,
+                fog: FogData::default(),
+            },
+            // all view rays are parallel, so the direction towards the camera is given
+            Camera::OrthographicCamera { .. } => ViewInfoData {
+                position: local_to_world * glm::vec4(0.0, 0.0, 1.0, 0.0),
+                camera_position,
+                fog: FogData::default(),
             },
         }
     }
@@ -0,0 +1,60 @@
+use super::object3d::{next_object_id, Object3D, Object3DObject, DEFAULT_LAYERS};
+use crate::util::{identity_matrix, MuckableMatrix};
+
+/// An endless grid on the xz plane of the grid object with its x, y and z axes in red,
+/// green and blue, drawn in a pass of its own after the scene.
+///
+/// The grid is computed for each pixel, so the lines stay as wide as `line_width` at any
+/// distance. Where the cells get too small to see, they fade into the next coarser level,
+/// every `major_every` minor lines make up a cell of it.
+#[derive(Debug)]
+pub struct Grid {
+    /// world units between the minor lines of the finest level
+    pub cell_size: f32,
+    pub major_every: u32,
+    pub minor_color: glm::Vec4,
+    pub major_color: glm::Vec4,
+    /// in pixels
+    pub line_width: f32,
+    pub axis_width: f32,
+    /// for perspective cameras the lines fade out at this many times the height of the
+    /// camera above the grid, so the grid reaches as far at any zoom
+    pub fade_distance: f32,
+    pub show_axes: bool,
+    pub grid_buffer: Option<wgpu::Buffer>,
+    pub bind_group: Option<wgpu::BindGroup>,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid {
+            cell_size: 1.0,
+            major_every: 10,
+            minor_color: glm::vec4(0.5, 0.5, 0.5, 0.4),
+            major_color: glm::vec4(0.5, 0.5, 0.5, 0.8),
+            line_width: 1.0,
+            axis_width: 2.0,
+            fade_distance: 40.0,
+            show_axes: true,
+            grid_buffer: None,
+            bind_group: None,
+        }
+    }
+}
+
+impl Grid {
+    pub fn new_object_3d(name: Option<String>, grid: Grid) -> Object3D {
+        Object3D {
+            id: next_object_id(),
+            object: Object3DObject::Grid(grid),
+            name,
+            matrix: MuckableMatrix(identity_matrix()),
+            children: vec![],
+            label: None,
+            visible: true,
+            layers: DEFAULT_LAYERS,
+            matrix_bind_group: None,
+            matrix_buffer: None,
+        }
+    }
+}
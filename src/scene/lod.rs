@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+
+use super::{
+    mesh::Mesh,
+    morph::{Morph, MorphTarget},
+    object3d::{next_object_id, Object3D, Object3DObject, DEFAULT_LAYERS},
+    scene_view::ViewKey,
+    skin::Skin,
+};
+use crate::util::{
+    identity_matrix,
+    simplify::{simplify, SimplifiedMesh},
+    MuckableMatrix,
+};
+
+/// Picks one of its children to draw from how large it is on screen. The children are
+/// the levels of detail, the most detailed first. Each view keeps its own level, so
+/// cameras at different distances do not switch the group back and forth.
+#[derive(Debug)]
+pub struct LodGroup {
+    /// one for each level, the smallest height on screen the level is drawn at, as a
+    /// fraction of the viewport height
+    ///
+    /// The last level is drawn at any size below the one before it.
+    pub min_screen_sizes: Vec<f32>,
+    /// of a sphere around the origin of the group that holds the most detailed level
+    pub radius: f32,
+    /// seconds two levels are dithered into each other when the group switches, 0
+    /// switches at once
+    pub fade_duration: f32,
+    /// the level of the group in each view it was drawn in
+    pub view_levels: HashMap<ViewKey, LodLevel>,
+    /// the level in the view that is being drawn
+    drawn_level: LodLevel,
+}
+
+/// The level a group is drawn at in one view.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LodLevel {
+    pub level: usize,
+    /// the level that is fading out and the scene time it started to
+    pub fading_from: Option<(usize, f64)>,
+    /// how far the fade has come from 0 to 1, as of the last update
+    pub fade_progress: f32,
+}
+
+impl LodGroup {
+    /// A group with these levels and the screen sizes they are drawn from.
+    pub fn new_object_3d(name: Option<String>, levels: Vec<(Object3D, f32)>) -> Object3D {
+        let (children, min_screen_sizes): (Vec<Object3D>, Vec<f32>) = levels.into_iter().unzip();
+        let radius = children
+            .first()
+            .map_or(0.0, |level| bounding_radius(level, &identity_matrix()));
+        let lod_group = LodGroup {
+            min_screen_sizes,
+            radius,
+            fade_duration: 0.0,
+            view_levels: HashMap::new(),
+            drawn_level: LodLevel::default(),
+        };
+
+        Object3D {
+            id: next_object_id(),
+            object: Object3DObject::LodGroup(lod_group),
+            name,
+            matrix: MuckableMatrix(identity_matrix()),
+            children,
+            label: None,
+            visible: true,
+            layers: DEFAULT_LAYERS,
+            matrix_bind_group: None,
+            matrix_buffer: None,
+        }
+    }
+
+    /// A group with `object3d` as the most detailed level and simplified copies of it
+    /// as the others. Each level is a fraction of the triangles of the original and the
+    /// screen size it is drawn from.
+    ///
+    /// The copies keep the skins, morphs, labels, layers and visibility of the original.
+    /// A skinned copy is moved by the same joints, a morphed copy has weights of its own
+    /// that have to be set along with the original's.
+    pub fn simplified_object_3d(
+        name: Option<String>,
+        object3d: Object3D,
+        min_screen_size: f32,
+        lower_levels: &[(f32, f32)],
+    ) -> Object3D {
+        let mut levels: Vec<(Object3D, f32)> = lower_levels
+            .iter()
+            .map(|(triangle_ratio, min_screen_size)| {
+                (
+                    simplified_copy(&object3d, *triangle_ratio),
+                    *min_screen_size,
+                )
+            })
+            .collect();
+        levels.insert(0, (object3d, min_screen_size));
+        Self::new_object_3d(name, levels)
+    }
+
+    /// The level for an object this tall on screen, as a fraction of the viewport height.
+    pub fn level_for_screen_size(&self, screen_size: f32) -> usize {
+        self.min_screen_sizes
+            .iter()
+            .position(|min_screen_size| screen_size >= *min_screen_size)
+            .unwrap_or(self.min_screen_sizes.len().saturating_sub(1))
+    }
+
+    /// Switch to `level` in `view`, fading from the level it had there if there is a fade
+    /// duration. `time` is the scene time in seconds.
+    pub fn set_level(&mut self, view: &ViewKey, level: usize, time: f64) {
+        let Some(view_level) = self.view_levels.get_mut(view) else {
+            // a view that has not drawn the group yet starts at its level
+            self.view_levels.insert(
+                view.clone(),
+                LodLevel {
+                    level,
+                    ..Default::default()
+                },
+            );
+            return;
+        };
+        if level == view_level.level {
+            return;
+        }
+        view_level.fading_from = match self.fade_duration > 0.0 {
+            true => Some((view_level.level, time)),
+            false => None,
+        };
+        view_level.fade_progress = 0.0;
+        view_level.level = level;
+    }
+
+    // Move the fade of `view` along and make it the level that is drawn.
+    fn update_fade(&mut self, view: &ViewKey, time: f64) {
+        let Some(view_level) = self.view_levels.get_mut(view) else {
+            return;
+        };
+        if let Some((_, start)) = view_level.fading_from {
+            view_level.fade_progress = (time - start) as f32 / self.fade_duration;
+            if view_level.fade_progress >= 1.0 {
+                view_level.fading_from = None;
+            }
+        }
+        self.drawn_level = *view_level;
+    }
+
+    /// Whether the child at this index is drawn in the view that is being drawn.
+    pub fn is_level_shown(&self, level: usize) -> bool {
+        let drawn_level = &self.drawn_level;
+        level == drawn_level.level
+            || matches!(drawn_level.fading_from, Some((from, _)) if from == level)
+    }
+
+    /// The range of the dither pattern the child at this index is drawn in. The fading
+    /// levels split the pattern between them, so that every pixel is drawn by one.
+    pub fn level_fade(&self, level: usize) -> [f32; 2] {
+        let drawn_level = &self.drawn_level;
+        match drawn_level.fading_from {
+            Some(_) if level == drawn_level.level => [0.0, drawn_level.fade_progress],
+            Some((from, _)) if level == from => [drawn_level.fade_progress, 1.0],
+            _ => [0.0, 1.0],
+        }
+    }
+}
+
+// Furthest any vertex of the meshes in this subtree is from its origin.
+fn bounding_radius(object3d: &Object3D, parent_matrix: &glm::Mat4) -> f32 {
+    let matrix: glm::Mat4 = object3d.matrix.into();
+    let matrix = parent_matrix.mul_m(&matrix);
+    let mut radius: f32 = 0.0;
+    if let Object3DObject::Mesh(mesh) = &object3d.object {
+        for vertex in mesh.vertex_array.chunks_exact(12) {
+            let position = matrix.mul_v(&glm::vec4(vertex[0], vertex[1], vertex[2], 1.0));
+            radius = radius.max(glm::length(glm::vec3(position.x, position.y, position.z)));
+        }
+    }
+    object3d.children.iter().fold(radius, |radius, child| {
+        radius.max(bounding_radius(child, &matrix))
+    })
+}
+
+// The same tree with every mesh simplified to this fraction of its triangles. Skins and
+// morphs follow the vertices that are left.
+fn simplified_copy(object3d: &Object3D, triangle_ratio: f32) -> Object3D {
+    let mut copy = match &object3d.object {
+        Object3DObject::Mesh(mesh) => {
+            let SimplifiedMesh {
+                vertex_array,
+                index_array,
+                source_vertices,
+            } = simplify(&mesh.vertex_array, &mesh.index_array, triangle_ratio);
+            let mut copy = Mesh::new_object_3d(
+                object3d.name.clone(),
+                vertex_array,
+                index_array,
+                mesh.material.duplicate(),
+            );
+            if let Object3DObject::Mesh(simplified_mesh) = &mut copy.object {
+                simplified_mesh.skin = mesh.skin.as_ref().map(|skin| {
+                    Skin::new(
+                        skin.joints.clone(),
+                        skin.inverse_bind_matrices.clone(),
+                        pick_vertices(&skin.vertex_array, &source_vertices),
+                    )
+                });
+                simplified_mesh.morph = mesh.morph.as_ref().map(|morph| Morph {
+                    weights: morph.weights.clone(),
+                    ..Morph::new(
+                        morph
+                            .targets
+                            .iter()
+                            .map(|target| MorphTarget {
+                                name: target.name.clone(),
+                                deltas: pick_vertices(&target.deltas, &source_vertices),
+                            })
+                            .collect(),
+                    )
+                });
+            }
+            copy
+        }
+        _ => Object3D {
+            name: object3d.name.clone(),
+            ..Object3D::create_empty()
+        },
+    };
+    copy.matrix = object3d.matrix;
+    copy.label = object3d.label.clone();
+    copy.visible = object3d.visible;
+    copy.layers = object3d.layers;
+    copy.children = object3d
+        .children
+        .iter()
+        .map(|child| simplified_copy(child, triangle_ratio))
+        .collect();
+    copy
+}
+
+fn pick_vertices<T: Copy>(vertex_array: &[T], vertices: &[u32]) -> Box<[T]> {
+    vertices
+        .iter()
+        .map(|vertex| vertex_array[*vertex as usize])
+        .collect()
+}
+
+/// Pick the level every level of detail group in this subtree has in `view` from its
+/// size on screen, and move their fades in that view along to the scene time `time`.
+/// The groups are drawn at these levels until this is called for another view.
+pub fn update_lod_groups(
+    object3d: &mut Object3D,
+    parent_matrix: &glm::Mat4,
+    projection_matrix: &glm::Mat4,
+    view: &ViewKey,
+    time: f64,
+) {
+    let matrix: glm::Mat4 = object3d.matrix.into();
+    let matrix = parent_matrix.mul_m(&matrix);
+
+    if let Object3DObject::LodGroup(lod_group) = &mut object3d.object {
+        let view_position = matrix.mul_v(&glm::vec4(0.0, 0.0, 0.0, 1.0));
+        let scale = glm::length(glm::vec3(matrix.c0.x, matrix.c0.y, matrix.c0.z));
+        // w is the depth for perspective cameras and 1 for orthographic ones
+        let clip_position = projection_matrix.mul_v(&view_position);
+        let screen_size =
+            lod_group.radius * scale * projection_matrix.c1.y / clip_position.w.max(1e-6);
+        let level = lod_group.level_for_screen_size(screen_size);
+        lod_group.set_level(view, level, time);
+        lod_group.update_fade(view, time);
+    }
+
+    for child in &mut object3d.children {
+        update_lod_groups(child, &matrix, projection_matrix, view, time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        renderer::material::{Material, PhongMaterial},
+        scene::{morph::MorphDelta, skin::SkinVertex},
+    };
+
+    fn material() -> Material {
+        let black = glm::vec3(0.0, 0.0, 0.0);
+        Material::PhongMaterial(PhongMaterial::new_without_texture(black, black, black, 1.0))
+    }
+
+    /// A square of quads in the xy plane from -1 to 1, the uv of each vertex is its index.
+    fn square(size: u32) -> Object3D {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for y in 0..=size {
+            for x in 0..=size {
+                let index = (y * (size + 1) + x) as f32;
+                let [px, py] = [x, y].map(|value| value as f32 / size as f32 * 2.0 - 1.0);
+                vertices.extend([px, py, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, index, 0.0, 0.0, 0.0]);
+            }
+        }
+        for y in 0..size {
+            for x in 0..size {
+                let index = |x: u32, y: u32| y * (size + 1) + x;
+                indices.extend([index(x, y), index(x + 1, y), index(x, y + 1)]);
+                indices.extend([index(x + 1, y), index(x + 1, y + 1), index(x, y + 1)]);
+            }
+        }
+        Mesh::new_object_3d(None, vertices.into(), indices.into(), material())
+    }
+
+    fn lod_group(object3d: &Object3D) -> &LodGroup {
+        match &object3d.object {
+            Object3DObject::LodGroup(lod_group) => lod_group,
+            _ => unreachable!(),
+        }
+    }
+
+    /// A group of two levels, the first drawn from a tenth of the viewport height.
+    fn two_levels(fade_duration: f32) -> Object3D {
+        let mut object3d = LodGroup::new_object_3d(None, vec![(square(1), 0.1), (square(1), 0.0)]);
+        if let Object3DObject::LodGroup(lod_group) = &mut object3d.object {
+            lod_group.fade_duration = fade_duration;
+        }
+        object3d
+    }
+
+    fn update_at_distance(object3d: &mut Object3D, distance: f32, view: &ViewKey, time: f64) {
+        let view_matrix = glm::ext::translate(&identity_matrix(), glm::vec3(0.0, 0.0, -distance));
+        let projection_matrix = glm::ext::perspective(90f32.to_radians(), 1.0, 0.1, 100.0);
+        update_lod_groups(object3d, &view_matrix, &projection_matrix, view, time);
+    }
+
+    #[test]
+    fn views_keep_their_own_level() {
+        let mut object3d = two_levels(1.0);
+        let (near, far) = (ViewKey::SceneCamera, ViewKey::View(0));
+        for frame in 0..3 {
+            let time = frame as f64 * 0.1;
+            update_at_distance(&mut object3d, 2.0, &near, time);
+            let group = lod_group(&object3d);
+            assert!(group.is_level_shown(0) && !group.is_level_shown(1));
+            assert_eq!(group.level_fade(0), [0.0, 1.0]);
+
+            update_at_distance(&mut object3d, 50.0, &far, time);
+            let group = lod_group(&object3d);
+            assert!(!group.is_level_shown(0) && group.is_level_shown(1));
+            assert_eq!(group.level_fade(1), [0.0, 1.0]);
+        }
+        let view_levels = &lod_group(&object3d).view_levels;
+        assert_eq!(view_levels[&near].level, 0);
+        assert_eq!(view_levels[&far].level, 1);
+    }
+
+    #[test]
+    fn fades_follow_the_scene_time() {
+        let mut object3d = two_levels(2.0);
+        let view = ViewKey::SceneCamera;
+        update_at_distance(&mut object3d, 2.0, &view, 0.0);
+        update_at_distance(&mut object3d, 50.0, &view, 10.0);
+        assert_eq!(lod_group(&object3d).level_fade(1), [0.0, 0.0]);
+
+        update_at_distance(&mut object3d, 50.0, &view, 10.5);
+        let group = lod_group(&object3d);
+        assert_eq!(group.level_fade(1), [0.0, 0.25]);
+        assert_eq!(group.level_fade(0), [0.25, 1.0]);
+
+        update_at_distance(&mut object3d, 50.0, &view, 12.0);
+        let group = lod_group(&object3d);
+        assert!(!group.is_level_shown(0));
+        assert_eq!(group.level_fade(1), [0.0, 1.0]);
+    }
+
+    #[test]
+    fn simplified_levels_keep_skin_morph_and_weights() {
+        let mut original = square(8);
+        let vertex_count = 81;
+        if let Object3DObject::Mesh(mesh) = &mut original.object {
+            let skin_vertices = (0..vertex_count).map(|index| SkinVertex {
+                joints: [0; 4],
+                weights: glm::vec4(index as f32, 0.0, 0.0, 0.0),
+            });
+            mesh.skin = Some(Skin::new(
+                vec![7],
+                vec![identity_matrix()],
+                skin_vertices.collect(),
+            ));
+            let deltas = (0..vertex_count).map(|index| MorphDelta {
+                position: glm::vec4(index as f32, 0.0, 0.0, 0.0),
+                normal: glm::vec4(0.0, 0.0, 0.0, 0.0),
+            });
+            let mut morph = Morph::new(vec![MorphTarget {
+                name: Some("smile".into()),
+                deltas: deltas.collect(),
+            }]);
+            morph.weights = vec![0.5];
+            mesh.morph = Some(morph);
+        }
+
+        let object3d = LodGroup::simplified_object_3d(None, original, 0.5, &[(0.25, 0.0)]);
+        let Object3DObject::Mesh(simplified) = &object3d.children[1].object else {
+            unreachable!()
+        };
+        let (skin, morph) = (simplified.skin.as_ref(), simplified.morph.as_ref());
+        let (skin, morph) = (skin.unwrap(), morph.unwrap());
+        let simplified_count = simplified.vertex_array.len() / 12;
+        assert!(simplified_count < vertex_count);
+        assert_eq!(skin.joints, [7]);
+        assert_eq!(skin.vertex_array.len(), simplified_count);
+        assert_eq!(morph.targets[0].name.as_deref(), Some("smile"));
+        assert_eq!(morph.targets[0].deltas.len(), simplified_count);
+        assert_eq!(morph.weights, [0.5]);
+        // each vertex brings the skin and morph data of the vertex it was made from
+        for (index, vertex) in simplified.vertex_array.chunks_exact(12).enumerate() {
+            assert_eq!(skin.vertex_array[index].weights.x, vertex[8]);
+            assert_eq!(morph.targets[0].deltas[index].position.x, vertex[8]);
+        }
+    }
+
+    #[test]
+    fn simplified_levels_keep_layers_and_visibility() {
+        let mut original = Object3D::create_empty();
+        original.layers = 0b100;
+        let mut hidden = square(4);
+        hidden.visible = false;
+        hidden.layers = 0b10;
+        original.children.push(hidden);
+
+        let object3d = LodGroup::simplified_object_3d(None, original, 0.5, &[(0.5, 0.0)]);
+        let simplified = &object3d.children[1];
+        assert_eq!(simplified.layers, 0b100);
+        assert!(simplified.visible);
+        assert_eq!(simplified.children[0].layers, 0b10);
+        assert!(!simplified.children[0].visible);
+    }
+}
@@ -1,6 +1,10 @@
-use super::object3d::{Object3D, Object3DObject};
+use super::{
+    morph::Morph,
+    object3d::{next_object_id, Object3D, Object3DObject, DEFAULT_LAYERS},
+    skin::Skin,
+};
 use crate::{
-    renderer::material::Material,
+    renderer::material::{Deformation, Material},
     util::{identity_matrix, MuckableMatrix},
 };
 
@@ -14,6 +18,11 @@ pub struct Mesh {
     pub material: Material,
     pub vertex_buffer: Option<wgpu::Buffer>,
     pub index_buffer: Option<wgpu::Buffer>,
+    /// drawn with the skinning pipelines when set
+    pub skin: Option<Skin>,
+    pub morph: Option<Morph>,
+    /// joint matrices and morph targets, for the deformed pipelines
+    pub deformation_bind_group: Option<wgpu::BindGroup>,
 }
 
 impl Mesh {
@@ -29,17 +38,63 @@ impl Mesh {
             material,
             vertex_buffer: None,
             index_buffer: None,
+            skin: None,
+            morph: None,
+            deformation_bind_group: None,
         };
 
         Object3D {
+            id: next_object_id(),
             object: Object3DObject::Mesh(mesh),
             name,
             matrix: MuckableMatrix(identity_matrix()),
             children: vec![],
+            label: None,
+            visible: true,
+            layers: DEFAULT_LAYERS,
             matrix_bind_group: None,
             matrix_buffer: None,
         }
     }
+
+    /// A mesh deformed by the joints of `skin`, which has a `SkinVertex` for each vertex.
+    pub fn new_skinned_object_3d(
+        name: Option<String>,
+        vertex_array: Box<[f32]>,
+        index_array: Box<[u32]>,
+        material: Material,
+        skin: Skin,
+    ) -> Object3D {
+        let mut object3d = Self::new_object_3d(name, vertex_array, index_array, material);
+        if let Object3DObject::Mesh(mesh) = &mut object3d.object {
+            mesh.skin = Some(skin);
+        }
+        object3d
+    }
+
+    /// A mesh with blend shapes, each target has a `MorphDelta` for each vertex.
+    pub fn new_morphed_object_3d(
+        name: Option<String>,
+        vertex_array: Box<[f32]>,
+        index_array: Box<[u32]>,
+        material: Material,
+        morph: Morph,
+    ) -> Object3D {
+        let mut object3d = Self::new_object_3d(name, vertex_array, index_array, material);
+        if let Object3DObject::Mesh(mesh) = &mut object3d.object {
+            mesh.morph = Some(morph);
+        }
+        object3d
+    }
+
+    pub fn deformation(&self) -> Deformation {
+        match (&self.skin, &self.morph) {
+            (None, None) => Deformation::None,
+            (Some(_), None) => Deformation::Skinned,
+            (None, Some(_)) => Deformation::Morphed,
+            (Some(_), Some(_)) => Deformation::SkinnedAndMorphed,
+        }
+    }
 }
 
 #[repr(C)]
@@ -1,4 +1,14 @@
 pub mod camera;
+pub mod grid;
+pub mod lod;
 pub mod mesh;
+pub mod morph;
 pub mod object3d;
+pub mod particles;
+pub mod render_texture;
 pub mod scene;
+pub mod scene_view;
+pub mod skin;
+pub mod sprite;
+pub mod terrain;
+pub mod text;
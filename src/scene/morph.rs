@@ -0,0 +1,195 @@
+use super::{
+    mesh::Mesh,
+    object3d::{Object3D, Object3DObject},
+};
+use crate::renderer::{
+    staging_belt_and_command_encoder::StagingBeltAndCommandEncoder, wgpu_handles::WgpuHandles,
+};
+
+/// Targets a morph can blend at once, the size of the weight uniform.
+pub const MAX_MORPH_TARGETS: usize = 16;
+
+/// How far one vertex moves when its target has a weight of 1.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct MorphDelta {
+    pub position: glm::Vec4,
+    pub normal: glm::Vec4,
+}
+
+unsafe impl bytemuck::Zeroable for MorphDelta {}
+unsafe impl bytemuck::Pod for MorphDelta {}
+
+impl MorphDelta {
+    pub fn new(position: glm::Vec3, normal: glm::Vec3) -> Self {
+        MorphDelta {
+            position: position.extend(0.0),
+            normal: normal.extend(0.0),
+        }
+    }
+}
+
+/// A blend shape, like a smile or a blink.
+#[derive(Debug)]
+pub struct MorphTarget {
+    pub name: Option<String>,
+    /// one for each vertex of the mesh, in the same order
+    pub deltas: Box<[MorphDelta]>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct MorphWeightData {
+    vertex_count: u32,
+    target_count: u32,
+    _padding: [u32; 2],
+    weights: [glm::Vec4; MAX_MORPH_TARGETS / 4],
+}
+
+unsafe impl bytemuck::Zeroable for MorphWeightData {}
+unsafe impl bytemuck::Pod for MorphWeightData {}
+
+/// Morph targets of a mesh and how much each one is applied. The vertex shader adds the
+/// weighted deltas of every target to the vertices.
+#[derive(Debug)]
+pub struct Morph {
+    pub targets: Vec<MorphTarget>,
+    /// one for each target, set from game code or by a `MorphClip`
+    pub weights: Vec<f32>,
+    /// the deltas of all targets one after the other
+    pub delta_buffer: Option<wgpu::Buffer>,
+    pub weight_buffer: Option<wgpu::Buffer>,
+}
+
+impl Morph {
+    pub fn new(targets: Vec<MorphTarget>) -> Self {
+        assert!(
+            targets.len() <= MAX_MORPH_TARGETS,
+            "a morph can have {MAX_MORPH_TARGETS} targets at most"
+        );
+        // the shader finds the deltas of a target by the vertex count of the first one
+        if let Some(first) = targets.first() {
+            for target in &targets {
+                assert_eq!(target.deltas.len(), first.deltas.len());
+            }
+        }
+        Morph {
+            weights: vec![0.0; targets.len()],
+            targets,
+            delta_buffer: None,
+            weight_buffer: None,
+        }
+    }
+
+    /// Set the weight of the target with this name, returns false if there is none.
+    pub fn set_weight(&mut self, name: &str, weight: f32) -> bool {
+        let index = self
+            .targets
+            .iter()
+            .position(|target| target.name.as_deref() == Some(name));
+        match index {
+            Some(index) => {
+                self.weights[index] = weight;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn deltas(&self) -> Vec<MorphDelta> {
+        self.targets
+            .iter()
+            .flat_map(|target| target.deltas.iter().copied())
+            .collect()
+    }
+
+    pub(crate) fn weight_data(&self) -> MorphWeightData {
+        let mut weights = [glm::vec4(0.0, 0.0, 0.0, 0.0); MAX_MORPH_TARGETS / 4];
+        for (index, weight) in self.weights.iter().take(self.targets.len()).enumerate() {
+            weights[index / 4][index % 4] = *weight;
+        }
+        MorphWeightData {
+            vertex_count: self.targets.first().map_or(0, |target| target.deltas.len()) as u32,
+            target_count: self.targets.len() as u32,
+            _padding: [0; 2],
+            weights,
+        }
+    }
+}
+
+/// Keyframed weights for a morph, interpolated linearly between the keyframes.
+#[derive(Debug, Clone)]
+pub struct MorphClip {
+    /// keyframe times in seconds, ascending
+    pub times: Vec<f32>,
+    /// the weights of all targets at each keyframe
+    pub weights: Vec<Vec<f32>>,
+    /// start over after the last keyframe instead of holding it
+    pub looping: bool,
+}
+
+impl MorphClip {
+    pub fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or(0.0)
+    }
+
+    /// The weights at `time` seconds into the clip.
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let (Some(first), Some(last)) = (self.weights.first(), self.weights.last()) else {
+            return vec![];
+        };
+        let duration = self.duration();
+        let time = match self.looping && duration > 0.0 {
+            true => time.rem_euclid(duration),
+            false => time,
+        };
+
+        let next = self.times.partition_point(|keyframe| *keyframe <= time);
+        if next == 0 {
+            return first.clone();
+        }
+        if next >= self.times.len() {
+            return last.clone();
+        }
+        let (from_time, to_time) = (self.times[next - 1], self.times[next]);
+        let t = (time - from_time) / (to_time - from_time);
+        self.weights[next - 1]
+            .iter()
+            .zip(&self.weights[next])
+            .map(|(from, to)| from + (to - from) * t)
+            .collect()
+    }
+
+    /// Set the weights of `morph` to the ones at `time` seconds into the clip.
+    pub fn apply(&self, morph: &mut Morph, time: f32) {
+        for (weight, sampled) in morph.weights.iter_mut().zip(self.sample(time)) {
+            *weight = sampled;
+        }
+    }
+}
+
+/// Write the weights of every morphed mesh in this subtree.
+pub fn write_morphs(
+    object3d: &Object3D,
+    wgpu_handles: &WgpuHandles,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+) {
+    if let Object3DObject::Mesh(Mesh {
+        morph: Some(morph), ..
+    }) = &object3d.object
+    {
+        // the buffers are there once the mesh is written
+        if let Some(weight_buffer) = &morph.weight_buffer {
+            staging_belt.write_buffer(
+                weight_buffer,
+                0,
+                bytemuck::cast_slice(&[morph.weight_data()]),
+                &wgpu_handles.device,
+            );
+        }
+    }
+
+    for child in &object3d.children {
+        write_morphs(child, wgpu_handles, staging_belt);
+    }
+}
@@ -1,4 +1,7 @@
-use std::num::NonZeroU64;
+use std::{
+    num::NonZeroU64,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use glm::GenSquareMat;
 use wgpu::{
@@ -6,10 +9,13 @@ use wgpu::{
     BindGroupDescriptor, BindGroupEntry, BufferBinding, BufferDescriptor, BufferUsages,
 };
 
-use super::mesh::Mesh;
+use super::{
+    grid::Grid, lod::LodGroup, mesh::Mesh, morph::MorphWeightData, particles::ParticleEmitter,
+    skin::MAX_JOINTS, terrain::Terrain, text::Label,
+};
 use crate::{
     renderer::draw_state::DrawState,
-    renderer::material::MaterialManager,
+    renderer::material::{Deformation, MaterialManager},
     renderer::{
         staging_belt_and_command_encoder::StagingBeltAndCommandEncoder, wgpu_handles::WgpuHandles,
     },
@@ -21,25 +27,74 @@ pub enum Object3DObject {
     Empty,
     Scene,
     Mesh(Mesh),
+    ParticleEmitter(ParticleEmitter),
+    Terrain(Terrain),
+    LodGroup(LodGroup),
+    Grid(Grid),
 }
 
 #[derive(Debug)]
 pub struct Object3D {
+    /// unique among all objects, this is what picking reports
+    pub id: u32,
     pub name: Option<String>,
     pub matrix: MuckableMatrix,
     pub object: Object3DObject,
     pub children: Vec<Object3D>,
+    pub label: Option<Label>,
+    /// hidden objects are left out together with their children
+    pub visible: bool,
+    /// bitmask of the layers the object is on, cameras, lights and picking only see the
+    /// objects on one of their layers, the children have their own
+    pub layers: u32,
     pub matrix_bind_group: Option<wgpu::BindGroup>,
     pub matrix_buffer: Option<wgpu::Buffer>,
 }
 
+/// Data in the matrix buffer of each object.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct MatrixData {
+    matrix: MuckableMatrix,
+    matrix_inverse: MuckableMatrix,
+    object_id: u32,
+    /// lights leave out the objects that are not on their layers
+    layers: u32,
+    /// the range of the dither pattern that is drawn, for fading levels of detail
+    fade: [f32; 2],
+    /// local to world, lighting and fog are done in world space
+    model: MuckableMatrix,
+    /// inverse transpose of `model`, for the normals
+    normal_matrix: MuckableMatrix,
+}
+
+unsafe impl bytemuck::Zeroable for MatrixData {}
+unsafe impl bytemuck::Pod for MatrixData {}
+
+pub const MATRIX_DATA_SIZE: u64 = std::mem::size_of::<MatrixData>() as u64;
+
+/// The layer new objects are on.
+pub const DEFAULT_LAYERS: u32 = 1;
+/// What cameras, lights and picking see by default.
+pub const ALL_LAYERS: u32 = u32::MAX;
+
+/// Id 0 is left for "no object" in the picking target.
+pub fn next_object_id() -> u32 {
+    static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 impl Object3D {
     pub fn create_empty() -> Self {
         Self {
+            id: next_object_id(),
             name: None,
             matrix: MuckableMatrix(identity_matrix()),
             object: Object3DObject::Empty,
             children: vec![],
+            label: None,
+            visible: true,
+            layers: DEFAULT_LAYERS,
             matrix_bind_group: None,
             matrix_buffer: None,
         }
@@ -49,6 +104,114 @@ impl Object3D {
         matches!(&self.object, Object3DObject::Mesh(_))
     }
 
+    /// Whether the object is on one of the given layers.
+    pub fn is_on_layers(&self, layers: u32) -> bool {
+        self.layers & layers != 0
+    }
+
+    /// Whether there is a skinned mesh in this subtree.
+    pub fn has_skins(&self) -> bool {
+        matches!(&self.object, Object3DObject::Mesh(mesh) if mesh.skin.is_some())
+            || self.children.iter().any(|child| child.has_skins())
+    }
+
+    /// Whether there is a mesh deformed this way in this subtree.
+    pub fn has_deformation(&self, deformation: Deformation) -> bool {
+        matches!(&self.object, Object3DObject::Mesh(mesh) if mesh.deformation() == deformation)
+            || self
+                .children
+                .iter()
+                .any(|child| child.has_deformation(deformation))
+    }
+
+    /// The children that are drawn, a level of detail group leaves out the levels it
+    /// does not show.
+    pub fn drawn_children(&self) -> impl Iterator<Item = &Object3D> {
+        self.children
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| match &self.object {
+                Object3DObject::LodGroup(lod_group) => lod_group.is_level_shown(*index),
+                _ => true,
+            })
+            .map(|(_, child)| child)
+    }
+
+    /// Call `f` with every mesh in this subtree that a camera seeing `layers` draws, and
+    /// the object holding it. A hidden object hides its subtree, an object on other
+    /// layers only itself.
+    pub fn for_each_drawn_mesh<'a, F>(&'a self, layers: u32, f: &mut F)
+    where
+        F: FnMut(&'a Object3D, &'a Mesh),
+    {
+        if !self.visible {
+            return;
+        }
+        if let (true, Object3DObject::Mesh(mesh)) = (self.is_on_layers(layers), &self.object) {
+            f(self, mesh);
+        }
+        for child in self.drawn_children() {
+            child.for_each_drawn_mesh(layers, f);
+        }
+    }
+
+    /// Find the object with the given id in this subtree.
+    pub fn find_by_id(&self, id: u32) -> Option<&Object3D> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find_by_id(id))
+    }
+
+    pub fn find_by_id_mut(&mut self, id: u32) -> Option<&mut Object3D> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .find_map(|child| child.find_by_id_mut(id))
+    }
+
+    /// Let go of the buffers and bind groups of this subtree, they are created and
+    /// written again the next time the objects are drawn.
+    pub fn drop_gpu_resources(&mut self) {
+        self.matrix_bind_group = None;
+        self.matrix_buffer = None;
+        match &mut self.object {
+            Object3DObject::Mesh(mesh) => {
+                mesh.vertex_buffer = None;
+                mesh.index_buffer = None;
+                mesh.deformation_bind_group = None;
+                if let Some(skin) = &mut mesh.skin {
+                    skin.vertex_buffer = None;
+                    skin.joint_buffer = None;
+                }
+                if let Some(morph) = &mut mesh.morph {
+                    morph.delta_buffer = None;
+                    morph.weight_buffer = None;
+                }
+                mesh.material.drop_gpu_resources();
+            }
+            // the particles start over with an empty ring
+            Object3DObject::ParticleEmitter(emitter) => {
+                emitter.particle_buffer = None;
+                emitter.emitter_buffer = None;
+                emitter.compute_bind_group = None;
+            }
+            Object3DObject::Grid(grid) => {
+                grid.grid_buffer = None;
+                grid.bind_group = None;
+            }
+            Object3DObject::Empty
+            | Object3DObject::Scene
+            | Object3DObject::Terrain(_)
+            | Object3DObject::LodGroup(_) => {}
+        }
+        for child in &mut self.children {
+            child.drop_gpu_resources();
+        }
+    }
+
     /// Compute the world matrices of all objects and write them to their buffers.
     pub fn write_matrices(
         &mut self,
@@ -71,7 +234,7 @@ impl Object3D {
         let matrix_buffer = self.matrix_buffer.get_or_insert_with(|| {
             wgpu_handles.device.create_buffer(&BufferDescriptor {
                 label: Some("SomeMatrixBuffer"),
-                size: 128,
+                size: MATRIX_DATA_SIZE,
                 usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
                 mapped_at_creation: false,
             })
@@ -90,27 +253,39 @@ impl Object3D {
                     resource: wgpu::BindingResource::Buffer(BufferBinding {
                         buffer: matrix_buffer,
                         offset: 0,
-                        size: NonZeroU64::new(128),
+                        size: NonZeroU64::new(MATRIX_DATA_SIZE),
                     }),
                 }],
             })
         });
 
-        let matrix_to_write = draw_state.get_matrix().to_owned();
+        let model = draw_state.get_matrix().to_owned();
+        let matrix_to_write = draw_state.view_projection.mul_m(&model);
         let matrix_inverse = matrix_to_write.inverse().unwrap();
+        let normal_matrix = glm::transpose(&model.inverse().unwrap());
         staging_belt.write_buffer(
             self.matrix_buffer.as_ref().unwrap(),
             0,
-            bytemuck::cast_slice(&[
-                MuckableMatrix(matrix_to_write),
-                MuckableMatrix(matrix_inverse),
-            ]),
+            bytemuck::cast_slice(&[MatrixData {
+                matrix: MuckableMatrix(matrix_to_write),
+                matrix_inverse: MuckableMatrix(matrix_inverse),
+                object_id: self.id,
+                layers: self.layers,
+                fade: draw_state.fade,
+                model: MuckableMatrix(model),
+                normal_matrix: MuckableMatrix(normal_matrix),
+            }]),
             &wgpu_handles.device,
         );
 
-        for child in &mut self.children {
+        let fade = draw_state.fade;
+        for (index, child) in self.children.iter_mut().enumerate() {
+            if let Object3DObject::LodGroup(lod_group) = &self.object {
+                draw_state.fade = lod_group.level_fade(index);
+            }
             child.write_matrices(wgpu_handles, draw_state, staging_belt);
         }
+        draw_state.fade = fade;
 
         draw_state.pop_matrix();
     }
@@ -137,6 +312,8 @@ impl Object3D {
 pub trait Object3DManager {
     fn write_object_3d_single(&self, object_3d: &mut Object3D, wgpu_handles: &WgpuHandles);
 
+    fn write_deformation(&self, mesh: &mut Mesh, wgpu_handles: &WgpuHandles);
+
     fn write_object_3d(&self, object_3d: &mut Object3D, wgpu_handles: &WgpuHandles);
 }
 
@@ -144,9 +321,13 @@ impl Object3DManager for MaterialManager {
     fn write_object_3d_single(&self, object_3d: &mut Object3D, wgpu_handles: &WgpuHandles) {
         match &mut object_3d.object {
             Object3DObject::Mesh(mesh) => {
-                if mesh.vertex_buffer.is_some() {
+                // a skin or morph can be added to a mesh that is already written
+                self.write_deformation(mesh, wgpu_handles);
+                // we allow only writing once, terrain chunks drop their index buffer when
+                // their level of detail changes
+                if mesh.vertex_buffer.is_some() && mesh.index_buffer.is_some() {
                     return;
-                } // we allow only writing once
+                }
                 mesh.vertex_buffer.get_or_insert_with(|| {
                     wgpu_handles
                         .device
@@ -170,6 +351,71 @@ impl Object3DManager for MaterialManager {
         }
     }
 
+    fn write_deformation(&self, mesh: &mut Mesh, wgpu_handles: &WgpuHandles) {
+        let Some(layout) = self.get_deformation_bind_group_layout(mesh.deformation()) else {
+            return;
+        };
+        if mesh.deformation_bind_group.is_some() {
+            return;
+        }
+
+        let device = &wgpu_handles.device;
+        let mut entries = vec![];
+        if let Some(skin) = &mut mesh.skin {
+            skin.vertex_buffer.get_or_insert_with(|| {
+                device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("SkinVertexBuffer"),
+                    contents: bytemuck::cast_slice(&skin.vertex_array),
+                    usage: BufferUsages::VERTEX.union(BufferUsages::COPY_DST),
+                })
+            });
+            let joint_buffer = skin.joint_buffer.get_or_insert_with(|| {
+                device.create_buffer(&BufferDescriptor {
+                    label: Some("JointMatrixBuffer"),
+                    size: (MAX_JOINTS * std::mem::size_of::<glm::Mat4>()) as u64,
+                    usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+                    mapped_at_creation: false,
+                })
+            });
+            entries.push(BindGroupEntry {
+                binding: 0,
+                resource: joint_buffer.as_entire_binding(),
+            });
+        }
+        if let Some(morph) = &mut mesh.morph {
+            let deltas = morph.deltas();
+            let delta_buffer = morph.delta_buffer.get_or_insert_with(|| {
+                device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("MorphDeltaBuffer"),
+                    contents: bytemuck::cast_slice(&deltas),
+                    usage: BufferUsages::STORAGE.union(BufferUsages::COPY_DST),
+                })
+            });
+            let weight_buffer = morph.weight_buffer.get_or_insert_with(|| {
+                device.create_buffer(&BufferDescriptor {
+                    label: Some("MorphWeightBuffer"),
+                    size: std::mem::size_of::<MorphWeightData>() as u64,
+                    usage: BufferUsages::UNIFORM.union(BufferUsages::COPY_DST),
+                    mapped_at_creation: false,
+                })
+            });
+            entries.push(BindGroupEntry {
+                binding: 1,
+                resource: delta_buffer.as_entire_binding(),
+            });
+            entries.push(BindGroupEntry {
+                binding: 2,
+                resource: weight_buffer.as_entire_binding(),
+            });
+        }
+
+        mesh.deformation_bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
+            label: Some("DeformationBindGroup"),
+            layout,
+            entries: &entries,
+        }));
+    }
+
     fn write_object_3d(&self, object_3d: &mut Object3D, wgpu_handles: &WgpuHandles) {
         self.write_object_3d_single(object_3d, wgpu_handles);
 
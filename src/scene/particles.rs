@@ -0,0 +1,89 @@
+use super::object3d::{next_object_id, Object3D, Object3DObject, DEFAULT_LAYERS};
+use crate::util::{identity_matrix, MuckableMatrix};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParticleBlend {
+    /// brightens what is behind, for fire and sparks
+    Additive,
+    /// covers what is behind, for smoke
+    Alpha,
+}
+
+/// Particles simulated on the GPU, spawned at the origin of the emitter object and
+/// moving in world space. They are drawn as round billboards facing the camera.
+///
+/// Particles live in a ring of `max_particles`, new particles replace the oldest ones
+/// when the ring is full.
+#[derive(Debug)]
+pub struct ParticleEmitter {
+    pub max_particles: u32,
+    /// particles per second, 0 stops emitting while the living particles play out
+    pub spawn_rate: f32,
+    /// in seconds
+    pub lifetime: f32,
+    /// initial velocity in the space of the emitter
+    pub velocity: glm::Vec3,
+    /// each component of the initial velocity varies by up to this much either way
+    pub velocity_spread: glm::Vec3,
+    /// world space acceleration
+    pub gravity: glm::Vec3,
+    /// fraction of the velocity lost per second
+    pub drag: f32,
+    pub start_color: glm::Vec4,
+    pub end_color: glm::Vec4,
+    /// billboard width and height in world units at spawn and at the end of life
+    pub start_size: f32,
+    pub end_size: f32,
+    pub blend: ParticleBlend,
+    /// spawns carried over to the next frame
+    pub(crate) spawn_remainder: f32,
+    /// where the next particle goes in the ring
+    pub(crate) next_particle: u32,
+    /// the render loop time of the last simulation step
+    pub(crate) last_update: Option<f64>,
+    pub(crate) frame: u32,
+    pub(crate) particle_buffer: Option<wgpu::Buffer>,
+    pub(crate) emitter_buffer: Option<wgpu::Buffer>,
+    pub(crate) compute_bind_group: Option<wgpu::BindGroup>,
+}
+
+impl ParticleEmitter {
+    pub fn new(max_particles: u32) -> Self {
+        ParticleEmitter {
+            max_particles,
+            spawn_rate: 100.0,
+            lifetime: 2.0,
+            velocity: glm::vec3(0.0, 1.0, 0.0),
+            velocity_spread: glm::vec3(0.2, 0.2, 0.2),
+            gravity: glm::vec3(0.0, 0.0, 0.0),
+            drag: 0.0,
+            start_color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            end_color: glm::vec4(1.0, 1.0, 1.0, 0.0),
+            start_size: 0.1,
+            end_size: 0.1,
+            blend: ParticleBlend::Alpha,
+            spawn_remainder: 0.0,
+            next_particle: 0,
+            last_update: None,
+            frame: 0,
+            particle_buffer: None,
+            emitter_buffer: None,
+            compute_bind_group: None,
+        }
+    }
+
+    pub fn new_object_3d(name: Option<String>, emitter: ParticleEmitter) -> Object3D {
+        Object3D {
+            id: next_object_id(),
+            object: Object3DObject::ParticleEmitter(emitter),
+            name,
+            matrix: MuckableMatrix(identity_matrix()),
+            children: vec![],
+            label: None,
+            visible: true,
+            layers: DEFAULT_LAYERS,
+            matrix_bind_group: None,
+            matrix_buffer: None,
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use super::{camera::Camera, scene::Scene};
+
+/// A camera drawing into an offscreen texture before the frame, for mirrors, monitors
+/// and minimaps. Materials sample it with `TextureSource::RenderTexture` using the same
+/// name.
+pub struct RenderTexture {
+    pub name: String,
+    pub camera: Camera,
+    /// in pixels
+    pub width: u32,
+    pub height: u32,
+    pub clear_color: wgpu::Color,
+    /// draw only the object with this id and its children
+    pub subtree: Option<u32>,
+    /// draw this scene instead of the one the render texture belongs to
+    pub scene: Option<Box<Scene>>,
+}
+
+impl RenderTexture {
+    pub fn new(name: impl Into<String>, camera: Camera, width: u32, height: u32) -> Self {
+        RenderTexture {
+            name: name.into(),
+            camera,
+            width,
+            height,
+            clear_color: wgpu::Color::BLACK,
+            subtree: None,
+            scene: None,
+        }
+    }
+}
@@ -1,33 +1,97 @@
 use crate::{
     renderer::wgpu_handles::WgpuHandles,
     renderer::{
-        light::{Light, LightManager},
+        fog::Fog,
+        light::{Light, LightManager, ViewInfoData},
         material::MaterialManager,
+        outline::Outline,
+        settings::RenderSettings,
         staging_belt_and_command_encoder::StagingBeltAndCommandEncoder,
+        stats::RenderStats,
     },
     util,
 };
 
-use super::{camera::Camera, object3d::Object3D};
+use super::{
+    camera::{Camera, OrthographicSize},
+    object3d::{Object3D, ALL_LAYERS},
+    render_texture::RenderTexture,
+    scene_view::SceneView,
+    sprite::Sprite,
+    terrain::update_terrain_lods,
+    text::Text,
+};
 
 pub struct Scene {
     pub lights: Vec<Light>,
+    /// draws the whole main window if there are no `views`
     pub camera: Camera,
+    pub views: Vec<SceneView>,
+    /// drawn before the views, which can sample them
+    pub render_textures: Vec<RenderTexture>,
+    pub clear_color: wgpu::Color,
     pub root: Object3D,
+    pub render_stats: RenderStats,
+    pub outline: Outline,
+    pub fog: Fog,
+    /// drawn over the views of the main window, under the texts
+    pub sprites: Vec<Sprite>,
+    /// what the sprites are seen through, one unit per logical pixel with the origin in
+    /// the middle of the window by default
+    pub sprite_camera: Camera,
+    /// drawn over the main window
+    pub texts: Vec<Text>,
+    /// keep the camera aspect ratio (and logical pixel size) in line with the render target
+    pub fit_camera_to_target: bool,
+    pub render_settings: RenderSettings,
+    /// seconds, the time the render loop was given for the frame being drawn
+    pub time: f64,
+    /// the `WgpuHandles::device_generation` its resources were uploaded to
+    pub(crate) device_generation: u64,
 }
 
 impl Scene {
     pub fn new() -> Scene {
         let camera = Camera::PerspectiveCamera {
             world_to_local: util::identity_matrix(),
+            fov: 90.0,
             near: 0.1,
             far: 3.0,
             aspect: 1.0,
+            layers: ALL_LAYERS,
         };
         Scene {
             lights: vec![],
             camera,
+            views: vec![],
+            render_textures: vec![],
+            clear_color: wgpu::Color {
+                r: 1.0,
+                g: 0.6,
+                b: 0.8,
+                a: 1.0,
+            },
             root: Object3D::create_empty(),
+            render_stats: RenderStats::default(),
+            outline: Outline::default(),
+            fog: Fog::default(),
+            sprites: vec![],
+            sprite_camera: Camera::OrthographicCamera {
+                world_to_local: util::identity_matrix(),
+                size: OrthographicSize::LogicalPixels {
+                    width: 1.0,
+                    height: 1.0,
+                },
+                near: -1.0,
+                far: 1.0,
+                aspect: 1.0,
+                layers: ALL_LAYERS,
+            },
+            texts: vec![],
+            fit_camera_to_target: true,
+            render_settings: RenderSettings::default(),
+            time: 0.0,
+            device_generation: 0,
         }
     }
     pub fn write_lights(
@@ -36,8 +100,8 @@ impl Scene {
         material_manager: &MaterialManager,
         staging_buffer: &mut StagingBeltAndCommandEncoder,
     ) {
-        for light in &mut self.lights {
-            material_manager.write_light(light, wgpu_handles, staging_buffer);
+        for (index, light) in self.lights.iter_mut().enumerate() {
+            material_manager.write_light(light, index == 0, wgpu_handles, staging_buffer);
         }
     }
     pub fn write_view_info(
@@ -46,11 +110,64 @@ impl Scene {
         material_manager: &MaterialManager,
         staging_buffer: &mut StagingBeltAndCommandEncoder,
     ) {
-        let view_info = &self.camera.get_view_info();
+        let view_info = &ViewInfoData {
+            fog: self.fog.to_data(),
+            ..self.camera.get_view_info()
+        };
         for light in &mut self.lights {
             material_manager.write_view_info(light, view_info, wgpu_handles, staging_buffer);
         }
     }
+
+    /// Pick the detail of the terrain chunks for all the cameras the frame is drawn with,
+    /// each chunk as detailed as the closest camera needs. Call this once per frame
+    /// before it is rendered, the chunks keep their detail in every view.
+    pub fn update_terrain_lods(&mut self) {
+        let mut view_matrices: Vec<glm::Mat4> = self
+            .views
+            .iter()
+            .map(|view| *view.camera.world_to_local())
+            .collect();
+        if self.views.is_empty() {
+            view_matrices.push(*self.camera.world_to_local());
+        }
+        for render_texture in &mut self.render_textures {
+            match &mut render_texture.scene {
+                Some(scene) => update_terrain_lods(
+                    &mut scene.root,
+                    &util::identity_matrix(),
+                    &[*render_texture.camera.world_to_local()],
+                ),
+                None => view_matrices.push(*render_texture.camera.world_to_local()),
+            }
+        }
+        update_terrain_lods(&mut self.root, &util::identity_matrix(), &view_matrices);
+    }
+
+    /// Drop the resources of the scene if they were uploaded to a device that was lost
+    /// since. Each scene the render loop returns is checked, not only the one drawn
+    /// right after the device was created anew.
+    pub fn drop_gpu_resources_of_lost_device(&mut self, device_generation: u64) {
+        if self.device_generation != device_generation {
+            self.drop_gpu_resources();
+            self.device_generation = device_generation;
+        }
+    }
+
+    /// Let go of everything uploaded for the scene and the scenes of its render textures,
+    /// so it is uploaded again with the next frame. This is needed after the device was
+    /// lost and created anew, the old resources cannot be used with the new one.
+    pub fn drop_gpu_resources(&mut self) {
+        self.root.drop_gpu_resources();
+        for light in &mut self.lights {
+            light.drop_gpu_resources();
+        }
+        for render_texture in &mut self.render_textures {
+            if let Some(scene) = &mut render_texture.scene {
+                scene.drop_gpu_resources();
+            }
+        }
+    }
 }
 
 impl Default for Scene {
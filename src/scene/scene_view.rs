@@ -0,0 +1,119 @@
+use super::camera::Camera;
+use crate::renderer::viewport::PixelRect;
+
+/// Position and size of a view relative to its window, from 0 to 1 with the origin at
+/// the top left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ViewRect {
+    pub fn full() -> Self {
+        ViewRect {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+
+    pub fn to_pixels(&self, target_size: (u32, u32)) -> PixelRect {
+        let to_pixel =
+            |value: f32, size: u32| (value * size as f32).round().clamp(0.0, size as f32) as u32;
+        let x = to_pixel(self.x, target_size.0);
+        let y = to_pixel(self.y, target_size.1);
+
+        PixelRect {
+            x,
+            y,
+            width: to_pixel(self.x + self.width, target_size.0).saturating_sub(x),
+            height: to_pixel(self.y + self.height, target_size.1).saturating_sub(y),
+        }
+    }
+}
+
+/// A camera drawing the scene into a part of a window, for split screen and editors
+/// with several views.
+pub struct SceneView {
+    pub camera: Camera,
+    /// index of the window, 0 is the main window and the rest are `WinitSettings::extra_windows`
+    pub window: usize,
+    pub rect: ViewRect,
+    /// `None` draws over what is already in the rectangle
+    pub clear_color: Option<wgpu::Color>,
+}
+
+/// Tells apart the cameras a frame is drawn with, for what is kept for each of them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ViewKey {
+    /// the scene camera, drawing the main window of a scene without views
+    SceneCamera,
+    /// the view at this index in `Scene::views`
+    View(usize),
+    /// the camera of the render texture with this name
+    RenderTexture(String),
+}
+
+impl SceneView {
+    pub fn new(camera: Camera, window: usize, rect: ViewRect) -> Self {
+        SceneView {
+            camera,
+            window,
+            rect,
+            clear_color: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_the_target_without_gaps() {
+        let left = ViewRect {
+            width: 0.5,
+            ..ViewRect::full()
+        };
+        let right = ViewRect {
+            x: 0.5,
+            width: 0.5,
+            ..ViewRect::full()
+        };
+        let (left, right) = (left.to_pixels((101, 50)), right.to_pixels((101, 50)));
+        assert_eq!(left.x + left.width, right.x);
+        assert_eq!(right.x + right.width, 101);
+        assert_eq!((left.height, right.height), (50, 50));
+    }
+
+    #[test]
+    fn negative_size_gives_an_empty_rect() {
+        let rect = ViewRect {
+            x: 0.5,
+            y: 0.5,
+            width: -0.25,
+            height: -1.0,
+        };
+        let pixels = rect.to_pixels((100, 100));
+        assert_eq!((pixels.width, pixels.height), (0, 0));
+    }
+
+    #[test]
+    fn rect_outside_the_target_is_clamped() {
+        let rect = ViewRect {
+            x: -1.0,
+            y: 0.75,
+            width: 3.0,
+            height: 1.0,
+        };
+        let pixels = rect.to_pixels((80, 40));
+        assert_eq!(
+            (pixels.x, pixels.y, pixels.width, pixels.height),
+            (0, 30, 80, 10)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use glm::GenSquareMat;
+
+use super::{
+    mesh::Mesh,
+    object3d::{Object3D, Object3DObject},
+};
+use crate::{
+    renderer::{
+        staging_belt_and_command_encoder::StagingBeltAndCommandEncoder, wgpu_handles::WgpuHandles,
+    },
+    util::{identity_matrix, MuckableMatrix},
+};
+
+/// Joints a skinned mesh can be bound to, the size of the joint matrix uniform.
+pub const MAX_JOINTS: usize = 64;
+
+/// Joint indices into `Skin::joints` and their weights, for one vertex of the mesh.
+/// Weights should add up to 1, unused joints get a weight of 0.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SkinVertex {
+    pub joints: [u32; 4],
+    pub weights: glm::Vec4,
+}
+
+unsafe impl bytemuck::Zeroable for SkinVertex {}
+unsafe impl bytemuck::Pod for SkinVertex {}
+
+/// Binds the vertices of a mesh to a skeleton. The joints are ordinary objects in the
+/// scene, anything that moves them deforms the mesh.
+#[derive(Debug)]
+pub struct Skin {
+    /// ids of the joint objects
+    pub joints: Vec<u32>,
+    /// from the space of the mesh to the space of each joint in the bind pose
+    pub inverse_bind_matrices: Vec<glm::Mat4>,
+    /// one for each vertex of the mesh, in the same order
+    pub vertex_array: Box<[SkinVertex]>,
+    pub vertex_buffer: Option<wgpu::Buffer>,
+    pub joint_buffer: Option<wgpu::Buffer>,
+}
+
+impl Skin {
+    pub fn new(
+        joints: Vec<u32>,
+        inverse_bind_matrices: Vec<glm::Mat4>,
+        vertex_array: Box<[SkinVertex]>,
+    ) -> Self {
+        assert!(
+            joints.len() <= MAX_JOINTS,
+            "a skin can have {MAX_JOINTS} joints at most"
+        );
+        assert_eq!(joints.len(), inverse_bind_matrices.len());
+        Skin {
+            joints,
+            inverse_bind_matrices,
+            vertex_array,
+            vertex_buffer: None,
+            joint_buffer: None,
+        }
+    }
+
+    /// Bind the joints in their current pose, so that the mesh is drawn as it is now. The
+    /// mesh and the joints have to be in the tree under `root` already.
+    pub fn bind_pose(
+        root: &Object3D,
+        mesh_id: u32,
+        joints: Vec<u32>,
+        vertex_array: Box<[SkinVertex]>,
+    ) -> Self {
+        let mut world_matrices = HashMap::new();
+        collect_world_matrices(root, &identity_matrix(), &mut world_matrices);
+        let mesh_matrix = world_matrices[&mesh_id];
+        let inverse_bind_matrices = joints
+            .iter()
+            .map(|joint| world_matrices[joint].inverse().unwrap().mul_m(&mesh_matrix))
+            .collect();
+        Skin::new(joints, inverse_bind_matrices, vertex_array)
+    }
+}
+
+// The world matrix of every object in the subtree, by id.
+fn collect_world_matrices(
+    object3d: &Object3D,
+    parent_matrix: &glm::Mat4,
+    world_matrices: &mut HashMap<u32, glm::Mat4>,
+) {
+    let matrix: glm::Mat4 = object3d.matrix.into();
+    let matrix = parent_matrix.mul_m(&matrix);
+    world_matrices.insert(object3d.id, matrix);
+    for child in &object3d.children {
+        collect_world_matrices(child, &matrix, world_matrices);
+    }
+}
+
+fn write_joint_matrices(
+    object3d: &Object3D,
+    world_matrices: &HashMap<u32, glm::Mat4>,
+    wgpu_handles: &WgpuHandles,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+) {
+    if let Object3DObject::Mesh(Mesh {
+        skin: Some(skin), ..
+    }) = &object3d.object
+    {
+        // the buffers are there once the mesh is written
+        if let Some(joint_buffer) = &skin.joint_buffer {
+            let mesh_inverse = world_matrices[&object3d.id].inverse().unwrap();
+            let mut joint_matrices = [MuckableMatrix(identity_matrix()); MAX_JOINTS];
+            for (index, joint) in skin.joints.iter().enumerate() {
+                // a joint that was removed from the scene stays in the bind pose
+                if let Some(joint_matrix) = world_matrices.get(joint) {
+                    joint_matrices[index] = MuckableMatrix(
+                        mesh_inverse
+                            .mul_m(joint_matrix)
+                            .mul_m(&skin.inverse_bind_matrices[index]),
+                    );
+                }
+            }
+            staging_belt.write_buffer(
+                joint_buffer,
+                0,
+                bytemuck::cast_slice(&joint_matrices),
+                &wgpu_handles.device,
+            );
+        }
+    }
+
+    for child in &object3d.children {
+        write_joint_matrices(child, world_matrices, wgpu_handles, staging_belt);
+    }
+}
+
+/// Write the joint matrices of every skinned mesh under `root` for the current pose of
+/// its joints.
+pub fn write_skins(
+    root: &Object3D,
+    wgpu_handles: &WgpuHandles,
+    staging_belt: &mut StagingBeltAndCommandEncoder,
+) {
+    if !root.has_skins() {
+        return;
+    }
+    let mut world_matrices = HashMap::new();
+    collect_world_matrices(root, &identity_matrix(), &mut world_matrices);
+    write_joint_matrices(root, &world_matrices, wgpu_handles, staging_belt);
+}
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use crate::importer::atlas::{AtlasRegion, TextureAtlas};
+
+/// A textured quad drawn with the sprite camera of the scene, over the views of the main
+/// window. Sprites sharing an atlas are drawn together.
+#[derive(Debug, Clone)]
+pub struct Sprite {
+    pub atlas: Arc<TextureAtlas>,
+    pub region: AtlasRegion,
+    /// where the pivot is, in the space of the sprite camera
+    pub position: glm::Vec2,
+    pub size: glm::Vec2,
+    /// counterclockwise around the pivot, in radians
+    pub rotation: f32,
+    /// the point the sprite is positioned and rotated by, from (0, 0) at the bottom left
+    /// to (1, 1) at the top right
+    pub pivot: glm::Vec2,
+    /// multiplies the texels
+    pub tint: glm::Vec4,
+    /// sprites with greater z are drawn over the ones with smaller z
+    pub z: i32,
+}
+
+impl Sprite {
+    /// A sprite of a region of the atlas, one unit per texel and pivoted at its center.
+    /// Returns `None` if the atlas has no region by that name.
+    pub fn new(atlas: Arc<TextureAtlas>, region: &str) -> Option<Self> {
+        let region = atlas.region(region)?;
+        Some(Sprite {
+            atlas,
+            region,
+            position: glm::vec2(0.0, 0.0),
+            size: glm::vec2(region.width as f32, region.height as f32),
+            rotation: 0.0,
+            pivot: glm::vec2(0.5, 0.5),
+            tint: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            z: 0,
+        })
+    }
+}
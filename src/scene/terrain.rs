@@ -0,0 +1,471 @@
+use super::{
+    mesh::Mesh,
+    object3d::{next_object_id, Object3D, Object3DObject, DEFAULT_LAYERS},
+};
+use crate::{
+    importer::heightmap::Heightmap,
+    renderer::material::Material,
+    util::{identity_matrix, MuckableMatrix},
+};
+
+/// How a terrain is split into chunks and how the chunks lose detail with distance.
+#[derive(Debug, Clone, Copy)]
+pub struct TerrainSettings {
+    /// quads along each side of a chunk at full detail, a power of two
+    pub chunk_size: u32,
+    /// world units between neighbouring heightmap samples
+    pub spacing: f32,
+    /// world height of a white heightmap sample
+    pub height_scale: f32,
+    /// levels of detail including the full one, each halves the quads along a side
+    pub lod_levels: u32,
+    /// chunks closer than this to the camera have full detail, each level after covers
+    /// twice the distance of the one before it
+    pub lod_distance: f32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        TerrainSettings {
+            chunk_size: 32,
+            spacing: 1.0,
+            height_scale: 10.0,
+            lod_levels: 4,
+            lod_distance: 50.0,
+        }
+    }
+}
+
+/// The level of detail of a chunk and the ones its edges are stitched to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLod {
+    pub lod: u32,
+    /// along -z, +x, +z and -x, the coarser of this chunk and the neighbour on that side
+    pub edges: [u32; 4],
+}
+
+/// A heightmap split into square chunks. The first children of the terrain are the
+/// chunks, row-major along x, and each one is an ordinary mesh with its own material.
+///
+/// Chunks further from the camera are drawn with fewer triangles, see
+/// `Scene::update_terrain_lods`. Where a chunk meets a coarser neighbour its edge follows
+/// the neighbour's, so there are no cracks between them.
+#[derive(Debug)]
+pub struct Terrain {
+    pub settings: TerrainSettings,
+    pub chunks_x: u32,
+    pub chunks_z: u32,
+    /// one for each chunk, in the order of the chunks
+    pub chunk_lods: Vec<ChunkLod>,
+    /// in the space of the terrain
+    chunk_centers: Vec<glm::Vec3>,
+}
+
+impl Terrain {
+    /// A terrain centered on the origin with y up. `material` is called once for each
+    /// chunk, the uv of the vertices goes from 0 to 1 across the whole terrain.
+    pub fn new_object_3d(
+        name: Option<String>,
+        heightmap: &Heightmap,
+        settings: TerrainSettings,
+        mut material: impl FnMut() -> Material,
+    ) -> Object3D {
+        assert!(
+            settings.chunk_size.is_power_of_two(),
+            "the chunk size of a terrain has to be a power of two"
+        );
+        let chunks_x = (heightmap.width - 1).div_ceil(settings.chunk_size);
+        let chunks_z = (heightmap.height - 1).div_ceil(settings.chunk_size);
+        let full_detail = ChunkLod {
+            lod: 0,
+            edges: [0; 4],
+        };
+
+        let mut chunk_centers = vec![];
+        let mut children = vec![];
+        for chunk_z in 0..chunks_z {
+            for chunk_x in 0..chunks_x {
+                let vertex_array = chunk_vertices(heightmap, &settings, chunk_x, chunk_z);
+                chunk_centers.push(chunk_center(&vertex_array));
+
+                children.push(Mesh::new_object_3d(
+                    Some(format!("TerrainChunk{chunk_x}x{chunk_z}")),
+                    vertex_array,
+                    chunk_indices(settings.chunk_size, full_detail),
+                    material(),
+                ));
+            }
+        }
+
+        let terrain = Terrain {
+            settings,
+            chunks_x,
+            chunks_z,
+            chunk_lods: vec![full_detail; (chunks_x * chunks_z) as usize],
+            chunk_centers,
+        };
+
+        Object3D {
+            id: next_object_id(),
+            object: Object3DObject::Terrain(terrain),
+            name,
+            matrix: MuckableMatrix(identity_matrix()),
+            children,
+            label: None,
+            visible: true,
+            layers: DEFAULT_LAYERS,
+            matrix_bind_group: None,
+            matrix_buffer: None,
+        }
+    }
+
+    /// The coarsest level a chunk can have, where it is a single quad at most.
+    pub fn max_lod(&self) -> u32 {
+        (self.settings.lod_levels.max(1) - 1).min(self.settings.chunk_size.trailing_zeros())
+    }
+
+    fn lod_for_distance(&self, distance: f32) -> u32 {
+        let mut lod = 0;
+        let mut limit = self.settings.lod_distance;
+        while lod < self.max_lod() && distance > limit {
+            lod += 1;
+            limit *= 2.0;
+        }
+        lod
+    }
+
+    /// The levels of detail for chunks at these distances from the camera, with the
+    /// edges stitched to their neighbours.
+    pub fn chunk_lods_for_distances(&self, distances: &[f32]) -> Vec<ChunkLod> {
+        let lods: Vec<u32> = distances
+            .iter()
+            .map(|distance| self.lod_for_distance(*distance))
+            .collect();
+        let (chunks_x, chunks_z) = (self.chunks_x as i64, self.chunks_z as i64);
+        let lod_at = |x: i64, z: i64| match (0..chunks_x).contains(&x) && (0..chunks_z).contains(&z)
+        {
+            true => Some(lods[(z * chunks_x + x) as usize]),
+            false => None,
+        };
+
+        (0..chunks_z)
+            .flat_map(|z| (0..chunks_x).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                let lod = lod_at(x, z).unwrap();
+                let neighbours = [(x, z - 1), (x + 1, z), (x, z + 1), (x - 1, z)];
+                ChunkLod {
+                    lod,
+                    edges: neighbours
+                        .map(|(x, z)| lod_at(x, z).map_or(lod, |other| other.max(lod))),
+                }
+            })
+            .collect()
+    }
+}
+
+fn chunk_vertices(
+    heightmap: &Heightmap,
+    settings: &TerrainSettings,
+    chunk_x: u32,
+    chunk_z: u32,
+) -> Box<[f32]> {
+    let size = settings.chunk_size;
+    let (last_x, last_z) = (heightmap.width as i64 - 1, heightmap.height as i64 - 1);
+    let mut vertices = Vec::with_capacity(((size + 1) * (size + 1) * 12) as usize);
+    for z in 0..=size {
+        for x in 0..=size {
+            // chunks on the far edges can reach past the map, they flatten out there
+            let sample_x = ((chunk_x * size + x) as i64).min(last_x);
+            let sample_z = ((chunk_z * size + z) as i64).min(last_z);
+            let height = heightmap.sample(sample_x, sample_z) * settings.height_scale;
+            // central differences, over twice the spacing
+            let slope_x = (heightmap.sample(sample_x + 1, sample_z)
+                - heightmap.sample(sample_x - 1, sample_z))
+                * settings.height_scale;
+            let slope_z = (heightmap.sample(sample_x, sample_z + 1)
+                - heightmap.sample(sample_x, sample_z - 1))
+                * settings.height_scale;
+            let normal = glm::normalize(glm::vec3(-slope_x, 2.0 * settings.spacing, -slope_z));
+
+            vertices.extend([
+                (sample_x as f32 - last_x as f32 * 0.5) * settings.spacing,
+                height,
+                (sample_z as f32 - last_z as f32 * 0.5) * settings.spacing,
+                1.0,
+                normal.x,
+                normal.y,
+                normal.z,
+                0.0,
+                sample_x as f32 / last_x as f32,
+                sample_z as f32 / last_z as f32,
+                0.0,
+                0.0,
+            ]);
+        }
+    }
+    vertices.into_boxed_slice()
+}
+
+// Halfway between the corners, at the height of the middle vertex.
+fn chunk_center(vertex_array: &[f32]) -> glm::Vec3 {
+    let (middle, last) = (vertex_array.len() / 24 * 12, vertex_array.len() - 12);
+    glm::vec3(
+        (vertex_array[0] + vertex_array[last]) * 0.5,
+        vertex_array[middle + 1],
+        (vertex_array[2] + vertex_array[last + 2]) * 0.5,
+    )
+}
+
+/// Triangles of a chunk with `chunk_size` quads along each side at full detail.
+///
+/// Edge vertices that a coarser neighbour leaves out are moved onto the last one it has
+/// before them. The triangles that collapse are left out, and the edge ends up with
+/// the same vertices as the neighbour's.
+pub fn chunk_indices(chunk_size: u32, chunk_lod: ChunkLod) -> Box<[u32]> {
+    let step: u32 = 1 << chunk_lod.lod;
+    let snap = |value: u32, lod: u32| value >> lod << lod;
+    let index = |x: u32, z: u32| {
+        let [top, right, bottom, left] = chunk_lod.edges;
+        let (x, z) = match (x, z) {
+            (x, 0) => (snap(x, top), 0),
+            (x, z) if x == chunk_size => (x, snap(z, right)),
+            (x, z) if z == chunk_size => (snap(x, bottom), z),
+            (0, z) => (0, snap(z, left)),
+            (x, z) => (x, z),
+        };
+        z * (chunk_size + 1) + x
+    };
+
+    let mut indices = vec![];
+    for z in (0..chunk_size).step_by(step as usize) {
+        for x in (0..chunk_size).step_by(step as usize) {
+            let corners = [
+                index(x, z),
+                index(x, z + step),
+                index(x + step, z),
+                index(x + step, z + step),
+            ];
+            // both edges of the last cell move away from its far corner, it is split
+            // through that corner so its triangles cannot flip
+            let triangles = match x + step == chunk_size && z + step == chunk_size {
+                true => [
+                    [corners[0], corners[1], corners[3]],
+                    [corners[0], corners[3], corners[2]],
+                ],
+                false => [
+                    [corners[0], corners[1], corners[2]],
+                    [corners[2], corners[1], corners[3]],
+                ],
+            };
+            for triangle in triangles {
+                if triangle[0] != triangle[1]
+                    && triangle[1] != triangle[2]
+                    && triangle[2] != triangle[0]
+                {
+                    indices.extend(triangle);
+                }
+            }
+        }
+    }
+    indices.into_boxed_slice()
+}
+
+/// Pick the level of detail of the chunks of every terrain in this subtree from their
+/// distance to the closest of the cameras with these view matrices. Chunks whose
+/// triangles change drop their index buffer, it is created again when the scene is
+/// written.
+pub fn update_terrain_lods(
+    object3d: &mut Object3D,
+    parent_matrix: &glm::Mat4,
+    view_matrices: &[glm::Mat4],
+) {
+    let matrix: glm::Mat4 = object3d.matrix.into();
+    let matrix = parent_matrix.mul_m(&matrix);
+
+    if let Object3DObject::Terrain(terrain) = &mut object3d.object {
+        let distances: Vec<f32> = terrain
+            .chunk_centers
+            .iter()
+            .map(|center| {
+                let world_position = matrix.mul_v(&center.extend(1.0));
+                view_matrices
+                    .iter()
+                    .map(|view_matrix| {
+                        let view_position = view_matrix.mul_v(&world_position);
+                        glm::length(glm::vec3(view_position.x, view_position.y, view_position.z))
+                    })
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .collect();
+        let chunk_lods = terrain.chunk_lods_for_distances(&distances);
+
+        for ((chunk_lod, new_lod), chunk) in terrain
+            .chunk_lods
+            .iter_mut()
+            .zip(chunk_lods)
+            .zip(&mut object3d.children)
+        {
+            if *chunk_lod == new_lod {
+                continue;
+            }
+            *chunk_lod = new_lod;
+            if let Object3DObject::Mesh(mesh) = &mut chunk.object {
+                mesh.index_array = chunk_indices(terrain.settings.chunk_size, new_lod);
+                mesh.index_buffer = None;
+            }
+        }
+    }
+
+    for child in &mut object3d.children {
+        update_terrain_lods(child, &matrix, view_matrices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::material::PhongMaterial;
+
+    fn terrain(chunks_x: u32, chunks_z: u32) -> Terrain {
+        Terrain {
+            settings: TerrainSettings {
+                chunk_size: 16,
+                lod_distance: 10.0,
+                ..Default::default()
+            },
+            chunks_x,
+            chunks_z,
+            chunk_lods: vec![],
+            chunk_centers: vec![],
+        }
+    }
+
+    #[test]
+    fn chunk_edges_take_the_coarser_level() {
+        // 3x2 chunks at levels 0 1 2 / 0 0 3, the last one clamped to the coarsest level
+        let terrain = terrain(3, 2);
+        let chunk_lods = terrain.chunk_lods_for_distances(&[5.0, 15.0, 35.0, 5.0, 5.0, 1e6]);
+
+        let lods: Vec<u32> = chunk_lods.iter().map(|chunk_lod| chunk_lod.lod).collect();
+        assert_eq!(lods, [0, 1, 2, 0, 0, 3]);
+        // edges along -z, +x, +z and -x, the map border keeps the chunk's own level
+        assert_eq!(chunk_lods[0].edges, [0, 1, 0, 0]);
+        assert_eq!(chunk_lods[1].edges, [1, 2, 1, 1]);
+        assert_eq!(chunk_lods[2].edges, [2, 2, 3, 2]);
+        assert_eq!(chunk_lods[4].edges, [1, 3, 0, 0]);
+        assert_eq!(chunk_lods[5].edges, [3, 3, 3, 3]);
+    }
+
+    fn check_chunk(chunk_size: u32, chunk_lod: ChunkLod) {
+        let indices = chunk_indices(chunk_size, chunk_lod);
+        let position = |index: u32| (index % (chunk_size + 1), index / (chunk_size + 1));
+
+        let mut area = 0;
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| {
+                let (x, z) = position(triangle[corner]);
+                (x as i64, z as i64)
+            });
+            // y up, seen from above the triangles wind counterclockwise
+            let cross = (b.1 - a.1) * (c.0 - a.0) - (b.0 - a.0) * (c.1 - a.1);
+            assert!(cross > 0, "{chunk_lod:?} flips {triangle:?}");
+            area += cross;
+        }
+        assert_eq!(area, 2 * (chunk_size * chunk_size) as i64, "{chunk_lod:?}");
+
+        let [top, right, bottom, left] = chunk_lod.edges.map(|lod| 1 << lod);
+        for &index in indices.iter() {
+            let (x, z) = position(index);
+            let on_neighbour = (z != 0 || x % top == 0)
+                && (x != chunk_size || z % right == 0)
+                && (z != chunk_size || x % bottom == 0)
+                && (x != 0 || z % left == 0);
+            assert!(on_neighbour, "{chunk_lod:?} uses edge vertex {x},{z}");
+        }
+    }
+
+    #[test]
+    fn stitched_edges_only_use_vertices_of_the_neighbour() {
+        let chunk_size = 16;
+        for lod in 0..=4 {
+            let levels = lod..=4;
+            for top in levels.clone() {
+                for right in levels.clone() {
+                    for bottom in levels.clone() {
+                        for left in levels.clone() {
+                            let edges = [top, right, bottom, left];
+                            check_chunk(chunk_size, ChunkLod { lod, edges });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normals_follow_a_known_slope() {
+        // rises by 0.25 per sample along x, with a height scale of 2 and a spacing of 0.5
+        // the terrain rises by 1 per world unit
+        let heightmap = Heightmap::new(
+            5,
+            5,
+            (0..25).map(|index| (index % 5) as f32 * 0.25).collect(),
+        );
+        let settings = TerrainSettings {
+            chunk_size: 4,
+            spacing: 0.5,
+            height_scale: 2.0,
+            ..Default::default()
+        };
+        let vertex_array = chunk_vertices(&heightmap, &settings, 0, 0);
+
+        let expected = glm::normalize(glm::vec3(-1.0, 1.0, 0.0));
+        for (index, vertex) in vertex_array.chunks_exact(12).enumerate() {
+            let x = index % 5;
+            let normal = glm::vec3(vertex[4], vertex[5], vertex[6]);
+            // the edges of the map have no sample outside it to take the slope from
+            if x == 0 || x == 4 {
+                continue;
+            }
+            assert!(
+                glm::distance(normal, expected) < 1e-6,
+                "{index}: {normal:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn chunks_take_the_detail_of_the_closest_camera() {
+        let heightmap = Heightmap::new(33, 17, vec![0.0; 33 * 17]);
+        let settings = TerrainSettings {
+            chunk_size: 16,
+            lod_distance: 10.0,
+            ..Default::default()
+        };
+        let material = || {
+            let black = glm::vec3(0.0, 0.0, 0.0);
+            Material::PhongMaterial(PhongMaterial::new_without_texture(black, black, black, 1.0))
+        };
+        let mut object3d = Terrain::new_object_3d(None, &heightmap, settings, material);
+        // the chunks are centered 8 units either side of the origin along x
+        let camera_at = |x: f32| glm::ext::translate(&identity_matrix(), glm::vec3(-x, -20.0, 0.0));
+
+        update_terrain_lods(&mut object3d, &identity_matrix(), &[camera_at(-8.0)]);
+        let Object3DObject::Terrain(terrain) = &object3d.object else {
+            unreachable!()
+        };
+        assert_eq!(terrain.chunk_lods[0].lod, 1);
+        assert_eq!(terrain.chunk_lods[1].lod, 2);
+
+        update_terrain_lods(
+            &mut object3d,
+            &identity_matrix(),
+            &[camera_at(-8.0), camera_at(8.0)],
+        );
+        let Object3DObject::Terrain(terrain) = &object3d.object else {
+            unreachable!()
+        };
+        assert_eq!(terrain.chunk_lods[0].lod, 1);
+        assert_eq!(terrain.chunk_lods[1].lod, 1);
+    }
+}
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use crate::importer::font::{AtlasGlyph, Font, SDF_FONT_SIZE};
+
+/// Text drawn over the main window, after all views.
+#[derive(Debug)]
+pub struct Text {
+    pub text: String,
+    pub font: Arc<Font>,
+    /// font size in logical pixels
+    pub size: f32,
+    pub color: glm::Vec4,
+    /// top left corner in logical pixels from the top left of the window
+    pub position: (f32, f32),
+    /// wrap lines longer than this, in logical pixels
+    pub max_width: Option<f32>,
+}
+
+impl Text {
+    pub fn new(text: impl Into<String>, font: Arc<Font>, size: f32) -> Self {
+        Text {
+            text: text.into(),
+            font,
+            size,
+            color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            position: (0.0, 0.0),
+            max_width: None,
+        }
+    }
+}
+
+/// Text that faces the camera above a point of an object. It keeps its size on screen
+/// and is hidden behind things in front of it.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub text: String,
+    pub font: Arc<Font>,
+    /// font size in logical pixels
+    pub size: f32,
+    pub color: glm::Vec4,
+    /// the point in the space of the object that the bottom center of the text is at
+    pub offset: glm::Vec3,
+    pub max_width: Option<f32>,
+}
+
+impl Label {
+    pub fn new(text: impl Into<String>, font: Arc<Font>, size: f32) -> Self {
+        Label {
+            text: text.into(),
+            font,
+            size,
+            color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            offset: glm::vec3(0.0, 0.0, 0.0),
+            max_width: None,
+        }
+    }
+}
+
+pub struct PositionedGlyph {
+    pub glyph: AtlasGlyph,
+    /// top left corner of the glyph's atlas rectangle from the top left of the text
+    pub x: f32,
+    pub y: f32,
+}
+
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    /// size of the laid out text in pixels
+    pub width: f32,
+    pub height: f32,
+    /// pixels per atlas pixel
+    pub scale: f32,
+}
+
+/// Lay out text at a font size in pixels with kerning. Lines break at newlines, and at
+/// spaces when they get longer than `max_width`. Words longer than that stick out.
+pub fn layout_text(font: &Font, text: &str, size: f32, max_width: Option<f32>) -> TextLayout {
+    let scale = size / SDF_FONT_SIZE;
+    let line_height = font.line_height(size);
+    let ascent = font.ascent(size);
+
+    let lines: Vec<String> = text
+        .split('\n')
+        .flat_map(|paragraph| match max_width {
+            Some(max_width) => wrap_line(font, paragraph, size, max_width),
+            None => vec![paragraph.to_string()],
+        })
+        .collect();
+
+    let mut glyphs = vec![];
+    let mut width: f32 = 0.0;
+    for (index, line) in lines.iter().enumerate() {
+        let baseline = ascent + index as f32 * line_height;
+        let line_width = place_line(font, line, size, |glyph, pen| {
+            glyphs.push(PositionedGlyph {
+                glyph: *glyph,
+                x: pen + glyph.offset.0 * scale,
+                y: baseline + glyph.offset.1 * scale,
+            });
+        });
+        width = width.max(line_width);
+    }
+
+    TextLayout {
+        glyphs,
+        width,
+        height: lines.len() as f32 * line_height,
+        scale,
+    }
+}
+
+/// Walk the glyphs of a line with their pen positions, returning the width of the line.
+fn place_line(font: &Font, line: &str, size: f32, mut f: impl FnMut(&AtlasGlyph, f32)) -> f32 {
+    let scale = size / SDF_FONT_SIZE;
+    let mut pen = 0.0;
+    let mut previous = None;
+
+    for character in line.chars() {
+        let Some(glyph) = font.glyphs.get(&character) else {
+            continue;
+        };
+        if let Some(previous) = previous {
+            pen += font.kern(previous, glyph.id, size);
+        }
+        f(glyph, pen);
+        pen += glyph.advance * scale;
+        previous = Some(glyph.id);
+    }
+
+    pen
+}
+
+fn wrap_line(font: &Font, paragraph: &str, size: f32, max_width: f32) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for word in paragraph.split(' ') {
+        let candidate = match line.is_empty() {
+            true => word.to_string(),
+            false => format!("{} {}", line, word),
+        };
+        if !line.is_empty() && place_line(font, &candidate, size, |_, _| {}) > max_width {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        } else {
+            line = candidate;
+        }
+    }
+    lines.push(line);
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importer::font::{default_characters, load_font_with_characters};
+
+    /// A font of rectangles with 1000 units per em, an ascent of 800 and a descent of
+    /// 200 and no kerning. 'a' advances by 500 units, 'b' by 1000 and space by 250, so
+    /// at size 10 they are 5, 10 and 2.5 pixels wide and lines are 10 pixels apart.
+    fn blocks_font() -> Font {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/blocks.ttf");
+        load_font_with_characters(path, default_characters()).unwrap()
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{a} != {b}");
+    }
+
+    #[test]
+    fn lines_break_at_the_last_space_that_fits() {
+        let font = blocks_font();
+        assert_eq!(wrap_line(&font, "aa bb a", 10.0, 30.0), ["aa", "bb a"]);
+        assert_eq!(wrap_line(&font, "aa bb a", 10.0, 50.0), ["aa bb a"]);
+    }
+
+    #[test]
+    fn words_longer_than_the_line_stick_out() {
+        let font = blocks_font();
+        assert_eq!(wrap_line(&font, "bbbb a", 10.0, 30.0), ["bbbb", "a"]);
+    }
+
+    #[test]
+    fn glyphs_advance_along_the_line_and_lines_move_down() {
+        let font = blocks_font();
+        let layout = layout_text(&font, "ab\nb", 10.0, None);
+        assert_eq!(layout.glyphs.len(), 3);
+        assert_close(layout.width, 15.0);
+        assert_close(layout.height, 20.0);
+        assert_close(layout.scale, 10.0 / SDF_FONT_SIZE);
+
+        let (first_b, second_b) = (&layout.glyphs[1], &layout.glyphs[2]);
+        assert_close(first_b.x - second_b.x, 5.0);
+        assert_close(second_b.y - first_b.y, 10.0);
+    }
+
+    #[test]
+    fn wrapped_text_is_as_wide_as_its_longest_line() {
+        let font = blocks_font();
+        let layout = layout_text(&font, "aa bb a", 10.0, Some(30.0));
+        assert_close(layout.width, 27.5);
+        assert_close(layout.height, 20.0);
+    }
+}
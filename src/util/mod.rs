@@ -1,8 +1,8 @@
 mod matrix_util;
 pub mod orbit_controls;
 pub mod frame_delta;
-pub mod simple_axes;
 pub mod boxed_slice;
+pub mod simplify;
 
 pub use matrix_util::identity_matrix;
 pub use matrix_util::MuckableMatrix;
\ No newline at end of file
@@ -12,9 +12,7 @@ pub fn orbit_controls(camera: &mut Camera, _delta: f64) {
     let delta_azimuth = (mouse_service.delta_x / 200.0) as f32;
     let delta_pitch = (mouse_service.delta_y / 200.0) as f32;
 
-    let world_to_local = match &camera {
-        Camera::PerspectiveCamera { world_to_local, near: _, far: _, aspect: _ } => world_to_local,
-    };
+    let world_to_local = camera.world_to_local();
 
     let camera_pos = (world_to_local.inverse().unwrap().mul_v(&glm::vec4(0.0, 0.0, 0.0, 1.0))).truncate(3);
     let center_pos = glm::vec4(0.0, 0.0, 0.0, 0.0).truncate(3);
@@ -28,9 +26,5 @@ pub fn orbit_controls(camera: &mut Camera, _delta: f64) {
 
     let tranformation = translate_forward;
 
-    match camera {
-        Camera::PerspectiveCamera { world_to_local, near: _, far: _, aspect: _ } => {
-            *world_to_local = tranformation;
-        }
-    }
+    *camera.world_to_local_mut() = tranformation;
 }
\ No newline at end of file
@@ -0,0 +1,580 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+/// Floats per vertex in the vertex array of a `Mesh`.
+const VERTEX_SIZE: usize = 12;
+/// Boundary edges are held in place this much more strongly than the surface.
+const BOUNDARY_WEIGHT: f64 = 100.0;
+
+/// The error of moving a point away from a set of planes, the symmetric 4x4 matrix
+/// stored as its upper triangle.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(normal: [f64; 3], distance: f64, weight: f64) -> Self {
+        let [a, b, c] = normal;
+        let d = distance;
+        Quadric(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|value| value * weight),
+        )
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (value, other) in self.0.iter_mut().zip(other.0) {
+            *value += other;
+        }
+    }
+
+    fn error(&self, [x, y, z]: [f64; 3]) -> f64 {
+        let q = &self.0;
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+/// An edge collapse waiting in the queue. It is stale once either end has changed since.
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    position: [f64; 3],
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // the cheapest collapse comes first out of the max-heap
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalized(a: [f64; 3]) -> Option<[f64; 3]> {
+    let length = dot(a, a).sqrt();
+    (length > 0.0).then(|| a.map(|value| value / length))
+}
+
+/// The result of `simplify`.
+pub struct SimplifiedMesh {
+    pub vertex_array: Box<[f32]>,
+    pub index_array: Box<[u32]>,
+    /// for each vertex, the vertex of the original it was made from, to carry other per
+    /// vertex data over
+    pub source_vertices: Box<[u32]>,
+}
+
+/// Mesh simplification by quadric error metrics, collapsing the edges that change the
+/// surface least until `target_ratio` of the triangles are left.
+///
+/// Vertices at the same position are welded while simplifying, so seams in the normals
+/// and uvs do not tear open. Every vertex keeps its own normal and uv and only moves to
+/// where its edge collapsed. Edges on the boundary of the mesh are kept in place as much
+/// as possible, and collapses that would flip a triangle are skipped.
+///
+/// The arrays are in the layout of `Mesh`, unused vertices are left out of the result.
+pub fn simplify(vertex_array: &[f32], index_array: &[u32], target_ratio: f32) -> SimplifiedMesh {
+    let vertex_count = vertex_array.len() / VERTEX_SIZE;
+    let target_triangles = (index_array.len() / 3) as f32 * target_ratio.clamp(0.0, 1.0);
+
+    // weld the vertices into nodes by position
+    let mut node_ids = HashMap::new();
+    let mut positions: Vec<[f64; 3]> = vec![];
+    let vertex_nodes: Vec<usize> = (0..vertex_count)
+        .map(|vertex| {
+            let position = &vertex_array[vertex * VERTEX_SIZE..vertex * VERTEX_SIZE + 3];
+            let key = [position[0], position[1], position[2]].map(f32::to_bits);
+            *node_ids.entry(key).or_insert_with(|| {
+                positions.push([position[0], position[1], position[2]].map(f64::from));
+                positions.len() - 1
+            })
+        })
+        .collect();
+    let node_count = positions.len();
+
+    // the welded nodes of each triangle, with the vertices it was made of
+    let (mut triangles, mut triangle_vertices): (Vec<[usize; 3]>, Vec<[u32; 3]>) = index_array
+        .chunks_exact(3)
+        .map(|vertices| {
+            let vertices = [vertices[0], vertices[1], vertices[2]];
+            (
+                vertices.map(|vertex| vertex_nodes[vertex as usize]),
+                vertices,
+            )
+        })
+        .filter(|([a, b, c], _)| a != b && b != c && c != a)
+        .unzip();
+    let mut alive = vec![true; triangles.len()];
+    let mut live_triangles = triangles.len();
+
+    let mut node_triangles = vec![vec![]; node_count];
+    for (triangle_index, triangle) in triangles.iter().enumerate() {
+        for node in triangle {
+            node_triangles[*node].push(triangle_index);
+        }
+    }
+
+    let mut quadrics = vec![Quadric::default(); node_count];
+    let mut edge_faces: HashMap<(usize, usize), (u32, usize)> = HashMap::new();
+    for (triangle_index, triangle) in triangles.iter().enumerate() {
+        let [a, b, c] = triangle.map(|node| positions[node]);
+        let Some(normal) = normalized(cross(sub(b, a), sub(c, a))) else {
+            continue;
+        };
+        let quadric = Quadric::from_plane(normal, -dot(normal, a), 1.0);
+        for node in triangle {
+            quadrics[*node].add(&quadric);
+        }
+        for edge in [(0, 1), (1, 2), (2, 0)] {
+            let (from, to) = (triangle[edge.0], triangle[edge.1]);
+            let entry = edge_faces
+                .entry((from.min(to), from.max(to)))
+                .or_insert((0, triangle_index));
+            entry.0 += 1;
+        }
+    }
+    // a plane through each boundary edge, perpendicular to its triangle
+    for ((from, to), (face_count, triangle_index)) in &edge_faces {
+        if *face_count != 1 {
+            continue;
+        }
+        let [a, b, c] = triangles[*triangle_index].map(|node| positions[node]);
+        let face_normal = cross(sub(b, a), sub(c, a));
+        let (from_position, to_position) = (positions[*from], positions[*to]);
+        let Some(normal) = normalized(cross(sub(to_position, from_position), face_normal)) else {
+            continue;
+        };
+        let quadric = Quadric::from_plane(normal, -dot(normal, from_position), BOUNDARY_WEIGHT);
+        quadrics[*from].add(&quadric);
+        quadrics[*to].add(&quadric);
+    }
+
+    let mut versions = vec![0u32; node_count];
+    let mut removed = vec![false; node_count];
+    let plan_collapse =
+        |from: usize, to: usize, positions: &[[f64; 3]], quadrics: &[Quadric], versions: &[u32]| {
+            let mut quadric = quadrics[from];
+            quadric.add(&quadrics[to]);
+            let (a, b) = (positions[from], positions[to]);
+            let middle = [
+                (a[0] + b[0]) / 2.0,
+                (a[1] + b[1]) / 2.0,
+                (a[2] + b[2]) / 2.0,
+            ];
+            let (cost, position) = [b, a, middle]
+                .into_iter()
+                .map(|position| (quadric.error(position), position))
+                .min_by(|(a, _), (b, _)| a.total_cmp(b))
+                .unwrap();
+            Collapse {
+                cost,
+                from,
+                to,
+                position,
+                versions: (versions[from], versions[to]),
+            }
+        };
+
+    let mut queue: BinaryHeap<Collapse> = edge_faces
+        .keys()
+        .map(|(from, to)| plan_collapse(*from, *to, &positions, &quadrics, &versions))
+        .collect();
+
+    while (live_triangles as f32) > target_triangles {
+        let Some(collapse) = queue.pop() else {
+            break;
+        };
+        let Collapse { from, to, .. } = collapse;
+        if removed[from] || removed[to] || collapse.versions != (versions[from], versions[to]) {
+            continue;
+        }
+
+        // a triangle that stays must not turn over
+        let flips = [from, to].iter().any(|node| {
+            node_triangles[*node].iter().any(|triangle_index| {
+                let triangle = triangles[*triangle_index];
+                if !alive[*triangle_index] || (triangle.contains(&from) && triangle.contains(&to)) {
+                    return false;
+                }
+                let [a, b, c] = triangle.map(|node| positions[node]);
+                let moved = triangle.map(|node| match node == from || node == to {
+                    true => collapse.position,
+                    false => positions[node],
+                });
+                let before = cross(sub(b, a), sub(c, a));
+                let after = cross(sub(moved[1], moved[0]), sub(moved[2], moved[0]));
+                dot(before, after) <= 0.0
+            })
+        });
+        if flips {
+            continue;
+        }
+
+        positions[to] = collapse.position;
+        let from_quadric = quadrics[from];
+        quadrics[to].add(&from_quadric);
+        versions[to] += 1;
+        removed[from] = true;
+
+        // the vertices of `from` take the attributes of the vertex across the edge, vertices
+        // on the other side of a seam do that through their own triangles
+        let from_triangles = std::mem::take(&mut node_triangles[from]);
+        let mut vertex_remap = HashMap::new();
+        for triangle_index in &from_triangles {
+            let triangle = triangles[*triangle_index];
+            if alive[*triangle_index] && triangle.contains(&to) {
+                let corner = |node| triangle.iter().position(|other| *other == node).unwrap();
+                let vertices = triangle_vertices[*triangle_index];
+                vertex_remap.insert(vertices[corner(from)], vertices[corner(to)]);
+            }
+        }
+        for triangle_index in from_triangles {
+            if !alive[triangle_index] {
+                continue;
+            }
+            let triangle = &mut triangles[triangle_index];
+            if triangle.contains(&to) {
+                alive[triangle_index] = false;
+                live_triangles -= 1;
+                continue;
+            }
+            for (node, vertex) in triangle
+                .iter_mut()
+                .zip(&mut triangle_vertices[triangle_index])
+            {
+                if *node == from {
+                    *node = to;
+                    *vertex = vertex_remap.get(vertex).copied().unwrap_or(*vertex);
+                }
+            }
+            node_triangles[to].push(triangle_index);
+        }
+        node_triangles[to].retain(|triangle_index| alive[*triangle_index]);
+
+        let mut neighbours: Vec<usize> = node_triangles[to]
+            .iter()
+            .flat_map(|triangle_index| triangles[*triangle_index])
+            .filter(|node| *node != to)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        for neighbour in neighbours {
+            queue.push(plan_collapse(
+                to, neighbour, &positions, &quadrics, &versions,
+            ));
+            queue.push(plan_collapse(
+                neighbour, to, &positions, &quadrics, &versions,
+            ));
+        }
+    }
+
+    let mut new_indices: Vec<Option<u32>> = vec![None; vertex_count];
+    let mut simplified_vertices = vec![];
+    let mut simplified_indices = vec![];
+    let mut source_vertices = vec![];
+    // every vertex that is left moves to where its node ended up
+    for (triangle, vertices) in triangles
+        .iter()
+        .zip(triangle_vertices)
+        .zip(&alive)
+        .filter_map(|(pair, alive)| alive.then_some(pair))
+    {
+        for (node, vertex) in triangle.iter().zip(vertices) {
+            let vertex = vertex as usize;
+            let index = *new_indices[vertex].get_or_insert_with(|| {
+                let start = vertex * VERTEX_SIZE;
+                let mut attributes = vertex_array[start..start + VERTEX_SIZE].to_vec();
+                for (value, position) in attributes.iter_mut().zip(positions[*node]) {
+                    *value = position as f32;
+                }
+                simplified_vertices.extend(attributes);
+                source_vertices.push(vertex as u32);
+                (simplified_vertices.len() / VERTEX_SIZE - 1) as u32
+            });
+            simplified_indices.push(index);
+        }
+    }
+
+    SimplifiedMesh {
+        vertex_array: simplified_vertices.into_boxed_slice(),
+        index_array: simplified_indices.into_boxed_slice(),
+        source_vertices: source_vertices.into_boxed_slice(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3], uv: [f32; 2]) -> [f32; VERTEX_SIZE] {
+        let [x, y, z] = position;
+        [x, y, z, 1.0, 0.0, 1.0, 0.0, 0.0, uv[0], uv[1], 0.0, 0.0]
+    }
+
+    /// A square of `size` quads along each side in the xz plane, raised by `height`.
+    /// With `seam` the two halves along x have vertices of their own where they meet.
+    fn grid(size: u32, seam: bool, height: impl Fn(u32, u32) -> f32) -> (Vec<f32>, Vec<u32>) {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        let halves: &[(u32, u32)] = match seam {
+            true => &[(0, size / 2), (size / 2, size)],
+            false => &[(0, size)],
+        };
+        for &(from_x, to_x) in halves {
+            let first = (vertices.len() / VERTEX_SIZE) as u32;
+            let columns = to_x - from_x + 1;
+            for z in 0..=size {
+                for x in from_x..=to_x {
+                    let position = [x as f32, height(x, z), z as f32];
+                    // the halves have uvs of their own, like the seam of an unwrapped mesh
+                    let uv = [(x - from_x) as f32, z as f32];
+                    vertices.extend(vertex(position, uv));
+                }
+            }
+            for z in 0..size {
+                for x in 0..to_x - from_x {
+                    let index = |x: u32, z: u32| first + z * columns + x;
+                    indices.extend([index(x, z), index(x, z + 1), index(x + 1, z)]);
+                    indices.extend([index(x + 1, z), index(x, z + 1), index(x + 1, z + 1)]);
+                }
+            }
+        }
+        (vertices, indices)
+    }
+
+    /// A cube from -size to size with every face a grid of quads facing out.
+    fn cube(size: i32) -> (Vec<f32>, Vec<u32>) {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        // the axis each face is on and the two along it, in an order that faces out
+        let faces = [(0, 1, 2), (1, 2, 0), (2, 0, 1)];
+        for (normal_axis, u_axis, v_axis) in faces {
+            for side in [-size, size] {
+                let first = (vertices.len() / VERTEX_SIZE) as u32;
+                for v in -size..=size {
+                    for u in -size..=size {
+                        let mut position = [0.0; 3];
+                        position[normal_axis] = side as f32;
+                        position[u_axis] = u as f32;
+                        position[v_axis] = v as f32;
+                        vertices.extend(vertex(position, [u as f32, v as f32]));
+                    }
+                }
+                let columns = (2 * size + 1) as u32;
+                let index = |u: u32, v: u32| first + v * columns + u;
+                for v in 0..columns - 1 {
+                    for u in 0..columns - 1 {
+                        let quad = [
+                            index(u, v),
+                            index(u + 1, v),
+                            index(u + 1, v + 1),
+                            index(u, v + 1),
+                        ];
+                        let (a, b, c, d) = match side > 0 {
+                            true => (quad[0], quad[1], quad[2], quad[3]),
+                            false => (quad[0], quad[3], quad[2], quad[1]),
+                        };
+                        indices.extend([a, b, c, a, c, d]);
+                    }
+                }
+            }
+        }
+        (vertices, indices)
+    }
+
+    fn position(vertex_array: &[f32], index: u32) -> [f32; 3] {
+        let start = index as usize * VERTEX_SIZE;
+        [
+            vertex_array[start],
+            vertex_array[start + 1],
+            vertex_array[start + 2],
+        ]
+    }
+
+    /// How often each edge is used by the triangles in each direction, with the ends
+    /// welded by position.
+    fn directed_edges(vertex_array: &[f32], index_array: &[u32]) -> HashMap<[[u32; 3]; 2], u32> {
+        let mut edges = HashMap::new();
+        for triangle in index_array.chunks_exact(3) {
+            let corners =
+                [0, 1, 2].map(|corner| position(vertex_array, triangle[corner]).map(f32::to_bits));
+            for (from, to) in [(0, 1), (1, 2), (2, 0)] {
+                *edges.entry([corners[from], corners[to]]).or_insert(0) += 1;
+            }
+        }
+        edges
+    }
+
+    fn triangle_normal(vertex_array: &[f32], triangle: &[u32]) -> [f64; 3] {
+        let [a, b, c] =
+            [0, 1, 2].map(|corner| position(vertex_array, triangle[corner]).map(f64::from));
+        cross(sub(b, a), sub(c, a))
+    }
+
+    #[test]
+    fn reaches_the_target_triangle_count() {
+        let (vertex_array, index_array) = grid(16, false, |_, _| 0.0);
+        let SimplifiedMesh {
+            vertex_array: vertices,
+            index_array: indices,
+            source_vertices: sources,
+        } = simplify(&vertex_array, &index_array, 0.25);
+
+        let triangles = indices.len() / 3;
+        let target = index_array.len() / 3 / 4;
+        // a collapse takes one or two triangles with it
+        assert!(
+            triangles <= target && triangles + 2 >= target,
+            "{triangles} for {target}"
+        );
+        assert_eq!(sources.len(), vertices.len() / VERTEX_SIZE);
+        assert!(indices
+            .iter()
+            .all(|index| (*index as usize) < sources.len()));
+    }
+
+    #[test]
+    fn open_mesh_keeps_its_boundary() {
+        let (vertex_array, index_array) = grid(16, false, |_, _| 0.0);
+        let SimplifiedMesh {
+            vertex_array: vertices,
+            index_array: indices,
+            ..
+        } = simplify(&vertex_array, &index_array, 0.1);
+
+        // the boundary stays on the outline of the square and the square stays whole
+        let on_outline = |value: f32| value == 0.0 || value == 16.0;
+        let mut area = 0.0;
+        for triangle in indices.chunks_exact(3) {
+            area += triangle_normal(&vertices, triangle)[1].abs() / 2.0;
+        }
+        assert!((area - 256.0).abs() < 1e-6, "area {area}");
+        let edges = directed_edges(&vertices, &indices);
+        for [from, to] in edges.keys() {
+            if !edges.contains_key(&[*to, *from]) {
+                let [from, to] = [from, to].map(|corner| corner.map(f32::from_bits));
+                let along_x = from[2] == to[2] && on_outline(from[2]);
+                let along_z = from[0] == to[0] && on_outline(from[0]);
+                assert!(along_x || along_z, "boundary edge {from:?} {to:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn closed_mesh_stays_closed() {
+        let (vertex_array, index_array) = cube(4);
+        let SimplifiedMesh {
+            vertex_array: vertices,
+            index_array: indices,
+            ..
+        } = simplify(&vertex_array, &index_array, 0.2);
+
+        assert!(indices.len() < index_array.len());
+        let edges = directed_edges(&vertices, &indices);
+        for ([from, to], count) in &edges {
+            // every edge is used once each way, by the triangles on either side of it
+            assert_eq!(*count, 1, "{from:?} {to:?}");
+            assert_eq!(edges.get(&[*to, *from]), Some(&1), "{from:?} {to:?}");
+        }
+    }
+
+    #[test]
+    fn no_triangle_turns_over() {
+        let (vertex_array, index_array) = grid(16, false, |x, z| {
+            ((x as f32 * 1.3).sin() + (z as f32 * 0.7).cos()) * 0.8
+        });
+        for ratio in [0.5, 0.2, 0.05] {
+            let SimplifiedMesh {
+                vertex_array: vertices,
+                index_array: indices,
+                ..
+            } = simplify(&vertex_array, &index_array, ratio);
+            for triangle in indices.chunks_exact(3) {
+                // the grid faces up everywhere, seen from above the triangles wind the same
+                let normal = triangle_normal(&vertices, triangle);
+                assert!(normal[1] > 0.0, "{ratio}: {triangle:?} turned over");
+            }
+        }
+    }
+
+    #[test]
+    fn welded_seams_stay_closed() {
+        let (vertex_array, index_array) = grid(16, true, |x, z| ((x * z) % 3) as f32 * 0.1);
+        let SimplifiedMesh {
+            vertex_array: vertices,
+            index_array: indices,
+            source_vertices: sources,
+        } = simplify(&vertex_array, &index_array, 0.25);
+
+        // by position the seam is no boundary, every edge inside the square is used
+        // both ways
+        let edges = directed_edges(&vertices, &indices);
+        for [from, to] in edges.keys() {
+            let [from, to] = [from, to].map(|corner| corner.map(f32::from_bits));
+            let on_outline = |value: f32| value == 0.0 || value == 16.0;
+            let is_outline = (from[2] == to[2] && on_outline(from[2]))
+                || (from[0] == to[0] && on_outline(from[0]));
+            let reverse = [to, from].map(|corner| corner.map(f32::to_bits));
+            assert!(
+                is_outline || edges.contains_key(&reverse),
+                "{from:?} {to:?}"
+            );
+        }
+        // the vertices on either side keep their own uvs
+        for (vertex, source) in sources.iter().enumerate() {
+            let uv = |array: &[f32], index: usize| array[index * VERTEX_SIZE + 8];
+            assert_eq!(uv(&vertices, vertex), uv(&vertex_array, *source as usize));
+        }
+    }
+}